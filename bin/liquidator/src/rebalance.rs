@@ -1045,7 +1045,7 @@ impl Rebalancer {
     }
 
     pub async fn init(&mut self, live_rpc_client: &RpcClient) {
-        match swap::sanctum::load_supported_token_mints(live_rpc_client).await {
+        match swap::sanctum::load_supported_token_mints(live_rpc_client, &[]).await {
             Err(e) => warn!("Could not load list of sanctum supported mint: {}", e),
             Ok(mint) => self.sanctum_supported_mints.extend(mint),
         }
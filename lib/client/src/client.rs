@@ -1,15 +1,19 @@
 use anchor_client::ClientError::AnchorError;
+use std::collections::{BTreeMap, HashMap};
 use std::ops::Deref;
 use std::str::FromStr;
 use std::sync::Arc;
-use std::time::Duration;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
 
 use anchor_client::Cluster;
 
 use anchor_lang::__private::bytemuck;
 use anchor_lang::prelude::System;
 use anchor_lang::{AccountDeserialize, AnchorDeserialize, Id};
-use anchor_spl::associated_token::get_associated_token_address;
+use anchor_spl::associated_token::{
+    get_associated_token_address, get_associated_token_address_with_program_id,
+};
 use anchor_spl::token::Token;
 
 use fixed::types::I80F48;
@@ -23,13 +27,15 @@ use mango_v4::accounts_ix::{
 use mango_v4::accounts_zerocopy::KeyedAccountSharedData;
 use mango_v4::health::HealthCache;
 use mango_v4::state::{
-    Bank, Group, MangoAccountValue, OracleAccountInfos, PerpMarket, PerpMarketIndex,
-    PlaceOrderType, SelfTradeBehavior, Serum3MarketIndex, Side, TokenIndex, INSURANCE_TOKEN_INDEX,
+    Bank, Group, MangoAccountValue, MintInfo, OracleAccountInfos, OracleState, PerpMarket,
+    PerpMarketIndex, PlaceOrderType, SelfTradeBehavior, Serum3MarketIndex, Side, TokenIndex,
+    INSURANCE_TOKEN_INDEX, QUOTE_DECIMALS,
 };
 
 use crate::confirm_transaction::{wait_for_transaction_confirmation, RpcConfirmTransactionConfig};
-use crate::context::MangoGroupContext;
-use crate::gpa::{fetch_anchor_account, fetch_mango_accounts};
+use crate::context::{ComputeEstimates, MangoGroupContext};
+use crate::gpa::{fetch_anchor_account, fetch_mango_accounts, fetch_mint_infos};
+use crate::health_cache::HealthRatios;
 use crate::priority_fees::{FixedPriorityFeeProvider, PriorityFeeProvider};
 use crate::util;
 use crate::util::PreparedInstructions;
@@ -49,14 +55,24 @@ use solana_sdk::transaction::TransactionError;
 
 use anyhow::Context;
 use mango_v4::error::{IsAnchorErrorWithCode, MangoError};
-use solana_sdk::account::ReadableAccount;
+use solana_sdk::account::{AccountSharedData, ReadableAccount};
 use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::program_pack::Pack;
 use solana_sdk::signature::{Keypair, Signature};
 use solana_sdk::sysvar;
 use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signer::Signer};
+use solana_transaction_status::TransactionStatus;
 
 pub const MAX_ACCOUNTS_PER_TRANSACTION: usize = 64;
 
+/// Compute units the runtime grants each instruction when there's no SetComputeUnitLimit
+const DEFAULT_CU_PER_INSTRUCTION: u64 = 200_000;
+const MAX_CU_PER_TRANSACTION: u64 = 1_400_000;
+const COMPUTE_BUDGET_INSTRUCTION_CU: u64 = 150;
+
+/// Number of token_register transactions that MangoClient::register_tokens() has in flight
+const TOKEN_REGISTER_PARALLEL_REQUESTS: usize = 4;
+
 // very close to anchor_client::Client, which unfortunately has no accessors or Clone
 #[derive(Clone, Builder)]
 #[builder(name = "ClientBuilder", build_fn(name = "build_config"))]
@@ -114,6 +130,10 @@ pub struct ClientConfig {
     #[builder(default = "Duration::from_secs(30)")]
     pub sanctum_timeout: Duration,
 
+    /// Jito block engine for submitting bundles, see jito::send_bundle()
+    #[builder(default = "\"https://mainnet.block-engine.jito.wtf/api/v1\".into()")]
+    pub jito_block_engine_url: String,
+
     /// Determines how fallback oracle accounts are provided to instructions. Defaults to Dynamic.
     #[builder(default = "FallbackOracleConfig::Dynamic")]
     pub fallback_oracle_config: FallbackOracleConfig,
@@ -122,6 +142,13 @@ pub struct ClientConfig {
     /// addresses configured here instead.
     #[builder(default = "None")]
     pub override_send_transaction_urls: Option<Vec<String>>,
+
+    /// Maximum age of cached mango address lookup tables, defaults to 5min
+    ///
+    /// Lookup tables that were extended, for example by a new token registration, are
+    /// picked up after this time or when the cache is invalidated. Zero disables caching.
+    #[builder(default = "Duration::from_secs(300)")]
+    pub address_lookup_table_cache_ttl: Duration,
 }
 
 impl ClientBuilder {
@@ -279,6 +306,9 @@ pub struct MangoClient {
     pub context: MangoGroupContext,
 
     pub http_client: reqwest::Client,
+
+    /// Mango address lookup tables, see mango_address_lookup_tables()
+    pub address_lookup_table_cache: AddressLookupTableCache,
 }
 
 // TODO: add retry framework for sending tx and rpc calls
@@ -438,6 +468,8 @@ impl MangoClient {
         group_context: MangoGroupContext,
         account_fetcher: Arc<dyn AccountFetcher>,
     ) -> anyhow::Result<Self> {
+        let address_lookup_table_cache =
+            AddressLookupTableCache::new(client.config.address_lookup_table_cache_ttl);
         Ok(Self {
             client,
             account_fetcher,
@@ -445,6 +477,7 @@ impl MangoClient {
             mango_account_address: account,
             context: group_context,
             http_client: reqwest::Client::new(),
+            address_lookup_table_cache,
         })
     }
 
@@ -456,16 +489,96 @@ impl MangoClient {
         self.context.group
     }
 
+    /// Fetches and decodes the group account
+    pub async fn load_group(&self) -> anyhow::Result<Group> {
+        let account = self
+            .account_fetcher
+            .fetch_raw_account(&self.context.group)
+            .await?;
+        decode_group(account.data())
+            .with_context(|| format!("deserializing group {}", self.context.group))
+    }
+
+    /// Checks that the group account is owned by the program id the client builds
+    /// instructions for.
+    ///
+    /// All instructions use `mango_v4::id()`, so a group deployed under a different
+    /// program id (like a fork) can't be used with this client.
+    pub async fn verify_program_id(&self) -> anyhow::Result<()> {
+        let account = self
+            .account_fetcher
+            .fetch_raw_account(&self.context.group)
+            .await
+            .with_context(|| format!("loading group {}", self.context.group))?;
+        check_program_owner(&self.context.group, &account, &mango_v4::id())
+    }
+
+    /// Whether a transaction using `token_index` would need to activate a new token
+    /// position on `account`, which costs compute and adds health accounts
+    pub fn will_activate_position(account: &MangoAccountValue, token_index: TokenIndex) -> bool {
+        account.token_position(token_index).is_err()
+    }
+
     pub async fn mango_account(&self) -> anyhow::Result<MangoAccountValue> {
         account_fetcher_fetch_mango_account(&*self.account_fetcher, &self.mango_account_address)
             .await
     }
 
+    /// Whether `mint` is a token of the client's group, according to the cached context
+    pub fn is_registered_mint(&self, mint: &Pubkey) -> bool {
+        self.context.is_registered_mint(mint)
+    }
+
     pub async fn first_bank(&self, token_index: TokenIndex) -> anyhow::Result<Bank> {
         let bank_address = self.context.token(token_index).first_bank();
         account_fetcher_fetch_anchor_account(&*self.account_fetcher, &bank_address).await
     }
 
+    /// Native balances of all active token positions of the client's mango account,
+    /// loading each position's bank once
+    pub async fn account_positions(&self) -> anyhow::Result<Vec<PositionSummary>> {
+        let account = self.mango_account().await?;
+        let mut banks = HashMap::new();
+        for position in account.active_token_positions() {
+            let bank = self.first_bank(position.token_index).await?;
+            banks.insert(position.token_index, bank);
+        }
+        position_summaries(&account, &banks)
+    }
+
+    /// Whether `balance_native` of `token_index` is small enough to be dusted when
+    /// the token position closes, according to the token's first bank
+    pub async fn is_dust(
+        &self,
+        token_index: TokenIndex,
+        balance_native: I80F48,
+    ) -> anyhow::Result<bool> {
+        let bank = self.first_bank(token_index).await?;
+        Ok(bank.is_dust(balance_native))
+    }
+
+    /// Fetches the current state of a bank for `mint`, without relying on the group context.
+    ///
+    /// Loads the MintInfo and returns the first of its banks that can still be decoded.
+    pub async fn fresh_bank_by_mint(&self, mint: Pubkey) -> anyhow::Result<(Pubkey, Bank)> {
+        let mint_info_address = Pubkey::find_program_address(
+            &[b"MintInfo".as_ref(), self.group().as_ref(), mint.as_ref()],
+            &mango_v4::id(),
+        )
+        .0;
+        let mint_info: MintInfo =
+            account_fetcher_fetch_anchor_account(&*self.account_fetcher, &mint_info_address)
+                .await
+                .with_context(|| format!("loading mint info for mint {}", mint))?;
+
+        let mut candidates = vec![];
+        for bank_address in mint_info.banks() {
+            let account = self.account_fetcher.fetch_raw_account(bank_address).await;
+            candidates.push((*bank_address, account));
+        }
+        first_valid_bank(&self.group(), &mint, candidates)
+    }
+
     pub async fn derive_health_check_remaining_account_metas(
         &self,
         account: &MangoAccountValue,
@@ -489,6 +602,28 @@ impl MangoClient {
         )
     }
 
+    pub async fn derive_partial_health_check_remaining_account_metas(
+        &self,
+        account: &MangoAccountValue,
+        affected_tokens: Vec<TokenIndex>,
+        writable_banks: Vec<TokenIndex>,
+    ) -> anyhow::Result<(Vec<AccountMeta>, u32)> {
+        let fallback_contexts = self
+            .context
+            .derive_fallback_oracle_keys(
+                &self.client.config.fallback_oracle_config,
+                &*self.account_fetcher,
+            )
+            .await?;
+        self.context
+            .derive_partial_health_check_remaining_account_metas(
+                &account,
+                affected_tokens,
+                writable_banks,
+                fallback_contexts,
+            )
+    }
+
     pub async fn derive_health_check_remaining_account_metas_two_accounts(
         &self,
         account_1: &MangoAccountValue,
@@ -527,6 +662,21 @@ impl MangoClient {
         .await
     }
 
+    /// Maint and init health ratios of the client's mango account.
+    ///
+    /// Also returns the health accounts that were needed, for reuse in instructions.
+    pub async fn health_ratios(&self) -> anyhow::Result<HealthRatios> {
+        let account = self.mango_account().await?;
+        let (health_cache, health_accounts) = health_cache::new_with_accounts(
+            &self.context,
+            &self.client.config.fallback_oracle_config,
+            &*self.account_fetcher,
+            &account,
+        )
+        .await?;
+        Ok(HealthRatios::new(&health_cache, health_accounts))
+    }
+
     pub async fn token_deposit(
         &self,
         mint: Pubkey,
@@ -558,9 +708,13 @@ impl MangoClient {
                             bank: token.first_bank(),
                             vault: token.first_vault(),
                             oracle: token.oracle,
-                            token_account: get_associated_token_address(&self.owner(), &token.mint),
+                            token_account: get_associated_token_address_with_program_id(
+                                &self.owner(),
+                                &token.mint,
+                                &token.token_program,
+                            ),
                             token_authority: self.owner(),
-                            token_program: Token::id(),
+                            token_program: token.token_program,
                         },
                         None,
                     );
@@ -670,7 +824,7 @@ impl MangoClient {
                     &self.owner(),
                     &self.owner(),
                     &mint,
-                    &Token::id(),
+                    &token.token_program,
                 ),
                 Instruction {
                     program_id: mango_v4::id(),
@@ -683,11 +837,12 @@ impl MangoClient {
                                 bank: token.first_bank(),
                                 vault: token.first_vault(),
                                 oracle: token.oracle,
-                                token_account: get_associated_token_address(
+                                token_account: get_associated_token_address_with_program_id(
                                     &self.owner(),
                                     &token.mint,
+                                    &token.token_program,
                                 ),
-                                token_program: Token::id(),
+                                token_program: token.token_program,
                             },
                             None,
                         );
@@ -718,8 +873,160 @@ impl MangoClient {
         self.send_and_confirm_owner_tx(ixs.to_instructions()).await
     }
 
+    /// Native amount of the token that can currently be borrowed from its first bank,
+    /// see Bank::available_liquidity(). Useful for bounding flash loan amounts.
+    pub async fn bank_available_liquidity(
+        &self,
+        token_index: TokenIndex,
+    ) -> anyhow::Result<I80F48> {
+        let bank = self.first_bank(token_index).await?;
+        let vault = self.account_fetcher.fetch_raw_account(&bank.vault).await?;
+        let vault_balance =
+            anchor_spl::token::spl_token::state::Account::unpack(vault.data())?.amount;
+        Ok(bank.available_liquidity(vault_balance))
+    }
+
+    /// Native fees collected by each token's banks that weren't withdrawn yet, ordered
+    /// by token index.
+    pub async fn total_collected_fees(&self) -> anyhow::Result<Vec<(TokenIndex, I80F48)>> {
+        let mut banks = vec![];
+        for token in self.context.tokens.values() {
+            for bank_address in token.banks() {
+                let bank: Bank =
+                    account_fetcher_fetch_anchor_account(&*self.account_fetcher, bank_address)
+                        .await?;
+                banks.push(bank);
+            }
+        }
+        Ok(collected_fees_by_token(&banks))
+    }
+
+    /// Protocol-owned funds: each token's vault balances in excess of the net user
+    /// deposits, and the group's insurance fund.
+    ///
+    /// Reads all banks and vaults. The total values the token excesses at oracle prices
+    /// and is in native units of the insurance token.
+    pub async fn protocol_balances(&self) -> anyhow::Result<ProtocolBalances> {
+        let group = account_fetcher_fetch_anchor_account::<Group>(
+            &*self.account_fetcher,
+            &self.context.group,
+        )
+        .await?;
+        let insurance_vault = self
+            .account_fetcher
+            .fetch_raw_account(&group.insurance_vault)
+            .await?;
+        let insurance_fund =
+            anchor_spl::token::spl_token::state::Account::unpack(insurance_vault.data())?.amount;
+
+        let mut banks = vec![];
+        let mut prices = HashMap::new();
+        for token in self.context.tokens.values() {
+            for (bank_address, vault_address) in token.banks().iter().zip(token.vaults.iter()) {
+                let bank: Bank =
+                    account_fetcher_fetch_anchor_account(&*self.account_fetcher, bank_address)
+                        .await?;
+                let vault = self
+                    .account_fetcher
+                    .fetch_raw_account(vault_address)
+                    .await?;
+                let vault_balance =
+                    anchor_spl::token::spl_token::state::Account::unpack(vault.data())?.amount;
+                banks.push((bank, vault_balance));
+            }
+            prices.insert(
+                token.token_index,
+                self.bank_oracle_price(token.token_index).await?,
+            );
+        }
+        let insurance_price = prices
+            .get(&INSURANCE_TOKEN_INDEX)
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("group has no insurance token bank"))?;
+        protocol_balances_from_banks(&banks, &prices, insurance_price, insurance_fund)
+    }
+
+    /// All tokens registered in the group, ordered by token index.
+    ///
+    /// Reads the MintInfo accounts from rpc instead of relying on the group context,
+    /// so tokens registered after the client was created are included.
+    pub async fn list_tokens(&self) -> anyhow::Result<Vec<TokenInfo>> {
+        let mint_infos =
+            fetch_mint_infos(self.client.rpc_async(), mango_v4::id(), self.group()).await?;
+        Ok(token_infos_from_mint_infos(&mint_infos))
+    }
+
+    /// Deposit and borrow index of `bank` as of `slot`.
+    ///
+    /// Banks only store their current indices, so this walks back through the bank's
+    /// transactions and returns the indices of the last UpdateIndexLog at or before `slot`.
+    /// Needs an rpc node with the full transaction history (an archival node) and can
+    /// take many requests for slots far in the past.
+    pub async fn bank_index_at_slot(
+        &self,
+        bank: &Pubkey,
+        slot: u64,
+    ) -> anyhow::Result<(I80F48, I80F48)> {
+        let bank_data: Bank = account_fetcher_fetch_anchor_account(&*self.account_fetcher, bank)
+            .await
+            .context("fetching bank")?;
+        let rpc = self.client.rpc_async();
+
+        let mut before = None;
+        loop {
+            let statuses = rpc
+                .get_signatures_for_address_with_config(
+                    bank,
+                    solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config {
+                        before,
+                        until: None,
+                        limit: None,
+                        commitment: Some(CommitmentConfig::confirmed()),
+                    },
+                )
+                .await
+                .context("fetching bank signatures")?;
+            let Some(last) = statuses.last() else {
+                anyhow::bail!("no index update for bank {bank} at or before slot {slot}");
+            };
+            before = Some(Signature::from_str(&last.signature)?);
+
+            for status in statuses
+                .iter()
+                .filter(|s| s.slot <= slot && s.err.is_none())
+            {
+                let tx = rpc
+                    .get_transaction_with_config(
+                        &Signature::from_str(&status.signature)?,
+                        solana_client::rpc_config::RpcTransactionConfig {
+                            encoding: Some(
+                                solana_transaction_status::UiTransactionEncoding::Base64,
+                            ),
+                            commitment: Some(CommitmentConfig::confirmed()),
+                            max_supported_transaction_version: Some(0),
+                        },
+                    )
+                    .await
+                    .with_context(|| format!("fetching transaction {}", status.signature))?;
+                let logs = tx
+                    .transaction
+                    .meta
+                    .and_then(|meta| Option::<Vec<String>>::from(meta.log_messages))
+                    .unwrap_or_default();
+                if let Some(indexes) =
+                    last_update_index_log(&logs, &bank_data.group, bank_data.token_index)
+                {
+                    return Ok(indexes);
+                }
+            }
+        }
+    }
+
     pub async fn bank_oracle_price(&self, token_index: TokenIndex) -> anyhow::Result<I80F48> {
         let bank = self.first_bank(token_index).await?;
+        if bank.has_fixed_price() {
+            return Ok(bank.fixed_price);
+        }
         let mint_info = self.context.token(token_index);
         let oracle = self
             .account_fetcher
@@ -730,6 +1037,48 @@ impl MangoClient {
         Ok(price)
     }
 
+    /// The token's oracle price in quote per whole token, like 150.0 USD for one SOL.
+    ///
+    /// bank_oracle_price() is in native units instead: native quote per native token.
+    pub async fn oracle_price(&self, token_index: TokenIndex) -> anyhow::Result<I80F48> {
+        let native_price = self.bank_oracle_price(token_index).await?;
+        ui_price(native_price, self.context.token(token_index).decimals)
+    }
+
+    /// The bank's oracle price together with its confidence, without staleness or
+    /// confidence checks.
+    pub async fn bank_oracle_state(&self, token_index: TokenIndex) -> anyhow::Result<OracleState> {
+        let bank = self.first_bank(token_index).await?;
+        let mint_info = self.context.token(token_index);
+        let oracle = self
+            .account_fetcher
+            .fetch_raw_account(&mint_info.oracle)
+            .await?;
+        let oracle_acc = &KeyedAccountSharedData::new(mint_info.oracle, oracle.into());
+        let state = bank.oracle_state_unchecked(&OracleAccountInfos::from_reader(oracle_acc))?;
+        Ok(state)
+    }
+
+    /// The bank's oracle price in native units, like bank_oracle_price(), and how many
+    /// slots ago the oracle was last updated.
+    ///
+    /// Doesn't check staleness or confidence, callers decide what age is acceptable.
+    /// Fixed price banks and stub oracles without an update slot have age zero.
+    pub async fn oracle_price_and_staleness(
+        &self,
+        token_index: TokenIndex,
+    ) -> anyhow::Result<(I80F48, u64)> {
+        let bank = self.first_bank(token_index).await?;
+        let mint_info = self.context.token(token_index);
+        let oracle = self
+            .account_fetcher
+            .fetch_raw_account(&mint_info.oracle)
+            .await?;
+        let oracle_acc = KeyedAccountSharedData::new(mint_info.oracle, oracle);
+        let now_slot = self.client.rpc_async().get_slot().await?;
+        oracle_price_and_slot_age(&bank, &oracle_acc, now_slot)
+    }
+
     pub async fn perp_oracle_price(
         &self,
         perp_market_index: PerpMarketIndex,
@@ -743,6 +1092,111 @@ impl MangoClient {
         Ok(price)
     }
 
+    //
+    // Admin
+    //
+
+    pub fn token_register_instruction(
+        &self,
+        params: &TokenRegisterParams,
+    ) -> anyhow::Result<Instruction> {
+        params.validate()?;
+
+        let group = self.group();
+        let token_index = params.args.token_index;
+        let bank_num = 0u32;
+        let bank = Pubkey::find_program_address(
+            &[
+                b"Bank".as_ref(),
+                group.as_ref(),
+                &token_index.to_le_bytes(),
+                &bank_num.to_le_bytes(),
+            ],
+            &mango_v4::id(),
+        )
+        .0;
+        let vault = Pubkey::find_program_address(
+            &[
+                b"Vault".as_ref(),
+                group.as_ref(),
+                &token_index.to_le_bytes(),
+                &bank_num.to_le_bytes(),
+            ],
+            &mango_v4::id(),
+        )
+        .0;
+        let mint_info = Pubkey::find_program_address(
+            &[b"MintInfo".as_ref(), group.as_ref(), params.mint.as_ref()],
+            &mango_v4::id(),
+        )
+        .0;
+
+        let mut accounts = anchor_lang::ToAccountMetas::to_account_metas(
+            &mango_v4::accounts::TokenRegister {
+                group,
+                admin: self.owner(),
+                mint: params.mint,
+                bank,
+                vault,
+                mint_info,
+                oracle: params.oracle,
+                fallback_oracle: params.fallback_oracle,
+                payer: self.client.fee_payer().pubkey(),
+                token_program: params.token_program,
+                system_program: System::id(),
+                rent: sysvar::rent::id(),
+                pre_checks: mango_v4::accounts::TokenRegisterPreChecks { mint_info, bank },
+            },
+            None,
+        );
+        // The alt and its program follow all struct accounts, as remaining accounts
+        if let Some(alt) = params.alt {
+            accounts.push(AccountMeta::new(alt, false));
+            accounts.push(AccountMeta::new_readonly(
+                solana_address_lookup_table_program::id(),
+                false,
+            ));
+        }
+
+        Ok(Instruction {
+            program_id: mango_v4::id(),
+            accounts,
+            data: anchor_lang::InstructionData::data(&params.args),
+        })
+    }
+
+    /// Registers several tokens, sending one transaction per token.
+    ///
+    /// The owner must be the group admin. The registrations can't share a transaction
+    /// because each one inits several PDAs. Results are in the same order as `configs`.
+    ///
+    /// A registration with an `alt` extends that address lookup table, so the cached ones
+    /// are dropped if such a token was registered.
+    pub async fn register_tokens(
+        &self,
+        configs: Vec<TokenRegisterParams>,
+    ) -> Vec<anyhow::Result<Signature>> {
+        let extends_alt: Vec<bool> = configs.iter().map(|p| p.alt.is_some()).collect();
+        let results: Vec<anyhow::Result<Signature>> = stream::iter(configs)
+            .map(|params| async move {
+                let ix = self.token_register_instruction(&params)?;
+                self.send_and_confirm_owner_tx(vec![ix])
+                    .await
+                    .with_context(|| format!("registering token {}", params.args.name))
+            })
+            .buffered(TOKEN_REGISTER_PARALLEL_REQUESTS)
+            .collect()
+            .await;
+        if results
+            .iter()
+            .zip(extends_alt)
+            .any(|(r, extends_alt)| r.is_ok() && extends_alt)
+        {
+            self.address_lookup_table_cache.invalidate();
+        }
+        results
+    }
+
     //
     // Serum3
     //
@@ -2205,10 +2659,46 @@ impl MangoClient {
         }
     }
 
+    /// The swap backend for `version`, to pick one at runtime
+    pub fn swap_provider(
+        &self,
+        version: swap::Version,
+    ) -> anyhow::Result<Box<dyn swap::SwapProvider + Send + Sync + '_>> {
+        Ok(match version {
+            swap::Version::Mock => anyhow::bail!("the mock has no swap provider"),
+            swap::Version::V6 => Box::new(self.jupiter_v6()),
+            swap::Version::Sanctum => Box::new(self.sanctum()),
+        })
+    }
+
     pub fn sanctum(&self) -> swap::sanctum::Sanctum {
         swap::sanctum::Sanctum {
             mango_client: self,
             timeout_duration: self.client.config.sanctum_timeout,
+            mango_account_address: None,
+            output_bank_selection: Default::default(),
+            partial_health_accounts: false,
+            settle_pnl_after_swap: None,
+            max_address_lookup_tables: swap::sanctum::DEFAULT_MAX_ADDRESS_LOOKUP_TABLES,
+            route_cu_cache: None,
+            conservative_health: false,
+            min_effective_slippage_bps: None,
+            loan_buffer_bps: 0,
+            retry_policy: Default::default(),
+            max_fee_pct: None,
+            instructions_sysvar: None,
+            fee_payer: None,
+            skip_source_ata_creation: false,
+            strip_compute_budget: false,
+            extra_swap_programs: vec![],
+            compute_unit_limit: None,
+            refresh_quote_on_slippage: false,
+            base_url_override: None,
+            unwrap_sol_on_completion: false,
+            max_oracle_slot_age: None,
+            create_output_ata: true,
+            check_transaction_size: false,
+            idempotency_window_slots: swap::sanctum::DEFAULT_IDEMPOTENCY_WINDOW_SLOTS,
         }
     }
 
@@ -2275,6 +2765,20 @@ impl MangoClient {
         })
     }
 
+    /// Whether the address lookup table `alt` currently contains `account`.
+    ///
+    /// Always reads the table from rpc, bypassing the account fetcher's cache, so
+    /// recently added addresses are seen.
+    pub async fn alt_contains(&self, alt: &Pubkey, account: &Pubkey) -> anyhow::Result<bool> {
+        let raw = self
+            .client
+            .rpc_async()
+            .get_account(alt)
+            .await
+            .with_context(|| format!("fetching address lookup table {alt}"))?;
+        lookup_table_contains(raw.data(), account)
+    }
+
     pub async fn fetch_address_lookup_tables(
         &self,
         alts: impl Iterator<Item = &Pubkey>,
@@ -2285,11 +2789,16 @@ impl MangoClient {
             .await
     }
 
+    /// The group's address lookup tables, served from address_lookup_table_cache while
+    /// they're younger than the configured ttl
     pub async fn mango_address_lookup_tables(
         &self,
     ) -> anyhow::Result<Vec<AddressLookupTableAccount>> {
         stream::iter(self.context.address_lookup_tables.iter())
-            .then(|&k| self.fetch_address_lookup_table(k))
+            .then(|&k| {
+                self.address_lookup_table_cache
+                    .get_or_fetch(k, |k| self.fetch_address_lookup_table(k))
+            })
             .try_collect::<Vec<_>>()
             .await
     }
@@ -2322,6 +2831,67 @@ impl MangoClient {
         .await
     }
 
+    /// Best-effort cancellation of a pending transaction that writes `mango_account`,
+    /// like a stuck swap.
+    ///
+    /// Sends a no-op self-transfer by the owner that write-locks the mango account and
+    /// pays twice the configured priority fee. Solana has no transaction replacement:
+    /// this only helps if the replacement lands first and the original's blockhash
+    /// expires before the leader picks it up. It errors if `signature` already landed.
+    pub async fn cancel_pending(
+        &self,
+        signature: &Signature,
+        mango_account: &Pubkey,
+    ) -> anyhow::Result<Signature> {
+        let status = self
+            .client
+            .rpc_async()
+            .get_signature_statuses(&[*signature])
+            .await?
+            .value
+            .into_iter()
+            .next()
+            .flatten();
+        if let Some(status) = status {
+            anyhow::bail!(
+                "transaction {signature} was already processed in slot {}",
+                status.slot
+            );
+        }
+
+        let tx_builder = self.transaction_builder().await?;
+        let priority_fee = tx_builder
+            .config
+            .priority_fee_provider
+            .as_ref()
+            .map(|provider| provider.compute_unit_fee_microlamports())
+            .unwrap_or(0);
+        let mut tx_builder = TransactionBuilder {
+            instructions: cancel_pending_instructions(
+                &self.owner(),
+                mango_account,
+                cancel_priority_fee(priority_fee),
+            ),
+            ..tx_builder
+        };
+        tx_builder.signers.push(self.owner.clone());
+        tx_builder.send(&self.client).await
+    }
+
+    /// Transactions that idempotently create the owner's associated token accounts
+    /// for `mints`, split so each stays within the transaction size limits.
+    ///
+    /// Running these before a batch of swaps means the swaps don't each need to
+    /// carry an ATA creation, see Sanctum::skip_source_ata_creation.
+    pub fn prepare_atas(&self, mints: &[Pubkey]) -> anyhow::Result<Vec<TransactionBuilder>> {
+        ata_creation_transactions(
+            &self.owner(),
+            mints,
+            self.client.fee_payer(),
+            &self.client.config.transaction_builder_config,
+        )
+    }
+
     pub async fn transaction_builder(&self) -> anyhow::Result<TransactionBuilder> {
         let fee_payer = self.client.fee_payer();
         Ok(TransactionBuilder {
@@ -2385,6 +2955,157 @@ pub enum MangoClientError {
     },
 }
 
+/// Everything needed for a token_register instruction, see MangoClient::register_tokens()
+pub struct TokenRegisterParams {
+    pub mint: Pubkey,
+    /// The program that owns the mint, token or token-2022. The vault is created with it.
+    pub token_program: Pubkey,
+    pub oracle: Pubkey,
+    pub fallback_oracle: Pubkey,
+    /// Group address lookup table to extend with the new bank and oracle.
+    ///
+    /// Needs a program built with the token-register-alt feature.
+    pub alt: Option<Pubkey>,
+    pub args: mango_v4::instruction::TokenRegister,
+}
+
+impl TokenRegisterParams {
+    /// Catches the common mistakes that would make Bank::verify() reject the registration
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let args = &self.args;
+        let name = &args.name;
+        anyhow::ensure!(
+            !name.is_empty() && name.len() <= 16,
+            "token name '{name}' must be between 1 and 16 bytes"
+        );
+
+        let ir = &args.interest_rate_params;
+        anyhow::ensure!(
+            0.0 <= ir.util0 && ir.util0 <= ir.util1 && ir.util1 <= 1.0,
+            "token {name}: utilization points must satisfy 0 <= util0 <= util1 <= 1"
+        );
+        anyhow::ensure!(
+            0.0 <= ir.rate0 && ir.rate0 <= ir.rate1 && ir.rate1 <= ir.max_rate,
+            "token {name}: rates must satisfy 0 <= rate0 <= rate1 <= max_rate"
+        );
+
+        anyhow::ensure!(
+            0.0 <= args.init_asset_weight && args.init_asset_weight <= args.maint_asset_weight,
+            "token {name}: asset weights must satisfy 0 <= init <= maint"
+        );
+        anyhow::ensure!(
+            0.0 <= args.maint_liab_weight && args.maint_liab_weight <= args.init_liab_weight,
+            "token {name}: liab weights must satisfy 0 <= maint <= init"
+        );
+        let fixed_price_oracle = self.oracle == mango_v4::state::fixed_price_oracle::ID;
+        match args.fixed_price {
+            Some(price) => anyhow::ensure!(
+                price > 0 && fixed_price_oracle,
+                "token {name}: a fixed price must be positive and use the fixed price oracle"
+            ),
+            None => anyhow::ensure!(
+                !fixed_price_oracle,
+                "token {name}: the fixed price oracle requires a fixed price"
+            ),
+        }
+        Ok(())
+    }
+}
+
+/// Protocol-owned funds of a group, see MangoClient::protocol_balances()
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProtocolBalances {
+    /// Per token, ordered by token index
+    pub tokens: Vec<TokenProtocolBalance>,
+    /// Native balance of the group's insurance vault
+    pub insurance_fund: u64,
+    /// Insurance fund plus all token excesses, in native insurance tokens
+    pub total_in_insurance_token: I80F48,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TokenProtocolBalance {
+    pub token_index: TokenIndex,
+    /// Sum of the native balances of the token's vaults
+    pub vault_balance: u64,
+    /// Native deposits minus borrows of all the token's banks
+    pub net_user_deposits: I80F48,
+    /// Vault balance minus net user deposits, the protocol-owned part of the vaults
+    pub excess: I80F48,
+}
+
+/// A registered token, see MangoClient::list_tokens()
+#[derive(Clone, Debug, PartialEq)]
+pub struct TokenInfo {
+    pub mint: Pubkey,
+    pub token_index: TokenIndex,
+    pub first_bank: Pubkey,
+    pub first_vault: Pubkey,
+    pub oracle: Pubkey,
+    pub num_banks: usize,
+}
+
+/// A token position of a mango account, see MangoClient::account_positions()
+#[derive(Clone, Debug, PartialEq)]
+pub struct PositionSummary {
+    pub token_index: TokenIndex,
+    pub mint: Pubkey,
+    /// Positive for deposits, negative for borrows
+    pub native_balance: I80F48,
+}
+
+/// Address lookup tables by address
+///
+/// Entries expire after the ttl, so extended tables are picked up eventually.
+pub struct AddressLookupTableCache {
+    ttl: Duration,
+    entries: RwLock<HashMap<Pubkey, (AddressLookupTableAccount, Instant)>>,
+}
+
+impl AddressLookupTableCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, address: &Pubkey) -> Option<AddressLookupTableAccount> {
+        let entries = self.entries.read().unwrap();
+        entries
+            .get(address)
+            .filter(|(_, fetched_at)| fetched_at.elapsed() < self.ttl)
+            .map(|(alt, _)| alt.clone())
+    }
+
+    pub fn insert(&self, alt: AddressLookupTableAccount) {
+        let mut entries = self.entries.write().unwrap();
+        entries.insert(alt.key, (alt, Instant::now()));
+    }
+
+    /// The cached table at `address`, or the result of `fetch` which is then cached
+    pub async fn get_or_fetch<Fut>(
+        &self,
+        address: Pubkey,
+        fetch: impl FnOnce(Pubkey) -> Fut,
+    ) -> anyhow::Result<AddressLookupTableAccount>
+    where
+        Fut: std::future::Future<Output = anyhow::Result<AddressLookupTableAccount>>,
+    {
+        if let Some(alt) = self.get(&address) {
+            return Ok(alt);
+        }
+        let alt = fetch(address).await?;
+        self.insert(alt.clone());
+        Ok(alt)
+    }
+
+    /// Drops all entries, for example after the client learned about a new token
+    pub fn invalidate(&self) {
+        self.entries.write().unwrap().clear();
+    }
+}
+
 #[derive(Copy, Clone, Debug, Default)]
 pub struct TransactionSize {
     pub accounts: usize,
@@ -2536,6 +3257,122 @@ impl TransactionBuilder {
         Ok(tx)
     }
 
+    fn compile_message(&self) -> anyhow::Result<solana_sdk::message::v0::Message> {
+        let ixs = self.instructions_with_cu_budget();
+        Ok(solana_sdk::message::v0::Message::try_compile(
+            &self.payer,
+            &ixs,
+            &self.address_lookup_tables,
+            Hash::default(),
+        )?)
+    }
+
+    /// Estimated fee in lamports: the base fee for each required signature plus the
+    /// priority fee for the requested compute unit limit.
+    ///
+    /// Without an explicit SetComputeUnitLimit, the runtime default of 200k CU per
+    /// instruction is assumed.
+    pub fn estimated_fee(&self, lamports_per_signature: u64) -> anyhow::Result<u64> {
+        let message = self.compile_message()?;
+        let signature_fee = message.header.num_required_signatures as u64 * lamports_per_signature;
+
+        let mut cu_limit = None;
+        let mut cu_price = 0u64;
+        let mut other_instructions = 0u64;
+        for ix in self.instructions_with_cu_budget() {
+            if ix.program_id != solana_sdk::compute_budget::id() {
+                other_instructions += 1;
+                continue;
+            }
+            match ComputeBudgetInstruction::try_from_slice(&ix.data) {
+                Ok(ComputeBudgetInstruction::SetComputeUnitLimit(limit)) => {
+                    cu_limit = Some(limit as u64)
+                }
+                Ok(ComputeBudgetInstruction::SetComputeUnitPrice(price)) => cu_price = price,
+                _ => {}
+            }
+        }
+        let cu_limit = cu_limit
+            .unwrap_or(other_instructions * DEFAULT_CU_PER_INSTRUCTION)
+            .min(MAX_CU_PER_TRANSACTION);
+        let priority_fee = (cu_limit as u128 * cu_price as u128 + 999_999) / 1_000_000;
+
+        Ok(signature_fee + priority_fee as u64)
+    }
+
+    /// Rough compute unit estimate for each instruction, as (program id, cu) pairs.
+    ///
+    /// Mango instructions are assumed to run a health check costing `health_cu`,
+    /// ATA creation and compute budget instructions cost a fixed amount, other programs
+    /// are looked up in `program_cu` and fall back to the runtime's default limit of
+    /// 200k per instruction. These are estimates for finding the instructions that
+    /// dominate a transaction's compute use, not measurements.
+    pub fn estimate_cu_per_instruction(
+        &self,
+        compute_estimates: &ComputeEstimates,
+        health_cu: u32,
+        program_cu: &HashMap<Pubkey, u64>,
+    ) -> Vec<(Pubkey, u64)> {
+        self.instructions
+            .iter()
+            .map(|ix| {
+                let cu = if ix.program_id == mango_v4::id() {
+                    (compute_estimates.cu_per_mango_instruction + health_cu) as u64
+                } else if ix.program_id == spl_associated_token_account::id() {
+                    compute_estimates.cu_per_associated_token_account_creation as u64
+                } else if ix.program_id == solana_sdk::compute_budget::id() {
+                    COMPUTE_BUDGET_INSTRUCTION_CU
+                } else {
+                    program_cu
+                        .get(&ix.program_id)
+                        .copied()
+                        .unwrap_or(DEFAULT_CU_PER_INSTRUCTION)
+                };
+                (ix.program_id, cu)
+            })
+            .collect()
+    }
+
+    /// Sum of estimate_cu_per_instruction(), to compare against MAX_CU_PER_TRANSACTION
+    pub fn estimated_compute_units(
+        &self,
+        compute_estimates: &ComputeEstimates,
+        health_cu: u32,
+        program_cu: &HashMap<Pubkey, u64>,
+    ) -> u64 {
+        self.estimate_cu_per_instruction(compute_estimates, health_cu, program_cu)
+            .iter()
+            .map(|(_, cu)| cu)
+            .sum()
+    }
+
+    /// Serialized size of the transaction in bytes, with the address lookup tables
+    /// applied, to compare against PACKET_DATA_SIZE.
+    ///
+    /// Unlike transaction_size() this doesn't sign, so it works without the signers.
+    pub fn estimated_size(&self) -> anyhow::Result<usize> {
+        let message = self.compile_message()?;
+        let tx = solana_sdk::transaction::VersionedTransaction {
+            signatures: vec![Signature::default(); message.header.num_required_signatures as usize],
+            message: solana_sdk::message::VersionedMessage::V0(message),
+        };
+        Ok(bincode::serialized_size(&tx)? as usize)
+    }
+
+    /// Checks that every pubkey that must sign the transaction has a keypair in `signers`.
+    ///
+    /// Catches fee payer and delegate misconfigurations before anything is sent.
+    pub fn verify_signers(&self) -> anyhow::Result<()> {
+        let message = self.compile_message()?;
+        let num_signers = message.header.num_required_signatures as usize;
+        for required in message.account_keys.iter().take(num_signers) {
+            if !self.signers.iter().any(|s| s.pubkey() == *required) {
+                anyhow::bail!("missing signer for {required}");
+            }
+        }
+        Ok(())
+    }
+
     // These two send() functions don't really belong into the transaction builder!
 
     pub async fn send(&self, client: &Client) -> anyhow::Result<Signature> {
@@ -2551,18 +3388,28 @@ impl TransactionBuilder {
     }
 
     pub async fn send_and_confirm(&self, client: &Client) -> anyhow::Result<Signature> {
+        let (signature, _) = self.send_and_confirm_with_status(client).await?;
+        Ok(signature)
+    }
+
+    /// Like send_and_confirm(), but also returns the confirmed transaction's status,
+    /// which has the slot it landed in
+    pub async fn send_and_confirm_with_status(
+        &self,
+        client: &Client,
+    ) -> anyhow::Result<(Signature, TransactionStatus)> {
         let rpc = client.rpc_async();
         let tx = self.transaction(&rpc).await?;
         let recent_blockhash = tx.message.recent_blockhash();
         let signature = client.send_transaction(&tx).await?;
-        wait_for_transaction_confirmation(
+        let status = wait_for_transaction_confirmation(
             &rpc,
             &signature,
             recent_blockhash,
             &client.config.rpc_confirm_transaction_config,
         )
         .await?;
-        Ok(signature)
+        Ok((signature, status))
     }
 
     pub fn transaction_size(&self) -> anyhow::Result<TransactionSize> {
@@ -2646,3 +3493,1294 @@ pub fn pubkey_from_cli(pubkey: &str) -> Pubkey {
         Err(_) => keypair_from_cli(pubkey).pubkey(),
     }
 }
+
+/// Deposit and borrow index of the last UpdateIndexLog event for the token in `logs`
+fn last_update_index_log(
+    logs: &[String],
+    group: &Pubkey,
+    token_index: TokenIndex,
+) -> Option<(I80F48, I80F48)> {
+    use anchor_lang::Discriminator;
+    use mango_v4::logs::UpdateIndexLog;
+
+    logs.iter()
+        .filter_map(|line| line.strip_prefix("Program data: "))
+        .filter_map(|data| base64::decode(data).ok())
+        .filter(|data| data.starts_with(&UpdateIndexLog::discriminator()))
+        .filter_map(|data| UpdateIndexLog::try_from_slice(&data[8..]).ok())
+        .filter(|log| log.mango_group == *group && log.token_index == token_index)
+        .last()
+        .map(|log| {
+            (
+                I80F48::from_bits(log.deposit_index),
+                I80F48::from_bits(log.borrow_index),
+            )
+        })
+}
+
+/// Sums collected_fees_native over all banks of each token
+fn collected_fees_by_token(banks: &[Bank]) -> Vec<(TokenIndex, I80F48)> {
+    let mut fees = BTreeMap::<TokenIndex, I80F48>::new();
+    for bank in banks {
+        *fees.entry(bank.token_index).or_insert(I80F48::ZERO) += bank.collected_fees_native;
+    }
+    fees.into_iter().collect()
+}
+
+/// Sums up vault balances and user deposits of `banks`, which are paired with their
+/// vault balance, and values the excess at `prices` in terms of the insurance token.
+fn protocol_balances_from_banks(
+    banks: &[(Bank, u64)],
+    prices: &HashMap<TokenIndex, I80F48>,
+    insurance_price: I80F48,
+    insurance_fund: u64,
+) -> anyhow::Result<ProtocolBalances> {
+    anyhow::ensure!(
+        insurance_price > 0,
+        "insurance token price must be positive"
+    );
+    let mut tokens = BTreeMap::<TokenIndex, TokenProtocolBalance>::new();
+    for (bank, vault_balance) in banks {
+        let entry = tokens
+            .entry(bank.token_index)
+            .or_insert(TokenProtocolBalance {
+                token_index: bank.token_index,
+                vault_balance: 0,
+                net_user_deposits: I80F48::ZERO,
+                excess: I80F48::ZERO,
+            });
+        entry.vault_balance += vault_balance;
+        entry.net_user_deposits += bank.native_deposits() - bank.native_borrows();
+        entry.excess = I80F48::from(entry.vault_balance) - entry.net_user_deposits;
+    }
+
+    let mut total_in_insurance_token = I80F48::from(insurance_fund);
+    for token in tokens.values() {
+        let price = prices
+            .get(&token.token_index)
+            .ok_or_else(|| anyhow::anyhow!("no price for token {}", token.token_index))?;
+        total_in_insurance_token += token.excess * price / insurance_price;
+    }
+
+    Ok(ProtocolBalances {
+        tokens: tokens.into_values().collect(),
+        insurance_fund,
+        total_in_insurance_token,
+    })
+}
+
+fn position_summaries(
+    account: &MangoAccountValue,
+    banks: &HashMap<TokenIndex, Bank>,
+) -> anyhow::Result<Vec<PositionSummary>> {
+    account
+        .active_token_positions()
+        .map(|position| {
+            let bank = banks
+                .get(&position.token_index)
+                .ok_or_else(|| anyhow::anyhow!("no bank for token {}", position.token_index))?;
+            Ok(PositionSummary {
+                token_index: position.token_index,
+                mint: bank.mint,
+                native_balance: position.native(bank),
+            })
+        })
+        .collect()
+}
+
+fn token_infos_from_mint_infos(mint_infos: &[(Pubkey, MintInfo)]) -> Vec<TokenInfo> {
+    let mut tokens = mint_infos
+        .iter()
+        .map(|(_, mint_info)| TokenInfo {
+            mint: mint_info.mint,
+            token_index: mint_info.token_index,
+            first_bank: mint_info.first_bank(),
+            first_vault: mint_info.first_vault(),
+            oracle: mint_info.oracle,
+            num_banks: mint_info.num_banks(),
+        })
+        .collect::<Vec<_>>();
+    tokens.sort_by_key(|t| t.token_index);
+    tokens
+}
+
+/// The bank's native oracle price and the age of the oracle in slots at `now_slot`
+fn oracle_price_and_slot_age(
+    bank: &Bank,
+    oracle: &KeyedAccountSharedData,
+    now_slot: u64,
+) -> anyhow::Result<(I80F48, u64)> {
+    let state = bank.oracle_state_unchecked(&OracleAccountInfos::from_reader(oracle))?;
+    Ok((state.price, now_slot.saturating_sub(state.last_update_slot)))
+}
+
+/// Priority fee for a cancellation, outbidding the regular `priority_fee`
+fn cancel_priority_fee(priority_fee: u64) -> u64 {
+    priority_fee.saturating_mul(2).max(1)
+}
+
+/// A zero lamport self-transfer by `owner` that write-locks `mango_account`
+fn cancel_pending_instructions(
+    owner: &Pubkey,
+    mango_account: &Pubkey,
+    priority_fee: u64,
+) -> Vec<Instruction> {
+    let mut transfer = solana_sdk::system_instruction::transfer(owner, owner, 0);
+    transfer
+        .accounts
+        .push(AccountMeta::new(*mango_account, false));
+    vec![
+        ComputeBudgetInstruction::set_compute_unit_price(priority_fee),
+        ComputeBudgetInstruction::set_compute_unit_limit(1_000),
+        transfer,
+    ]
+}
+
+fn ata_creation_transactions(
+    owner: &Pubkey,
+    mints: &[Pubkey],
+    fee_payer: Arc<Keypair>,
+    config: &TransactionBuilderConfig,
+) -> anyhow::Result<Vec<TransactionBuilder>> {
+    let new_builder = || TransactionBuilder {
+        instructions: vec![],
+        address_lookup_tables: vec![],
+        payer: fee_payer.pubkey(),
+        signers: vec![fee_payer.clone()],
+        config: config.clone(),
+    };
+    let mut builders = vec![];
+    let mut builder = new_builder();
+    for mint in mints.iter().unique() {
+        let ix =
+            spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+                &fee_payer.pubkey(),
+                owner,
+                mint,
+                &Token::id(),
+            );
+        builder.instructions.push(ix.clone());
+        if builder.transaction_size()?.is_within_limit() {
+            continue;
+        }
+        builder.instructions.pop();
+        anyhow::ensure!(
+            !builder.instructions.is_empty(),
+            "ata creation for mint {mint} exceeds the transaction size limit"
+        );
+        builders.push(std::mem::replace(&mut builder, new_builder()));
+        builder.instructions.push(ix);
+    }
+    if !builder.instructions.is_empty() {
+        builders.push(builder);
+    }
+    Ok(builders)
+}
+
+fn lookup_table_contains(data: &[u8], account: &Pubkey) -> anyhow::Result<bool> {
+    let table = AddressLookupTable::deserialize(data)?;
+    Ok(table.addresses.contains(account))
+}
+
+/// Converts a price in native quote per native token to quote per whole token
+fn ui_price(native_price: I80F48, mint_decimals: u8) -> anyhow::Result<I80F48> {
+    let decimals = i8::try_from(mint_decimals)? - QUOTE_DECIMALS;
+    anyhow::ensure!(
+        (-12..=12).contains(&decimals),
+        "unsupported mint decimals {mint_decimals}"
+    );
+    Ok(native_price * mango_v4::state::power_of_ten(decimals))
+}
+
+fn decode_group(mut data: &[u8]) -> anyhow::Result<Group> {
+    Ok(Group::try_deserialize(&mut data)?)
+}
+
+fn check_program_owner(
+    address: &Pubkey,
+    account: &AccountSharedData,
+    program_id: &Pubkey,
+) -> anyhow::Result<()> {
+    if account.owner() != program_id {
+        anyhow::bail!(
+            "account {} is owned by {}, but the client uses mango program {}",
+            address,
+            account.owner(),
+            program_id
+        );
+    }
+    Ok(())
+}
+
+/// Returns the first bank account that decodes and belongs to `group` and `mint`.
+///
+/// Accounts that fail to load or decode, like closed banks, are skipped.
+fn first_valid_bank(
+    group: &Pubkey,
+    mint: &Pubkey,
+    candidates: Vec<(Pubkey, anyhow::Result<AccountSharedData>)>,
+) -> anyhow::Result<(Pubkey, Bank)> {
+    for (address, account) in candidates {
+        let account = match account {
+            Ok(account) => account,
+            Err(err) => {
+                debug!("skipping bank {}: {:?}", address, err);
+                continue;
+            }
+        };
+        let mut data: &[u8] = account.data();
+        let bank = match Bank::try_deserialize(&mut data) {
+            Ok(bank) => bank,
+            Err(err) => {
+                debug!("skipping bank {}: {:?}", address, err);
+                continue;
+            }
+        };
+        if bank.group != *group || bank.mint != *mint {
+            debug!("skipping bank {}: group or mint mismatch", address);
+            continue;
+        }
+        return Ok((address, bank));
+    }
+    anyhow::bail!("no valid bank for mint {}", mint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mango_v4::accounts_ix::InterestRateParams;
+    use mango_v4::state::OracleConfigParams;
+
+    fn token_register_params(token_index: TokenIndex, name: &str) -> TokenRegisterParams {
+        TokenRegisterParams {
+            mint: Pubkey::new_unique(),
+            token_program: Token::id(),
+            oracle: Pubkey::new_unique(),
+            fallback_oracle: Pubkey::default(),
+            alt: None,
+            args: mango_v4::instruction::TokenRegister {
+                token_index,
+                name: name.to_string(),
+                oracle_config: OracleConfigParams {
+                    conf_filter: 0.1,
+                    max_staleness_slots: None,
+                },
+                interest_rate_params: InterestRateParams {
+                    util0: 0.4,
+                    rate0: 0.07,
+                    util1: 0.8,
+                    rate1: 0.9,
+                    max_rate: 1.5,
+                    adjustment_factor: 0.004,
+                },
+                loan_fee_rate: 0.0005,
+                loan_origination_fee_rate: 0.005,
+                maint_asset_weight: 0.9,
+                init_asset_weight: 0.8,
+                maint_liab_weight: 1.1,
+                init_liab_weight: 1.2,
+                liquidation_fee: 0.05,
+                stable_price_delay_interval_seconds: 60 * 60,
+                stable_price_delay_growth_limit: 0.06,
+                stable_price_growth_limit: 0.0003,
+                min_vault_to_deposits_ratio: 0.2,
+                net_borrow_limit_window_size_ts: 24 * 60 * 60,
+                net_borrow_limit_per_window_quote: -1,
+                borrow_weight_scale_start_quote: f64::MAX,
+                deposit_weight_scale_start_quote: f64::MAX,
+                reduce_only: 0,
+                token_conditional_swap_taker_fee_rate: 0.0,
+                token_conditional_swap_maker_fee_rate: 0.0,
+                flash_loan_swap_fee_rate: 0.0,
+                interest_curve_scaling: 1.0,
+                interest_target_utilization: 0.5,
+                group_insurance_fund: true,
+                deposit_limit: 0,
+                zero_util_rate: 0.0,
+                platform_liquidation_fee: 0.0,
+                disable_asset_liquidation: false,
+                collateral_fee_per_day: 0.0,
+                fixed_price: None,
+                borrow_limit: 0,
+            },
+        }
+    }
+
+    #[test]
+    fn test_transaction_builder_verify_signers() {
+        let payer = Arc::new(Keypair::new());
+        let owner = Arc::new(Keypair::new());
+        let mut builder = TransactionBuilder {
+            instructions: vec![Instruction {
+                program_id: mango_v4::id(),
+                accounts: vec![AccountMeta::new_readonly(owner.pubkey(), true)],
+                data: vec![],
+            }],
+            address_lookup_tables: vec![],
+            signers: vec![payer.clone()],
+            payer: payer.pubkey(),
+            config: TransactionBuilderConfig::default(),
+        };
+
+        let err = builder.verify_signers().unwrap_err();
+        assert!(err.to_string().contains(&owner.pubkey().to_string()));
+
+        builder.signers.push(owner);
+        builder.verify_signers().unwrap();
+
+        builder.signers.remove(0);
+        let err = builder.verify_signers().unwrap_err();
+        assert!(err.to_string().contains(&payer.pubkey().to_string()));
+    }
+
+    #[test]
+    fn test_transaction_builder_estimated_fee() {
+        let payer = Arc::new(Keypair::new());
+        let delegate = Arc::new(Keypair::new());
+        let ix = |signer: Pubkey| Instruction {
+            program_id: mango_v4::id(),
+            accounts: vec![AccountMeta::new_readonly(signer, true)],
+            data: vec![],
+        };
+
+        // single signer, no compute budget: two instructions at the default limit
+        let mut builder = TransactionBuilder {
+            instructions: vec![ix(payer.pubkey()), ix(payer.pubkey())],
+            address_lookup_tables: vec![],
+            signers: vec![payer.clone()],
+            payer: payer.pubkey(),
+            config: TransactionBuilderConfig::default(),
+        };
+        assert_eq!(builder.estimated_fee(5000).unwrap(), 5000);
+
+        // with a priority fee: 2 * 200k CU at 10 microlamports
+        builder.config = TransactionBuilderConfig {
+            priority_fee_provider: Some(Arc::new(FixedPriorityFeeProvider::new(10))),
+            compute_budget_per_instruction: None,
+        };
+        assert_eq!(builder.estimated_fee(5000).unwrap(), 5000 + 4);
+
+        // delegate and fee payer sign, and the limit is set explicitly
+        builder.instructions = vec![ix(delegate.pubkey())];
+        builder.signers.push(delegate);
+        builder.config.compute_budget_per_instruction = Some(150_000);
+        assert_eq!(builder.estimated_fee(5000).unwrap(), 2 * 5000 + 2);
+    }
+
+    #[test]
+    fn test_transaction_builder_estimated_size_and_compute_units() {
+        let payer = Arc::new(Keypair::new());
+        let ix = |accounts: usize| Instruction {
+            program_id: mango_v4::id(),
+            accounts: (0..accounts)
+                .map(|_| AccountMeta::new(Pubkey::new_unique(), false))
+                .collect(),
+            data: vec![],
+        };
+        let mut builder = TransactionBuilder {
+            instructions: vec![ix(3)],
+            address_lookup_tables: vec![],
+            signers: vec![payer.clone()],
+            payer: payer.pubkey(),
+            config: TransactionBuilderConfig::default(),
+        };
+        let size = builder.estimated_size().unwrap();
+        assert_eq!(size, builder.transaction_size().unwrap().length);
+        assert!(size <= solana_sdk::packet::PACKET_DATA_SIZE);
+
+        // the same without signers
+        builder.signers.clear();
+        assert_eq!(builder.estimated_size().unwrap(), size);
+
+        // 80 accounts don't fit without lookup tables
+        builder.instructions = vec![ix(40), ix(40)];
+        assert!(builder.estimated_size().unwrap() > solana_sdk::packet::PACKET_DATA_SIZE);
+
+        // but do when the lookup table has them
+        let table = AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: builder
+                .instructions
+                .iter()
+                .flat_map(|ix| ix.accounts.iter().map(|am| am.pubkey))
+                .collect(),
+        };
+        builder.address_lookup_tables = vec![table];
+        assert!(builder.estimated_size().unwrap() <= solana_sdk::packet::PACKET_DATA_SIZE);
+
+        let estimates = ComputeEstimates::default();
+        let mango_cu = (estimates.cu_per_mango_instruction + 10_000) as u64;
+        assert_eq!(
+            builder.estimated_compute_units(&estimates, 10_000, &HashMap::new()),
+            2 * mango_cu
+        );
+        builder.instructions = vec![];
+        assert_eq!(
+            builder.estimated_compute_units(&estimates, 10_000, &HashMap::new()),
+            0
+        );
+    }
+
+    #[test]
+    fn test_transaction_builder_estimate_cu_per_instruction() {
+        let payer = Arc::new(Keypair::new());
+        let mango_ix = Instruction {
+            program_id: mango_v4::id(),
+            accounts: vec![],
+            data: vec![],
+        };
+        let swap_program = Pubkey::new_unique();
+        let other_program = Pubkey::new_unique();
+        let builder = TransactionBuilder {
+            instructions: vec![
+                ComputeBudgetInstruction::set_compute_unit_limit(500_000),
+                spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+                    &payer.pubkey(),
+                    &payer.pubkey(),
+                    &Pubkey::new_unique(),
+                    &Token::id(),
+                ),
+                mango_ix.clone(),
+                Instruction {
+                    program_id: swap_program,
+                    accounts: vec![],
+                    data: vec![],
+                },
+                mango_ix,
+                Instruction {
+                    program_id: other_program,
+                    accounts: vec![],
+                    data: vec![],
+                },
+            ],
+            address_lookup_tables: vec![],
+            signers: vec![payer.clone()],
+            payer: payer.pubkey(),
+            config: TransactionBuilderConfig::default(),
+        };
+
+        let estimates = ComputeEstimates::default();
+        let health_cu = 12_000;
+        let program_cu = HashMap::from([(swap_program, 80_000)]);
+        let mango_cu = (estimates.cu_per_mango_instruction + health_cu) as u64;
+        assert_eq!(
+            builder.estimate_cu_per_instruction(&estimates, health_cu, &program_cu),
+            vec![
+                (solana_sdk::compute_budget::id(), 150),
+                (
+                    spl_associated_token_account::id(),
+                    estimates.cu_per_associated_token_account_creation as u64
+                ),
+                (mango_v4::id(), mango_cu),
+                (swap_program, 80_000),
+                (mango_v4::id(), mango_cu),
+                (other_program, 200_000),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_token_register_params_validate() {
+        for (i, name) in ["USDC", "SOL", "MNGO"].iter().enumerate() {
+            token_register_params(i as TokenIndex, name)
+                .validate()
+                .unwrap();
+        }
+
+        let mut p = token_register_params(0, "");
+        assert!(p.validate().is_err());
+        p.args.name = "ABCDEFGHIJKLMNOPQ".to_string();
+        assert!(p.validate().is_err());
+
+        let mut p = token_register_params(0, "A");
+        p.args.interest_rate_params.util1 = 0.3;
+        assert!(p.validate().is_err());
+
+        let mut p = token_register_params(0, "A");
+        p.args.interest_rate_params.util1 = 1.1;
+        assert!(p.validate().is_err());
+
+        let mut p = token_register_params(0, "A");
+        p.args.interest_rate_params.util0 = 0.0;
+        p.args.interest_rate_params.util1 = 0.0;
+        assert!(p.validate().is_ok());
+
+        let mut p = token_register_params(0, "A");
+        p.args.interest_rate_params.rate1 = 2.0;
+        assert!(p.validate().is_err());
+
+        let mut p = token_register_params(0, "A");
+        p.args.interest_rate_params.rate0 = -0.1;
+        assert!(p.validate().is_err());
+
+        let mut p = token_register_params(0, "A");
+        p.args.init_asset_weight = 0.95;
+        assert!(p.validate().is_err());
+
+        let mut p = token_register_params(0, "A");
+        p.args.fixed_price = Some(I80F48::ONE);
+        assert!(p.validate().is_err());
+        p.oracle = mango_v4::state::fixed_price_oracle::ID;
+        p.validate().unwrap();
+        p.args.fixed_price = Some(I80F48::ZERO);
+        assert!(p.validate().is_err());
+        p.args.fixed_price = None;
+        assert!(p.validate().is_err());
+
+        let mut p = token_register_params(0, "A");
+        p.args.init_liab_weight = 1.05;
+        assert!(p.validate().is_err());
+    }
+
+    #[test]
+    fn test_will_activate_position() {
+        use anchor_lang::AnchorSerialize;
+        use mango_v4::state::{MangoAccount, TokenPosition};
+
+        let mut account = MangoAccount::default_for_tests();
+        account.tokens.resize(4, TokenPosition::default());
+        let bytes = AnchorSerialize::try_to_vec(&account).unwrap();
+        let mut account = MangoAccountValue::from_bytes(&bytes).unwrap();
+        account.ensure_token_position(2).unwrap();
+
+        assert!(!MangoClient::will_activate_position(&account, 2));
+        assert!(MangoClient::will_activate_position(&account, 5));
+    }
+
+    #[test]
+    fn test_position_summaries() {
+        use anchor_lang::AnchorSerialize;
+        use bytemuck::Zeroable;
+        use mango_v4::state::{MangoAccount, TokenPosition};
+
+        let bank = |token_index: TokenIndex, index: f64| {
+            let mut bank = Bank::zeroed();
+            bank.token_index = token_index;
+            bank.mint = Pubkey::new_unique();
+            bank.deposit_index = I80F48::from_num(index);
+            bank.borrow_index = I80F48::from_num(index);
+            bank
+        };
+        let banks = HashMap::from([(0, bank(0, 1.0)), (3, bank(3, 1.5))]);
+
+        let mut account = MangoAccount::default_for_tests();
+        account.tokens.resize(4, TokenPosition::default());
+        let bytes = AnchorSerialize::try_to_vec(&account).unwrap();
+        let mut account = MangoAccountValue::from_bytes(&bytes).unwrap();
+
+        // deposited 100 of token 0, then borrowed 30 of token 3
+        account.ensure_token_position(0).unwrap().0.indexed_position = I80F48::from(100);
+        account.ensure_token_position(3).unwrap().0.indexed_position = I80F48::from(-20);
+
+        let positions = position_summaries(&account, &banks).unwrap();
+        assert_eq!(
+            positions,
+            vec![
+                PositionSummary {
+                    token_index: 0,
+                    mint: banks[&0].mint,
+                    native_balance: I80F48::from(100),
+                },
+                PositionSummary {
+                    token_index: 3,
+                    mint: banks[&3].mint,
+                    native_balance: I80F48::from(-30),
+                },
+            ]
+        );
+
+        // every active position needs its bank
+        account.ensure_token_position(2).unwrap();
+        assert!(position_summaries(&account, &banks).is_err());
+    }
+
+    #[test]
+    fn test_partial_health_check_needs_bank() {
+        use anchor_lang::AnchorSerialize;
+        use mango_v4::state::{MangoAccount, TokenPosition};
+
+        let mut account = MangoAccount::default_for_tests();
+        account.tokens.resize(8, TokenPosition::default());
+        let bytes = AnchorSerialize::try_to_vec(&account).unwrap();
+        let mut account = MangoAccountValue::from_bytes(&bytes).unwrap();
+
+        // deposits in tokens 0..7, a borrow in token 7
+        for token_index in 0..8 {
+            account.ensure_token_position(token_index).unwrap();
+        }
+        account.token_position_mut(7).unwrap().0.indexed_position = I80F48::from(-1);
+
+        let swap_tokens = [0, 1];
+        let perp_settle_tokens = [2];
+        let kept = account
+            .active_token_positions()
+            .filter(|p| partial_health_check_needs_bank(p, &swap_tokens, &perp_settle_tokens))
+            .map(|p| p.token_index)
+            .collect::<Vec<_>>();
+        assert_eq!(kept, vec![0, 1, 2, 7]);
+
+        // every bank comes with its oracle
+        let full_size = account.active_token_positions().count() * 2 * 32;
+        let partial_size = kept.len() * 2 * 32;
+        assert_eq!(full_size - partial_size, 256);
+    }
+
+    #[test]
+    fn test_decode_group() {
+        use anchor_lang::Discriminator;
+        use bytemuck::Zeroable;
+
+        let mut group = Group::zeroed();
+        group.admin = Pubkey::new_unique();
+        group.insurance_mint = Pubkey::new_unique();
+        group.group_num = 3;
+        let mut data = Group::discriminator().to_vec();
+        data.extend_from_slice(bytemuck::bytes_of(&group));
+
+        let decoded = decode_group(&data).unwrap();
+        assert_eq!(decoded.admin, group.admin);
+        assert_eq!(decoded.insurance_mint, group.insurance_mint);
+        assert_eq!(decoded.group_num, 3);
+
+        // not a group
+        let mut data = Bank::discriminator().to_vec();
+        data.extend_from_slice(bytemuck::bytes_of(&group));
+        assert!(decode_group(&data).is_err());
+        assert!(decode_group(&[]).is_err());
+    }
+
+    #[test]
+    fn test_first_valid_bank() {
+        use anchor_lang::Discriminator;
+        use bytemuck::Zeroable;
+        use solana_sdk::account::WritableAccount;
+
+        let group = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let closed = Pubkey::new_unique();
+        let open = Pubkey::new_unique();
+
+        let mut bank = Bank::zeroed();
+        bank.group = group;
+        bank.mint = mint;
+        bank.bank_num = 1;
+        let mut data = Bank::discriminator().to_vec();
+        data.extend_from_slice(bytemuck::bytes_of(&bank));
+        let mut bank_account = AccountSharedData::new(1, data.len(), &mango_v4::id());
+        bank_account.set_data_from_slice(&data);
+
+        let closed_account = AccountSharedData::new(0, 0, &solana_sdk::system_program::id());
+        let (address, found) = first_valid_bank(
+            &group,
+            &mint,
+            vec![
+                (closed, Ok(closed_account)),
+                (open, Ok(bank_account.clone())),
+            ],
+        )
+        .unwrap();
+        assert_eq!(address, open);
+        assert_eq!(found.bank_num, 1);
+
+        // a failed fetch is skipped as well
+        let (address, _) = first_valid_bank(
+            &group,
+            &mint,
+            vec![
+                (closed, Err(anyhow::anyhow!("account not found"))),
+                (open, Ok(bank_account.clone())),
+            ],
+        )
+        .unwrap();
+        assert_eq!(address, open);
+
+        assert!(first_valid_bank(
+            &group,
+            &Pubkey::new_unique(),
+            vec![(open, Ok(bank_account))]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_collected_fees_by_token() {
+        use bytemuck::Zeroable;
+
+        let bank = |token_index: TokenIndex, bank_num: u32| {
+            let mut bank = Bank::zeroed();
+            bank.token_index = token_index;
+            bank.bank_num = bank_num;
+            bank
+        };
+        let mut banks = vec![bank(3, 0), bank(1, 0), bank(1, 1)];
+        assert_eq!(
+            collected_fees_by_token(&banks),
+            vec![(1, I80F48::ZERO), (3, I80F48::ZERO)]
+        );
+
+        // accrue fees on the two banks of token 1 and the bank of token 3
+        banks[0].collected_fees_native += I80F48::from(7);
+        banks[1].collected_fees_native += I80F48::from_num(2.5);
+        banks[2].collected_fees_native += I80F48::from_num(0.25);
+        assert_eq!(
+            collected_fees_by_token(&banks),
+            vec![(1, I80F48::from_num(2.75)), (3, I80F48::from(7)),]
+        );
+    }
+
+    #[test]
+    fn test_protocol_balances_from_banks() {
+        use bytemuck::Zeroable;
+
+        let bank = |token_index: TokenIndex, deposits: i32, borrows: i32| {
+            let mut bank = Bank::zeroed();
+            bank.token_index = token_index;
+            bank.deposit_index = I80F48::ONE;
+            bank.borrow_index = I80F48::ONE;
+            bank.indexed_deposits = I80F48::from(deposits);
+            bank.indexed_borrows = I80F48::from(borrows);
+            bank
+        };
+        let prices = HashMap::from([(0, I80F48::ONE), (1, I80F48::from(4))]);
+
+        // users deposited 1000 of token 0 and 500 of token 1 over two banks,
+        // 200 of token 1 were borrowed out again
+        let mut banks = vec![
+            (bank(0, 1000, 0), 1000),
+            (bank(1, 300, 200), 100),
+            (bank(1, 200, 0), 200),
+        ];
+        let balances = protocol_balances_from_banks(&banks, &prices, I80F48::ONE, 50).unwrap();
+        assert_eq!(
+            balances.tokens,
+            vec![
+                TokenProtocolBalance {
+                    token_index: 0,
+                    vault_balance: 1000,
+                    net_user_deposits: I80F48::from(1000),
+                    excess: I80F48::ZERO,
+                },
+                TokenProtocolBalance {
+                    token_index: 1,
+                    vault_balance: 300,
+                    net_user_deposits: I80F48::from(300),
+                    excess: I80F48::ZERO,
+                },
+            ]
+        );
+        assert_eq!(balances.total_in_insurance_token, I80F48::from(50));
+
+        // fees collected: 10 of token 0 and 5 of token 1 stay in the vaults
+        banks[0].1 += 10;
+        banks[2].1 += 5;
+        let balances = protocol_balances_from_banks(&banks, &prices, I80F48::ONE, 50).unwrap();
+        assert_eq!(balances.tokens[0].excess, I80F48::from(10));
+        assert_eq!(balances.tokens[1].excess, I80F48::from(5));
+        assert_eq!(
+            balances.total_in_insurance_token,
+            I80F48::from(50 + 10 + 5 * 4)
+        );
+
+        // valued in an insurance token worth 2
+        let balances = protocol_balances_from_banks(&banks, &prices, I80F48::from(2), 50).unwrap();
+        assert_eq!(balances.total_in_insurance_token, I80F48::from(50 + 15));
+
+        assert!(protocol_balances_from_banks(&banks, &HashMap::new(), I80F48::ONE, 0).is_err());
+    }
+
+    #[test]
+    fn test_token_infos_from_mint_infos() {
+        use bytemuck::Zeroable;
+
+        let mint_info = |token_index: TokenIndex, num_banks: usize| {
+            let mut mint_info = MintInfo::zeroed();
+            mint_info.token_index = token_index;
+            mint_info.mint = Pubkey::new_unique();
+            mint_info.oracle = Pubkey::new_unique();
+            for (bank, vault) in mint_info
+                .banks
+                .iter_mut()
+                .zip(mint_info.vaults.iter_mut())
+                .take(num_banks)
+            {
+                *bank = Pubkey::new_unique();
+                *vault = Pubkey::new_unique();
+            }
+            (Pubkey::new_unique(), mint_info)
+        };
+
+        // gpa returns accounts in no particular order
+        let mint_infos = vec![mint_info(4, 1), mint_info(0, 1), mint_info(2, 3)];
+        let tokens = token_infos_from_mint_infos(&mint_infos);
+
+        assert_eq!(
+            tokens.iter().map(|t| t.token_index).collect::<Vec<_>>(),
+            vec![0, 2, 4]
+        );
+        for token in &tokens {
+            let (_, mint_info) = mint_infos
+                .iter()
+                .find(|(_, mi)| mi.token_index == token.token_index)
+                .unwrap();
+            assert_eq!(token.mint, mint_info.mint);
+            assert_eq!(token.oracle, mint_info.oracle);
+            assert_eq!(token.first_bank, mint_info.banks[0]);
+            assert_eq!(token.first_vault, mint_info.vaults[0]);
+        }
+        assert_eq!(tokens[0].num_banks, 1);
+        assert_eq!(tokens[1].num_banks, 3);
+
+        assert!(token_infos_from_mint_infos(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_cancel_pending_instructions() {
+        assert_eq!(cancel_priority_fee(0), 1);
+        assert_eq!(cancel_priority_fee(500), 1000);
+
+        let owner = Arc::new(Keypair::new());
+        let mango_account = Pubkey::new_unique();
+        let builder = TransactionBuilder {
+            instructions: cancel_pending_instructions(
+                &owner.pubkey(),
+                &mango_account,
+                cancel_priority_fee(500),
+            ),
+            address_lookup_tables: vec![],
+            signers: vec![owner.clone()],
+            payer: owner.pubkey(),
+            config: TransactionBuilderConfig::default(),
+        };
+        builder.verify_signers().unwrap();
+
+        let tx = builder.transaction_with_blockhash(Hash::default()).unwrap();
+        let message = &tx.message;
+        let mango_account_index = message
+            .static_account_keys()
+            .iter()
+            .position(|k| *k == mango_account)
+            .unwrap();
+        assert!(message.is_maybe_writable(mango_account_index));
+        assert_eq!(message.instructions().len(), 3);
+
+        // the explicit price and limit are kept: fee is 1000 microlamports for 1k CU
+        assert_eq!(builder.estimated_fee(5000).unwrap(), 5000 + 1);
+    }
+
+    #[test]
+    fn test_lookup_table_contains() {
+        let present = Pubkey::new_unique();
+        let data = AddressLookupTable {
+            meta: Default::default(),
+            addresses: std::borrow::Cow::Owned(vec![Pubkey::new_unique(), present]),
+        }
+        .serialize_for_tests()
+        .unwrap();
+        assert!(lookup_table_contains(&data, &present).unwrap());
+        assert!(!lookup_table_contains(&data, &Pubkey::new_unique()).unwrap());
+        assert!(lookup_table_contains(&[0u8; 10], &present).is_err());
+    }
+
+    #[test]
+    fn test_is_registered_mint() {
+        let mint = Pubkey::new_unique();
+        let token = TokenContext {
+            group: Pubkey::new_unique(),
+            token_index: 0,
+            name: "USDC".to_string(),
+            mint,
+            oracle: Pubkey::new_unique(),
+            banks: [Pubkey::new_unique(); mango_v4::state::MAX_BANKS],
+            vaults: [Pubkey::new_unique(); mango_v4::state::MAX_BANKS],
+            fallback_context: crate::context::FallbackOracleContext {
+                key: Pubkey::default(),
+                quote_key: Pubkey::default(),
+            },
+            mint_info_address: Pubkey::new_unique(),
+            decimals: 6,
+            oracle_config: bytemuck::Zeroable::zeroed(),
+            token_program: anchor_spl::token::ID,
+        };
+        let context = MangoGroupContext {
+            group: token.group,
+            tokens: [(0, token)].into_iter().collect(),
+            token_indexes_by_name: [("USDC".to_string(), 0)].into_iter().collect(),
+            serum3_markets: Default::default(),
+            serum3_market_indexes_by_name: Default::default(),
+            perp_markets: Default::default(),
+            perp_market_indexes_by_name: Default::default(),
+            address_lookup_tables: vec![],
+            compute_estimates: Default::default(),
+        };
+
+        assert!(context.is_registered_mint(&mint));
+        assert!(!context.is_registered_mint(&Pubkey::new_unique()));
+    }
+
+    #[test]
+    fn test_health_check_metas_no_duplicates() {
+        use anchor_lang::AnchorSerialize;
+        use mango_v4::state::{MangoAccount, TokenPosition};
+
+        let tokens = (0..8)
+            .map(|token_index: TokenIndex| {
+                let token = TokenContext {
+                    group: Pubkey::default(),
+                    token_index,
+                    name: format!("T{token_index}"),
+                    mint: Pubkey::new_unique(),
+                    oracle: Pubkey::new_unique(),
+                    banks: [Pubkey::new_unique(); mango_v4::state::MAX_BANKS],
+                    vaults: [Pubkey::new_unique(); mango_v4::state::MAX_BANKS],
+                    fallback_context: crate::context::FallbackOracleContext {
+                        key: Pubkey::default(),
+                        quote_key: Pubkey::default(),
+                    },
+                    mint_info_address: Pubkey::new_unique(),
+                    decimals: 6,
+                    oracle_config: bytemuck::Zeroable::zeroed(),
+                    token_program: anchor_spl::token::ID,
+                };
+                (token_index, token)
+            })
+            .collect::<HashMap<_, _>>();
+        let context = MangoGroupContext {
+            group: Pubkey::default(),
+            tokens,
+            token_indexes_by_name: Default::default(),
+            serum3_markets: Default::default(),
+            serum3_market_indexes_by_name: Default::default(),
+            perp_markets: Default::default(),
+            perp_market_indexes_by_name: Default::default(),
+            address_lookup_tables: vec![],
+            compute_estimates: Default::default(),
+        };
+
+        let mut account = MangoAccount::default_for_tests();
+        account.tokens.resize(8, TokenPosition::default());
+        let bytes = AnchorSerialize::try_to_vec(&account).unwrap();
+        let mut account = MangoAccountValue::from_bytes(&bytes).unwrap();
+        for token_index in [5, 0, 3, 1, 6] {
+            account.ensure_token_position(token_index).unwrap();
+        }
+
+        // swap between two tokens the account already holds, plus one new one,
+        // listed repeatedly like callers do
+        let (metas, _) = context
+            .derive_health_check_remaining_account_metas(
+                &account,
+                vec![3, 1, 3, 7],
+                vec![3, 1, 7],
+                vec![],
+                HashMap::new(),
+            )
+            .unwrap();
+
+        let keys = metas.iter().map(|m| m.pubkey).collect::<Vec<_>>();
+        assert_eq!(keys.iter().unique().count(), keys.len());
+
+        // banks in position order, then their oracles in the same order
+        let order = [5, 0, 3, 1, 6, 7];
+        let expected = order
+            .iter()
+            .map(|ti| context.token(*ti).first_bank())
+            .chain(order.iter().map(|ti| context.token(*ti).oracle))
+            .collect::<Vec<_>>();
+        assert_eq!(keys, expected);
+
+        let writable = metas
+            .iter()
+            .filter(|m| m.is_writable)
+            .map(|m| m.pubkey)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            writable,
+            [3, 1, 7].map(|ti| context.token(ti).first_bank()).to_vec()
+        );
+    }
+
+    #[test]
+    fn test_last_update_index_log() {
+        use anchor_lang::{AnchorSerialize, Discriminator};
+        use mango_v4::logs::UpdateIndexLog;
+
+        let group = Pubkey::new_unique();
+        let log_line = |group: Pubkey, token_index: TokenIndex, deposit_index: f64| {
+            let log = UpdateIndexLog {
+                mango_group: group,
+                token_index,
+                deposit_index: I80F48::from_num(deposit_index).to_bits(),
+                borrow_index: I80F48::from_num(deposit_index + 0.5).to_bits(),
+                avg_utilization: 0,
+                price: 0,
+                stable_price: 0,
+                collected_fees: 0,
+                loan_fee_rate: 0,
+                total_borrows: 0,
+                total_deposits: 0,
+                borrow_rate: 0,
+                deposit_rate: 0,
+            };
+            let mut data = UpdateIndexLog::discriminator().to_vec();
+            data.extend(log.try_to_vec().unwrap());
+            format!("Program data: {}", base64::encode(data))
+        };
+
+        // logs of a historical TokenUpdateIndexAndRate transaction
+        let logs = vec![
+            "Program 4MangoMjqJ2firMokCjjGgoK8d4MXcrgL7XJaL3w6fVg invoke [1]".to_string(),
+            "Program log: Instruction: TokenUpdateIndexAndRate".to_string(),
+            log_line(group, 1, 1.25),
+            log_line(Pubkey::new_unique(), 2, 7.0),
+            "Program data: not base64!".to_string(),
+            log_line(group, 2, 1.5),
+            log_line(group, 2, 1.75),
+            "Program 4MangoMjqJ2firMokCjjGgoK8d4MXcrgL7XJaL3w6fVg success".to_string(),
+        ];
+
+        assert_eq!(
+            last_update_index_log(&logs, &group, 2),
+            Some((I80F48::from_num(1.75), I80F48::from_num(2.25)))
+        );
+        assert_eq!(
+            last_update_index_log(&logs, &group, 1),
+            Some((I80F48::from_num(1.25), I80F48::from_num(1.75)))
+        );
+        assert_eq!(last_update_index_log(&logs, &group, 3), None);
+    }
+
+    #[test]
+    fn test_ata_creation_transactions() {
+        let owner = Pubkey::new_unique();
+        let fee_payer = Arc::new(Keypair::new());
+        let config = TransactionBuilderConfig::default();
+
+        let created_atas = |builders: &[TransactionBuilder]| {
+            builders
+                .iter()
+                .flat_map(|b| b.instructions.iter())
+                .map(|ix| {
+                    assert_eq!(ix.program_id, spl_associated_token_account::ID);
+                    assert_eq!(ix.accounts[0].pubkey, fee_payer.pubkey());
+                    assert_eq!(ix.accounts[2].pubkey, owner);
+                    ix.accounts[1].pubkey
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mint_a = Pubkey::new_unique();
+        let mint_b = Pubkey::new_unique();
+        let builders = ata_creation_transactions(
+            &owner,
+            &[mint_a, mint_b, mint_a],
+            fee_payer.clone(),
+            &config,
+        )
+        .unwrap();
+        assert_eq!(builders.len(), 1);
+        assert_eq!(builders[0].payer, fee_payer.pubkey());
+        assert_eq!(
+            created_atas(&builders),
+            vec![
+                get_associated_token_address(&owner, &mint_a),
+                get_associated_token_address(&owner, &mint_b),
+            ]
+        );
+
+        // many mints get split into several transactions
+        let mints = (0..40).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+        let builders =
+            ata_creation_transactions(&owner, &mints, fee_payer.clone(), &config).unwrap();
+        assert!(builders.len() > 1);
+        for builder in builders.iter() {
+            assert!(builder.transaction_size().unwrap().is_within_limit());
+        }
+        assert_eq!(
+            created_atas(&builders),
+            mints
+                .iter()
+                .map(|mint| get_associated_token_address(&owner, mint))
+                .collect::<Vec<_>>()
+        );
+
+        assert!(ata_creation_transactions(&owner, &[], fee_payer, &config)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_address_lookup_table_cache() {
+        let alt = |addresses: usize| AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: (0..addresses).map(|_| Pubkey::new_unique()).collect(),
+        };
+
+        let cache = AddressLookupTableCache::new(Duration::from_millis(50));
+        let a = alt(3);
+        let b = alt(1);
+        assert!(cache.get(&a.key).is_none());
+        cache.insert(a.clone());
+        cache.insert(b.clone());
+        assert_eq!(cache.get(&a.key).unwrap().addresses, a.addresses);
+
+        // refreshed after the ttl
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(cache.get(&a.key).is_none());
+        let a_extended = AddressLookupTableAccount {
+            key: a.key,
+            addresses: a
+                .addresses
+                .iter()
+                .copied()
+                .chain([Pubkey::new_unique()])
+                .collect(),
+        };
+        cache.insert(a_extended.clone());
+        assert_eq!(cache.get(&a.key).unwrap().addresses, a_extended.addresses);
+
+        cache.invalidate();
+        assert!(cache.get(&a.key).is_none());
+        assert!(cache.get(&b.key).is_none());
+
+        let disabled = AddressLookupTableCache::new(Duration::ZERO);
+        disabled.insert(b.clone());
+        assert!(disabled.get(&b.key).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_address_lookup_table_cache_get_or_fetch() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        let group_alts = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let fetches = AtomicU32::new(0);
+        let fetch = |key: Pubkey| {
+            fetches.fetch_add(1, Ordering::SeqCst);
+            async move {
+                Ok(AddressLookupTableAccount {
+                    key,
+                    addresses: vec![Pubkey::new_unique()],
+                })
+            }
+        };
+        // what each swap does to get the group's tables
+        let swap_alts = |cache: &AddressLookupTableCache| {
+            futures::future::try_join_all(group_alts.map(|k| cache.get_or_fetch(k, fetch)))
+        };
+
+        let cache = AddressLookupTableCache::new(Duration::from_secs(60));
+        let first = swap_alts(&cache).await.unwrap();
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+
+        // a second swap within the ttl doesn't fetch
+        let second = swap_alts(&cache).await.unwrap();
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            first.iter().map(|a| &a.addresses).collect::<Vec<_>>(),
+            second.iter().map(|a| &a.addresses).collect::<Vec<_>>()
+        );
+
+        // after a token registration, the tables are fetched again
+        cache.invalidate();
+        swap_alts(&cache).await.unwrap();
+        assert_eq!(fetches.load(Ordering::SeqCst), 4);
+
+        // failed fetches aren't cached
+        let key = Pubkey::new_unique();
+        let failed = cache
+            .get_or_fetch(key, |_| async { Err(anyhow::anyhow!("rpc down")) })
+            .await;
+        assert!(failed.is_err());
+        assert!(cache.get(&key).is_none());
+    }
+
+    #[test]
+    fn test_check_program_owner() {
+        let group = Pubkey::new_unique();
+        let account = AccountSharedData::new(1, 0, &mango_v4::id());
+        assert!(check_program_owner(&group, &account, &mango_v4::id()).is_ok());
+
+        let fork = Pubkey::new_unique();
+        let forked_account = AccountSharedData::new(1, 0, &fork);
+        let err = check_program_owner(&group, &forked_account, &mango_v4::id()).unwrap_err();
+        assert!(err.to_string().contains(&fork.to_string()));
+    }
+
+    #[test]
+    fn test_oracle_price_and_slot_age() {
+        use anchor_lang::Discriminator;
+        use bytemuck::Zeroable;
+        use mango_v4::state::StubOracle;
+
+        let oracle_key = Pubkey::new_unique();
+        let oracle_account = |last_update_slot: u64| {
+            let mut oracle = StubOracle::zeroed();
+            oracle.price = I80F48::from(3);
+            oracle.last_update_slot = last_update_slot;
+            let mut data = StubOracle::discriminator().to_vec();
+            data.extend_from_slice(bytemuck::bytes_of(&oracle));
+            let mut account = AccountSharedData::new(1, data.len(), &mango_v4::id());
+            solana_sdk::account::WritableAccount::set_data_from_slice(&mut account, &data);
+            KeyedAccountSharedData::new(oracle_key, account)
+        };
+        let mut bank = Bank::zeroed();
+        bank.oracle = oracle_key;
+        bank.mint_decimals = 6;
+
+        // last updated long ago
+        let (price, age) = oracle_price_and_slot_age(&bank, &oracle_account(100), 1100).unwrap();
+        assert_eq!(price, I80F48::from(3));
+        assert_eq!(age, 1000);
+
+        // updated in the current slot, or after the slot the caller saw
+        let (_, age) = oracle_price_and_slot_age(&bank, &oracle_account(1100), 1100).unwrap();
+        assert_eq!(age, 0);
+        let (_, age) = oracle_price_and_slot_age(&bank, &oracle_account(1200), 1100).unwrap();
+        assert_eq!(age, 0);
+
+        // stubs without an update slot and fixed prices never age
+        let (_, age) = oracle_price_and_slot_age(&bank, &oracle_account(0), 1100).unwrap();
+        assert_eq!(age, 0);
+        bank.oracle = mango_v4::state::fixed_price_oracle::ID;
+        bank.fixed_price = I80F48::from(5);
+        let fixed_oracle = KeyedAccountSharedData::new(
+            bank.oracle,
+            AccountSharedData::new(1, 0, &Pubkey::default()),
+        );
+        let (price, age) = oracle_price_and_slot_age(&bank, &fixed_oracle, 1100).unwrap();
+        assert_eq!(price, I80F48::from(5));
+        assert_eq!(age, 0);
+    }
+
+    #[test]
+    fn test_ui_price() {
+        assert_eq!(ui_price(I80F48::ONE, 6).unwrap(), I80F48::ONE);
+        assert_eq!(ui_price(I80F48::from(2), 8).unwrap(), I80F48::from(200));
+        assert!(ui_price(I80F48::ONE, 30).is_err());
+
+        // the pyth fixture decoded for mints of different decimals gives the same ui price
+        let oracle = Pubkey::from_str("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap();
+        let data = std::fs::read(format!(
+            "{}/../../programs/mango-v4/resources/test/{}.bin",
+            env!("CARGO_MANIFEST_DIR"),
+            oracle
+        ))
+        .unwrap();
+        let mut account = AccountSharedData::new(1, data.len(), &Pubkey::new_unique());
+        solana_sdk::account::WritableAccount::set_data_from_slice(&mut account, &data);
+        let oracle_acc = KeyedAccountSharedData::new(oracle, account);
+        let ui_prices = [6u8, 9]
+            .iter()
+            .map(|&decimals| {
+                let state = mango_v4::state::oracle_state_unchecked(
+                    &OracleAccountInfos::from_reader(&oracle_acc),
+                    decimals,
+                )
+                .unwrap();
+                assert_eq!(state.oracle_type, mango_v4::state::OracleType::Pyth);
+                ui_price(state.price, decimals).unwrap()
+            })
+            .collect_vec();
+        assert!(ui_prices[0] > 0);
+        assert!((ui_prices[0] - ui_prices[1]).abs() < I80F48::from_num(1e-6));
+    }
+}
@@ -9,7 +9,8 @@ use mango_v4::{
     state::{
         determine_oracle_type, load_orca_pool_state, load_raydium_pool_state,
         oracle_state_unchecked, Group, MangoAccountValue, OracleAccountInfos, OracleConfig,
-        OracleConfigParams, OracleType, PerpMarketIndex, Serum3MarketIndex, TokenIndex, MAX_BANKS,
+        OracleConfigParams, OracleType, PerpMarketIndex, Serum3MarketIndex, TokenIndex,
+        TokenPosition, MAX_BANKS,
     },
 };
 
@@ -24,6 +25,25 @@ use solana_sdk::account::Account;
 use solana_sdk::instruction::AccountMeta;
 use solana_sdk::pubkey::Pubkey;
 
+/// Whether a health check that tolerates missing banks (like FlashLoanEnd) still needs
+/// the bank and oracle for this token position.
+///
+/// The program only allows skipping banks for nonnegative balances, and requires the
+/// settle tokens of perp positions. Perp markets and serum3 open orders can't be skipped.
+///
+/// Leaving out a bank drops that asset from the health computation, so the partial health
+/// is never higher than the full one: accounts that are close to zero init health may fail
+/// a health check that they would pass with all banks.
+pub fn partial_health_check_needs_bank(
+    position: &TokenPosition,
+    kept_tokens: &[TokenIndex],
+    perp_settle_tokens: &[TokenIndex],
+) -> bool {
+    kept_tokens.contains(&position.token_index)
+        || perp_settle_tokens.contains(&position.token_index)
+        || position.indexed_position.is_negative()
+}
+
 #[derive(Clone, PartialEq, Eq)]
 pub struct TokenContext {
     pub group: Pubkey,
@@ -37,9 +57,20 @@ pub struct TokenContext {
     pub mint_info_address: Pubkey,
     pub decimals: u8,
     pub oracle_config: OracleConfig,
+    /// The token program that owns the vaults and the mint, token or token-2022
+    pub token_program: Pubkey,
 }
 
 impl TokenContext {
+    /// The associated token account of `owner` for this token's mint and token program
+    pub fn associated_token_address(&self, owner: &Pubkey) -> Pubkey {
+        anchor_spl::associated_token::get_associated_token_address_with_program_id(
+            owner,
+            &self.mint,
+            &self.token_program,
+        )
+    }
+
     pub fn native_to_ui(&self, native: I80F48) -> f64 {
         (native / I80F48::from(10u64.pow(self.decimals.into()))).to_num()
     }
@@ -239,6 +270,11 @@ impl MangoGroupContext {
             .ok_or_else(|| anyhow::anyhow!("no token for mint {}", mint))
     }
 
+    /// Whether `mint` belongs to one of the group's tokens, like token_by_mint().is_ok()
+    pub fn is_registered_mint(&self, mint: &Pubkey) -> bool {
+        self.tokens.values().any(|tc| tc.mint == *mint)
+    }
+
     pub fn token_by_name(&self, name: &str) -> &TokenContext {
         let mut tc_iter = self.tokens.values().filter(|tc| tc.name == name);
         let tc = tc_iter.next();
@@ -274,6 +310,7 @@ impl MangoGroupContext {
                             quote_key: Pubkey::default(),
                         },
                         oracle_config: OracleConfigParams::default().to_oracle_config(),
+                        token_program: Pubkey::default(),
                         group: mi.group,
                         mint: mi.mint,
                     },
@@ -281,7 +318,8 @@ impl MangoGroupContext {
             })
             .collect::<HashMap<_, _>>();
 
-        // reading the banks is only needed for the token names, decimals and oracle configs
+        // reading the banks is only needed for the token names, decimals, oracle configs
+        // and token programs
         // FUTURE: either store the names on MintInfo as well, or maybe don't store them at all
         //         because they are in metaplex?
         let bank_tuples = fetch_banks(rpc, program, group).await?;
@@ -295,6 +333,7 @@ impl MangoGroupContext {
             token.name = bank.name().into();
             token.decimals = bank.mint_decimals;
             token.oracle_config = bank.oracle_config;
+            token.token_program = bank.vault_token_program();
             let (key, acc_info) = fallback_oracle_accounts[index].clone();
             token.fallback_context.quote_key =
                 get_fallback_quote_key(&KeyedAccountSharedData::new(key, acc_info));
@@ -405,6 +444,14 @@ impl MangoGroupContext {
         })
     }
 
+    /// Health accounts for `account` after activating positions for the affected tokens
+    /// and perp markets.
+    ///
+    /// Token indexes may repeat across `affected_tokens` and `writable_banks`: each token
+    /// contributes the first bank of its mint exactly once, so no bank is listed twice.
+    /// Oracles follow in the same order, one per bank, because the program pairs them by
+    /// position. Tokens that share an oracle therefore repeat its key; that is cheap, since
+    /// the transaction message lists each distinct account only once.
     pub fn derive_health_check_remaining_account_metas(
         &self,
         account: &MangoAccountValue,
@@ -412,6 +459,47 @@ impl MangoGroupContext {
         writable_banks: Vec<TokenIndex>,
         affected_perp_markets: Vec<PerpMarketIndex>,
         fallback_contexts: HashMap<Pubkey, FallbackOracleContext>,
+    ) -> anyhow::Result<(Vec<AccountMeta>, u32)> {
+        self.derive_health_check_remaining_account_metas_inner(
+            account,
+            affected_tokens,
+            writable_banks,
+            affected_perp_markets,
+            fallback_contexts,
+            false,
+        )
+    }
+
+    /// Like derive_health_check_remaining_account_metas(), but leaves out the banks and
+    /// oracles of token positions that the health check may skip.
+    ///
+    /// Only usable for instructions that tolerate missing banks, like FlashLoanEnd. See
+    /// partial_health_check_needs_bank() for which banks are kept.
+    pub fn derive_partial_health_check_remaining_account_metas(
+        &self,
+        account: &MangoAccountValue,
+        affected_tokens: Vec<TokenIndex>,
+        writable_banks: Vec<TokenIndex>,
+        fallback_contexts: HashMap<Pubkey, FallbackOracleContext>,
+    ) -> anyhow::Result<(Vec<AccountMeta>, u32)> {
+        self.derive_health_check_remaining_account_metas_inner(
+            account,
+            affected_tokens,
+            writable_banks,
+            vec![],
+            fallback_contexts,
+            true,
+        )
+    }
+
+    fn derive_health_check_remaining_account_metas_inner(
+        &self,
+        account: &MangoAccountValue,
+        affected_tokens: Vec<TokenIndex>,
+        writable_banks: Vec<TokenIndex>,
+        affected_perp_markets: Vec<PerpMarketIndex>,
+        fallback_contexts: HashMap<Pubkey, FallbackOracleContext>,
+        partial: bool,
     ) -> anyhow::Result<(Vec<AccountMeta>, u32)> {
         let mut account = account.clone();
         for affected_token_index in affected_tokens.iter().chain(writable_banks.iter()) {
@@ -422,11 +510,26 @@ impl MangoGroupContext {
             account.ensure_perp_position(affected_perp_market_index, settle_token_index)?;
         }
 
+        let kept_tokens = affected_tokens
+            .iter()
+            .chain(writable_banks.iter())
+            .copied()
+            .collect::<Vec<_>>();
+        let perp_settle_tokens = account
+            .active_perp_positions()
+            .map(|pp| self.perp(pp.market_index).settle_token_index)
+            .collect::<Vec<_>>();
+
         // figure out all the banks/oracles that need to be passed for the health check
         let mut banks = vec![];
         let mut oracles = vec![];
         let mut fallbacks = vec![];
         for position in account.active_token_positions() {
+            if partial
+                && !partial_health_check_needs_bank(position, &kept_tokens, &perp_settle_tokens)
+            {
+                continue;
+            }
             let token = self.token(position.token_index);
             banks.push((
                 token.first_bank(),
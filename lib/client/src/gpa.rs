@@ -2,7 +2,10 @@ use anchor_lang::{AccountDeserialize, Discriminator};
 use futures::{stream, StreamExt};
 use mango_v4::state::{Bank, MangoAccount, MangoAccountValue, MintInfo, PerpMarket, Serum3Market};
 
+use std::time::Duration;
+
 use solana_account_decoder::UiAccountEncoding;
+use solana_client::client_error::{ClientError, ClientErrorKind};
 use solana_client::nonblocking::rpc_client::RpcClient as RpcClientAsync;
 use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
 use solana_client::rpc_filter::{Memcmp, RpcFilterType};
@@ -153,6 +156,9 @@ pub async fn fetch_multiple_accounts(
 /// Fetch multiple account using one request per chunk of `max_chunk_size` accounts
 /// Can execute in parallel up to `parallel_rpc_requests`
 ///
+/// Chunks that fail with a transient rpc error are retried. The result is in the
+/// order of `keys`.
+///
 /// WARNING: some accounts requested may be missing from the result
 pub async fn fetch_multiple_accounts_in_chunks(
     rpc: &RpcClientAsync,
@@ -165,33 +171,201 @@ pub async fn fetch_multiple_accounts_in_chunks(
         ..RpcAccountInfoConfig::default()
     };
 
-    let raw_results = stream::iter(keys)
-        .chunks(max_chunk_size)
-        .map(|keys| {
+    let mut raw_results = stream::iter(keys.chunks(max_chunk_size).enumerate())
+        .map(|(chunk_index, keys)| {
             let account_info_config = config.clone();
             async move {
-                let keys = keys.iter().map(|x| **x).collect::<Vec<Pubkey>>();
-                let req_res = rpc
-                    .get_multiple_accounts_with_config(&keys, account_info_config)
-                    .await;
-
-                match req_res {
-                    Ok(v) => Ok(keys.into_iter().zip(v.value).collect::<Vec<_>>()),
-                    Err(e) => Err(e),
+                let mut attempt = 1;
+                loop {
+                    let req_res = rpc
+                        .get_multiple_accounts_with_config(keys, account_info_config.clone())
+                        .await;
+                    match req_res {
+                        Ok(v) => {
+                            return Ok((
+                                chunk_index,
+                                keys.iter().copied().zip(v.value).collect::<Vec<_>>(),
+                            ))
+                        }
+                        Err(e)
+                            if attempt < CHUNK_FETCH_MAX_ATTEMPTS && is_transient_rpc_error(&e) =>
+                        {
+                            tracing::warn!(
+                                chunk_index,
+                                attempt,
+                                "fetching accounts failed: {e}, retrying"
+                            );
+                            tokio::time::sleep(CHUNK_FETCH_RETRY_DELAY * attempt).await;
+                            attempt += 1;
+                        }
+                        Err(e) => return Err(e),
+                    }
                 }
             }
         })
-        .buffer_unordered(parallel_rpc_requests)
+        .buffer_unordered(parallel_rpc_requests.max(1))
         .collect::<Vec<_>>()
-        .await;
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?;
+    raw_results.sort_by_key(|(chunk_index, _)| *chunk_index);
 
     let result = raw_results
         .into_iter()
-        .collect::<Result<Vec<_>, _>>()?
-        .into_iter()
-        .flatten()
+        .flat_map(|(_, accounts)| accounts)
         .filter_map(|(pubkey, account_opt)| account_opt.map(|acc| (pubkey, acc)))
         .collect::<Vec<_>>();
 
     Ok(result)
 }
+
+const CHUNK_FETCH_MAX_ATTEMPTS: u32 = 3;
+const CHUNK_FETCH_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// Whether an rpc request failed in a way that may go away when retrying
+fn is_transient_rpc_error(err: &ClientError) -> bool {
+    match err.kind() {
+        ClientErrorKind::Io(_) => true,
+        ClientErrorKind::Reqwest(err) => {
+            err.is_timeout()
+                || err.is_connect()
+                || matches!(err.status(), Some(status) if status.is_server_error() || status.as_u16() == 429)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Reads one http request and returns its body
+    async fn read_request_body(stream: &mut tokio::net::TcpStream) -> String {
+        let mut data = vec![];
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            data.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&data).to_string();
+            if let Some(header_end) = text.find("\r\n\r\n") {
+                let content_length = text[..header_end]
+                    .lines()
+                    .find_map(|l| {
+                        let (name, value) = l.split_once(':')?;
+                        name.eq_ignore_ascii_case("content-length")
+                            .then(|| value.trim().parse::<usize>().unwrap())
+                    })
+                    .unwrap_or(0);
+                if data.len() >= header_end + 4 + content_length {
+                    return text[header_end + 4..].to_string();
+                }
+            }
+            if n == 0 {
+                panic!("connection closed before the request was complete");
+            }
+        }
+    }
+
+    /// A json rpc server that answers getMultipleAccounts with an account owned by
+    /// the requested address, for every address except `missing`.
+    ///
+    /// The first request for each chunk fails with a 503. Returns the url and the
+    /// number of getMultipleAccounts requests served so far.
+    async fn mock_rpc(missing: Pubkey) -> (String, Arc<Mutex<usize>>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(0));
+        let seen_chunks = Arc::new(Mutex::new(HashSet::<Vec<String>>::new()));
+        let requests_count = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let requests = requests.clone();
+                let seen_chunks = seen_chunks.clone();
+                tokio::spawn(async move {
+                    let body = read_request_body(&mut stream).await;
+                    let request: serde_json::Value = serde_json::from_str(&body).unwrap();
+                    let id = request["id"].clone();
+                    let (status, result) = match request["method"].as_str().unwrap() {
+                        "getVersion" => (
+                            "200 OK",
+                            serde_json::json!({"solana-core": "1.16.7", "feature-set": 0}),
+                        ),
+                        "getMultipleAccounts" => {
+                            *requests.lock().unwrap() += 1;
+                            let keys = request["params"][0]
+                                .as_array()
+                                .unwrap()
+                                .iter()
+                                .map(|k| k.as_str().unwrap().to_string())
+                                .collect::<Vec<_>>();
+                            if seen_chunks.lock().unwrap().insert(keys.clone()) {
+                                ("503 Service Unavailable", serde_json::Value::Null)
+                            } else {
+                                let accounts = keys
+                                    .iter()
+                                    .map(|k| {
+                                        if *k == missing.to_string() {
+                                            return serde_json::Value::Null;
+                                        }
+                                        serde_json::json!({
+                                            "data": ["", "base64"],
+                                            "executable": false,
+                                            "lamports": 1,
+                                            "owner": k,
+                                            "rentEpoch": 0,
+                                        })
+                                    })
+                                    .collect::<Vec<_>>();
+                                (
+                                    "200 OK",
+                                    serde_json::json!({"context": {"slot": 1}, "value": accounts}),
+                                )
+                            }
+                        }
+                        method => panic!("unexpected rpc method {method}"),
+                    };
+                    let body = serde_json::json!({"jsonrpc": "2.0", "result": result, "id": id})
+                        .to_string();
+                    let response = format!(
+                        "HTTP/1.1 {status}\r\nconnection: close\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{body}",
+                        body.len()
+                    );
+                    stream.write_all(response.as_bytes()).await.unwrap();
+                });
+            }
+        });
+        (url, requests_count)
+    }
+
+    #[tokio::test]
+    async fn test_fetch_multiple_accounts_in_chunks() {
+        let keys = (0..7).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+        let (url, requests) = mock_rpc(keys[3]).await;
+        let rpc = RpcClientAsync::new(url);
+
+        let accounts = fetch_multiple_accounts_in_chunks(&rpc, &keys, 2, 4)
+            .await
+            .unwrap();
+
+        // every chunk failed once and was retried
+        assert_eq!(*requests.lock().unwrap(), 8);
+
+        // in input order, without the missing account
+        let expected = keys
+            .iter()
+            .filter(|k| **k != keys[3])
+            .copied()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            accounts.iter().map(|(k, _)| *k).collect::<Vec<_>>(),
+            expected
+        );
+        for (key, account) in &accounts {
+            assert_eq!(account.owner, *key);
+        }
+    }
+}
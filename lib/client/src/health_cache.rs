@@ -1,19 +1,53 @@
 use crate::{AccountFetcher, FallbackOracleConfig, MangoGroupContext};
 use anyhow::Context;
+use fixed::types::I80F48;
 use futures::{stream, StreamExt, TryStreamExt};
 use mango_v4::accounts_zerocopy::KeyedAccountSharedData;
-use mango_v4::health::{FixedOrderAccountRetriever, HealthCache};
+use mango_v4::health::{FixedOrderAccountRetriever, HealthCache, HealthType};
 use mango_v4::state::{pyth_mainnet_sol_oracle, pyth_mainnet_usdc_oracle, MangoAccountValue};
+use solana_sdk::instruction::AccountMeta;
 
 use std::collections::HashMap;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+/// Health ratios of an account, see HealthCache::health_ratio()
+#[derive(Clone, Debug)]
+pub struct HealthRatios {
+    pub maint: I80F48,
+    pub init: I80F48,
+    /// The banks, oracles and other accounts the health was computed from, in the order
+    /// health checks expect them
+    pub health_accounts: Vec<AccountMeta>,
+}
+
+impl HealthRatios {
+    pub fn new(health_cache: &HealthCache, health_accounts: Vec<AccountMeta>) -> Self {
+        Self {
+            maint: health_cache.health_ratio(HealthType::Maint),
+            init: health_cache.health_ratio(HealthType::Init),
+            health_accounts,
+        }
+    }
+}
+
 pub async fn new(
     context: &MangoGroupContext,
     fallback_config: &FallbackOracleConfig,
     account_fetcher: &dyn AccountFetcher,
     account: &MangoAccountValue,
 ) -> anyhow::Result<HealthCache> {
+    let (health_cache, _) =
+        new_with_accounts(context, fallback_config, account_fetcher, account).await?;
+    Ok(health_cache)
+}
+
+/// Like new(), but also returns the health accounts that were used
+pub async fn new_with_accounts(
+    context: &MangoGroupContext,
+    fallback_config: &FallbackOracleConfig,
+    account_fetcher: &dyn AccountFetcher,
+    account: &MangoAccountValue,
+) -> anyhow::Result<(HealthCache, Vec<AccountMeta>)> {
     let active_token_len = account.active_token_positions().count();
     let active_perp_len = account.active_perp_positions().count();
 
@@ -53,8 +87,9 @@ pub async fn new(
             .position(|m| m.pubkey == pyth_mainnet_sol_oracle::ID),
     };
     let now_ts = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
-    mango_v4::health::new_health_cache(&account.borrow(), &retriever, now_ts)
-        .context("make health cache")
+    let health_cache = mango_v4::health::new_health_cache(&account.borrow(), &retriever, now_ts)
+        .context("make health cache")?;
+    Ok((health_cache, metas))
 }
 
 pub fn new_sync(
@@ -97,3 +132,95 @@ pub fn new_sync(
     mango_v4::health::new_health_cache(&account.borrow(), &retriever, now_ts)
         .context("make health cache")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::__private::bytemuck;
+    use anchor_lang::{AnchorSerialize, Discriminator};
+    use mango_v4::state::{Bank, MangoAccount, StubOracle, TokenPosition};
+    use solana_sdk::account::AccountSharedData;
+    use solana_sdk::pubkey::Pubkey;
+
+    fn zero_copy_account<T: bytemuck::Pod + Discriminator>(data: &T) -> AccountSharedData {
+        let mut bytes = T::discriminator().to_vec();
+        bytes.extend_from_slice(bytemuck::bytes_of(data));
+        let mut account = AccountSharedData::new(1, bytes.len(), &mango_v4::id());
+        account.set_data_from_slice(&bytes);
+        account
+    }
+
+    #[test]
+    fn test_health_ratios() {
+        let group = Pubkey::new_unique();
+        let mut metas = vec![];
+        let mut banks = vec![];
+        let mut oracles = vec![];
+        // token 0 has weights 0.8/1.2 (init) and 0.9/1.1 (maint), token 1 0.6/1.4 and 0.8/1.2
+        for (token_index, init, maint) in [(0, 0.2, 0.1), (1, 0.4, 0.2)] {
+            let oracle_key = Pubkey::new_unique();
+            let mut oracle: StubOracle = bytemuck::Zeroable::zeroed();
+            oracle.price = I80F48::ONE;
+            oracles.push(KeyedAccountSharedData::new(
+                oracle_key,
+                zero_copy_account(&oracle),
+            ));
+
+            let bank_key = Pubkey::new_unique();
+            let mut bank: Bank = bytemuck::Zeroable::zeroed();
+            bank.group = group;
+            bank.token_index = token_index;
+            bank.oracle = oracle_key;
+            bank.deposit_index = I80F48::ONE;
+            bank.borrow_index = I80F48::ONE;
+            bank.init_asset_weight = I80F48::from_num(1.0 - init);
+            bank.init_liab_weight = I80F48::from_num(1.0 + init);
+            bank.maint_asset_weight = I80F48::from_num(1.0 - maint);
+            bank.maint_liab_weight = I80F48::from_num(1.0 + maint);
+            bank.stable_price_model.reset_to_price(1.0, 0);
+            bank.deposit_weight_scale_start_quote = f64::MAX;
+            bank.borrow_weight_scale_start_quote = f64::MAX;
+            banks.push(KeyedAccountSharedData::new(
+                bank_key,
+                zero_copy_account(&bank),
+            ));
+            metas.push(AccountMeta::new_readonly(bank_key, false));
+        }
+        metas.extend(
+            oracles
+                .iter()
+                .map(|o| AccountMeta::new_readonly(o.key, false)),
+        );
+
+        // funded with 100 of token 0, and borrowed 50 of token 1
+        let mut account = MangoAccount::default_for_tests();
+        account.group = group;
+        account.tokens.resize(2, TokenPosition::default());
+        let bytes = AnchorSerialize::try_to_vec(&account).unwrap();
+        let mut account = MangoAccountValue::from_bytes(&bytes).unwrap();
+        account.ensure_token_position(0).unwrap().0.indexed_position = I80F48::from(100);
+        account.ensure_token_position(1).unwrap().0.indexed_position = I80F48::from(-50);
+
+        let retriever = FixedOrderAccountRetriever {
+            ais: banks.into_iter().chain(oracles).collect(),
+            n_banks: 2,
+            n_perps: 0,
+            begin_perp: 4,
+            begin_serum3: 4,
+            staleness_slot: None,
+            begin_fallback_oracles: 4,
+            usdc_oracle_index: None,
+            sol_oracle_index: None,
+        };
+        let health_cache =
+            mango_v4::health::new_health_cache(&account.borrow(), &retriever, 0).unwrap();
+        let ratios = HealthRatios::new(&health_cache, metas.clone());
+
+        // init: assets 80, liabs 70; maint: assets 90, liabs 60
+        let ratio = |assets: f64, liabs: f64| 100.0 * (assets - liabs) / liabs;
+        assert!((ratios.init.to_num::<f64>() - ratio(80.0, 70.0)).abs() < 1e-6);
+        assert!((ratios.maint.to_num::<f64>() - ratio(90.0, 60.0)).abs() < 1e-6);
+        assert!(ratios.maint > ratios.init);
+        assert_eq!(ratios.health_accounts, metas);
+    }
+}
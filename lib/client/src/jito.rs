@@ -0,0 +1,166 @@
+//! Sending transactions as bundles to a Jito block engine
+
+use std::time::Duration;
+
+use anyhow::Context;
+use serde::Deserialize;
+use solana_sdk::transaction::VersionedTransaction;
+
+use crate::util;
+
+/// Id that the block engine returns for an accepted bundle
+pub type BundleId = String;
+
+#[derive(Deserialize)]
+struct SendBundleResponse {
+    result: Option<BundleId>,
+    error: Option<serde_json::Value>,
+}
+
+/// JSON-RPC body of a sendBundle request, transactions are base64 encoded
+fn send_bundle_request(transactions: &[VersionedTransaction]) -> anyhow::Result<serde_json::Value> {
+    let encoded = transactions
+        .iter()
+        .map(|tx| Ok(base64::encode(bincode::serialize(tx)?)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "sendBundle",
+        "params": [encoded, { "encoding": "base64" }],
+    }))
+}
+
+/// Submits `transactions` as one bundle to the block engine at `block_engine_url`.
+///
+/// The bundle executes atomically and in order; one of the transactions should pay a
+/// tip to a Jito tip account, or the bundle won't be considered.
+pub async fn send_bundle(
+    http_client: &reqwest::Client,
+    block_engine_url: &str,
+    transactions: &[VersionedTransaction],
+    timeout: Duration,
+) -> anyhow::Result<BundleId> {
+    let response = http_client
+        .post(format!("{block_engine_url}/bundles"))
+        .json(&send_bundle_request(transactions)?)
+        .timeout(timeout)
+        .send()
+        .await
+        .context("sendBundle request to block engine")?;
+    let response: SendBundleResponse = util::http_error_handling(response)
+        .await
+        .with_context(|| format!("error sending bundle to {block_engine_url}"))?;
+    if let Some(error) = response.error {
+        anyhow::bail!("block engine rejected bundle: {error}");
+    }
+    response
+        .result
+        .ok_or_else(|| anyhow::anyhow!("block engine response has no bundle id"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::{Keypair, Signer};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn transfer_tx() -> VersionedTransaction {
+        let payer = Keypair::new();
+        let ix = solana_sdk::system_instruction::transfer(
+            &payer.pubkey(),
+            &solana_sdk::pubkey::Pubkey::new_unique(),
+            1000,
+        );
+        let message = solana_sdk::message::v0::Message::try_compile(
+            &payer.pubkey(),
+            &[ix],
+            &[],
+            solana_sdk::hash::Hash::default(),
+        )
+        .unwrap();
+        VersionedTransaction::try_new(
+            solana_sdk::message::VersionedMessage::V0(message),
+            &[&payer],
+        )
+        .unwrap()
+    }
+
+    /// Serves a single http request with `body`, returns the url and the received request
+    async fn mock_block_engine(body: &'static str) -> (String, tokio::task::JoinHandle<String>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/api/v1", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![];
+            let mut buf = [0u8; 4096];
+            // read until the full json body arrived
+            loop {
+                let n = stream.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request);
+                if let Some((head, content)) = text.split_once("\r\n\r\n") {
+                    let length = head
+                        .lines()
+                        .find_map(|l| {
+                            l.to_lowercase()
+                                .strip_prefix("content-length: ")
+                                .map(|v| v.parse::<usize>().unwrap())
+                        })
+                        .unwrap_or(0);
+                    if content.len() >= length {
+                        break;
+                    }
+                }
+            }
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        (url, handle)
+    }
+
+    #[tokio::test]
+    async fn test_send_bundle() {
+        let tx = transfer_tx();
+        let (url, request) =
+            mock_block_engine(r#"{"jsonrpc":"2.0","result":"bundle-id-1","id":1}"#).await;
+        let bundle_id = send_bundle(
+            &reqwest::Client::new(),
+            &url,
+            &[tx.clone()],
+            Duration::from_secs(5),
+        )
+        .await
+        .unwrap();
+        assert_eq!(bundle_id, "bundle-id-1");
+
+        let request = request.await.unwrap();
+        assert!(request.starts_with("POST /api/v1/bundles "));
+        let body: serde_json::Value =
+            serde_json::from_str(request.split_once("\r\n\r\n").unwrap().1).unwrap();
+        assert_eq!(body["method"], "sendBundle");
+        assert_eq!(body["params"][1]["encoding"], "base64");
+        let sent: VersionedTransaction =
+            bincode::deserialize(&base64::decode(body["params"][0][0].as_str().unwrap()).unwrap())
+                .unwrap();
+        assert_eq!(sent, tx);
+
+        let (url, _) = mock_block_engine(
+            r#"{"jsonrpc":"2.0","error":{"code":-32602,"message":"bundle contains an expired blockhash"},"id":1}"#,
+        )
+        .await;
+        assert!(
+            send_bundle(&reqwest::Client::new(), &url, &[tx], Duration::from_secs(5))
+                .await
+                .is_err()
+        );
+    }
+}
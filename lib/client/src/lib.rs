@@ -13,6 +13,7 @@ mod context;
 pub mod error_tracking;
 pub mod gpa;
 pub mod health_cache;
+pub mod jito;
 pub mod perp_pnl;
 pub mod priority_fees;
 pub mod priority_fees_cli;
@@ -4,9 +4,7 @@ use std::time::Duration;
 use anchor_lang::prelude::Pubkey;
 use serde::{Deserialize, Serialize};
 
-use anchor_lang::Id;
-use anchor_spl::token::Token;
-
+use crate::swap::{flash_loan_token_program, FlashLoanSwap};
 use crate::MangoClient;
 use crate::{util, TransactionBuilder};
 
@@ -236,14 +234,11 @@ impl<'a> JupiterV6<'a> {
         let owner = self.mango_client.owner();
         let account = &self.mango_client.mango_account().await?;
 
-        let token_ams = [source_token.mint, target_token.mint]
+        let token_ams = [source_token, target_token]
             .into_iter()
-            .map(|mint| {
-                util::to_writable_account_meta(
-                    anchor_spl::associated_token::get_associated_token_address(&owner, &mint),
-                )
-            })
+            .map(|token| util::to_writable_account_meta(token.associated_token_address(&owner)))
             .collect::<Vec<_>>();
+        let token_program = flash_loan_token_program(source_token, target_token)?;
 
         let source_loan = quote
             .in_amount
@@ -251,7 +246,6 @@ impl<'a> JupiterV6<'a> {
             .map(|v| u64::from_str(v).unwrap())
             .unwrap_or(0);
         let loan_amounts = vec![source_loan, 0u64];
-        let num_loans: u8 = loan_amounts.len().try_into().unwrap();
 
         // This relies on the fact that health account banks will be identical to the first_bank above!
         let (health_ams, _health_cu) = self
@@ -311,55 +305,23 @@ impl<'a> JupiterV6<'a> {
                 &owner,
                 &owner,
                 &source_token.mint,
-                &Token::id(),
+                &source_token.token_program,
             ),
         );
 
-        instructions.push(Instruction {
-            program_id: mango_v4::id(),
-            accounts: {
-                let mut ams = anchor_lang::ToAccountMetas::to_account_metas(
-                    &mango_v4::accounts::FlashLoanBegin {
-                        account: self.mango_client.mango_account_address,
-                        owner,
-                        token_program: Token::id(),
-                        instructions: solana_sdk::sysvar::instructions::id(),
-                    },
-                    None,
-                );
-                ams.extend(bank_ams);
-                ams.extend(vault_ams.clone());
-                ams.extend(token_ams.clone());
-                ams.push(util::to_readonly_account_meta(self.mango_client.group()));
-                ams
-            },
-            data: anchor_lang::InstructionData::data(&mango_v4::instruction::FlashLoanBegin {
-                loan_amounts,
-            }),
-        });
-        instructions.push((&swap.swap_instruction).try_into()?);
-        instructions.push(Instruction {
-            program_id: mango_v4::id(),
-            accounts: {
-                let mut ams = anchor_lang::ToAccountMetas::to_account_metas(
-                    &mango_v4::accounts::FlashLoanEnd {
-                        account: self.mango_client.mango_account_address,
-                        owner,
-                        token_program: Token::id(),
-                    },
-                    None,
-                );
-                ams.extend(health_ams);
-                ams.extend(vault_ams);
-                ams.extend(token_ams);
-                ams.push(util::to_readonly_account_meta(self.mango_client.group()));
-                ams
-            },
-            data: anchor_lang::InstructionData::data(&mango_v4::instruction::FlashLoanEndV2 {
-                num_loans,
-                flash_loan_type: mango_v4::accounts_ix::FlashLoanType::Swap,
-            }),
-        });
+        let flash_loan = FlashLoanSwap {
+            account: self.mango_client.mango_account_address,
+            owner,
+            group: self.mango_client.group(),
+            instructions_sysvar: solana_sdk::sysvar::instructions::id(),
+            banks: bank_ams,
+            vaults: vault_ams,
+            token_accounts: token_ams,
+            token_program,
+            loan_amounts,
+            health_accounts: health_ams,
+        };
+        instructions.extend(flash_loan.wrap(vec![(&swap.swap_instruction).try_into()?]));
         for ix in &swap.cleanup_instructions.unwrap_or_default() {
             instructions.push(ix.try_into()?);
         }
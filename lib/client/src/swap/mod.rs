@@ -3,10 +3,45 @@ pub mod sanctum;
 pub mod sanctum_state;
 
 use anchor_lang::prelude::*;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::signature::Signature;
 use std::str::FromStr;
 
-use crate::{MangoClient, TransactionBuilder};
+use crate::{util, MangoClient, TokenContext, TransactionBuilder};
 use fixed::types::I80F48;
+use mango_v4::state::TokenIndex;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SwapError {
+    #[error("swap instruction {instruction_index} sends output tokens to {destination} instead of the owner {owner}")]
+    OutputRedirected {
+        instruction_index: usize,
+        destination: Pubkey,
+        owner: Pubkey,
+    },
+    #[error("swap instruction {instruction_index} moves tokens to {destination}, which is not one of the owner's swap token accounts")]
+    TokensRedirected {
+        instruction_index: usize,
+        destination: Pubkey,
+    },
+    #[error("swap fee of {fee_pct}% exceeds the maximum of {max_fee_pct}%")]
+    FeeTooHigh { fee_pct: f64, max_fee_pct: f64 },
+    #[error(
+        "swap instruction {instruction_index} invokes program {program_id}, which is not allowed"
+    )]
+    UnexpectedProgram {
+        instruction_index: usize,
+        program_id: Pubkey,
+    },
+    #[error("oracle of token {token_index} was last updated {slot_age} slots ago, more than the maximum of {max_slot_age}")]
+    StaleOracle {
+        token_index: TokenIndex,
+        slot_age: u64,
+        max_slot_age: u64,
+    },
+    #[error("swap transaction is {size} bytes, more than the limit of {limit}")]
+    TransactionTooLarge { size: usize, limit: usize },
+}
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Version {
@@ -57,7 +92,7 @@ impl Quote {
         Ok(Quote {
             input_mint: input_mint,
             output_mint: output_mint,
-            price_impact_pct: query.fee_pct.parse()?,
+            price_impact_pct: query.fee_pct_f64()?,
             in_amount: query
                 .in_amount
                 .as_ref()
@@ -68,6 +103,15 @@ impl Quote {
         })
     }
 
+    /// Name of the swap backend the quote is from
+    pub fn provider(&self) -> &'static str {
+        match &self.raw {
+            RawQuote::Mock => "mock",
+            RawQuote::V6(_) => "jupiter",
+            RawQuote::Sanctum(_) => "sanctum",
+        }
+    }
+
     pub fn first_route_label(&self) -> String {
         let label_maybe = match &self.raw {
             RawQuote::Mock => Some("mock".into()),
@@ -83,6 +127,243 @@ impl Quote {
     }
 }
 
+/// A swap backend, so the backend can be chosen at runtime,
+/// see MangoClient::swap_provider()
+#[async_trait::async_trait]
+pub trait SwapProvider {
+    async fn quote(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        amount: u64,
+        max_slippage_bps: u64,
+    ) -> anyhow::Result<Quote>;
+
+    /// A transaction that swaps through a flash loan of the mango account.
+    ///
+    /// Fails for quotes of other providers.
+    async fn prepare_swap_transaction(
+        &self,
+        quote: &Quote,
+        max_slippage_bps: u64,
+    ) -> anyhow::Result<TransactionBuilder>;
+
+    async fn swap(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        max_slippage_bps: u64,
+        amount: u64,
+    ) -> anyhow::Result<Signature>;
+}
+
+#[async_trait::async_trait]
+impl<'a> SwapProvider for sanctum::Sanctum<'a> {
+    /// Sanctum quotes don't depend on the slippage
+    async fn quote(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        amount: u64,
+        _max_slippage_bps: u64,
+    ) -> anyhow::Result<Quote> {
+        let quote = sanctum::Sanctum::quote(self, input_mint, output_mint, amount).await?;
+        Quote::try_from_sanctum(input_mint, output_mint, quote)
+    }
+
+    async fn prepare_swap_transaction(
+        &self,
+        quote: &Quote,
+        max_slippage_bps: u64,
+    ) -> anyhow::Result<TransactionBuilder> {
+        let RawQuote::Sanctum(raw) = &quote.raw else {
+            anyhow::bail!("can't prepare a sanctum swap for a {} quote", quote.provider());
+        };
+        sanctum::Sanctum::prepare_swap_transaction(
+            self,
+            quote.input_mint,
+            quote.output_mint,
+            max_slippage_bps,
+            raw,
+        )
+        .await
+    }
+
+    async fn swap(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        max_slippage_bps: u64,
+        amount: u64,
+    ) -> anyhow::Result<Signature> {
+        sanctum::Sanctum::swap(self, input_mint, output_mint, max_slippage_bps, amount).await
+    }
+}
+
+#[async_trait::async_trait]
+impl<'a> SwapProvider for jupiter_v6::JupiterV6<'a> {
+    async fn quote(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        amount: u64,
+        max_slippage_bps: u64,
+    ) -> anyhow::Result<Quote> {
+        let quote = jupiter_v6::JupiterV6::quote(
+            self,
+            input_mint,
+            output_mint,
+            amount,
+            max_slippage_bps,
+            false,
+        )
+        .await?;
+        Quote::try_from_v6(quote)
+    }
+
+    /// Jupiter uses the slippage the quote was requested with
+    async fn prepare_swap_transaction(
+        &self,
+        quote: &Quote,
+        _max_slippage_bps: u64,
+    ) -> anyhow::Result<TransactionBuilder> {
+        let RawQuote::V6(raw) = &quote.raw else {
+            anyhow::bail!("can't prepare a jupiter swap for a {} quote", quote.provider());
+        };
+        jupiter_v6::JupiterV6::prepare_swap_transaction(self, raw).await
+    }
+
+    async fn swap(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        max_slippage_bps: u64,
+        amount: u64,
+    ) -> anyhow::Result<Signature> {
+        jupiter_v6::JupiterV6::swap(
+            self,
+            input_mint,
+            output_mint,
+            amount,
+            max_slippage_bps,
+            false,
+        )
+        .await
+    }
+}
+
+/// The mango side of a swap: FlashLoanBegin lends the input token from its bank and
+/// FlashLoanEnd deposits what's left in the token accounts and checks health
+pub(crate) struct FlashLoanSwap {
+    pub account: Pubkey,
+    pub owner: Pubkey,
+    pub group: Pubkey,
+    pub instructions_sysvar: Pubkey,
+    /// Writable banks of the input and output token, in that order
+    pub banks: Vec<AccountMeta>,
+    /// Writable vaults of the banks
+    pub vaults: Vec<AccountMeta>,
+    /// Writable token accounts of the owner for the input and output token
+    pub token_accounts: Vec<AccountMeta>,
+    /// Token program of the banks' vaults, see flash_loan_token_program()
+    pub token_program: Pubkey,
+    pub loan_amounts: Vec<u64>,
+    pub health_accounts: Vec<AccountMeta>,
+}
+
+impl FlashLoanSwap {
+    /// FlashLoanBegin, then `swap_instructions`, then FlashLoanEnd
+    pub(crate) fn wrap(self, swap_instructions: Vec<Instruction>) -> Vec<Instruction> {
+        let num_loans: u8 = self.loan_amounts.len().try_into().unwrap();
+        let mut instructions = vec![flash_loan_begin_instruction(
+            self.account,
+            self.owner,
+            self.token_program,
+            self.instructions_sysvar,
+            self.group,
+            self.banks
+                .into_iter()
+                .chain(self.vaults.clone())
+                .chain(self.token_accounts.clone())
+                .collect(),
+            self.loan_amounts,
+        )];
+        instructions.extend(swap_instructions);
+        instructions.push(Instruction {
+            program_id: mango_v4::id(),
+            accounts: {
+                let mut ams = anchor_lang::ToAccountMetas::to_account_metas(
+                    &mango_v4::accounts::FlashLoanEnd {
+                        account: self.account,
+                        owner: self.owner,
+                        token_program: self.token_program,
+                    },
+                    None,
+                );
+                ams.extend(self.health_accounts);
+                ams.extend(self.vaults);
+                ams.extend(self.token_accounts);
+                ams.push(util::to_readonly_account_meta(self.group));
+                ams
+            },
+            data: anchor_lang::InstructionData::data(&mango_v4::instruction::FlashLoanEndV2 {
+                num_loans,
+                flash_loan_type: mango_v4::accounts_ix::FlashLoanType::Swap,
+            }),
+        });
+        instructions
+    }
+}
+
+/// Token program for a flash loan swap from `source` to `target`.
+///
+/// FlashLoanBegin and End take a single token program, which must be the one of every
+/// loaned bank's vault.
+pub(crate) fn flash_loan_token_program(
+    source: &TokenContext,
+    target: &TokenContext,
+) -> anyhow::Result<Pubkey> {
+    anyhow::ensure!(
+        source.token_program == target.token_program,
+        "can't swap {} to {} in one flash loan, their vaults use different token programs",
+        source.name,
+        target.name
+    );
+    Ok(source.token_program)
+}
+
+/// FlashLoanBegin with `loan_accounts` being the banks, vaults and token accounts
+fn flash_loan_begin_instruction(
+    account: Pubkey,
+    owner: Pubkey,
+    token_program: Pubkey,
+    instructions_sysvar: Pubkey,
+    group: Pubkey,
+    loan_accounts: Vec<AccountMeta>,
+    loan_amounts: Vec<u64>,
+) -> Instruction {
+    Instruction {
+        program_id: mango_v4::id(),
+        accounts: {
+            let mut ams = anchor_lang::ToAccountMetas::to_account_metas(
+                &mango_v4::accounts::FlashLoanBegin {
+                    account,
+                    owner,
+                    token_program,
+                    instructions: instructions_sysvar,
+                },
+                None,
+            );
+            ams.extend(loan_accounts);
+            ams.push(util::to_readonly_account_meta(group));
+            ams
+        },
+        data: anchor_lang::InstructionData::data(&mango_v4::instruction::FlashLoanBegin {
+            loan_amounts,
+        }),
+    }
+}
+
 pub struct Swap<'a> {
     pub mango_client: &'a MangoClient,
 }
@@ -185,3 +466,261 @@ impl<'a> Swap<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_spl::token::Token;
+    use std::sync::{Arc, Mutex};
+
+    /// Logs calls as (name, method) and quotes 1:1
+    struct RecordingProvider {
+        name: &'static str,
+        log: Arc<Mutex<Vec<(&'static str, &'static str)>>>,
+    }
+
+    impl RecordingProvider {
+        fn record(&self, method: &'static str) {
+            self.log.lock().unwrap().push((self.name, method));
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl SwapProvider for RecordingProvider {
+        async fn quote(
+            &self,
+            input_mint: Pubkey,
+            output_mint: Pubkey,
+            amount: u64,
+            _max_slippage_bps: u64,
+        ) -> anyhow::Result<Quote> {
+            self.record("quote");
+            Ok(Quote {
+                input_mint,
+                output_mint,
+                price_impact_pct: 0.0,
+                in_amount: amount,
+                out_amount: amount,
+                raw: RawQuote::Mock,
+            })
+        }
+
+        async fn prepare_swap_transaction(
+            &self,
+            _quote: &Quote,
+            _max_slippage_bps: u64,
+        ) -> anyhow::Result<TransactionBuilder> {
+            self.record("prepare_swap_transaction");
+            anyhow::bail!("can't build transactions")
+        }
+
+        async fn swap(
+            &self,
+            _input_mint: Pubkey,
+            _output_mint: Pubkey,
+            _max_slippage_bps: u64,
+            _amount: u64,
+        ) -> anyhow::Result<Signature> {
+            self.record("swap");
+            Ok(Signature::default())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_swap_provider_dispatch() {
+        let log = Arc::new(Mutex::new(vec![]));
+        let providers: Vec<Box<dyn SwapProvider>> = ["sanctum", "jupiter"]
+            .into_iter()
+            .map(|name| {
+                Box::new(RecordingProvider {
+                    name,
+                    log: log.clone(),
+                }) as Box<dyn SwapProvider>
+            })
+            .collect();
+        let (input_mint, output_mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        // chosen at runtime, only the selected provider is called
+        let provider = &providers[1];
+        let quote = provider
+            .quote(input_mint, output_mint, 100, 50)
+            .await
+            .unwrap();
+        assert_eq!(quote.input_mint, input_mint);
+        assert_eq!(quote.out_amount, 100);
+        assert!(provider.prepare_swap_transaction(&quote, 50).await.is_err());
+        provider
+            .swap(input_mint, output_mint, 50, 100)
+            .await
+            .unwrap();
+        providers[0]
+            .swap(input_mint, output_mint, 50, 100)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![
+                ("jupiter", "quote"),
+                ("jupiter", "prepare_swap_transaction"),
+                ("jupiter", "swap"),
+                ("sanctum", "swap"),
+            ]
+        );
+    }
+
+    /// A client for tests that never reach the network
+    fn offline_mango_client() -> MangoClient {
+        let client = crate::Client::new(
+            anchor_client::Cluster::Localnet,
+            solana_sdk::commitment_config::CommitmentConfig::processed(),
+            Arc::new(solana_sdk::signature::Keypair::new()),
+            None,
+            Default::default(),
+        );
+        let account_fetcher = Arc::new(crate::RpcAccountFetcher {
+            rpc: client.new_rpc_async(),
+        });
+        let context = crate::MangoGroupContext {
+            group: Pubkey::new_unique(),
+            tokens: Default::default(),
+            token_indexes_by_name: Default::default(),
+            serum3_markets: Default::default(),
+            serum3_market_indexes_by_name: Default::default(),
+            perp_markets: Default::default(),
+            perp_market_indexes_by_name: Default::default(),
+            address_lookup_tables: vec![],
+            compute_estimates: Default::default(),
+        };
+        MangoClient::new_detail(
+            client,
+            Pubkey::new_unique(),
+            Arc::new(solana_sdk::signature::Keypair::new()),
+            context,
+            account_fetcher,
+        )
+        .unwrap()
+    }
+
+    async fn prepare_error(
+        provider: &(dyn SwapProvider + Send + Sync + '_),
+        quote: &Quote,
+    ) -> String {
+        provider
+            .prepare_swap_transaction(quote, 50)
+            .await
+            .err()
+            .unwrap()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_swap_provider_rejects_other_quotes() {
+        let mango_client = offline_mango_client();
+        let (input_mint, output_mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mock_quote = Quote {
+            input_mint,
+            output_mint,
+            price_impact_pct: 0.0,
+            in_amount: 100,
+            out_amount: 100,
+            raw: RawQuote::Mock,
+        };
+        let sanctum_quote = Quote::try_from_sanctum(
+            input_mint,
+            output_mint,
+            sanctum::QuoteResponse {
+                in_amount: Some("100".into()),
+                out_amount: "99".into(),
+                fee_amount: "1".into(),
+                fee_mint: output_mint.to_string(),
+                fee_pct: "0.01".into(),
+                swap_src: "SPool".into(),
+                mode: sanctum::SwapMode::ExactIn,
+            },
+        )
+        .unwrap();
+
+        // the real backends, as picked at runtime, bail before doing any requests
+        let sanctum = mango_client.swap_provider(Version::Sanctum).unwrap();
+        assert_eq!(
+            prepare_error(&*sanctum, &mock_quote).await,
+            "can't prepare a sanctum swap for a mock quote"
+        );
+
+        let jupiter = mango_client.swap_provider(Version::V6).unwrap();
+        assert_eq!(
+            prepare_error(&*jupiter, &mock_quote).await,
+            "can't prepare a jupiter swap for a mock quote"
+        );
+        assert_eq!(
+            prepare_error(&*jupiter, &sanctum_quote).await,
+            "can't prepare a jupiter swap for a sanctum quote"
+        );
+
+        assert!(mango_client.swap_provider(Version::Mock).is_err());
+    }
+
+    #[test]
+    fn test_flash_loan_swap_wrap() {
+        let account = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let group = Pubkey::new_unique();
+        let writable = |n: usize| {
+            (0..n)
+                .map(|_| util::to_writable_account_meta(Pubkey::new_unique()))
+                .collect::<Vec<_>>()
+        };
+        let (banks, vaults, token_accounts, health_accounts) =
+            (writable(2), writable(2), writable(2), writable(4));
+        let swap_ix = Instruction {
+            program_id: Pubkey::new_unique(),
+            accounts: vec![],
+            data: vec![],
+        };
+
+        let instructions = FlashLoanSwap {
+            account,
+            owner,
+            group,
+            instructions_sysvar: solana_sdk::sysvar::instructions::id(),
+            banks: banks.clone(),
+            vaults: vaults.clone(),
+            token_accounts: token_accounts.clone(),
+            token_program: Token::id(),
+            loan_amounts: vec![100, 0],
+            health_accounts: health_accounts.clone(),
+        }
+        .wrap(vec![swap_ix.clone(), swap_ix.clone()]);
+
+        assert_eq!(instructions.len(), 4);
+        assert_eq!(instructions[1], swap_ix);
+        assert_eq!(instructions[2], swap_ix);
+
+        let begin = &instructions[0];
+        assert_eq!(
+            begin.data,
+            anchor_lang::InstructionData::data(&mango_v4::instruction::FlashLoanBegin {
+                loan_amounts: vec![100, 0],
+            })
+        );
+        let loan_accounts = [banks, vaults.clone(), token_accounts.clone()].concat();
+        assert_eq!(begin.accounts[4..10], loan_accounts[..]);
+        assert_eq!(begin.accounts[10].pubkey, group);
+
+        let end = &instructions[3];
+        assert_eq!(end.program_id, mango_v4::id());
+        assert_eq!(
+            end.data,
+            anchor_lang::InstructionData::data(&mango_v4::instruction::FlashLoanEndV2 {
+                num_loans: 2,
+                flash_loan_type: mango_v4::accounts_ix::FlashLoanType::Swap,
+            })
+        );
+        assert_eq!(end.accounts[0].pubkey, account);
+        assert_eq!(end.accounts[1].pubkey, owner);
+        let end_accounts = [health_accounts, vaults, token_accounts].concat();
+        assert_eq!(end.accounts[3..11], end_accounts[..]);
+        assert_eq!(end.accounts[11].pubkey, group);
+    }
+}
@@ -1,21 +1,45 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
 
 use anchor_lang::{system_program, Id};
 use anchor_spl::token::Token;
 use anyhow::Context;
 use bincode::Options;
+use fixed::types::I80F48;
+use itertools::Itertools;
 use mango_v4::accounts_zerocopy::AccountReader;
+use mango_v4::error::MangoError;
+use mango_v4::health::{HealthType, Prices, TokenInfo};
+use mango_v4::state::{Bank, MangoAccountValue, PerpMarketIndex, TokenIndex};
 use serde::{Deserialize, Serialize};
+use solana_account_decoder::UiAccountEncoding;
 use solana_address_lookup_table_program::state::AddressLookupTable;
 use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::{
+    RpcAccountInfoConfig, RpcProgramAccountsConfig, RpcSimulateTransactionAccountsConfig,
+    RpcSimulateTransactionConfig,
+};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_client::rpc_response::RpcConfirmedTransactionStatusWithSignature;
 use solana_sdk::account::Account;
-use solana_sdk::{instruction::Instruction, pubkey::Pubkey, signature::Signature};
-use std::time::Duration;
+use solana_sdk::address_lookup_table_account::AddressLookupTableAccount;
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use solana_sdk::transaction::TransactionError;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
 use crate::gpa::fetch_multiple_accounts_in_chunks;
-use crate::swap::sanctum_state;
-use crate::{util, MangoClient, TransactionBuilder};
+use crate::jito::{self, BundleId};
+use crate::swap::{flash_loan_token_program, sanctum_state, FlashLoanSwap, SwapError};
+use crate::{
+    account_fetcher_fetch_anchor_account, account_fetcher_fetch_mango_account, util, MangoClient,
+    TokenContext, TransactionBuilder, TransactionSize,
+};
 use borsh::BorshDeserialize;
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -27,6 +51,199 @@ pub struct QuoteResponse {
     pub fee_mint: String,
     pub fee_pct: String,
     pub swap_src: String,
+    /// Mode the quote was requested in, not part of sanctum's response
+    #[serde(skip)]
+    pub mode: SwapMode,
+}
+
+impl QuoteResponse {
+    /// The fee in native units of fee_mint
+    pub fn fee_amount_u64(&self) -> anyhow::Result<u64> {
+        self.fee_amount
+            .parse::<u64>()
+            .with_context(|| format!("parsing fee_amount {:?}", self.fee_amount))
+    }
+
+    /// The fee in percent of the swap
+    pub fn fee_pct_f64(&self) -> anyhow::Result<f64> {
+        self.fee_pct
+            .parse::<f64>()
+            .with_context(|| format!("parsing fee_pct {:?}", self.fee_pct))
+    }
+
+    pub fn fee_mint_pubkey(&self) -> anyhow::Result<Pubkey> {
+        Pubkey::from_str(&self.fee_mint)
+            .with_context(|| format!("parsing fee_mint {:?}", self.fee_mint))
+    }
+
+    /// The fee as a fraction of the swap, fee_pct is in percent
+    pub fn fee_fraction(&self) -> anyhow::Result<f64> {
+        Ok(self.fee_pct_f64()? / 100.0)
+    }
+
+    /// The quote's in_amount and out_amount in native units.
+    ///
+    /// Sanctum swaps need the in amount, so a quote without one is an error.
+    pub fn in_and_out_amounts(&self) -> anyhow::Result<(u64, u64)> {
+        let in_amount = self
+            .in_amount
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("quote has no in amount"))?
+            .parse::<u64>()
+            .context("parsing in_amount")?;
+        let out_amount = self
+            .out_amount
+            .parse::<u64>()
+            .context("parsing out_amount")?;
+        Ok((in_amount, out_amount))
+    }
+
+    /// Output tokens per input token, in ui amounts
+    pub fn effective_rate(&self, in_decimals: u8, out_decimals: u8) -> anyhow::Result<f64> {
+        let (in_amount, out_amount) = self.in_and_out_amounts()?;
+        anyhow::ensure!(in_amount > 0, "quote has a zero in amount");
+        let in_ui = in_amount as f64 / 10f64.powi(in_decimals as i32);
+        let out_ui = out_amount as f64 / 10f64.powi(out_decimals as i32);
+        Ok(out_ui / in_ui)
+    }
+
+    /// How this quote differs from `other`, a quote for the same pair.
+    ///
+    /// All deltas are this quote's value minus the other's, so positive out amount
+    /// and rate deltas mean this quote is better.
+    pub fn compare(
+        &self,
+        other: &QuoteResponse,
+        in_decimals: u8,
+        out_decimals: u8,
+    ) -> anyhow::Result<QuoteComparison> {
+        let out_ui = |quote: &QuoteResponse| -> anyhow::Result<f64> {
+            let out_amount = quote
+                .out_amount
+                .parse::<u64>()
+                .context("parsing out_amount")?;
+            Ok(out_amount as f64 / 10f64.powi(out_decimals as i32))
+        };
+        Ok(QuoteComparison {
+            out_amount_delta: out_ui(self)? - out_ui(other)?,
+            fee_pct_delta: self.fee_pct_f64()? - other.fee_pct_f64()?,
+            effective_rate_delta: self.effective_rate(in_decimals, out_decimals)?
+                - other.effective_rate(in_decimals, out_decimals)?,
+        })
+    }
+}
+
+/// Differences between two quotes, see QuoteResponse::compare()
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QuoteComparison {
+    /// Difference of the out amounts, in ui output tokens
+    pub out_amount_delta: f64,
+    /// Difference of the fees, in percent of the swap
+    pub fee_pct_delta: f64,
+    /// Difference of the output per input rates, in ui amounts
+    pub effective_rate_delta: f64,
+}
+
+/// Whether a quote's amount is the exact input or the exact output of the swap
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SwapMode {
+    #[default]
+    ExactIn,
+    ExactOut,
+}
+
+impl SwapMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SwapMode::ExactIn => "ExactIn",
+            SwapMode::ExactOut => "ExactOut",
+        }
+    }
+}
+
+/// Result of a confirmed Sanctum::swap_with_outcome()
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SwapOutcome {
+    pub signature: Signature,
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    /// The quote's out_amount, in native output tokens
+    pub quoted_out: u64,
+    /// The least output the swap was allowed to produce after slippage
+    pub min_out: u64,
+    /// Native input tokens charged at FlashLoanEnd, see Sanctum::flash_loan_repayment()
+    pub flash_loan_repayment: u64,
+    /// Slot the swap transaction confirmed in
+    pub slot: u64,
+}
+
+impl SwapOutcome {
+    fn new(
+        signature: Signature,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        quote: &QuoteResponse,
+        max_slippage_bps: u64,
+        flash_loan_repayment: u64,
+        slot: u64,
+    ) -> anyhow::Result<Self> {
+        let (in_amount, quoted_out) = quote.in_and_out_amounts()?;
+        let amounts = SwapAmounts::new(quote.mode, in_amount, quoted_out, max_slippage_bps);
+        Ok(Self {
+            signature,
+            input_mint,
+            output_mint,
+            quoted_out,
+            min_out: amounts.min_out,
+            flash_loan_repayment,
+            slot,
+        })
+    }
+}
+
+/// A prepared swap without keypairs, for callers that have it signed elsewhere,
+/// see Sanctum::prepare_unsigned_swap()
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnsignedSwap {
+    pub instructions: Vec<Instruction>,
+    pub address_lookup_tables: Vec<AddressLookupTableAccount>,
+    pub payer: Pubkey,
+    /// Everyone who has to sign, the payer first
+    pub signers: Vec<Pubkey>,
+}
+
+impl UnsignedSwap {
+    /// Drops the keypairs of `tx_builder`, keeping only who has to sign
+    fn new(tx_builder: TransactionBuilder) -> Self {
+        let signers = std::iter::once(tx_builder.payer)
+            .chain(
+                tx_builder
+                    .instructions
+                    .iter()
+                    .flat_map(|ix| ix.accounts.iter())
+                    .filter(|am| am.is_signer)
+                    .map(|am| am.pubkey),
+            )
+            .unique()
+            .collect();
+        Self {
+            instructions: tx_builder.instructions,
+            address_lookup_tables: tx_builder.address_lookup_tables,
+            payer: tx_builder.payer,
+            signers,
+        }
+    }
+}
+
+/// How much worse than the quote a swap may execute
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlippageLimit {
+    /// Relative to the quote, in basis points
+    Bps(u64),
+    /// Absolute least output in native units, only for ExactIn quotes.
+    ///
+    /// Must not exceed the quote's out_amount.
+    MinOut(u64),
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -47,23 +264,502 @@ pub struct SanctumSwapResponse {
     pub tx: String,
 }
 
+/// How Sanctum retries http requests that failed transiently
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first
+    pub max_attempts: u32,
+    /// Delay before the first retry
+    pub base_delay: Duration,
+    /// Each further retry waits this many times longer than the previous one
+    pub backoff_factor: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(200),
+            backoff_factor: 2.0,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before the `retry`-th retry, starting at 1
+    fn delay(&self, retry: u32) -> Duration {
+        self.base_delay
+            .mul_f64(self.backoff_factor.powi(retry.saturating_sub(1) as i32))
+    }
+}
+
+/// Outcome of simulating a swap transaction, see Sanctum::simulate_swap()
+#[derive(Clone, Debug)]
+pub struct SwapSimulation {
+    pub error: Option<TransactionError>,
+    /// Whether FlashLoanEnd's health check passed. None if the transaction failed for
+    /// another reason, so the health check result is unknown.
+    pub health_check_passed: Option<bool>,
+    pub logs: Vec<String>,
+    pub units_consumed: Option<u64>,
+    /// Native input and output token positions of the account after the swap, empty
+    /// if the simulation failed
+    pub post_balances: Vec<(TokenIndex, I80F48)>,
+}
+
+impl SwapSimulation {
+    pub fn diagnostics(&self) -> SimulationDiagnostics {
+        SimulationDiagnostics::from_logs(&self.logs)
+    }
+}
+
+/// Errors and compute use extracted from the logs of a simulation
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SimulationDiagnostics {
+    /// The mango error the transaction failed with
+    pub mango_error: Option<MangoError>,
+    /// Failures of other programs, like sanctum's router or a stake pool, with the
+    /// reason from their "failed:" log line
+    pub program_errors: Vec<(Pubkey, String)>,
+    /// Log lines other than program invocations, results and compute reports
+    pub other_logs: Vec<String>,
+    /// Compute units consumed by the top level instructions
+    pub consumed_cu: u64,
+}
+
+impl SimulationDiagnostics {
+    pub fn from_logs(logs: &[String]) -> Self {
+        let mut diagnostics = Self::default();
+        // programs of the current invocation and its callers
+        let mut invocations: Vec<Pubkey> = vec![];
+        for line in logs {
+            let Some(rest) = line.strip_prefix("Program ") else {
+                diagnostics.other_logs.push(line.clone());
+                continue;
+            };
+            if let Some(code) = anchor_error_number(rest) {
+                if invocations.last() == Some(&mango_v4::id()) {
+                    diagnostics.mango_error = diagnostics
+                        .mango_error
+                        .or_else(|| MangoError::from_error_code(code));
+                }
+                diagnostics.other_logs.push(line.clone());
+                continue;
+            }
+
+            let (program, event) = rest.split_once(' ').unwrap_or((rest, ""));
+            let Ok(program_id) = Pubkey::from_str(program) else {
+                // "Program log:", "Program data:", "Program return:" and the like
+                diagnostics.other_logs.push(line.clone());
+                continue;
+            };
+            if event.starts_with("invoke [") {
+                invocations.push(program_id);
+            } else if event == "success" {
+                invocations.pop();
+            } else if let Some(reason) = event.strip_prefix("failed: ") {
+                invocations.pop();
+                let code = reason
+                    .strip_prefix("custom program error: 0x")
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok());
+                match code {
+                    Some(code) if program_id == mango_v4::id() => {
+                        diagnostics.mango_error = diagnostics
+                            .mango_error
+                            .or_else(|| MangoError::from_error_code(code));
+                    }
+                    _ => diagnostics
+                        .program_errors
+                        .push((program_id, reason.to_string())),
+                }
+            } else if let Some(consumed) = event.strip_prefix("consumed ") {
+                if invocations.len() == 1 {
+                    diagnostics.consumed_cu += consumed
+                        .split_once(' ')
+                        .and_then(|(cu, _)| cu.parse::<u64>().ok())
+                        .unwrap_or(0);
+                }
+            } else {
+                diagnostics.other_logs.push(line.clone());
+            }
+        }
+        diagnostics
+    }
+}
+
+/// The error number of an anchor error log line, without the "Program " prefix
+fn anchor_error_number(line: &str) -> Option<u32> {
+    let rest = line.strip_prefix("log: AnchorError ")?;
+    let (_, number) = rest.split_once("Error Number: ")?;
+    number.split_once('.')?.0.parse().ok()
+}
+
+/// What a swap would change about the account's positions, see Sanctum::pre_swap_info()
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PreSwapInfo {
+    /// The account has no position in the input token yet, the flash loan opens one
+    pub activates_input_position: bool,
+    /// The account has no position in the output token yet
+    pub activates_output_position: bool,
+}
+
+/// Instruction structure of a prepared swap transaction, see describe_prepared_swap()
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SwapLayout {
+    /// Indexes of the instructions before FlashLoanBegin and after FlashLoanEnd
+    pub setup: Vec<usize>,
+    pub flash_loan_begin: usize,
+    /// Indexes of the swap instructions wrapped by the flash loan
+    pub action: Vec<usize>,
+    pub flash_loan_end: usize,
+    pub address_lookup_table_count: usize,
+    /// Every account the transaction references, payer first. Flags are merged over
+    /// all instructions: writable or signer if any instruction requires it.
+    pub accounts: Vec<AccountMeta>,
+}
+
+/// Swap sources sanctum quotes can be restricted to, see Sanctum::quote_from_source()
+pub const SWAP_SOURCES: &[&str] = &["Stakedex", "Jup", "SPool"];
+
+/// Default for Sanctum::max_address_lookup_tables
+///
+/// Every referenced table costs at least 34 bytes of transaction size.
+pub const DEFAULT_MAX_ADDRESS_LOOKUP_TABLES: usize = 8;
+
+/// About a day of slots
+pub const DEFAULT_IDEMPOTENCY_WINDOW_SLOTS: u64 = 216_000;
+
+/// Which of the output token's banks receives the swap output
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputBankSelection {
+    #[default]
+    FirstBank,
+    /// The bank that has used the smallest fraction of its deposit limit,
+    /// banks without limit are preferred
+    LeastDeposits,
+}
+
+/// Settle perp pnl with a counterparty right after the swap's FlashLoanEnd
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SettlePnlAfterSwap {
+    pub perp_market_index: PerpMarketIndex,
+    /// Mango account to settle against, must have opposite pnl
+    pub counterparty: Pubkey,
+    /// Whether the counterparty has positive pnl (and the swapping account negative pnl)
+    pub counterparty_is_profitable: bool,
+}
+
+/// Compute units that simulations of sanctum swaps consumed, by route (swap_src)
+///
+/// Entries expire after the ttl, so changes to a route's cost are picked up.
+pub struct RouteCuCache {
+    ttl: Duration,
+    entries: RwLock<HashMap<String, (u32, Instant)>>,
+}
+
+impl RouteCuCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, route: &str) -> Option<u32> {
+        let entries = self.entries.read().unwrap();
+        entries
+            .get(route)
+            .filter(|(_, observed_at)| observed_at.elapsed() < self.ttl)
+            .map(|(cu, _)| *cu)
+    }
+
+    pub fn insert(&self, route: &str, cu: u32) {
+        let mut entries = self.entries.write().unwrap();
+        entries.insert(route.to_string(), (cu, Instant::now()));
+    }
+}
+
 pub struct Sanctum<'a> {
     pub mango_client: &'a MangoClient,
     pub timeout_duration: Duration,
+    /// Mango account to swap with, if not the client's default account.
+    /// Must be owned by the client's owner.
+    pub mango_account_address: Option<Pubkey>,
+    pub output_bank_selection: OutputBankSelection,
+    /// Only pass the health accounts FlashLoanEnd requires: the swapped tokens, borrows,
+    /// perp settle tokens, perp markets and serum3 open orders. Shrinks the transaction
+    /// for accounts with many deposits.
+    ///
+    /// FlashLoanEnd skips the banks of left out deposits, so they don't count towards
+    /// health. That is safe, but a swap that needs them as collateral fails the health
+    /// check where it would pass with all health accounts.
+    pub partial_health_accounts: bool,
+    pub settle_pnl_after_swap: Option<SettlePnlAfterSwap>,
+    /// Maximum number of mango and sanctum lookup tables the swap transaction may reference
+    pub max_address_lookup_tables: usize,
+    /// If set, swaps on routes with a cached compute unit cost use it for their limit.
+    /// Two hop swaps use the sum of both routes' costs.
+    pub route_cu_cache: Option<&'a RouteCuCache>,
+    /// Before building a swap, check that init health stays positive (or doesn't
+    /// decrease) even when both oracles are at the adverse edge of their confidence
+    /// band: assets at price minus confidence, liabilities at price plus confidence.
+    pub conservative_health: bool,
+    /// If set, swaps use at least this slippage, even if the caller asks for less
+    pub min_effective_slippage_bps: Option<u64>,
+    /// Borrow this much more than the quote's in_amount, to not fail on rounding at the
+    /// bank. FlashLoanEnd repays the unused part.
+    pub loan_buffer_bps: u64,
+    /// Retries of the quote and swap requests to sanctum on server and network errors
+    pub retry_policy: RetryPolicy,
+    /// If set, swaps fail with SwapError::FeeTooHigh when the quote's fee_pct is larger
+    pub max_fee_pct: Option<f64>,
+    /// Instructions sysvar address passed to FlashLoanBegin, if not the canonical one.
+    /// Only useful for test environments.
+    pub instructions_sysvar: Option<Pubkey>,
+    /// Pays transaction fees and rent of created token accounts instead of the owner.
+    /// The owner still signs as the mango account authority.
+    pub fee_payer: Option<Arc<Keypair>>,
+    /// Don't add an idempotent creation of the owner's source token account, because
+    /// it already exists, for example after MangoClient::prepare_atas()
+    pub skip_source_ata_creation: bool,
+    /// Leave out all compute budget instructions, for callers that add their own
+    /// compute unit limit and price to the transaction
+    pub strip_compute_budget: bool,
+    /// Programs the swap instructions may invoke in addition to SANCTUM_SWAP_PROGRAMS
+    pub extra_swap_programs: Vec<Pubkey>,
+    /// Compute unit limit of swap transactions, instead of the estimate from sanctum's
+    /// limit, the route_cu_cache and the health check
+    pub compute_unit_limit: Option<u32>,
+    /// When swap() fails on the swap program's slippage check, quote and build it
+    /// again with the fresh price and retry once
+    pub refresh_quote_on_slippage: bool,
+    /// Sanctum api to use for quotes and swaps instead of ClientConfig::sanctum_url,
+    /// for example a self-hosted mirror
+    pub base_url_override: Option<String>,
+    /// When the input or output mint is wSOL, close the owner's wSOL token account after
+    /// FlashLoanEnd so its lamports return to the owner as SOL.
+    ///
+    /// FlashLoanEnd moves the swap's wSOL into the bank, so this returns the account's
+    /// rent and any wSOL it held before the swap.
+    pub unwrap_sol_on_completion: bool,
+    /// If set, price_impact_bps() fails with SwapError::StaleOracle when an oracle was
+    /// last updated more than this many slots ago
+    pub max_oracle_slot_age: Option<u64>,
+    /// Add an idempotent creation of the owner's output token account, in case the
+    /// sanctum response doesn't create it
+    pub create_output_ata: bool,
+    /// Fail with SwapError::TransactionTooLarge when the prepared transaction doesn't
+    /// fit in a packet, instead of failing on send
+    pub check_transaction_size: bool,
+    /// How many slots back swap_idempotent() looks for an earlier swap with the same
+    /// idempotency key. Resubmitting after a longer time swaps again.
+    pub idempotency_window_slots: u64,
 }
 
 impl<'a> Sanctum<'a> {
+    fn base_url(&self) -> &str {
+        base_url(
+            self.base_url_override.as_deref(),
+            &self.mango_client.client.config().sanctum_url,
+        )
+    }
+
+    fn mango_account_address(&self) -> Pubkey {
+        self.mango_account_address
+            .unwrap_or(self.mango_client.mango_account_address)
+    }
+
+    async fn mango_account(&self) -> anyhow::Result<MangoAccountValue> {
+        let address = self.mango_account_address();
+        let account =
+            account_fetcher_fetch_mango_account(&*self.mango_client.account_fetcher, &address)
+                .await?;
+        check_account_owner(
+            &address,
+            &account,
+            &self.mango_client.owner(),
+            &self.mango_client.group(),
+        )?;
+        Ok(account)
+    }
+
+    async fn output_bank_and_vault(
+        &self,
+        token: &TokenContext,
+    ) -> anyhow::Result<(Pubkey, Pubkey)> {
+        if self.output_bank_selection == OutputBankSelection::FirstBank || token.banks().len() == 1
+        {
+            return Ok((token.first_bank(), token.first_vault()));
+        }
+
+        let mut banks = vec![];
+        for address in token.banks() {
+            let bank: Bank =
+                account_fetcher_fetch_anchor_account(&*self.mango_client.account_fetcher, address)
+                    .await?;
+            banks.push(bank);
+        }
+        let index = least_full_bank_index(&banks);
+        Ok((token.banks()[index], token.vaults[index]))
+    }
+
+    async fn settle_pnl_instructions(
+        &self,
+        settle: &SettlePnlAfterSwap,
+        account: &MangoAccountValue,
+        swap_tokens: &[TokenIndex],
+    ) -> anyhow::Result<Vec<Instruction>> {
+        let group = self.mango_client.group();
+        let perp = self
+            .mango_client
+            .context
+            .perp_markets
+            .get(&settle.perp_market_index)
+            .ok_or_else(|| {
+                anyhow::anyhow!("no perp market with index {}", settle.perp_market_index)
+            })?;
+        anyhow::ensure!(
+            perp.group == group,
+            "perp market {} does not belong to group {}",
+            perp.address,
+            group
+        );
+
+        let counterparty = account_fetcher_fetch_mango_account(
+            &*self.mango_client.account_fetcher,
+            &settle.counterparty,
+        )
+        .await?;
+        anyhow::ensure!(
+            counterparty.fixed.group == group,
+            "settle counterparty {} does not belong to group {}",
+            settle.counterparty,
+            group
+        );
+
+        // the health check must see the positions the swap may have created
+        let mut account = account.clone();
+        for token_index in swap_tokens {
+            account.ensure_token_position(*token_index)?;
+        }
+
+        let address = self.mango_account_address();
+        let own = (&address, &account);
+        let other = (&settle.counterparty, &counterparty);
+        let (account_a, account_b) = if settle.counterparty_is_profitable {
+            (other, own)
+        } else {
+            (own, other)
+        };
+        let ixs = self
+            .mango_client
+            .perp_settle_pnl_instruction(settle.perp_market_index, account_a, account_b)
+            .await?;
+        Ok(ixs.instructions)
+    }
+
+    /// Errors if the token balance `changes` would leave the account with negative and
+    /// decreasing init health when the oracles are at the adverse edge of their
+    /// confidence bands, see conservative_health.
+    async fn check_conservative_health(
+        &self,
+        account: &MangoAccountValue,
+        changes: [(TokenIndex, I80F48); 2],
+    ) -> anyhow::Result<()> {
+        let mut account = account.clone();
+        for (token_index, _) in changes.iter() {
+            account.ensure_token_position(*token_index)?;
+        }
+        let health_cache = self.mango_client.health_cache(&account).await?;
+
+        let mut token_changes = vec![];
+        for (token_index, change) in changes {
+            let deviation = self
+                .mango_client
+                .bank_oracle_state(token_index)
+                .await?
+                .deviation;
+            let info = health_cache.token_info(token_index)?.clone();
+            token_changes.push((info, deviation, change));
+        }
+
+        let spot_health = health_cache.health(HealthType::Init);
+        let (before, after) = conservative_health_before_and_after(spot_health, &token_changes);
+        anyhow::ensure!(
+            after >= 0 || after >= before,
+            "swap fails the conservative health check: init health at the oracle confidence edges would go from {before} to {after}"
+        );
+        Ok(())
+    }
+
     pub async fn quote(
         &self,
         input_mint: Pubkey,
         output_mint: Pubkey,
         amount: u64,
+    ) -> anyhow::Result<QuoteResponse> {
+        self.quote_from_source(input_mint, output_mint, amount, None)
+            .await
+    }
+
+    /// Like quote(), but restricted to one of sanctum's swap sources if `swap_src` is set
+    pub async fn quote_from_source(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        amount: u64,
+        swap_src: Option<&str>,
+    ) -> anyhow::Result<QuoteResponse> {
+        self.request_quote(input_mint, output_mint, amount, swap_src, SwapMode::ExactIn)
+            .await
+    }
+
+    /// Like quote(), but `amount` is the exact output amount for SwapMode::ExactOut.
+    ///
+    /// The quote remembers its mode, so prepare_swap_transaction() builds the matching swap.
+    pub async fn quote_with_mode(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        amount: u64,
+        mode: SwapMode,
+    ) -> anyhow::Result<QuoteResponse> {
+        self.request_quote(input_mint, output_mint, amount, None, mode)
+            .await
+    }
+
+    /// Quote for receiving exactly `out_amount` of `output_mint`.
+    ///
+    /// The quote's in_amount is the input needed for that.
+    pub async fn quote_exact_out(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        out_amount: u64,
+    ) -> anyhow::Result<QuoteResponse> {
+        self.quote_with_mode(input_mint, output_mint, out_amount, SwapMode::ExactOut)
+            .await
+    }
+
+    async fn request_quote(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        amount: u64,
+        swap_src: Option<&str>,
+        mode: SwapMode,
     ) -> anyhow::Result<QuoteResponse> {
         if input_mint == output_mint {
             anyhow::bail!("Need two distinct mint to swap");
         }
+        for mint in [input_mint, output_mint] {
+            anyhow::ensure!(
+                self.mango_client.is_registered_mint(&mint),
+                "mint {mint} is not a token of the mango group"
+            );
+        }
 
-        let mut account = self.mango_client.mango_account().await?;
+        let mut account = self.mango_account().await?;
         let input_token_index = self
             .mango_client
             .context
@@ -77,30 +773,81 @@ impl<'a> Sanctum<'a> {
         account.ensure_token_position(input_token_index)?;
         account.ensure_token_position(output_token_index)?;
 
-        let query_args = vec![
+        let mut query_args = vec![
             ("input", input_mint.to_string()),
             ("outputLstMint", output_mint.to_string()),
             ("amount", format!("{}", amount)),
+            ("mode", mode.as_str().to_string()),
         ];
-        let config = self.mango_client.client.config();
+        if let Some(swap_src) = swap_src {
+            query_args.push(("swapSrc", swap_src.to_string()));
+        }
+        let base_url = self.base_url();
 
-        let response = self
-            .mango_client
-            .http_client
-            .get(format!("{}/swap/quote", config.sanctum_url))
-            .query(&query_args)
-            .timeout(self.timeout_duration)
-            .send()
-            .await
-            .context("quote request to sanctum")?;
-        let quote: QuoteResponse =
-            util::http_error_handling(response).await.with_context(|| {
-                format!("error requesting sanctum route between {input_mint} and {output_mint} (using url: {})", config.sanctum_url)
-            })?;
+        let mut quote = fetch_quote(
+            &self.mango_client.http_client,
+            &self.retry_policy,
+            self.timeout_duration,
+            base_url,
+            &query_args,
+        )
+        .await
+        .with_context(|| {
+            format!("error requesting sanctum route between {input_mint} and {output_mint} (using url: {base_url})")
+        })?;
 
+        quote.mode = mode;
         Ok(quote)
     }
 
+    /// Quotes swapping `input_mint` into each of `outputs`, given as (output mint, amount).
+    ///
+    /// The requests run concurrently, each with the usual timeout. The results are in the
+    /// order of `outputs`, and a failed quote doesn't affect the others.
+    pub async fn quote_many(
+        &self,
+        input_mint: Pubkey,
+        outputs: &[(Pubkey, u64)],
+    ) -> Vec<anyhow::Result<QuoteResponse>> {
+        futures::future::join_all(
+            outputs
+                .iter()
+                .map(|&(output_mint, amount)| self.quote(input_mint, output_mint, amount)),
+        )
+        .await
+    }
+
+    /// Builds swap transactions for the best `n` of sanctum's swap sources.
+    ///
+    /// The transactions are alternatives for racing: send them all and keep the first
+    /// that confirms. Only one of them should execute, since each swaps the full
+    /// `amount`; if several land, the account swaps several times.
+    ///
+    /// Sources that fail to quote are skipped. The result is ordered by quoted
+    /// output, best first, and may hold fewer than `n` transactions.
+    pub async fn prepare_top_routes(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        amount: u64,
+        max_slippage_bps: u64,
+        n: usize,
+    ) -> anyhow::Result<Vec<TransactionBuilder>> {
+        let quotes = futures::future::join_all(SWAP_SOURCES.iter().map(|swap_src| {
+            self.quote_from_source(input_mint, output_mint, amount, Some(*swap_src))
+        }))
+        .await;
+
+        let mut tx_builders = vec![];
+        for quote in top_quotes(quotes, n) {
+            tx_builders.push(
+                self.prepare_swap_transaction(input_mint, output_mint, max_slippage_bps, &quote)
+                    .await?,
+            );
+        }
+        Ok(tx_builders)
+    }
+
     /// Find the instructions and account lookup tables for a sanctum swap through mango
     pub async fn prepare_swap_transaction(
         &self,
@@ -108,85 +855,364 @@ impl<'a> Sanctum<'a> {
         output_mint: Pubkey,
         max_slippage_bps: u64,
         quote: &QuoteResponse,
+    ) -> anyhow::Result<TransactionBuilder> {
+        self.prepare_swap_transaction_with_limit(
+            input_mint,
+            output_mint,
+            SlippageLimit::Bps(max_slippage_bps),
+            quote,
+        )
+        .await
+    }
+
+    /// Like prepare_swap_transaction(), but the slippage can also be given as an absolute
+    /// minimum output, which is used as is instead of being derived from the quote.
+    pub async fn prepare_swap_transaction_with_limit(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        slippage_limit: SlippageLimit,
+        quote: &QuoteResponse,
+    ) -> anyhow::Result<TransactionBuilder> {
+        self.prepare_swap_transaction_with_memo(
+            input_mint,
+            output_mint,
+            slippage_limit,
+            quote,
+            None,
+        )
+        .await
+    }
+
+    /// Like prepare_swap_transaction_with_limit(), but adds a memo instruction before the
+    /// transaction's lookup tables and size are checked
+    async fn prepare_swap_transaction_with_memo(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        slippage_limit: SlippageLimit,
+        quote: &QuoteResponse,
+        memo: Option<&str>,
     ) -> anyhow::Result<TransactionBuilder> {
         tracing::info!("swapping using sanctum");
+        let slippage_limit = match slippage_limit {
+            SlippageLimit::Bps(bps) => {
+                SlippageLimit::Bps(effective_slippage_bps(bps, self.min_effective_slippage_bps))
+            }
+            min_out => min_out,
+        };
+        if let Some(max_fee_pct) = self.max_fee_pct {
+            check_fee_pct(quote, max_fee_pct)?;
+        }
+        let (quote_in_amount, quote_out_amount) =
+            quote.in_and_out_amounts().with_context(|| {
+                format!("invalid sanctum quote for swapping {input_mint} to {output_mint}")
+            })?;
+        let amounts = SwapAmounts::with_limit(
+            quote.mode,
+            quote_in_amount,
+            quote_out_amount,
+            slippage_limit,
+        )?;
+
+        let hop = SwapHop {
+            input_mint,
+            output_mint,
+            amounts,
+            quote,
+        };
+        self.build_flash_loan_swap(&[hop], memo).await
+    }
+
+    /// Swaps `input_mint` to `output_mint` through `mid_mint`, for pairs that sanctum
+    /// has no direct route for.
+    ///
+    /// Both hops run between a single FlashLoanBegin and End that only borrow the input.
+    /// The second hop swaps the first hop's minimum output, so anything the first hop
+    /// produces above that stays in the owner's `mid_mint` token account.
+    pub async fn prepare_two_hop_swap(
+        &self,
+        input_mint: Pubkey,
+        mid_mint: Pubkey,
+        output_mint: Pubkey,
+        max_slippage_bps: u64,
+        amount: u64,
+    ) -> anyhow::Result<TransactionBuilder> {
+        tracing::info!("two hop swapping using sanctum");
+        let max_slippage_bps =
+            effective_slippage_bps(max_slippage_bps, self.min_effective_slippage_bps);
+
+        let first_quote = self.quote(input_mint, mid_mint, amount).await?;
+        let (first_in_amount, first_out_amount) =
+            first_quote.in_and_out_amounts().with_context(|| {
+                format!("invalid sanctum quote for swapping {input_mint} to {mid_mint}")
+            })?;
+        let first_amounts = SwapAmounts::new(
+            SwapMode::ExactIn,
+            first_in_amount,
+            first_out_amount,
+            max_slippage_bps,
+        );
+        let second_quote = self
+            .quote(mid_mint, output_mint, first_amounts.min_out)
+            .await?;
+        let (second_in_amount, second_out_amount) =
+            second_quote.in_and_out_amounts().with_context(|| {
+                format!("invalid sanctum quote for swapping {mid_mint} to {output_mint}")
+            })?;
+        let second_amounts = SwapAmounts::new(
+            SwapMode::ExactIn,
+            second_in_amount,
+            second_out_amount,
+            max_slippage_bps,
+        );
+        if let Some(max_fee_pct) = self.max_fee_pct {
+            check_fee_pct(&first_quote, max_fee_pct)?;
+            check_fee_pct(&second_quote, max_fee_pct)?;
+        }
+
+        let hops = [
+            SwapHop {
+                input_mint,
+                output_mint: mid_mint,
+                amounts: first_amounts,
+                quote: &first_quote,
+            },
+            SwapHop {
+                input_mint: mid_mint,
+                output_mint,
+                amounts: second_amounts,
+                quote: &second_quote,
+            },
+        ];
+        self.build_flash_loan_swap(&hops, None).await
+    }
+
+    /// Builds the transaction that runs `hops` one after the other between a single
+    /// FlashLoanBegin and End, borrowing the first hop's input and depositing the last
+    /// hop's output.
+    ///
+    /// The tokens in between are not part of the flash loan: FlashLoanEnd for swaps
+    /// expects exactly two vault changes. Their token accounts are created up front,
+    /// since each hop pays out into the account the next one consumes.
+    async fn build_flash_loan_swap(
+        &self,
+        hops: &[SwapHop<'_>],
+        memo: Option<&str>,
+    ) -> anyhow::Result<TransactionBuilder> {
+        let (first_hop, last_hop) = match (hops.first(), hops.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => anyhow::bail!("swap without hops"),
+        };
+        let input_mint = first_hop.input_mint;
+        let output_mint = last_hop.output_mint;
 
         let source_token = self.mango_client.context.token_by_mint(&input_mint)?;
+        let mid_tokens = hops[..hops.len() - 1]
+            .iter()
+            .map(|hop| self.mango_client.context.token_by_mint(&hop.output_mint))
+            .collect::<anyhow::Result<Vec<_>>>()?;
         let target_token = self.mango_client.context.token_by_mint(&output_mint)?;
 
-        let bank_ams = [source_token.first_bank(), target_token.first_bank()]
+        let (target_bank, target_vault) = self.output_bank_and_vault(target_token).await?;
+
+        let bank_ams = [source_token.first_bank(), target_bank]
             .into_iter()
             .map(util::to_writable_account_meta)
             .collect::<Vec<_>>();
 
-        let vault_ams = [source_token.first_vault(), target_token.first_vault()]
+        let vault_ams = [source_token.first_vault(), target_vault]
             .into_iter()
             .map(util::to_writable_account_meta)
             .collect::<Vec<_>>();
 
         let owner = self.mango_client.owner();
-        let account = &self.mango_client.mango_account().await?;
+        let (payer, signers) =
+            swap_payer_and_signers(&self.mango_client.owner, self.fee_payer.as_ref());
+        let account = &self.mango_account().await?;
 
-        let token_ams = [source_token.mint, target_token.mint]
+        let token_ams = [source_token, target_token]
             .into_iter()
-            .map(|mint| {
-                util::to_writable_account_meta(
-                    anchor_spl::associated_token::get_associated_token_address(&owner, &mint),
-                )
-            })
+            .map(|token| util::to_writable_account_meta(token.associated_token_address(&owner)))
             .collect::<Vec<_>>();
+        let token_program = flash_loan_token_program(source_token, target_token)?;
 
-        let source_loan = quote
-            .in_amount
-            .as_ref()
-            .map(|v| u64::from_str(v).unwrap())
-            .unwrap_or(0);
-        let loan_amounts = vec![source_loan, 0u64];
-        let num_loans: u8 = loan_amounts.len().try_into().unwrap();
+        // With ExactOut the swap may use up to the maximum input
+        let source_loan = first_hop.amounts.max_in;
+        let buffered_source_loan = buffered_loan_amount(source_loan, self.loan_buffer_bps);
+        if buffered_source_loan > source_loan {
+            let available = self
+                .mango_client
+                .bank_available_liquidity(source_token.token_index)
+                .await?;
+            anyhow::ensure!(
+                I80F48::from(buffered_source_loan) <= available,
+                "loan of {buffered_source_loan} including the buffer exceeds the available liquidity of {available}"
+            );
+        }
+        let loan_amounts = vec![buffered_source_loan, 0u64];
 
         // This relies on the fact that health account banks will be identical to the first_bank above!
-        let (health_ams, _health_cu) = self
-            .mango_client
-            .derive_health_check_remaining_account_metas(
+        let swap_tokens = std::iter::once(source_token)
+            .chain(mid_tokens.iter().copied())
+            .chain(std::iter::once(target_token))
+            .map(|token| token.token_index)
+            .collect::<Vec<_>>();
+        let (mut health_ams, health_cu) = if self.partial_health_accounts {
+            self.mango_client
+                .derive_partial_health_check_remaining_account_metas(
+                    account,
+                    swap_tokens.clone(),
+                    swap_tokens.clone(),
+                )
+                .await
+        } else {
+            self.mango_client
+                .derive_health_check_remaining_account_metas(
+                    account,
+                    swap_tokens.clone(),
+                    swap_tokens.clone(),
+                    vec![],
+                )
+                .await
+        }
+        .context("building health accounts")?;
+        // ...except if a different output bank was selected
+        for am in health_ams.iter_mut() {
+            if am.pubkey == target_token.first_bank() {
+                am.pubkey = target_bank;
+            }
+        }
+
+        if self.conservative_health {
+            self.check_conservative_health(
                 account,
-                vec![source_token.token_index, target_token.token_index],
-                vec![source_token.token_index, target_token.token_index],
-                vec![],
+                [
+                    (source_token.token_index, -I80F48::from(source_loan)),
+                    (
+                        target_token.token_index,
+                        I80F48::from(last_hop.amounts.min_out),
+                    ),
+                ],
             )
-            .await
-            .context("building health accounts")?;
+            .await?;
+        }
 
-        let config = self.mango_client.client.config();
+        let mut layouts = vec![];
+        let mut sanctum_alts = vec![];
+        for hop in hops {
+            let (layout, alts) = self
+                .fetch_swap_layout(hop.input_mint, hop.output_mint, &hop.amounts, hop.quote)
+                .await?;
+            layouts.push(layout);
+            sanctum_alts.extend(alts);
+        }
+        let sanctum_cu = sanctum_cu_limit(&layouts);
+        let layout = layouts
+            .into_iter()
+            .reduce(SwapInstructionLayout::then)
+            .unwrap();
 
-        let in_amount = quote
-            .in_amount
-            .clone()
-            .expect("sanctum require a in amount");
-        let quote_amount_u64 = quote.out_amount.parse::<u64>()?;
-        let out_amount = ((quote_amount_u64 as f64) * (1.0 - (max_slippage_bps as f64) / 10_000.0))
-            .ceil() as u64;
+        let mut instructions: Vec<Instruction> = Vec::new();
 
-        let swap_response = self
-            .mango_client
-            .http_client
-            .post(format!("{}/swap", config.sanctum_url))
-            .json(&SwapRequest {
-                amount: in_amount.clone(),
-                quoted_amount: out_amount.to_string(),
-                input: input_mint.to_string(),
-                mode: "ExactIn".to_string(),
-                output_lst_mint: output_mint.to_string(),
-                signer: owner.to_string(),
-                swap_src: quote.swap_src.clone(),
-            })
-            .timeout(self.timeout_duration)
-            .send()
-            .await
-            .context("swap transaction request to sanctum")?;
+        let mut setup_ixs = layout.pre_setup;
+        // Ensure the token accounts exist, FlashLoanBegin and End need the source and
+        // target ones. Sanctum usually creates the output account, but not for all mints.
+        let mut ata_tokens = vec![];
+        if !self.skip_source_ata_creation {
+            ata_tokens.push(source_token);
+        }
+        ata_tokens.extend(mid_tokens.iter().copied());
+        if self.create_output_ata {
+            ata_tokens.push(target_token);
+        }
+        let ata_mints = ata_tokens
+            .iter()
+            .map(|token| (token.mint, token.token_program))
+            .collect::<Vec<_>>();
+        setup_ixs.extend(ata_creation_instructions(&payer, &owner, &ata_mints));
+        instructions.extend(dedupe_setup_instructions(setup_ixs));
 
-        let swap_r: SanctumSwapResponse = util::http_error_handling(swap_response)
-            .await
-            .context("error requesting sanctum swap")?;
+        let flash_loan = FlashLoanSwap {
+            account: self.mango_account_address(),
+            owner,
+            group: self.mango_client.group(),
+            instructions_sysvar: self
+                .instructions_sysvar
+                .unwrap_or(solana_sdk::sysvar::instructions::id()),
+            banks: bank_ams,
+            vaults: vault_ams,
+            token_accounts: token_ams,
+            token_program,
+            loan_amounts,
+            health_accounts: health_ams,
+        };
+        instructions.extend(flash_loan.wrap(layout.action));
+
+        instructions.extend(layout.post_setup);
+
+        if self.unwrap_sol_on_completion {
+            append_close_wsol_account(&mut instructions, &owner, [input_mint, output_mint])?;
+        }
+
+        if let Some(cu_limit) = self.compute_unit_limit {
+            set_compute_unit_limit(&mut instructions, cu_limit);
+        } else if !self.strip_compute_budget {
+            let cached_cu = self
+                .route_cu_cache
+                .and_then(|cache| cached_route_cu(cache, hops));
+            if let Some(cu_limit) = swap_cu_limit(cached_cu, sanctum_cu, health_cu) {
+                set_compute_unit_limit(&mut instructions, cu_limit);
+            }
+        }
+
+        if let Some(settle) = &self.settle_pnl_after_swap {
+            let settle_ixs = self
+                .settle_pnl_instructions(settle, account, &swap_tokens)
+                .await
+                .context("building settle pnl instructions")?;
+            insert_after_flash_loan_end(&mut instructions, settle_ixs)?;
+        }
+
+        if let Some(memo) = memo {
+            instructions.push(memo_instruction(memo));
+        }
+
+        self.swap_transaction_builder(instructions, sanctum_alts, payer, signers)
+            .await
+    }
+
+    /// Requests sanctum's swap transaction for `quote` and splits its instructions into
+    /// the parts around the flash loan, after checking the programs they call.
+    async fn fetch_swap_layout(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        amounts: &SwapAmounts,
+        quote: &QuoteResponse,
+    ) -> anyhow::Result<(SwapInstructionLayout, Vec<AddressLookupTableAccount>)> {
+        let owner = self.mango_client.owner();
+        let swap_request = SwapRequest {
+            amount: amounts.request_amount.to_string(),
+            quoted_amount: amounts.quoted_amount.to_string(),
+            input: input_mint.to_string(),
+            mode: quote.mode.as_str().to_string(),
+            output_lst_mint: output_mint.to_string(),
+            signer: owner.to_string(),
+            swap_src: quote.swap_src.clone(),
+        };
+        let url = format!("{}/swap", self.base_url());
+        let swap_response = send_with_retry(
+            &self.retry_policy,
+            self.timeout_duration,
+            "swap transaction request to sanctum",
+            || self.mango_client.http_client.post(&url).json(&swap_request),
+        )
+        .await?;
+
+        let swap_r: SanctumSwapResponse = util::http_error_handling(swap_response)
+            .await
+            .context("error requesting sanctum swap")?;
 
         let tx = bincode::options()
             .with_fixint_encoding()
@@ -202,18 +1228,14 @@ impl<'a> Sanctum<'a> {
             .await?;
 
         let system_program = system_program::ID;
-        let ata_program = anchor_spl::associated_token::ID;
         let token_program = anchor_spl::token::ID;
-        let compute_budget_program: Pubkey = solana_sdk::compute_budget::ID;
-        // these setup instructions should be placed outside of flashloan begin-end
-        let is_setup_ix = |k: Pubkey| -> bool {
-            k == ata_program || k == token_program || k == compute_budget_program
-        };
         let sync_native_pack =
             anchor_spl::token::spl_token::instruction::TokenInstruction::SyncNative.pack();
 
+        check_output_not_redirected(&sanctum_ixs_orig, &owner, &output_mint)?;
+
         // Remove auto wrapping of SOL->wSOL
-        let sanctum_ixs: Vec<Instruction> = sanctum_ixs_orig
+        let mut sanctum_ixs: Vec<Instruction> = sanctum_ixs_orig
             .clone()
             .into_iter()
             .filter(|ix| {
@@ -221,109 +1243,336 @@ impl<'a> Sanctum<'a> {
                     && !(ix.program_id == token_program && ix.data == sync_native_pack)
             })
             .collect();
-
-        let sanctum_action_ix_begin = sanctum_ixs
-            .iter()
-            .position(|ix| !is_setup_ix(ix.program_id))
-            .ok_or_else(|| {
-                anyhow::anyhow!("sanctum swap response only had setup-like instructions")
-            })?;
-        let sanctum_action_ix_end = sanctum_ixs.len()
-            - sanctum_ixs
-                .iter()
-                .rev()
-                .position(|ix| !is_setup_ix(ix.program_id))
-                .unwrap();
-
-        let mut instructions: Vec<Instruction> = Vec::new();
-
-        for ix in &sanctum_ixs[..sanctum_action_ix_begin] {
-            instructions.push(ix.clone());
+        if self.strip_compute_budget {
+            sanctum_ixs = strip_compute_budget_instructions(sanctum_ixs);
         }
 
-        // Ensure the source token account is created (sanctum takes care of the output account)
-        instructions.push(
-            spl_associated_token_account::instruction::create_associated_token_account_idempotent(
-                &owner,
-                &owner,
-                &source_token.mint,
-                &Token::id(),
-            ),
-        );
-
-        instructions.push(Instruction {
-            program_id: mango_v4::id(),
-            accounts: {
-                let mut ams = anchor_lang::ToAccountMetas::to_account_metas(
-                    &mango_v4::accounts::FlashLoanBegin {
-                        account: self.mango_client.mango_account_address,
-                        owner,
-                        token_program: Token::id(),
-                        instructions: solana_sdk::sysvar::instructions::id(),
-                    },
-                    None,
-                );
-                ams.extend(bank_ams);
-                ams.extend(vault_ams.clone());
-                ams.extend(token_ams.clone());
-                ams.push(util::to_readonly_account_meta(self.mango_client.group()));
-                ams
-            },
-            data: anchor_lang::InstructionData::data(&mango_v4::instruction::FlashLoanBegin {
-                loan_amounts,
-            }),
-        });
-
-        for ix in &sanctum_ixs[sanctum_action_ix_begin..sanctum_action_ix_end] {
-            instructions.push(ix.clone());
+        let layout = SwapInstructionLayout::new(sanctum_ixs)?;
+        let mut allowed_programs = sanctum_swap_programs()?;
+        allowed_programs.extend(self.extra_swap_programs.iter().copied());
+        let mut owner_token_accounts = vec![];
+        for mint in [input_mint, output_mint] {
+            let token = self.mango_client.context.token_by_mint(&mint)?;
+            owner_token_accounts.push(token.associated_token_address(&owner));
         }
+        check_swap_programs(&layout, &allowed_programs, &owner, &owner_token_accounts)?;
 
-        instructions.push(Instruction {
-            program_id: mango_v4::id(),
-            accounts: {
-                let mut ams = anchor_lang::ToAccountMetas::to_account_metas(
-                    &mango_v4::accounts::FlashLoanEnd {
-                        account: self.mango_client.mango_account_address,
-                        owner,
-                        token_program: Token::id(),
-                    },
-                    None,
-                );
-                ams.extend(health_ams);
-                ams.extend(vault_ams);
-                ams.extend(token_ams);
-                ams.push(util::to_readonly_account_meta(self.mango_client.group()));
-                ams
-            },
-            data: anchor_lang::InstructionData::data(&mango_v4::instruction::FlashLoanEndV2 {
-                num_loans,
-                flash_loan_type: mango_v4::accounts_ix::FlashLoanType::Swap,
-            }),
-        });
-
-        for ix in &sanctum_ixs[sanctum_action_ix_end..] {
-            instructions.push(ix.clone());
-        }
+        Ok((layout, sanctum_alts))
+    }
 
+    /// Adds the group's lookup tables to `sanctum_alts` and builds the transaction, checking
+    /// its size as configured.
+    async fn swap_transaction_builder(
+        &self,
+        instructions: Vec<Instruction>,
+        sanctum_alts: Vec<AddressLookupTableAccount>,
+        payer: Pubkey,
+        signers: Vec<Arc<Keypair>>,
+    ) -> anyhow::Result<TransactionBuilder> {
         let mut address_lookup_tables = self.mango_client.mango_address_lookup_tables().await?;
+        let no_mango_alts = address_lookup_tables.is_empty();
+        if no_mango_alts {
+            tracing::warn!(
+                group = %self.mango_client.group(),
+                "mango group has no address lookup tables, swap transactions will be large"
+            );
+        }
         address_lookup_tables.extend(sanctum_alts.into_iter());
+        let address_lookup_tables = limit_address_lookup_tables(
+            address_lookup_tables,
+            &instructions,
+            self.max_address_lookup_tables,
+        )?;
 
-        let payer = owner; // maybe use fee_payer? but usually it's the same
-
-        Ok(TransactionBuilder {
+        let tx_builder = TransactionBuilder {
             instructions,
             address_lookup_tables,
             payer,
-            signers: vec![self.mango_client.owner.clone()],
+            signers,
             config: self
                 .mango_client
                 .client
                 .config()
                 .transaction_builder_config
                 .clone(),
+        };
+        if no_mango_alts {
+            check_size_without_mango_alts(&tx_builder)?;
+        }
+        if self.check_transaction_size {
+            check_transaction_size(&tx_builder)?;
+        }
+
+        Ok(tx_builder)
+    }
+
+    /// Like prepare_swap_transaction(), but returns the instructions, lookup tables and
+    /// signer pubkeys instead of a TransactionBuilder holding the keypairs, for embedding
+    /// the swap in a transaction that is signed elsewhere.
+    pub async fn prepare_unsigned_swap(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        max_slippage_bps: u64,
+        quote: &QuoteResponse,
+    ) -> anyhow::Result<UnsignedSwap> {
+        let tx_builder = self
+            .prepare_swap_transaction(input_mint, output_mint, max_slippage_bps, quote)
+            .await?;
+        Ok(UnsignedSwap::new(tx_builder))
+    }
+
+    /// Like swap(), but tags the transaction with a memo containing `idempotency_key`.
+    ///
+    /// If a successful transaction carrying that memo already touched the mango account
+    /// within the last idempotency_window_slots, its signature is returned instead of
+    /// swapping again. This protects bots that resubmit swaps after a crash.
+    pub async fn swap_idempotent(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        max_slippage_bps: u64,
+        amount: u64,
+        idempotency_key: &str,
+    ) -> anyhow::Result<Signature> {
+        if let Some(signature) = self.find_swap_by_idempotency_key(idempotency_key).await? {
+            tracing::info!(%signature, idempotency_key, "swap was already executed");
+            return Ok(signature);
+        }
+
+        let route = self.quote(input_mint, output_mint, amount).await?;
+
+        let tx_builder = self
+            .prepare_swap_transaction_with_memo(
+                input_mint,
+                output_mint,
+                SlippageLimit::Bps(max_slippage_bps),
+                &route,
+                Some(idempotency_key),
+            )
+            .await?;
+
+        tx_builder.send_and_confirm(&self.mango_client.client).await
+    }
+
+    /// Looks through the mango account's transactions of the last
+    /// idempotency_window_slots for a successful one with the `idempotency_key` memo.
+    pub async fn find_swap_by_idempotency_key(
+        &self,
+        idempotency_key: &str,
+    ) -> anyhow::Result<Option<Signature>> {
+        let rpc = self.mango_client.client.rpc_async();
+        let min_slot = rpc
+            .get_slot()
+            .await?
+            .saturating_sub(self.idempotency_window_slots);
+        let address = self.mango_account_address();
+        let rpc = &rpc;
+        find_signature_with_memo_since(
+            move |before| async move {
+                rpc.get_signatures_for_address_with_config(
+                    &address,
+                    solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config {
+                        before,
+                        until: None,
+                        limit: None,
+                        commitment: None,
+                    },
+                )
+                .await
+                .context("fetching mango account signatures")
+            },
+            idempotency_key,
+            min_slot,
+        )
+        .await
+    }
+
+    /// The quote's fee converted to native units of `output_mint` using the bank oracles.
+    ///
+    /// Sanctum can charge the fee in a mint that is neither the input nor the output.
+    pub async fn fee_in_output_terms(
+        &self,
+        output_mint: Pubkey,
+        quote: &QuoteResponse,
+    ) -> anyhow::Result<u64> {
+        let fee_amount = quote.fee_amount_u64()?;
+        let fee_mint = quote.fee_mint_pubkey()?;
+        if fee_mint == output_mint {
+            return Ok(fee_amount);
+        }
+
+        let context = &self.mango_client.context;
+        let fee_price = self
+            .mango_client
+            .bank_oracle_price(context.token_by_mint(&fee_mint)?.token_index)
+            .await?;
+        let output_price = self
+            .mango_client
+            .bank_oracle_price(context.token_by_mint(&output_mint)?.token_index)
+            .await?;
+        convert_native_amount(fee_amount, fee_price, output_price)
+    }
+
+    /// Native amount of `input_mint` that the account is charged at FlashLoanEnd for
+    /// swapping `quote`: the flash loan of the quote's in_amount plus the loan
+    /// origination fee.
+    ///
+    /// Bots can compare this against the quote's output to check that the swap pays
+    /// for itself.
+    pub async fn flash_loan_repayment(
+        &self,
+        input_mint: Pubkey,
+        quote: &QuoteResponse,
+    ) -> anyhow::Result<u64> {
+        let (source_loan, _) = quote.in_and_out_amounts()?;
+        let source_token = self.mango_client.context.token_by_mint(&input_mint)?;
+        let bank: Bank = account_fetcher_fetch_anchor_account(
+            &*self.mango_client.account_fetcher,
+            &source_token.first_bank(),
+        )
+        .await?;
+        Ok(bank.flash_loan_repayment(source_loan))
+    }
+
+    /// The quote's out_amount minus the loan origination fee on the borrowed input,
+    /// converted to native `output_mint` using the bank oracles.
+    ///
+    /// The sanctum fee is already deducted from out_amount; this additionally nets out
+    /// what mango charges at FlashLoanEnd, so it's what the account really ends up with.
+    pub async fn net_output_estimate(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        quote: &QuoteResponse,
+    ) -> anyhow::Result<u64> {
+        let (source_loan, out_amount) = quote.in_and_out_amounts()?;
+        let repayment = self.flash_loan_repayment(input_mint, quote).await?;
+
+        let context = &self.mango_client.context;
+        let input_price = self
+            .mango_client
+            .bank_oracle_price(context.token_by_mint(&input_mint)?.token_index)
+            .await?;
+        let output_price = self
+            .mango_client
+            .bank_oracle_price(context.token_by_mint(&output_mint)?.token_index)
+            .await?;
+        net_output(
+            out_amount,
+            repayment.saturating_sub(source_loan),
+            input_price,
+            output_price,
+        )
+    }
+
+    /// Days the output LST must be held for its higher yield to make up for the quote's
+    /// swap fee. APYs are fractions, like 0.07 for 7%.
+    ///
+    /// Compares simple, non-compounding yields. Infinite if the target LST doesn't
+    /// yield more than the current one.
+    pub fn break_even_days(
+        quote: &QuoteResponse,
+        current_lst_apy: f64,
+        target_lst_apy: f64,
+    ) -> anyhow::Result<f64> {
+        let fee = quote.fee_fraction()?;
+        let daily_advantage = (target_lst_apy - current_lst_apy) / 365.0;
+        if fee <= 0.0 {
+            return Ok(0.0);
+        }
+        if daily_advantage <= 0.0 {
+            return Ok(f64::INFINITY);
+        }
+        Ok(fee / daily_advantage)
+    }
+
+    /// Which token positions a swap between the mints would activate on the account
+    pub async fn pre_swap_info(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+    ) -> anyhow::Result<PreSwapInfo> {
+        let account = self.mango_account().await?;
+        let context = &self.mango_client.context;
+        let activates = |mint: &Pubkey| -> anyhow::Result<bool> {
+            let token_index = context.token_by_mint(mint)?.token_index;
+            Ok(MangoClient::will_activate_position(&account, token_index))
+        };
+        Ok(PreSwapInfo {
+            activates_input_position: activates(&input_mint)?,
+            activates_output_position: activates(&output_mint)?,
         })
     }
 
+    /// How much worse the quote's rate is than the oracle rate between the two mints,
+    /// in bps. Negative if the quote is better than the oracles.
+    ///
+    /// Cheap to compute, so swaps with too much impact can be skipped before building
+    /// a transaction.
+    pub async fn price_impact_bps(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        quote: &QuoteResponse,
+    ) -> anyhow::Result<f64> {
+        let context = &self.mango_client.context;
+        let input_token = context.token_by_mint(&input_mint)?;
+        let output_token = context.token_by_mint(&output_mint)?;
+        let input_price = self.fresh_oracle_price(input_token.token_index).await?;
+        let output_price = self.fresh_oracle_price(output_token.token_index).await?;
+        price_impact_bps(
+            quote,
+            input_token.decimals,
+            output_token.decimals,
+            input_price,
+            output_price,
+        )
+    }
+
+    /// The bank oracle price, checked against max_oracle_slot_age if set
+    async fn fresh_oracle_price(&self, token_index: TokenIndex) -> anyhow::Result<I80F48> {
+        let Some(max_slot_age) = self.max_oracle_slot_age else {
+            return self.mango_client.bank_oracle_price(token_index).await;
+        };
+        let (price, slot_age) = self
+            .mango_client
+            .oracle_price_and_staleness(token_index)
+            .await?;
+        check_oracle_slot_age(token_index, slot_age, max_slot_age)?;
+        Ok(price)
+    }
+
+    /// How the quote's output would be split between repaying an existing borrow in
+    /// `output_mint` and a new deposit, as (repaid, deposited) native amounts.
+    ///
+    /// FlashLoanEnd credits the whole output to the token position, so a borrow is
+    /// always repaid first and only the excess becomes a deposit.
+    pub async fn output_repay_and_deposit(
+        &self,
+        output_mint: Pubkey,
+        quote: &QuoteResponse,
+    ) -> anyhow::Result<(u64, u64)> {
+        let out_amount = quote
+            .out_amount
+            .parse::<u64>()
+            .context("parsing out_amount")?;
+        let target_token = self.mango_client.context.token_by_mint(&output_mint)?;
+        let account = self.mango_account().await?;
+        let native = match account.token_position(target_token.token_index) {
+            Ok(position) => {
+                let bank: Bank = account_fetcher_fetch_anchor_account(
+                    &*self.mango_client.account_fetcher,
+                    &target_token.first_bank(),
+                )
+                .await?;
+                position.native(&bank)
+            }
+            Err(_) => I80F48::ZERO,
+        };
+        Ok(split_repay_and_deposit(native, out_amount))
+    }
+
     pub async fn swap(
         &self,
         input_mint: Pubkey,
@@ -331,71 +1580,3691 @@ impl<'a> Sanctum<'a> {
         max_slippage_bps: u64,
         amount: u64,
     ) -> anyhow::Result<Signature> {
+        let outcome = self
+            .swap_with_outcome(input_mint, output_mint, max_slippage_bps, amount)
+            .await?;
+        Ok(outcome.signature)
+    }
+
+    /// Like swap(), but returns the quoted amounts and the slot the swap confirmed in
+    pub async fn swap_with_outcome(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        max_slippage_bps: u64,
+        amount: u64,
+    ) -> anyhow::Result<SwapOutcome> {
+        retry_once_on_slippage(self.refresh_quote_on_slippage, || async move {
+            let route = self.quote(input_mint, output_mint, amount).await?;
+            let flash_loan_repayment = self.flash_loan_repayment(input_mint, &route).await?;
+
+            let tx_builder = self
+                .prepare_swap_transaction(input_mint, output_mint, max_slippage_bps, &route)
+                .await?;
+
+            let (signature, status) = tx_builder
+                .send_and_confirm_with_status(&self.mango_client.client)
+                .await?;
+            SwapOutcome::new(
+                signature,
+                input_mint,
+                output_mint,
+                &route,
+                effective_slippage_bps(max_slippage_bps, self.min_effective_slippage_bps),
+                flash_loan_repayment,
+                status.slot,
+            )
+        })
+        .await
+    }
+
+    /// Swaps for exactly `exact_out` of `output_mint`, sizing the input automatically.
+    ///
+    /// The required input comes from an ExactOut quote and the swap fails without
+    /// sending anything if it exceeds `max_input`. Slippage applies to the input.
+    pub async fn swap_for_exact_output(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        exact_out: u64,
+        max_input: u64,
+        max_slippage_bps: u64,
+    ) -> anyhow::Result<Signature> {
+        let exact_out_quote = self
+            .quote_exact_out(input_mint, output_mint, exact_out)
+            .await?;
+        let (required_input, _) = exact_out_quote.in_and_out_amounts()?;
+        check_max_input(required_input, max_input)?;
+
+        let tx_builder = self
+            .prepare_swap_transaction(input_mint, output_mint, max_slippage_bps, &exact_out_quote)
+            .await?;
+        tx_builder.send_and_confirm(&self.mango_client.client).await
+    }
+
+    /// Like swap(), but submits the transaction as a Jito bundle for MEV protection.
+    ///
+    /// A transfer of `tip_lamports` to `tip_account`, which must be one of Jito's tip
+    /// accounts, is appended to the swap. The bundle goes to the block engine configured
+    /// in jito_block_engine_url. Landing is not awaited.
+    pub async fn swap_via_jito(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        max_slippage_bps: u64,
+        amount: u64,
+        tip_lamports: u64,
+        tip_account: Pubkey,
+    ) -> anyhow::Result<BundleId> {
+        let route = self.quote(input_mint, output_mint, amount).await?;
+
+        let mut tx_builder = self
+            .prepare_swap_transaction(input_mint, output_mint, max_slippage_bps, &route)
+            .await?;
+        tx_builder
+            .instructions
+            .push(solana_sdk::system_instruction::transfer(
+                &tx_builder.payer,
+                &tip_account,
+                tip_lamports,
+            ));
+
+        let client = &self.mango_client.client;
+        let tx = tx_builder.transaction(client.rpc_async()).await?;
+        jito::send_bundle(
+            &self.mango_client.http_client,
+            &client.config().jito_block_engine_url,
+            &[tx],
+            self.timeout_duration,
+        )
+        .await
+    }
+
+    /// Builds the swap for `quote` and simulates it instead of sending it.
+    ///
+    /// Shows whether FlashLoanEnd would pass its health check, the account's input and
+    /// output token positions afterwards and the consumed compute units.
+    pub async fn simulate_swap(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        max_slippage_bps: u64,
+        quote: &QuoteResponse,
+    ) -> anyhow::Result<SwapSimulation> {
+        let tx_builder = self
+            .prepare_swap_transaction(input_mint, output_mint, max_slippage_bps, quote)
+            .await?;
+        let rpc = self.mango_client.client.rpc_async();
+        let tx = tx_builder.transaction(&rpc).await?;
+        let account_address = self.mango_account_address();
+        let simulation = rpc
+            .simulate_transaction_with_config(
+                &tx,
+                RpcSimulateTransactionConfig {
+                    accounts: Some(RpcSimulateTransactionAccountsConfig {
+                        encoding: Some(UiAccountEncoding::Base64),
+                        addresses: vec![account_address.to_string()],
+                    }),
+                    ..Default::default()
+                },
+            )
+            .await
+            .context("simulating swap")?
+            .value;
+
+        let mut post_balances = vec![];
+        let post_account = simulation
+            .accounts
+            .as_ref()
+            .and_then(|accounts| accounts.first().cloned().flatten())
+            .and_then(|ui_account| ui_account.decode::<Account>());
+        if let (None, Some(post_account)) = (&simulation.err, post_account) {
+            let data = post_account
+                .data
+                .get(8..)
+                .context("simulated mango account is shorter than its discriminator")?;
+            let account = MangoAccountValue::from_bytes(data)
+                .context("deserializing simulated mango account")?;
+            for mint in [input_mint, output_mint] {
+                let token = self.mango_client.context.token_by_mint(&mint)?;
+                let native = match account.token_position(token.token_index) {
+                    Ok(position) => {
+                        let bank: Bank = account_fetcher_fetch_anchor_account(
+                            &*self.mango_client.account_fetcher,
+                            &token.first_bank(),
+                        )
+                        .await?;
+                        position.native(&bank)
+                    }
+                    Err(_) => I80F48::ZERO,
+                };
+                post_balances.push((token.token_index, native));
+            }
+        }
+
+        Ok(SwapSimulation {
+            health_check_passed: health_check_passed(simulation.err.as_ref()),
+            error: simulation.err,
+            logs: simulation.logs.unwrap_or_default(),
+            units_consumed: simulation.units_consumed,
+            post_balances,
+        })
+    }
+
+    /// Simulates a prepared swap and stores its compute unit consumption in the
+    /// route_cu_cache, if one is configured.
+    ///
+    /// Returns the consumed compute units of a successful simulation.
+    pub async fn simulate_and_cache_cu(
+        &self,
+        quote: &QuoteResponse,
+        tx_builder: &TransactionBuilder,
+    ) -> anyhow::Result<Option<u32>> {
+        let simulation = tx_builder.simulate(&self.mango_client.client).await?;
+        if simulation.value.err.is_some() {
+            return Ok(None);
+        }
+        let cu = match simulation.value.units_consumed {
+            Some(cu) => u32::try_from(cu)?,
+            None => return Ok(None),
+        };
+        if let Some(cache) = self.route_cu_cache {
+            cache.insert(&quote.swap_src, cu);
+        }
+        Ok(Some(cu))
+    }
+
+    /// Like swap(), but also returns the transaction builder that was sent, for archival.
+    ///
+    /// Errors from quoting or preparing the swap are returned directly, while the result
+    /// of sending and confirming comes alongside the transaction builder.
+    pub async fn swap_and_return_tx(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        max_slippage_bps: u64,
+        amount: u64,
+    ) -> anyhow::Result<(TransactionBuilder, anyhow::Result<Signature>)> {
         let route = self.quote(input_mint, output_mint, amount).await?;
 
         let tx_builder = self
             .prepare_swap_transaction(input_mint, output_mint, max_slippage_bps, &route)
             .await?;
 
-        tx_builder.send_and_confirm(&self.mango_client.client).await
+        let result = tx_builder.send_and_confirm(&self.mango_client.client).await;
+        Ok((tx_builder, result))
     }
 }
 
-pub async fn load_supported_token_mints(
-    live_rpc_client: &RpcClient,
-) -> anyhow::Result<HashSet<Pubkey>> {
-    let address = Pubkey::from_str("EhWxBHdmQ3yDmPzhJbKtGMM9oaZD42emt71kSieghy5")?;
+/// Estimated yield of swapping into an LST and holding it for `holding_period_days`.
+///
+/// The stake pool's recent staking yield minus the quote's swap fee, amortized
+/// over the holding period.
+pub fn net_apy(
+    stake_pool: &sanctum_state::StakePool,
+    quote: &QuoteResponse,
+    holding_period_days: f64,
+) -> anyhow::Result<f64> {
+    anyhow::ensure!(holding_period_days > 0.0, "holding period must be positive");
+    let staking_apy = stake_pool
+        .estimated_apy()
+        .ok_or_else(|| anyhow::anyhow!("stake pool has no supply to derive a yield from"))?;
+    let amortized_fee = quote.fee_fraction()? * 365.0 / holding_period_days;
+    Ok(staking_apy - amortized_fee)
+}
 
-    let lookup_table_data = live_rpc_client.get_account(&address).await?;
-    let lookup_table = AddressLookupTable::deserialize(&lookup_table_data.data())?;
-    let accounts: Vec<Account> =
-        fetch_multiple_accounts_in_chunks(live_rpc_client, &lookup_table.addresses, 100, 1)
-            .await?
-            .into_iter()
-            .map(|x| x.1)
-            .collect();
+/// Index of the bank that has used the smallest fraction of its deposit limit.
+/// Banks without a deposit limit count as empty, ties go to the smaller deposits.
+fn least_full_bank_index(banks: &[Bank]) -> usize {
+    let fill = |bank: &Bank| {
+        let deposits = bank.native_deposits();
+        let fraction = if bank.deposit_limit == 0 {
+            I80F48::ZERO
+        } else {
+            deposits / I80F48::from(bank.deposit_limit)
+        };
+        (fraction, deposits)
+    };
+    banks
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, bank)| fill(bank))
+        .map(|(i, _)| i)
+        .unwrap_or(0)
+}
+
+/// Ensures no instruction from the sanctum response moves `output_mint` tokens away from `owner`.
+///
+/// Checks associated token account creations for the output mint and token transfers
+/// out of or into accounts holding the output mint.
+fn check_output_not_redirected(
+    instructions: &[Instruction],
+    owner: &Pubkey,
+    output_mint: &Pubkey,
+) -> Result<(), SwapError> {
+    use anchor_spl::token::spl_token::instruction::TokenInstruction;
 
-    let mut lst_mints = HashSet::new();
-    for account in accounts {
-        let account = Account::from(account);
-        let mut account_data = account.data();
-        let t = sanctum_state::StakePool::deserialize(&mut account_data);
-        if let Ok(d) = t {
-            lst_mints.insert(d.pool_mint);
+    let owner_ata = anchor_spl::associated_token::get_associated_token_address(owner, output_mint);
+    let redirected = |instruction_index: usize, destination: Pubkey| SwapError::OutputRedirected {
+        instruction_index,
+        destination,
+        owner: *owner,
+    };
+    let account = |ix: &Instruction, i: usize| ix.accounts.get(i).map(|am| am.pubkey);
+
+    for (index, ix) in instructions.iter().enumerate() {
+        if ix.program_id == anchor_spl::associated_token::ID {
+            // Create and CreateIdempotent: payer, ata, wallet, mint, ...
+            let is_create = ix.data.is_empty() || ix.data[0] <= 1;
+            if let (true, Some(wallet), Some(mint)) = (is_create, account(ix, 2), account(ix, 3)) {
+                if mint == *output_mint && wallet != *owner {
+                    return Err(redirected(index, wallet));
+                }
+            }
+        } else if ix.program_id == anchor_spl::token::ID {
+            match TokenInstruction::unpack(&ix.data) {
+                // source, destination, authority
+                Ok(TokenInstruction::Transfer { .. }) => {
+                    if let (Some(source), Some(destination)) = (account(ix, 0), account(ix, 1)) {
+                        if source == owner_ata && destination != owner_ata {
+                            return Err(redirected(index, destination));
+                        }
+                    }
+                }
+                // source, mint, destination, authority
+                Ok(TokenInstruction::TransferChecked { .. }) => {
+                    if let (Some(mint), Some(destination)) = (account(ix, 1), account(ix, 2)) {
+                        if mint == *output_mint && destination != owner_ata {
+                            return Err(redirected(index, destination));
+                        }
+                    }
+                }
+                _ => {}
+            }
         }
     }
+    Ok(())
+}
 
-    // Hardcoded for now
-    lst_mints.insert(
-        Pubkey::from_str("CgntPoLka5pD5fesJYhGmUCF8KU1QS1ZmZiuAuMZr2az").expect("invalid lst mint"),
-    );
-    lst_mints.insert(
-        Pubkey::from_str("7ge2xKsZXmqPxa3YmXxXmzCp9Hc2ezrTxh6PECaxCwrL").expect("invalid lst mint"),
-    );
-    lst_mints.insert(
-        Pubkey::from_str("GUAMR8ciiaijraJeLDEDrFVaueLm9YzWWY9R7CBPL9rA").expect("invalid lst mint"),
-    );
-    lst_mints.insert(
-        Pubkey::from_str("Jito4APyf642JPZPx3hGc6WWJ8zPKtRbRs4P815Awbb").expect("invalid lst mint"),
-    );
-    lst_mints.insert(
-        Pubkey::from_str("CtMyWsrUtAwXWiGr9WjHT5fC3p3fgV8cyGpLTo2LJzG1").expect("invalid lst mint"),
-    );
-    lst_mints.insert(
-        Pubkey::from_str("2qyEeSAWKfU18AFthrF7JA8z8ZCi1yt76Tqs917vwQTV").expect("invalid lst mint"),
-    );
-    lst_mints.insert(
-        Pubkey::from_str("DqhH94PjkZsjAqEze2BEkWhFQJ6EyU6MdtMphMgnXqeK").expect("invalid lst mint"),
-    );
-    lst_mints.insert(
-        Pubkey::from_str("F8h46pYkaqPJNP2MRkUUUtRkf8efCkpoqehn9g1bTTm7").expect("invalid lst mint"),
-    );
-    lst_mints.insert(
-        Pubkey::from_str("5oc4nmbNTda9fx8Tw57ShLD132aqDK65vuHH4RU1K4LZ").expect("invalid lst mint"),
-    );
-    lst_mints.insert(
-        Pubkey::from_str("stk9ApL5HeVAwPLr3TLhDXdZS8ptVu7zp6ov8HFDuMi").expect("invalid lst mint"),
-    );
+/// Keeps at most `max` lookup tables, preferring those that resolve the most accounts
+/// used by `instructions`.
+///
+/// Errors if a dropped table is the only one that provides some account.
+fn limit_address_lookup_tables(
+    tables: Vec<AddressLookupTableAccount>,
+    instructions: &[Instruction],
+    max: usize,
+) -> anyhow::Result<Vec<AddressLookupTableAccount>> {
+    if tables.len() <= max {
+        return Ok(tables);
+    }
 
-    Ok(lst_mints)
+    // signers and invoked programs can't be loaded from lookup tables
+    let program_ids = instructions
+        .iter()
+        .map(|ix| ix.program_id)
+        .collect::<HashSet<_>>();
+    let referenced = instructions
+        .iter()
+        .flat_map(|ix| ix.accounts.iter())
+        .filter(|am| !am.is_signer && !program_ids.contains(&am.pubkey))
+        .map(|am| am.pubkey)
+        .collect::<HashSet<_>>();
+
+    // greedily keep the tables that resolve the most not yet resolved accounts,
+    // the first table wins ties so mango tables are preferred
+    let mut dropped = tables;
+    let mut tables = vec![];
+    let mut resolved = HashSet::new();
+    while tables.len() < max && !dropped.is_empty() {
+        let newly_resolved = |table: &AddressLookupTableAccount| {
+            table
+                .addresses
+                .iter()
+                .filter(|a| referenced.contains(*a) && !resolved.contains(*a))
+                .unique()
+                .count()
+        };
+        let best = (1..dropped.len()).fold(0, |best, i| {
+            if newly_resolved(&dropped[i]) > newly_resolved(&dropped[best]) {
+                i
+            } else {
+                best
+            }
+        });
+        let table = dropped.remove(best);
+        resolved.extend(table.addresses.iter().copied());
+        tables.push(table);
+    }
+
+    for table in dropped.iter() {
+        if let Some(address) = table
+            .addresses
+            .iter()
+            .find(|address| referenced.contains(*address) && !resolved.contains(*address))
+        {
+            anyhow::bail!(
+                "transaction needs more than {} address lookup tables: dropping table {} would leave account {} unresolved",
+                max,
+                table.key,
+                address,
+            );
+        }
+    }
+    tracing::warn!(
+        max,
+        dropped = ?dropped.iter().map(|table| table.key).collect::<Vec<_>>(),
+        "dropped address lookup tables that weren't needed"
+    );
+
+    Ok(tables)
+}
+
+/// Compute unit limit for a sanctum swap through mango.
+///
+/// A cached observation for the route gets a 10% margin. Otherwise sanctum's own limit
+/// is raised by the health check estimate, since it doesn't know about FlashLoanEnd.
+fn swap_cu_limit(cached: Option<u32>, sanctum_limit: Option<u32>, health_cu: u32) -> Option<u32> {
+    match cached {
+        Some(cu) => Some(cu.saturating_add(cu / 10)),
+        None => sanctum_limit.map(|limit| limit.saturating_add(health_cu)),
+    }
+}
+
+/// Cached compute units of a swap through `hops`: the sum of the cached costs of the
+/// hops' routes, if all of them are cached.
+///
+/// Each cached cost includes a flash loan and health check, so for several hops this
+/// overestimates.
+fn cached_route_cu(cache: &RouteCuCache, hops: &[SwapHop<'_>]) -> Option<u32> {
+    hops.iter().try_fold(0u32, |total, hop| {
+        cache
+            .get(&hop.quote.swap_src)
+            .map(|cu| total.saturating_add(cu))
+    })
+}
+
+/// Sum of sanctum's compute unit limits for the swaps of `layouts`, if each has one
+fn sanctum_cu_limit(layouts: &[SwapInstructionLayout]) -> Option<u32> {
+    layouts.iter().try_fold(0u32, |total, layout| {
+        compute_unit_limit(&layout.pre_setup).map(|limit| total.saturating_add(limit))
+    })
+}
+
+fn compute_unit_limit(instructions: &[Instruction]) -> Option<u32> {
+    instructions.iter().find_map(|ix| {
+        if ix.program_id != solana_sdk::compute_budget::ID {
+            return None;
+        }
+        match ComputeBudgetInstruction::try_from_slice(&ix.data) {
+            Ok(ComputeBudgetInstruction::SetComputeUnitLimit(limit)) => Some(limit),
+            _ => None,
+        }
+    })
+}
+
+fn strip_compute_budget_instructions(instructions: Vec<Instruction>) -> Vec<Instruction> {
+    instructions
+        .into_iter()
+        .filter(|ix| ix.program_id != solana_sdk::compute_budget::ID)
+        .collect()
+}
+
+/// Replaces the SetComputeUnitLimit instructions by a single one in place of the first,
+/// or adds one in front
+fn set_compute_unit_limit(instructions: &mut Vec<Instruction>, limit: u32) {
+    let new_ix = ComputeBudgetInstruction::set_compute_unit_limit(limit);
+    let is_limit = |ix: &Instruction| {
+        ix.program_id == solana_sdk::compute_budget::ID
+            && matches!(
+                ComputeBudgetInstruction::try_from_slice(&ix.data),
+                Ok(ComputeBudgetInstruction::SetComputeUnitLimit(_))
+            )
+    };
+    let index = instructions.iter().position(is_limit).unwrap_or(0);
+    instructions.retain(|ix| !is_limit(ix));
+    instructions.insert(index, new_ix);
+}
+
+/// Index of the first mango instruction with the same anchor discriminator as `ix_data`
+fn mango_instruction_position(instructions: &[Instruction], ix_data: &[u8]) -> Option<usize> {
+    let discriminator = &ix_data[..8];
+    instructions
+        .iter()
+        .position(|ix| ix.program_id == mango_v4::id() && ix.data.starts_with(discriminator))
+}
+
+fn flash_loan_begin_position(instructions: &[Instruction]) -> Option<usize> {
+    let data = anchor_lang::InstructionData::data(&mango_v4::instruction::FlashLoanBegin {
+        loan_amounts: vec![],
+    });
+    mango_instruction_position(instructions, &data)
+}
+
+fn flash_loan_end_position(instructions: &[Instruction]) -> Option<usize> {
+    let data = anchor_lang::InstructionData::data(&mango_v4::instruction::FlashLoanEndV2 {
+        num_loans: 0,
+        flash_loan_type: mango_v4::accounts_ix::FlashLoanType::Swap,
+    });
+    mango_instruction_position(instructions, &data)
+}
+
+/// Inserts `extra` directly after the mango FlashLoanEnd instruction
+fn insert_after_flash_loan_end(
+    instructions: &mut Vec<Instruction>,
+    extra: Vec<Instruction>,
+) -> anyhow::Result<()> {
+    let end_index = flash_loan_end_position(instructions)
+        .ok_or_else(|| anyhow::anyhow!("no FlashLoanEnd instruction"))?;
+    instructions.splice(end_index + 1..end_index + 1, extra);
+    Ok(())
+}
+
+/// Appends closing the owner's wSOL token account if one of `mints` is wSOL.
+///
+/// Leaves the account open if an instruction after FlashLoanEnd still uses it, like
+/// sanctum's own cleanup that already closes it.
+fn append_close_wsol_account(
+    instructions: &mut Vec<Instruction>,
+    owner: &Pubkey,
+    mints: [Pubkey; 2],
+) -> anyhow::Result<()> {
+    let wsol = anchor_spl::token::spl_token::native_mint::ID;
+    if !mints.contains(&wsol) {
+        return Ok(());
+    }
+    let wsol_account = anchor_spl::associated_token::get_associated_token_address(owner, &wsol);
+    let end_index = flash_loan_end_position(instructions)
+        .ok_or_else(|| anyhow::anyhow!("no FlashLoanEnd instruction"))?;
+    let used_later = instructions[end_index + 1..]
+        .iter()
+        .any(|ix| ix.accounts.iter().any(|am| am.pubkey == wsol_account));
+    if used_later {
+        return Ok(());
+    }
+    instructions.push(anchor_spl::token::spl_token::instruction::close_account(
+        &Token::id(),
+        &wsol_account,
+        owner,
+        owner,
+        &[],
+    )?);
+    Ok(())
+}
+
+/// Splits a prepared swap transaction into setup and the swap instructions wrapped by
+/// the flash loan, and lists the accounts it references.
+///
+/// Useful for logging the structure of swaps that fail on-chain.
+pub fn describe_prepared_swap(tx_builder: &TransactionBuilder) -> anyhow::Result<SwapLayout> {
+    let instructions = &tx_builder.instructions;
+    let flash_loan_begin = flash_loan_begin_position(instructions)
+        .ok_or_else(|| anyhow::anyhow!("no FlashLoanBegin instruction"))?;
+    let flash_loan_end = flash_loan_end_position(instructions)
+        .ok_or_else(|| anyhow::anyhow!("no FlashLoanEnd instruction"))?;
+    anyhow::ensure!(
+        flash_loan_begin < flash_loan_end,
+        "FlashLoanEnd at {flash_loan_end} comes before FlashLoanBegin at {flash_loan_begin}"
+    );
+
+    let mut accounts = vec![AccountMeta::new(tx_builder.payer, true)];
+    let mut add_account = |meta: AccountMeta| {
+        match accounts.iter_mut().find(|am| am.pubkey == meta.pubkey) {
+            Some(am) => {
+                am.is_writable |= meta.is_writable;
+                am.is_signer |= meta.is_signer;
+            }
+            None => accounts.push(meta),
+        };
+    };
+    for ix in instructions {
+        add_account(AccountMeta::new_readonly(ix.program_id, false));
+        for meta in ix.accounts.iter() {
+            add_account(meta.clone());
+        }
+    }
+
+    Ok(SwapLayout {
+        setup: (0..flash_loan_begin)
+            .chain(flash_loan_end + 1..instructions.len())
+            .collect(),
+        flash_loan_begin,
+        action: (flash_loan_begin + 1..flash_loan_end).collect(),
+        flash_loan_end,
+        address_lookup_table_count: tx_builder.address_lookup_tables.len(),
+        accounts,
+    })
+}
+
+/// Turns oversized transactions into an error that points at the missing mango ALTs
+fn check_size_without_mango_alts(tx_builder: &TransactionBuilder) -> anyhow::Result<()> {
+    let size = tx_builder.transaction_size()?;
+    if !size.is_within_limit() {
+        anyhow::bail!(
+            "swap transaction is too large ({} accounts, {} bytes; limit is {:?}) because the mango group has no address lookup tables configured",
+            size.accounts,
+            size.length,
+            TransactionSize::limit(),
+        );
+    }
+    Ok(())
+}
+
+fn check_transaction_size(tx_builder: &TransactionBuilder) -> anyhow::Result<()> {
+    let size = tx_builder.estimated_size()?;
+    let limit = solana_sdk::packet::PACKET_DATA_SIZE;
+    if size > limit {
+        return Err(SwapError::TransactionTooLarge { size, limit }.into());
+    }
+    Ok(())
+}
+
+fn check_account_owner(
+    address: &Pubkey,
+    account: &MangoAccountValue,
+    owner: &Pubkey,
+    group: &Pubkey,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        account.fixed.owner == *owner,
+        "mango account {address} is owned by {}, not {owner}",
+        account.fixed.owner
+    );
+    anyhow::ensure!(
+        account.fixed.group == *group,
+        "mango account {address} is in group {}, not {group}",
+        account.fixed.group
+    );
+    Ok(())
+}
+
+pub mod spl_memo {
+    use solana_sdk::declare_id;
+    declare_id!("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr");
+}
+
+fn memo_instruction(memo: &str) -> Instruction {
+    Instruction {
+        program_id: spl_memo::id(),
+        accounts: vec![],
+        data: memo.as_bytes().to_vec(),
+    }
+}
+
+/// Finds the first successful transaction whose memo is exactly `memo`.
+///
+/// The rpc reports memos as "[len] memo", joined with "; " if there are several.
+fn find_signature_with_memo(
+    statuses: &[RpcConfirmedTransactionStatusWithSignature],
+    memo: &str,
+) -> anyhow::Result<Option<Signature>> {
+    let expected = format!("[{}] {}", memo.len(), memo);
+    let found = statuses.iter().find(|status| {
+        status.err.is_none()
+            && status
+                .memo
+                .as_ref()
+                .map(|m| m.split("; ").any(|part| part == expected))
+                .unwrap_or(false)
+    });
+    found
+        .map(|status| Signature::from_str(&status.signature))
+        .transpose()
+        .context("parsing signature")
+}
+
+/// Pages back through the statuses returned by `fetch_page(before)`, newest first, until
+/// a successful transaction with `memo` is found or the statuses are older than `min_slot`.
+async fn find_signature_with_memo_since<F, Fut>(
+    mut fetch_page: F,
+    memo: &str,
+    min_slot: u64,
+) -> anyhow::Result<Option<Signature>>
+where
+    F: FnMut(Option<Signature>) -> Fut,
+    Fut: std::future::Future<
+        Output = anyhow::Result<Vec<RpcConfirmedTransactionStatusWithSignature>>,
+    >,
+{
+    let mut before = None;
+    loop {
+        let statuses = fetch_page(before).await?;
+        let in_window = statuses
+            .iter()
+            .position(|status| status.slot < min_slot)
+            .unwrap_or(statuses.len());
+        if let Some(signature) = find_signature_with_memo(&statuses[..in_window], memo)? {
+            return Ok(Some(signature));
+        }
+        if in_window < statuses.len() {
+            return Ok(None);
+        }
+        let Some(last) = statuses.last() else {
+            return Ok(None);
+        };
+        before = Some(Signature::from_str(&last.signature).context("parsing signature")?);
+    }
+}
+
+/// Converts a native amount between tokens given their oracle prices, rounding up.
+fn convert_native_amount(amount: u64, price: I80F48, target_price: I80F48) -> anyhow::Result<u64> {
+    anyhow::ensure!(target_price > 0, "target oracle price must be positive");
+    let converted = (I80F48::from(amount) * price / target_price).ceil();
+    converted
+        .checked_to_num::<u64>()
+        .ok_or_else(|| anyhow::anyhow!("converted amount {converted} does not fit into u64"))
+}
+
+/// Deviation of the quote's effective rate from the oracle rate in bps, positive if worse.
+///
+/// Oracle prices are per native token, so the ui rate needs the decimal difference.
+fn price_impact_bps(
+    quote: &QuoteResponse,
+    in_decimals: u8,
+    out_decimals: u8,
+    input_price: I80F48,
+    output_price: I80F48,
+) -> anyhow::Result<f64> {
+    anyhow::ensure!(output_price > 0, "output oracle price must be positive");
+    let effective_rate = quote.effective_rate(in_decimals, out_decimals)?;
+    let oracle_rate = (input_price / output_price).to_num::<f64>()
+        * 10f64.powi(in_decimals as i32 - out_decimals as i32);
+    anyhow::ensure!(oracle_rate > 0.0, "oracle rate must be positive");
+    Ok((1.0 - effective_rate / oracle_rate) * 10_000.0)
+}
+
+/// Output left after paying `origination_fee` native input tokens, saturating at zero.
+fn net_output(
+    out_amount: u64,
+    origination_fee: u64,
+    input_price: I80F48,
+    output_price: I80F48,
+) -> anyhow::Result<u64> {
+    let fee_in_output = convert_native_amount(origination_fee, input_price, output_price)?;
+    Ok(out_amount.saturating_sub(fee_in_output))
+}
+
+/// The sanctum api url: `base_url_override` if set, otherwise the client config's
+/// sanctum_url
+fn base_url<'b>(base_url_override: Option<&'b str>, config_url: &'b str) -> &'b str {
+    base_url_override.unwrap_or(config_url)
+}
+
+async fn fetch_quote(
+    http_client: &reqwest::Client,
+    policy: &RetryPolicy,
+    timeout: Duration,
+    sanctum_url: &str,
+    query_args: &[(&str, String)],
+) -> anyhow::Result<QuoteResponse> {
+    let url = format!("{}/swap/quote", sanctum_url);
+    let response = send_with_retry(policy, timeout, "quote request to sanctum", || {
+        http_client.get(&url).query(query_args)
+    })
+    .await?;
+    util::http_error_handling(response).await
+}
+
+/// Sends the request built by `request` until a response isn't a server error.
+///
+/// Network errors and timeouts are retried as well, client errors are not. All
+/// attempts and delays together take at most `timeout`.
+async fn send_with_retry(
+    policy: &RetryPolicy,
+    timeout: Duration,
+    what: &str,
+    request: impl Fn() -> reqwest::RequestBuilder,
+) -> anyhow::Result<reqwest::Response> {
+    let deadline = Instant::now() + timeout;
+    let mut attempt = 1;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let result = request().timeout(remaining).send().await;
+        let retryable = match &result {
+            Ok(response) => response.status().is_server_error(),
+            Err(err) => err.is_timeout() || err.is_connect() || err.is_request(),
+        };
+        let delay = policy.delay(attempt);
+        if !retryable || attempt >= policy.max_attempts || Instant::now() + delay >= deadline {
+            return result.context(what.to_string());
+        }
+
+        match &result {
+            Ok(response) => tracing::warn!(
+                attempt,
+                status = %response.status(),
+                "{what} failed, retrying"
+            ),
+            Err(err) => tracing::warn!(attempt, "{what} failed: {err}, retrying"),
+        }
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// SlippageToleranceExceeded error codes of the programs that check a swap's slippage
+const SLIPPAGE_ERROR_CODES: &[(&str, u32)] = &[
+    // router
+    ("stkitrT1Uoy18Dk1fTrgPw8W6MVzoCfYoAFT4MLsmhq", 0),
+    // jupiter v6
+    ("JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4", 6001),
+];
+
+/// Whether a swap failed on the swap program's slippage check.
+///
+/// Looks at the custom error code of the failed simulation and, since codes are
+/// per program, at the log line of the program that returned it.
+fn is_slippage_error(err: &anyhow::Error) -> bool {
+    use solana_sdk::instruction::InstructionError;
+
+    let Some(crate::MangoClientError::SendTransactionPreflightFailure {
+        err: Some(TransactionError::InstructionError(_, InstructionError::Custom(code))),
+        logs,
+    }) = err
+        .chain()
+        .find_map(|e| e.downcast_ref::<crate::MangoClientError>())
+    else {
+        return false;
+    };
+    let failure = format!(" failed: custom program error: {code:#x}");
+    SLIPPAGE_ERROR_CODES
+        .iter()
+        .filter(|(_, slippage_code)| slippage_code == code)
+        .any(|(program, _)| {
+            let failed_line = format!("Program {program}{failure}");
+            logs.iter().any(|line| *line == failed_line)
+        })
+}
+
+/// Runs `attempt`, and a second time if it failed on slippage and `retry_on_slippage`
+/// is set. Never more than twice.
+async fn retry_once_on_slippage<T, Fut>(
+    retry_on_slippage: bool,
+    attempt: impl Fn() -> Fut,
+) -> anyhow::Result<T>
+where
+    Fut: std::future::Future<Output = anyhow::Result<T>>,
+{
+    match attempt().await {
+        Err(err) if retry_on_slippage && is_slippage_error(&err) => {
+            tracing::info!("swap failed on slippage, retrying with a fresh quote: {err:#}");
+            attempt().await
+        }
+        result => result,
+    }
+}
+
+/// Least output an ExactIn swap quoted at `out_amount` may produce with the slippage.
+///
+/// Rounds down, so the floor never asks for more than the slippage allows. Computed
+/// in integers, f64 can't represent large amounts exactly.
+fn min_out_amount(out_amount: u64, max_slippage_bps: u64) -> u64 {
+    let kept_bps = 10_000u64.saturating_sub(max_slippage_bps);
+    (out_amount as u128 * kept_bps as u128 / 10_000) as u64
+}
+
+/// Most input an ExactOut swap quoted at `in_amount` may use with the slippage.
+///
+/// Rounds up, so the cap never allows less than the slippage does. Computed in
+/// integers like min_out_amount(), saturating at u64::MAX.
+fn max_in_amount(in_amount: u64, max_slippage_bps: u64) -> u64 {
+    let scaled_bps = 10_000u128 + max_slippage_bps as u128;
+    let max_in = (in_amount as u128 * scaled_bps + 9_999) / 10_000;
+    max_in.min(u64::MAX as u128) as u64
+}
+
+/// Amounts of a swap after applying slippage to the quote
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct SwapAmounts {
+    /// Most input the swap can use
+    max_in: u64,
+    /// Least output the swap must produce
+    min_out: u64,
+    /// The swap request's amount: the input for ExactIn, the output for ExactOut
+    request_amount: u64,
+    /// The swap request's slippage bound: min_out for ExactIn, max_in for ExactOut
+    quoted_amount: u64,
+}
+
+impl SwapAmounts {
+    fn new(mode: SwapMode, in_amount: u64, out_amount: u64, max_slippage_bps: u64) -> Self {
+        match mode {
+            SwapMode::ExactIn => {
+                let min_out = min_out_amount(out_amount, max_slippage_bps);
+                Self {
+                    max_in: in_amount,
+                    min_out,
+                    request_amount: in_amount,
+                    quoted_amount: min_out,
+                }
+            }
+            SwapMode::ExactOut => {
+                let max_in = max_in_amount(in_amount, max_slippage_bps);
+                Self {
+                    max_in,
+                    min_out: out_amount,
+                    request_amount: out_amount,
+                    quoted_amount: max_in,
+                }
+            }
+        }
+    }
+
+    fn with_limit(
+        mode: SwapMode,
+        in_amount: u64,
+        out_amount: u64,
+        limit: SlippageLimit,
+    ) -> anyhow::Result<Self> {
+        match limit {
+            SlippageLimit::Bps(bps) => Ok(Self::new(mode, in_amount, out_amount, bps)),
+            SlippageLimit::MinOut(min_out) => {
+                anyhow::ensure!(
+                    mode == SwapMode::ExactIn,
+                    "an absolute minimum output needs an ExactIn quote"
+                );
+                anyhow::ensure!(
+                    min_out <= out_amount,
+                    "minimum output {min_out} exceeds the quoted output {out_amount}"
+                );
+                Ok(Self {
+                    max_in: in_amount,
+                    min_out,
+                    request_amount: in_amount,
+                    quoted_amount: min_out,
+                })
+            }
+        }
+    }
+}
+
+/// `amount` increased by `buffer_bps`, rounding up
+fn buffered_loan_amount(amount: u64, buffer_bps: u64) -> u64 {
+    let buffer = (amount as u128 * buffer_bps as u128 + 9_999) / 10_000;
+    amount.saturating_add(buffer.try_into().unwrap_or(u64::MAX))
+}
+
+/// Whether a transaction with `err` passed the health check, None if it failed in a
+/// way that doesn't tell
+fn health_check_passed(err: Option<&TransactionError>) -> Option<bool> {
+    use solana_sdk::instruction::InstructionError;
+
+    let health_errors: [u32; 2] = [
+        MangoError::HealthMustBePositive.into(),
+        MangoError::HealthMustBePositiveOrIncrease.into(),
+    ];
+    match err {
+        None => Some(true),
+        Some(TransactionError::InstructionError(_, InstructionError::Custom(code)))
+            if health_errors.contains(code) =>
+        {
+            Some(false)
+        }
+        Some(_) => None,
+    }
+}
+
+fn check_fee_pct(quote: &QuoteResponse, max_fee_pct: f64) -> anyhow::Result<()> {
+    let fee_pct = quote.fee_pct_f64()?;
+    if fee_pct > max_fee_pct {
+        return Err(SwapError::FeeTooHigh {
+            fee_pct,
+            max_fee_pct,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+fn check_oracle_slot_age(
+    token_index: TokenIndex,
+    slot_age: u64,
+    max_slot_age: u64,
+) -> anyhow::Result<()> {
+    if slot_age > max_slot_age {
+        return Err(SwapError::StaleOracle {
+            token_index,
+            slot_age,
+            max_slot_age,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+fn check_max_input(required_input: u64, max_input: u64) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        required_input <= max_input,
+        "swap needs an input of {required_input}, more than the maximum of {max_input}"
+    );
+    Ok(())
+}
+
+/// The token info with prices moved to the adverse edge of the oracle confidence band.
+///
+/// Init health values assets at min(oracle, stable) and liabilities at max(oracle, stable),
+/// so widening the two prices by `deviation` makes both sides worse.
+fn with_adverse_prices(info: &TokenInfo, deviation: I80F48) -> TokenInfo {
+    let Prices { oracle, stable } = info.prices;
+    let mut info = info.clone();
+    info.prices = Prices {
+        oracle: (oracle - deviation).max(I80F48::ZERO).min(stable),
+        stable: (oracle + deviation).max(stable),
+    };
+    info
+}
+
+/// Init health before and after applying the balance changes, with the affected tokens
+/// priced at the adverse edge of their oracle confidence band.
+///
+/// `spot_health` is the account's current init health, `token_changes` holds the
+/// token's health info, its oracle deviation and the native balance change.
+fn conservative_health_before_and_after(
+    spot_health: I80F48,
+    token_changes: &[(TokenInfo, I80F48, I80F48)],
+) -> (I80F48, I80F48) {
+    let health_type = HealthType::Init;
+    let mut before = spot_health;
+    let mut after = spot_health;
+    for (info, deviation, change) in token_changes {
+        let adverse = with_adverse_prices(info, *deviation);
+        let balance = info.balance_spot;
+        let spot_contribution = info.health_contribution(health_type, balance);
+        before += adverse.health_contribution(health_type, balance) - spot_contribution;
+        after += adverse.health_contribution(health_type, balance + change) - spot_contribution;
+    }
+    (before, after)
+}
+
+/// The slippage to use for a swap: `max_slippage_bps`, raised to the floor if given
+fn effective_slippage_bps(max_slippage_bps: u64, min_effective_slippage_bps: Option<u64>) -> u64 {
+    match min_effective_slippage_bps {
+        Some(floor) if max_slippage_bps < floor => {
+            tracing::info!(
+                max_slippage_bps,
+                floor,
+                "raising swap slippage to the configured minimum"
+            );
+            floor
+        }
+        _ => max_slippage_bps,
+    }
+}
+
+/// One sanctum swap of a flash loan swap transaction, see build_flash_loan_swap()
+struct SwapHop<'q> {
+    input_mint: Pubkey,
+    output_mint: Pubkey,
+    amounts: SwapAmounts,
+    quote: &'q QuoteResponse,
+}
+
+/// Sanctum's swap instructions, split into the parts that go before, inside and after
+/// the FlashLoanBegin/End pair
+#[derive(Clone, Debug, PartialEq)]
+struct SwapInstructionLayout {
+    /// Setup before the first swap instruction, placed before FlashLoanBegin
+    pre_setup: Vec<Instruction>,
+    /// From the first to the last swap instruction, wrapped by the flash loan
+    action: Vec<Instruction>,
+    /// Cleanup after the last swap instruction, placed after FlashLoanEnd
+    post_setup: Vec<Instruction>,
+}
+
+impl SwapInstructionLayout {
+    /// Setup instructions (ATA, token and compute budget program) must be placed
+    /// outside of the flash loan
+    fn is_setup_instruction(ix: &Instruction) -> bool {
+        ix.program_id == anchor_spl::associated_token::ID
+            || ix.program_id == anchor_spl::token::ID
+            || ix.program_id == solana_sdk::compute_budget::ID
+    }
+
+    fn new(mut instructions: Vec<Instruction>) -> anyhow::Result<Self> {
+        let action_begin = instructions
+            .iter()
+            .position(|ix| !Self::is_setup_instruction(ix))
+            .ok_or_else(|| {
+                anyhow::anyhow!("sanctum swap response only had setup-like instructions")
+            })?;
+        let action_end = instructions
+            .iter()
+            .rposition(|ix| !Self::is_setup_instruction(ix))
+            .unwrap()
+            + 1;
+
+        let post_setup = instructions.split_off(action_end);
+        let action = instructions.split_off(action_begin);
+        Ok(Self {
+            pre_setup: instructions,
+            action,
+            post_setup,
+        })
+    }
+
+    /// Runs `next` after this swap inside the same flash loan, with the setup of both
+    /// before it and the cleanup of both after it
+    fn then(mut self, next: Self) -> Self {
+        self.pre_setup.extend(next.pre_setup);
+        self.action.extend(next.action);
+        self.post_setup.extend(next.post_setup);
+        self
+    }
+}
+
+/// Ensures the sanctum response doesn't call into mango and that the swap instructions
+/// wrapped by the flash loan only invoke `allowed_programs`.
+///
+/// Token program instructions wrapped by the flash loan hold the borrowed input, so they
+/// are checked as well, see check_action_token_instruction().
+///
+/// Instruction indexes in errors count from the start of the response.
+fn check_swap_programs(
+    layout: &SwapInstructionLayout,
+    allowed_programs: &[Pubkey],
+    owner: &Pubkey,
+    owner_token_accounts: &[Pubkey],
+) -> Result<(), SwapError> {
+    let unexpected = |instruction_index: usize, program_id: Pubkey| SwapError::UnexpectedProgram {
+        instruction_index,
+        program_id,
+    };
+    let action_begin = layout.pre_setup.len();
+    let action_end = action_begin + layout.action.len();
+    let instructions = layout
+        .pre_setup
+        .iter()
+        .chain(layout.action.iter())
+        .chain(layout.post_setup.iter());
+    for (index, ix) in instructions.enumerate() {
+        if ix.program_id == mango_v4::id() {
+            return Err(unexpected(index, ix.program_id));
+        }
+        let in_action = (action_begin..action_end).contains(&index);
+        if !in_action {
+            continue;
+        }
+        if ix.program_id == anchor_spl::token::ID {
+            check_action_token_instruction(index, ix, owner, owner_token_accounts)?;
+        } else if !SwapInstructionLayout::is_setup_instruction(ix)
+            && !allowed_programs.contains(&ix.program_id)
+        {
+            return Err(unexpected(index, ix.program_id));
+        }
+    }
+    Ok(())
+}
+
+/// Token program instructions between the swap instructions may only move tokens into
+/// `owner_token_accounts` and lamports to `owner`. Everything else, like approvals or
+/// authority changes, is rejected.
+fn check_action_token_instruction(
+    index: usize,
+    ix: &Instruction,
+    owner: &Pubkey,
+    owner_token_accounts: &[Pubkey],
+) -> Result<(), SwapError> {
+    use anchor_spl::token::spl_token::instruction::TokenInstruction;
+
+    let unexpected = || SwapError::UnexpectedProgram {
+        instruction_index: index,
+        program_id: ix.program_id,
+    };
+    let (destination, allowed) = match TokenInstruction::unpack(&ix.data) {
+        Ok(TokenInstruction::SyncNative) => return Ok(()),
+        // source, destination, authority
+        Ok(TokenInstruction::Transfer { .. }) => (ix.accounts.get(1), owner_token_accounts),
+        // source, mint, destination, authority
+        Ok(TokenInstruction::TransferChecked { .. }) => (ix.accounts.get(2), owner_token_accounts),
+        // account, destination, authority
+        Ok(TokenInstruction::CloseAccount) => (ix.accounts.get(1), std::slice::from_ref(owner)),
+        _ => return Err(unexpected()),
+    };
+    let destination = destination.ok_or_else(unexpected)?.pubkey;
+    if !allowed.contains(&destination) {
+        return Err(SwapError::TokensRedirected {
+            instruction_index: index,
+            destination,
+        });
+    }
+    Ok(())
+}
+
+/// Idempotent creation of `owner`'s associated token accounts for `mints`, given as
+/// (mint, token program) pairs, paid by `payer`
+fn ata_creation_instructions(
+    payer: &Pubkey,
+    owner: &Pubkey,
+    mints: &[(Pubkey, Pubkey)],
+) -> Vec<Instruction> {
+    mints
+        .iter()
+        .map(|(mint, token_program)| {
+            spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+                payer,
+                owner,
+                mint,
+                token_program,
+            )
+        })
+        .collect()
+}
+
+/// Drops redundant setup instructions that sanctum responses sometimes contain.
+///
+/// Keeps the first compute unit limit and the first compute unit price instruction,
+/// and one associated token account creation per token account. Other instructions
+/// are kept as they are.
+fn dedupe_setup_instructions(setup_ixs: Vec<Instruction>) -> Vec<Instruction> {
+    let mut has_cu_limit = false;
+    let mut has_cu_price = false;
+    let mut created_atas = HashSet::new();
+    setup_ixs
+        .into_iter()
+        .filter(|ix| {
+            if ix.program_id == solana_sdk::compute_budget::ID {
+                let seen = match ComputeBudgetInstruction::try_from_slice(&ix.data) {
+                    Ok(ComputeBudgetInstruction::SetComputeUnitLimit(_)) => &mut has_cu_limit,
+                    Ok(ComputeBudgetInstruction::SetComputeUnitPrice(_)) => &mut has_cu_price,
+                    _ => return true,
+                };
+                return !std::mem::replace(seen, true);
+            }
+            if ix.program_id == anchor_spl::associated_token::ID {
+                // create and create_idempotent both have the token account at index 1
+                return match ix.accounts.get(1) {
+                    Some(ata) => created_atas.insert(ata.pubkey),
+                    None => true,
+                };
+            }
+            true
+        })
+        .collect()
+}
+
+/// The best `n` successful quotes with distinct swap sources, by out amount
+fn top_quotes(quotes: Vec<anyhow::Result<QuoteResponse>>, n: usize) -> Vec<QuoteResponse> {
+    quotes
+        .into_iter()
+        .filter_map(|quote| match quote {
+            Ok(quote) => Some(quote),
+            Err(e) => {
+                tracing::debug!("skipping sanctum source: {e:?}");
+                None
+            }
+        })
+        .filter_map(|quote| {
+            let out_amount = quote.out_amount.parse::<u64>().ok()?;
+            Some((out_amount, quote))
+        })
+        .sorted_by_key(|(out_amount, _)| std::cmp::Reverse(*out_amount))
+        .unique_by(|(_, quote)| quote.swap_src.clone())
+        .take(n)
+        .map(|(_, quote)| quote)
+        .collect()
+}
+
+/// Splits `amount` credited to a token position with balance `native` into the part
+/// that repays the borrow and the part that ends up deposited.
+///
+/// Fractional borrows are repaid in full, rounding the repaid part up.
+fn split_repay_and_deposit(native: I80F48, amount: u64) -> (u64, u64) {
+    let borrow = (-native).max(I80F48::ZERO).ceil();
+    let repaid = borrow
+        .checked_to_num::<u64>()
+        .unwrap_or(u64::MAX)
+        .min(amount);
+    (repaid, amount - repaid)
+}
+
+pub mod spl_stake_pool {
+    use solana_sdk::declare_id;
+    declare_id!("SPoo1Ku8WFXoNDMHPsrGSTSG1Y47rzgn41SLUNakuHy");
+}
+
+/// Payer and signers of a swap transaction: the owner always signs, the fee payer
+/// additionally if it is a different keypair
+fn swap_payer_and_signers(
+    owner: &Arc<Keypair>,
+    fee_payer: Option<&Arc<Keypair>>,
+) -> (Pubkey, Vec<Arc<Keypair>>) {
+    let mut signers = vec![owner.clone()];
+    match fee_payer {
+        Some(fee_payer) if fee_payer.pubkey() != owner.pubkey() => {
+            signers.push(fee_payer.clone());
+            (fee_payer.pubkey(), signers)
+        }
+        _ => (owner.pubkey(), signers),
+    }
+}
+
+/// The stake pools among `datas`, other accounts are skipped
+fn stake_pools<'b>(
+    datas: impl Iterator<Item = &'b [u8]>,
+) -> impl Iterator<Item = sanctum_state::StakePool> {
+    datas
+        .filter_map(|mut data| sanctum_state::StakePool::deserialize(&mut data).ok())
+        .filter(|pool| pool.account_type == sanctum_state::AccountType::StakePool)
+}
+
+/// Pool mints of all account datas that decode as a stake pool
+fn lst_mints_from_stake_pools<'b>(datas: impl Iterator<Item = &'b [u8]>) -> HashSet<Pubkey> {
+    stake_pools(datas).map(|pool| pool.pool_mint).collect()
+}
+
+/// SOL per LST of each stake pool's mint, pools without supply are skipped
+fn lst_exchange_rates_from_stake_pools<'b>(
+    datas: impl Iterator<Item = &'b [u8]>,
+) -> HashMap<Pubkey, f64> {
+    stake_pools(datas)
+        .filter_map(|pool| Some((pool.pool_mint, pool.exchange_rate()?)))
+        .collect()
+}
+
+/// Finds LST mints by scanning the accounts of the SPL stake pool program.
+///
+/// Unlike load_supported_token_mints() this doesn't depend on Sanctum's lookup table,
+/// but it only sees LSTs that are SPL stake pools. Callers can union both results.
+pub async fn discover_lst_mints_via_program_scan(
+    rpc: &RpcClient,
+) -> anyhow::Result<HashSet<Pubkey>> {
+    let stake_pool_account_type = vec![sanctum_state::AccountType::StakePool as u8];
+    let config = RpcProgramAccountsConfig {
+        filters: Some(vec![RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+            0,
+            stake_pool_account_type,
+        ))]),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            ..RpcAccountInfoConfig::default()
+        },
+        ..RpcProgramAccountsConfig::default()
+    };
+    let accounts = rpc
+        .get_program_accounts_with_config(&spl_stake_pool::id(), config)
+        .await
+        .context("scanning stake pool program accounts")?;
+    Ok(lst_mints_from_stake_pools(
+        accounts.iter().map(|(_, a)| a.data.as_slice()),
+    ))
+}
+
+/// All accounts referenced by sanctum's stake pool lookup table
+async fn fetch_stake_pool_lookup_table_accounts(
+    live_rpc_client: &RpcClient,
+) -> anyhow::Result<Vec<Account>> {
+    let address = Pubkey::from_str("EhWxBHdmQ3yDmPzhJbKtGMM9oaZD42emt71kSieghy5")?;
+
+    let lookup_table_data = live_rpc_client.get_account(&address).await?;
+    let lookup_table = AddressLookupTable::deserialize(&lookup_table_data.data())?;
+    Ok(
+        fetch_multiple_accounts_in_chunks(live_rpc_client, &lookup_table.addresses, 100, 4)
+            .await?
+            .into_iter()
+            .map(|x| x.1)
+            .collect(),
+    )
+}
+
+/// SOL per token for each LST in sanctum's stake pool lookup table, from the pools'
+/// total_lamports and pool_token_supply.
+///
+/// Pools only update these once per epoch.
+pub async fn load_lst_exchange_rates(
+    live_rpc_client: &RpcClient,
+) -> anyhow::Result<HashMap<Pubkey, f64>> {
+    let accounts = fetch_stake_pool_lookup_table_accounts(live_rpc_client).await?;
+    Ok(lst_exchange_rates_from_stake_pools(
+        accounts.iter().map(|a| a.data()),
+    ))
+}
+
+/// LST mints sanctum supports that aren't in its stake pool lookup table
+pub const SANCTUM_EXTRA_LST_MINTS: &[&str] = &[
+    "CgntPoLka5pD5fesJYhGmUCF8KU1QS1ZmZiuAuMZr2az",
+    "7ge2xKsZXmqPxa3YmXxXmzCp9Hc2ezrTxh6PECaxCwrL",
+    "GUAMR8ciiaijraJeLDEDrFVaueLm9YzWWY9R7CBPL9rA",
+    "Jito4APyf642JPZPx3hGc6WWJ8zPKtRbRs4P815Awbb",
+    "CtMyWsrUtAwXWiGr9WjHT5fC3p3fgV8cyGpLTo2LJzG1",
+    "2qyEeSAWKfU18AFthrF7JA8z8ZCi1yt76Tqs917vwQTV",
+    "DqhH94PjkZsjAqEze2BEkWhFQJ6EyU6MdtMphMgnXqeK",
+    "F8h46pYkaqPJNP2MRkUUUtRkf8efCkpoqehn9g1bTTm7",
+    "5oc4nmbNTda9fx8Tw57ShLD132aqDK65vuHH4RU1K4LZ",
+    "stk9ApL5HeVAwPLr3TLhDXdZS8ptVu7zp6ov8HFDuMi",
+];
+
+/// Programs sanctum swaps go through: its router, its own pools and the LST stake pools
+pub const SANCTUM_SWAP_PROGRAMS: &[&str] = &[
+    // router
+    "stkitrT1Uoy18Dk1fTrgPw8W6MVzoCfYoAFT4MLsmhq",
+    // infinity
+    "5ocnV1qiCgaQR8Jb8xWnVbApfaygJ8tNoZfgPwsgx9kx",
+    // unstake
+    "unpXTU2Ndrc7WWNyEhQWe4udTzSibLPi25SXv2xbCHQ",
+    // spl stake pool and sanctum's deployments of it
+    "SPoo1Ku8WFXoNDMHPsrGSTSG1Y47rzgn41SLUNakuHy",
+    "SP12tWFxD9oJsVWNavTTBZvMbA6gkAmxtVgxdqvyvhY",
+    "SPMBzsVUuoHA4Jm6KunbsotaahvVikZs1JyTW6iJvbn",
+    // marinade
+    "MarBmsSgKXdrN1egZf5sqe1TMai9K1rChYNDJgjq7aD",
+    // lido
+    "CrX7kMhLC3cSsXJdT7JDgqrRVWGnUpX3gfEfxxU2NVLi",
+    // native stake program
+    "Stake11111111111111111111111111111111111111",
+    // jupiter v6, for routes with the "Jup" swap source
+    "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4",
+];
+
+fn sanctum_swap_programs() -> anyhow::Result<Vec<Pubkey>> {
+    SANCTUM_SWAP_PROGRAMS
+        .iter()
+        .map(|program| {
+            Pubkey::from_str(program).with_context(|| format!("invalid swap program {program}"))
+        })
+        .collect()
+}
+
+fn sanctum_extra_lst_mints() -> anyhow::Result<Vec<Pubkey>> {
+    SANCTUM_EXTRA_LST_MINTS
+        .iter()
+        .map(|mint| Pubkey::from_str(mint).with_context(|| format!("invalid lst mint {mint}")))
+        .collect()
+}
+
+/// Mints of the LSTs in sanctum's stake pool lookup table, SANCTUM_EXTRA_LST_MINTS and
+/// `extra_mints`.
+///
+/// `extra_mints` allows adding LSTs sanctum started supporting since this was built.
+pub async fn load_supported_token_mints(
+    live_rpc_client: &RpcClient,
+    extra_mints: &[Pubkey],
+) -> anyhow::Result<HashSet<Pubkey>> {
+    let accounts = fetch_stake_pool_lookup_table_accounts(live_rpc_client).await?;
+    let mut lst_mints = lst_mints_from_stake_pools(accounts.iter().map(|a| a.data()));
+    lst_mints.extend(sanctum_extra_lst_mints()?);
+    lst_mints.extend(extra_mints.iter().copied());
+
+    Ok(lst_mints)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::swap::flash_loan_begin_instruction;
+
+    fn status(
+        signature: Signature,
+        memo: Option<&str>,
+        failed: bool,
+    ) -> RpcConfirmedTransactionStatusWithSignature {
+        RpcConfirmedTransactionStatusWithSignature {
+            signature: signature.to_string(),
+            slot: 1,
+            err: failed.then_some(solana_sdk::transaction::TransactionError::AccountInUse),
+            memo: memo.map(|m| m.to_string()),
+            block_time: None,
+            confirmation_status: None,
+        }
+    }
+
+    #[test]
+    fn test_find_signature_with_memo() {
+        let key = "rebalance-42";
+        let first_send = Signature::new_unique();
+        let failed_send = Signature::new_unique();
+
+        // nothing was sent yet
+        let mut statuses = vec![status(Signature::new_unique(), None, false)];
+        assert_eq!(find_signature_with_memo(&statuses, key).unwrap(), None);
+
+        // a failed attempt doesn't count, and neither do prefixes of the key
+        statuses.push(status(failed_send, Some("[12] rebalance-42"), true));
+        statuses.push(status(
+            Signature::new_unique(),
+            Some("[11] rebalance-4"),
+            false,
+        ));
+        assert_eq!(find_signature_with_memo(&statuses, key).unwrap(), None);
+
+        // the resubmit finds the original swap, even among several memos
+        statuses.push(status(
+            first_send,
+            Some("[3] foo; [12] rebalance-42"),
+            false,
+        ));
+        assert_eq!(
+            find_signature_with_memo(&statuses, key).unwrap(),
+            Some(first_send)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_find_signature_with_memo_since() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let key = "rebalance-42";
+        let original = Signature::new_unique();
+        let status_at = |signature: Signature, slot: u64, memo: Option<&str>| {
+            RpcConfirmedTransactionStatusWithSignature {
+                slot,
+                ..status(signature, memo, false)
+            }
+        };
+        // three pages, newest first, the swap is on the second one
+        let pages = vec![
+            vec![
+                status_at(Signature::new_unique(), 300, None),
+                status_at(Signature::new_unique(), 290, None),
+            ],
+            vec![
+                status_at(Signature::new_unique(), 280, None),
+                status_at(original, 270, Some("[12] rebalance-42")),
+            ],
+            vec![status_at(Signature::new_unique(), 260, None)],
+        ];
+        let fetch = |requests: Rc<Cell<usize>>| {
+            let pages = pages.clone();
+            move |before: Option<Signature>| {
+                requests.set(requests.get() + 1);
+                let page = match before {
+                    None => pages[0].clone(),
+                    Some(before) => pages
+                        .iter()
+                        .position(|page| page.last().unwrap().signature == before.to_string())
+                        .and_then(|index| pages.get(index + 1).cloned())
+                        .unwrap_or_default(),
+                };
+                std::future::ready(Ok(page))
+            }
+        };
+
+        // found beyond the first page
+        let requests = Rc::new(Cell::new(0));
+        let found = find_signature_with_memo_since(fetch(requests.clone()), key, 0)
+            .await
+            .unwrap();
+        assert_eq!(found, Some(original));
+        assert_eq!(requests.get(), 2);
+
+        // swaps before min_slot are ignored and paging stops there
+        let requests = Rc::new(Cell::new(0));
+        let found = find_signature_with_memo_since(fetch(requests.clone()), key, 275)
+            .await
+            .unwrap();
+        assert_eq!(found, None);
+        assert_eq!(requests.get(), 2);
+
+        // paging stops at the end of the history
+        let requests = Rc::new(Cell::new(0));
+        let found = find_signature_with_memo_since(fetch(requests.clone()), "other", 0)
+            .await
+            .unwrap();
+        assert_eq!(found, None);
+        assert_eq!(requests.get(), 4);
+    }
+
+    #[test]
+    fn test_check_account_owner() {
+        use anchor_lang::AnchorSerialize;
+        use mango_v4::state::MangoAccount;
+
+        let owner = Pubkey::new_unique();
+        let group = Pubkey::new_unique();
+        let address = Pubkey::new_unique();
+        let mut account = MangoAccount::default_for_tests();
+        account.owner = owner;
+        account.group = group;
+        account.account_num = 1;
+        let bytes = AnchorSerialize::try_to_vec(&account).unwrap();
+        let account = MangoAccountValue::from_bytes(&bytes).unwrap();
+
+        check_account_owner(&address, &account, &owner, &group).unwrap();
+        assert!(check_account_owner(&address, &account, &Pubkey::new_unique(), &group).is_err());
+        assert!(check_account_owner(&address, &account, &owner, &Pubkey::new_unique()).is_err());
+    }
+
+    pub(crate) fn stake_pool_fixture(pool_mint: Pubkey) -> sanctum_state::StakePool {
+        use sanctum_state::{AccountType, Fee, FutureEpoch, StakePool};
+        let fee = Fee {
+            denominator: 100,
+            numerator: 0,
+        };
+        StakePool {
+            account_type: AccountType::StakePool,
+            manager: Pubkey::new_unique(),
+            staker: Pubkey::new_unique(),
+            stake_deposit_authority: Pubkey::new_unique(),
+            stake_withdraw_bump_seed: 255,
+            validator_list: Pubkey::new_unique(),
+            reserve_stake: Pubkey::new_unique(),
+            pool_mint,
+            manager_fee_account: Pubkey::new_unique(),
+            token_program_id: Token::id(),
+            total_lamports: 1_100_000_000,
+            pool_token_supply: 1_000_000_000,
+            last_update_epoch: 600,
+            lockup: Default::default(),
+            epoch_fee: fee,
+            next_epoch_fee: FutureEpoch::None,
+            preferred_deposit_validator_vote_address: None,
+            preferred_withdraw_validator_vote_address: None,
+            stake_deposit_fee: fee,
+            stake_withdrawal_fee: fee,
+            next_stake_withdrawal_fee: FutureEpoch::None,
+            stake_referral_fee: 0,
+            sol_deposit_authority: None,
+            sol_deposit_fee: fee,
+            sol_referral_fee: 0,
+            sol_withdraw_authority: None,
+            sol_withdrawal_fee: fee,
+            next_sol_withdrawal_fee: FutureEpoch::None,
+            last_epoch_pool_token_supply: 1_000_000_000,
+            last_epoch_total_lamports: 1_099_800_000,
+        }
+    }
+
+    #[test]
+    fn test_lst_mints_from_stake_pools() {
+        use borsh::BorshSerialize;
+
+        let mint_a = Pubkey::new_unique();
+        let mint_b = Pubkey::new_unique();
+        let mut pool_a = stake_pool_fixture(mint_a).try_to_vec().unwrap();
+        // real accounts have trailing space
+        pool_a.extend([0u8; 64]);
+        let pool_b = stake_pool_fixture(mint_b).try_to_vec().unwrap();
+
+        let mut validator_list = stake_pool_fixture(Pubkey::new_unique());
+        validator_list.account_type = sanctum_state::AccountType::ValidatorList;
+        let validator_list = validator_list.try_to_vec().unwrap();
+
+        let garbage = vec![1u8; 10];
+
+        let datas = [pool_a, validator_list, garbage, pool_b];
+        let mints = lst_mints_from_stake_pools(datas.iter().map(|d| d.as_slice()));
+        assert_eq!(mints, HashSet::from([mint_a, mint_b]));
+    }
+
+    #[test]
+    fn test_lst_exchange_rates_from_stake_pools() {
+        use borsh::BorshSerialize;
+
+        let mint_a = Pubkey::new_unique();
+        let mint_b = Pubkey::new_unique();
+        let mint_empty = Pubkey::new_unique();
+        // 1.1 SOL per token
+        let pool_a = stake_pool_fixture(mint_a).try_to_vec().unwrap();
+        let mut pool_b = stake_pool_fixture(mint_b);
+        pool_b.total_lamports = 1_250_000_000;
+        let pool_b = pool_b.try_to_vec().unwrap();
+        let mut pool_empty = stake_pool_fixture(mint_empty);
+        pool_empty.pool_token_supply = 0;
+        let pool_empty = pool_empty.try_to_vec().unwrap();
+        let garbage = vec![1u8; 10];
+
+        let datas = [pool_a, garbage, pool_b, pool_empty];
+        let rates = lst_exchange_rates_from_stake_pools(datas.iter().map(|d| d.as_slice()));
+        assert_eq!(rates.len(), 2);
+        assert!((rates[&mint_a] - 1.1).abs() < 1e-12);
+        assert_eq!(rates[&mint_b], 1.25);
+    }
+
+    #[test]
+    fn test_check_size_without_mango_alts() {
+        use crate::TransactionBuilderConfig;
+
+        let owner = Arc::new(Keypair::new());
+        let ix = |accounts: usize| Instruction {
+            program_id: mango_v4::id(),
+            accounts: (0..accounts)
+                .map(|_| AccountMeta::new(Pubkey::new_unique(), false))
+                .collect(),
+            data: vec![],
+        };
+        let mut tx_builder = TransactionBuilder {
+            instructions: vec![ix(10)],
+            address_lookup_tables: vec![],
+            signers: vec![owner.clone()],
+            payer: owner.pubkey(),
+            config: TransactionBuilderConfig::default(),
+        };
+        check_size_without_mango_alts(&tx_builder).unwrap();
+
+        tx_builder.instructions = vec![ix(40), ix(40)];
+        let err = check_size_without_mango_alts(&tx_builder).unwrap_err();
+        assert!(err.to_string().contains("no address lookup tables"));
+    }
+
+    #[test]
+    fn test_unsigned_swap() {
+        use crate::TransactionBuilderConfig;
+
+        let owner = Arc::new(Keypair::new());
+        let fee_payer = Arc::new(Keypair::new());
+        let mango_account = Pubkey::new_unique();
+        let table = AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: vec![Pubkey::new_unique()],
+        };
+        let instructions = vec![
+            Instruction {
+                program_id: mango_v4::id(),
+                accounts: vec![
+                    AccountMeta::new(mango_account, false),
+                    AccountMeta::new_readonly(owner.pubkey(), true),
+                ],
+                data: vec![1, 2, 3],
+            },
+            Instruction {
+                program_id: mango_v4::id(),
+                accounts: vec![AccountMeta::new_readonly(owner.pubkey(), true)],
+                data: vec![],
+            },
+        ];
+        let tx_builder = TransactionBuilder {
+            instructions: instructions.clone(),
+            address_lookup_tables: vec![table.clone()],
+            signers: vec![owner.clone(), fee_payer.clone()],
+            payer: fee_payer.pubkey(),
+            config: TransactionBuilderConfig::default(),
+        };
+
+        let unsigned = UnsignedSwap::new(tx_builder);
+        assert_eq!(unsigned.instructions, instructions);
+        assert_eq!(unsigned.address_lookup_tables, vec![table]);
+        assert_eq!(unsigned.payer, fee_payer.pubkey());
+        assert_eq!(unsigned.signers, vec![fee_payer.pubkey(), owner.pubkey()]);
+
+        // no secret key bytes anywhere
+        let mut bytes = format!("{unsigned:?}").into_bytes();
+        for ix in &unsigned.instructions {
+            bytes.extend_from_slice(ix.program_id.as_ref());
+            bytes.extend_from_slice(&ix.data);
+            for am in &ix.accounts {
+                bytes.extend_from_slice(am.pubkey.as_ref());
+            }
+        }
+        for alt in &unsigned.address_lookup_tables {
+            bytes.extend_from_slice(alt.key.as_ref());
+            for address in &alt.addresses {
+                bytes.extend_from_slice(address.as_ref());
+            }
+        }
+        for signer in &unsigned.signers {
+            bytes.extend_from_slice(signer.as_ref());
+        }
+        for keypair in [&owner, &fee_payer] {
+            let secret = keypair.secret().to_bytes();
+            assert!(!bytes.windows(secret.len()).any(|w| w == secret));
+            let debug = String::from_utf8_lossy(&bytes);
+            assert!(!debug.contains(&keypair.to_base58_string()));
+            assert!(!debug.contains(&format!("{:?}", secret)));
+        }
+    }
+
+    #[test]
+    fn test_check_transaction_size() {
+        use crate::TransactionBuilderConfig;
+
+        let owner = Arc::new(Keypair::new());
+        let ix = |accounts: usize| Instruction {
+            program_id: mango_v4::id(),
+            accounts: (0..accounts)
+                .map(|_| AccountMeta::new(Pubkey::new_unique(), false))
+                .collect(),
+            data: vec![],
+        };
+        let mut tx_builder = TransactionBuilder {
+            instructions: vec![ix(10)],
+            address_lookup_tables: vec![],
+            signers: vec![owner.clone()],
+            payer: owner.pubkey(),
+            config: TransactionBuilderConfig::default(),
+        };
+        check_transaction_size(&tx_builder).unwrap();
+
+        tx_builder.instructions = vec![ix(40), ix(40)];
+        let err = check_transaction_size(&tx_builder).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<SwapError>(),
+            Some(SwapError::TransactionTooLarge { size, limit: 1232 }) if *size > 1232
+        ));
+    }
+
+    #[test]
+    fn test_check_output_not_redirected() {
+        use anchor_spl::token::spl_token;
+
+        let owner = Pubkey::new_unique();
+        let thief = Pubkey::new_unique();
+        let output_mint = Pubkey::new_unique();
+        let other_mint = Pubkey::new_unique();
+        let ata = |wallet: &Pubkey, mint: &Pubkey| {
+            anchor_spl::associated_token::get_associated_token_address(wallet, mint)
+        };
+        let create_ata = |wallet: &Pubkey, mint: &Pubkey| {
+            spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+                &owner,
+                wallet,
+                mint,
+                &spl_token::ID,
+            )
+        };
+        let transfer_checked = |mint: &Pubkey, destination: &Pubkey| {
+            spl_token::instruction::transfer_checked(
+                &spl_token::ID,
+                &ata(&owner, mint),
+                mint,
+                destination,
+                &owner,
+                &[],
+                1,
+                9,
+            )
+            .unwrap()
+        };
+
+        let honest = vec![
+            create_ata(&owner, &output_mint),
+            create_ata(&thief, &other_mint),
+            transfer_checked(&other_mint, &ata(&thief, &other_mint)),
+            transfer_checked(&output_mint, &ata(&owner, &output_mint)),
+        ];
+        check_output_not_redirected(&honest, &owner, &output_mint).unwrap();
+
+        let err =
+            check_output_not_redirected(&[create_ata(&thief, &output_mint)], &owner, &output_mint)
+                .unwrap_err();
+        assert!(matches!(
+            err,
+            SwapError::OutputRedirected { instruction_index: 0, destination, .. } if destination == thief
+        ));
+
+        let steal = transfer_checked(&output_mint, &ata(&thief, &output_mint));
+        let err = check_output_not_redirected(
+            &[create_ata(&owner, &output_mint), steal],
+            &owner,
+            &output_mint,
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            SwapError::OutputRedirected {
+                instruction_index: 1,
+                ..
+            }
+        ));
+
+        let steal = spl_token::instruction::transfer(
+            &spl_token::ID,
+            &ata(&owner, &output_mint),
+            &ata(&thief, &output_mint),
+            &owner,
+            &[],
+            1,
+        )
+        .unwrap();
+        assert!(check_output_not_redirected(&[steal], &owner, &output_mint).is_err());
+    }
+
+    #[test]
+    fn test_limit_address_lookup_tables() {
+        let accounts = (0..6).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+        let instructions = vec![Instruction {
+            program_id: Pubkey::new_unique(),
+            accounts: accounts
+                .iter()
+                .map(|&a| AccountMeta::new(a, false))
+                .collect(),
+            data: vec![],
+        }];
+        let table = |addresses: &[Pubkey]| AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: addresses.to_vec(),
+        };
+
+        let big = table(&accounts[0..4]);
+        let small = table(&accounts[4..6]);
+        let redundant = table(&accounts[0..2]);
+        let unrelated = table(&[Pubkey::new_unique(), Pubkey::new_unique()]);
+        let tables = vec![
+            unrelated.clone(),
+            redundant.clone(),
+            small.clone(),
+            big.clone(),
+        ];
+
+        let kept = limit_address_lookup_tables(tables.clone(), &instructions, 4).unwrap();
+        assert_eq!(kept.len(), 4);
+
+        let kept = limit_address_lookup_tables(tables.clone(), &instructions, 2).unwrap();
+        let kept_keys = kept.iter().map(|t| t.key).collect::<Vec<_>>();
+        assert_eq!(kept_keys, vec![big.key, small.key]);
+
+        // only one table allowed, but both big and small are needed
+        let err = limit_address_lookup_tables(tables, &instructions, 1).unwrap_err();
+        assert!(err.to_string().contains(&small.key.to_string()));
+    }
+
+    #[test]
+    fn test_insert_after_flash_loan_end() {
+        let ix = |program_id: Pubkey, data: Vec<u8>| Instruction {
+            program_id,
+            accounts: vec![],
+            data,
+        };
+        let flash_loan_end = ix(
+            mango_v4::id(),
+            anchor_lang::InstructionData::data(&mango_v4::instruction::FlashLoanEndV2 {
+                num_loans: 2,
+                flash_loan_type: mango_v4::accounts_ix::FlashLoanType::Swap,
+            }),
+        );
+        let swap_program = Pubkey::new_unique();
+        let settle = ix(mango_v4::id(), vec![9; 8]);
+        let mut instructions = vec![
+            ix(mango_v4::id(), vec![1; 8]),
+            ix(swap_program, vec![2]),
+            flash_loan_end.clone(),
+            ix(swap_program, vec![3]),
+        ];
+
+        insert_after_flash_loan_end(&mut instructions, vec![settle.clone()]).unwrap();
+        assert_eq!(instructions.len(), 5);
+        assert_eq!(instructions[2], flash_loan_end);
+        assert_eq!(instructions[3], settle);
+        assert_eq!(instructions[4].data, vec![3]);
+
+        let mut without_end = vec![ix(swap_program, vec![2])];
+        assert!(insert_after_flash_loan_end(&mut without_end, vec![settle]).is_err());
+    }
+
+    #[test]
+    fn test_append_close_wsol_account() {
+        let owner = Pubkey::new_unique();
+        let wsol = anchor_spl::token::spl_token::native_mint::ID;
+        let lst = Pubkey::new_unique();
+        let wsol_account =
+            anchor_spl::associated_token::get_associated_token_address(&owner, &wsol);
+        let ix = |program_id: Pubkey, accounts: Vec<Pubkey>| Instruction {
+            program_id,
+            accounts: accounts
+                .into_iter()
+                .map(util::to_writable_account_meta)
+                .collect(),
+            data: vec![],
+        };
+        let flash_loan_end = Instruction {
+            data: anchor_lang::InstructionData::data(&mango_v4::instruction::FlashLoanEndV2 {
+                num_loans: 2,
+                flash_loan_type: mango_v4::accounts_ix::FlashLoanType::Swap,
+            }),
+            ..ix(mango_v4::id(), vec![wsol_account])
+        };
+        let swap_program = Pubkey::new_unique();
+        let swap = vec![ix(swap_program, vec![wsol_account]), flash_loan_end.clone()];
+
+        // wSOL -> LST: the wSOL account is closed to the owner at the end
+        let mut instructions = swap.clone();
+        append_close_wsol_account(&mut instructions, &owner, [wsol, lst]).unwrap();
+        assert_eq!(instructions.len(), 3);
+        let close = &instructions[2];
+        assert_eq!(close.program_id, Token::id());
+        use anchor_spl::token::spl_token::instruction::TokenInstruction;
+        assert_eq!(
+            TokenInstruction::unpack(&close.data).unwrap(),
+            TokenInstruction::CloseAccount
+        );
+        assert_eq!(close.accounts[0].pubkey, wsol_account);
+        assert_eq!(close.accounts[1].pubkey, owner);
+        assert_eq!(close.accounts[2].pubkey, owner);
+
+        // LST -> LST: nothing to unwrap
+        let mut instructions = swap.clone();
+        append_close_wsol_account(&mut instructions, &owner, [lst, Pubkey::new_unique()]).unwrap();
+        assert_eq!(instructions, swap);
+
+        // the account is still used after FlashLoanEnd
+        let mut instructions = swap.clone();
+        instructions.push(ix(Token::id(), vec![wsol_account, owner]));
+        let expected = instructions.clone();
+        append_close_wsol_account(&mut instructions, &owner, [lst, wsol]).unwrap();
+        assert_eq!(instructions, expected);
+
+        // no flash loan to close after
+        let mut instructions = vec![ix(swap_program, vec![wsol_account])];
+        assert!(append_close_wsol_account(&mut instructions, &owner, [wsol, lst]).is_err());
+    }
+
+    #[test]
+    fn test_least_full_bank_index() {
+        use anchor_lang::__private::bytemuck::Zeroable;
+
+        let bank = |deposits: u64, deposit_limit: u64| {
+            let mut bank = Bank::zeroed();
+            bank.deposit_index = I80F48::ONE;
+            bank.indexed_deposits = I80F48::from(deposits);
+            bank.deposit_limit = deposit_limit;
+            bank
+        };
+
+        // 50%, 20% and 90% of the limit used
+        let banks = [bank(500, 1000), bank(400, 2000), bank(90, 100)];
+        assert_eq!(least_full_bank_index(&banks), 1);
+
+        // a bank without limit is preferred
+        let banks = [bank(100, 1000), bank(5000, 0)];
+        assert_eq!(least_full_bank_index(&banks), 1);
+
+        // among unlimited banks, the one with the least deposits
+        let banks = [bank(300, 0), bank(200, 0), bank(250, 0)];
+        assert_eq!(least_full_bank_index(&banks), 1);
+    }
+
+    #[test]
+    fn test_route_cu_cache() {
+        let cache = RouteCuCache::new(Duration::from_secs(60));
+        assert_eq!(cache.get("Spool"), None);
+        cache.insert("Spool", 100_000);
+        assert_eq!(cache.get("Spool"), Some(100_000));
+        assert_eq!(cache.get("Stakedex"), None);
+
+        // cache hit: observed cu with margin, cache miss: sanctum limit plus health
+        assert_eq!(
+            swap_cu_limit(cache.get("Spool"), Some(300_000), 50_000),
+            Some(110_000)
+        );
+        assert_eq!(
+            swap_cu_limit(cache.get("Stakedex"), Some(300_000), 50_000),
+            Some(350_000)
+        );
+        assert_eq!(swap_cu_limit(None, None, 50_000), None);
+
+        // multiple hops add up their routes' costs, but only if all are cached
+        let quote = |swap_src: &str| QuoteResponse {
+            in_amount: Some("1000".into()),
+            out_amount: "1000".into(),
+            fee_amount: "0".into(),
+            fee_mint: Pubkey::new_unique().to_string(),
+            fee_pct: "0".into(),
+            swap_src: swap_src.into(),
+            mode: SwapMode::ExactIn,
+        };
+        let (spool, stakedex) = (quote("Spool"), quote("Stakedex"));
+        fn hop(quote: &QuoteResponse) -> SwapHop<'_> {
+            SwapHop {
+                input_mint: Pubkey::new_unique(),
+                output_mint: Pubkey::new_unique(),
+                amounts: SwapAmounts::new(SwapMode::ExactIn, 1000, 1000, 0),
+                quote,
+            }
+        }
+        assert_eq!(cached_route_cu(&cache, &[hop(&spool)]), Some(100_000));
+        assert_eq!(
+            cached_route_cu(&cache, &[hop(&spool), hop(&spool)]),
+            Some(200_000)
+        );
+        assert_eq!(
+            cached_route_cu(&cache, &[hop(&spool), hop(&stakedex)]),
+            None
+        );
+
+        let expired = RouteCuCache::new(Duration::ZERO);
+        expired.insert("Spool", 100_000);
+        assert_eq!(expired.get("Spool"), None);
+
+        let other = Instruction {
+            program_id: Pubkey::new_unique(),
+            accounts: vec![],
+            data: vec![],
+        };
+        let mut instructions = vec![
+            ComputeBudgetInstruction::set_compute_unit_price(5),
+            ComputeBudgetInstruction::set_compute_unit_limit(300_000),
+            other.clone(),
+        ];
+        assert_eq!(compute_unit_limit(&instructions), Some(300_000));
+        set_compute_unit_limit(&mut instructions, 110_000);
+        assert_eq!(instructions.len(), 3);
+        assert_eq!(compute_unit_limit(&instructions), Some(110_000));
+
+        let mut instructions = vec![other.clone()];
+        assert_eq!(compute_unit_limit(&instructions), None);
+        set_compute_unit_limit(&mut instructions, 110_000);
+        assert_eq!(instructions.len(), 2);
+        assert_eq!(compute_unit_limit(&instructions), Some(110_000));
+
+        // an override replaces all existing limits
+        let price = ComputeBudgetInstruction::set_compute_unit_price(5);
+        let mut instructions = vec![
+            price.clone(),
+            ComputeBudgetInstruction::set_compute_unit_limit(300_000),
+            other.clone(),
+            ComputeBudgetInstruction::set_compute_unit_limit(400_000),
+        ];
+        set_compute_unit_limit(&mut instructions, 123_456);
+        assert_eq!(
+            instructions,
+            vec![
+                price,
+                ComputeBudgetInstruction::set_compute_unit_limit(123_456),
+                other,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_quote_compare() {
+        let quote = |in_amount: &str, out_amount: &str, fee_pct: &str| QuoteResponse {
+            in_amount: Some(in_amount.to_string()),
+            out_amount: out_amount.to_string(),
+            fee_amount: "0".to_string(),
+            fee_mint: Pubkey::new_unique().to_string(),
+            fee_pct: fee_pct.to_string(),
+            swap_src: "Spool".to_string(),
+            mode: SwapMode::ExactIn,
+        };
+        // 2 input tokens with 9 decimals into output tokens with 6 decimals
+        let a = quote("2000000000", "1500000", "0.5");
+        let b = quote("2000000000", "1000000", "0.25");
+
+        assert_eq!(a.effective_rate(9, 6).unwrap(), 0.75);
+        assert_eq!(b.effective_rate(9, 6).unwrap(), 0.5);
+
+        let comparison = a.compare(&b, 9, 6).unwrap();
+        assert_eq!(
+            comparison,
+            QuoteComparison {
+                out_amount_delta: 0.5,
+                fee_pct_delta: 0.25,
+                effective_rate_delta: 0.25,
+            }
+        );
+        assert_eq!(b.compare(&a, 9, 6).unwrap().out_amount_delta, -0.5);
+
+        let mut no_in_amount = b.clone();
+        no_in_amount.in_amount = None;
+        assert!(a.compare(&no_in_amount, 9, 6).is_err());
+    }
+
+    #[test]
+    fn test_net_apy() {
+        let mut pool = stake_pool_fixture(Pubkey::new_unique());
+        pool.last_epoch_total_lamports = 1_000_000_000;
+        pool.last_epoch_pool_token_supply = 1_000_000_000;
+        pool.total_lamports = 1_000_200_000;
+        pool.pool_token_supply = 1_000_000_000;
+        let staking_apy = 1.0002f64.powf(sanctum_state::ESTIMATED_EPOCHS_PER_YEAR) - 1.0;
+        assert!((pool.estimated_apy().unwrap() - staking_apy).abs() < 1e-9);
+
+        let quote = QuoteResponse {
+            in_amount: Some("1000000".to_string()),
+            out_amount: "999000".to_string(),
+            fee_amount: "1000".to_string(),
+            fee_mint: Pubkey::new_unique().to_string(),
+            fee_pct: "0.1".to_string(),
+            swap_src: "Spool".to_string(),
+            mode: SwapMode::ExactIn,
+        };
+
+        // holding for a year pays the full fee once
+        let apy = net_apy(&pool, &quote, 365.0).unwrap();
+        assert!((apy - (staking_apy - 0.001)).abs() < 1e-9);
+
+        // holding for a tenth of a year makes the fee ten times as expensive
+        let apy = net_apy(&pool, &quote, 36.5).unwrap();
+        assert!((apy - (staking_apy - 0.01)).abs() < 1e-9);
+
+        assert!(net_apy(&pool, &quote, 0.0).is_err());
+        pool.last_epoch_pool_token_supply = 0;
+        assert!(net_apy(&pool, &quote, 365.0).is_err());
+    }
+
+    #[test]
+    fn test_convert_native_amount() {
+        // fee in the output mint: same price, no change
+        assert_eq!(
+            convert_native_amount(1000, I80F48::from(2), I80F48::from(2)).unwrap(),
+            1000
+        );
+        // fee in the input mint, which is worth 1.5x the output mint
+        assert_eq!(
+            convert_native_amount(1000, I80F48::from_num(1.5), I80F48::ONE).unwrap(),
+            1500
+        );
+        // fee in a third mint, result rounds up
+        assert_eq!(
+            convert_native_amount(10, I80F48::ONE, I80F48::from(3)).unwrap(),
+            4
+        );
+        assert!(convert_native_amount(10, I80F48::ONE, I80F48::ZERO).is_err());
+    }
+
+    #[test]
+    fn test_split_repay_and_deposit() {
+        // no borrow: everything is deposited
+        assert_eq!(split_repay_and_deposit(I80F48::ZERO, 1000), (0, 1000));
+        assert_eq!(split_repay_and_deposit(I80F48::from(50), 1000), (0, 1000));
+
+        // existing borrow of 300: repaid first, the excess is deposited
+        assert_eq!(
+            split_repay_and_deposit(I80F48::from(-300), 1000),
+            (300, 700)
+        );
+        // output smaller than the borrow only reduces it
+        assert_eq!(split_repay_and_deposit(I80F48::from(-300), 200), (200, 0));
+        // fractional borrows round up
+        assert_eq!(
+            split_repay_and_deposit(I80F48::from_num(-299.5), 1000),
+            (300, 700)
+        );
+    }
+
+    #[test]
+    fn test_top_quotes() {
+        let quote = |swap_src: &str, out_amount: u64| QuoteResponse {
+            in_amount: Some("1000".into()),
+            out_amount: out_amount.to_string(),
+            fee_amount: "0".into(),
+            fee_mint: Pubkey::new_unique().to_string(),
+            fee_pct: "0".into(),
+            swap_src: swap_src.into(),
+            mode: SwapMode::ExactIn,
+        };
+        let quotes = || {
+            vec![
+                Ok(quote("Stakedex", 990)),
+                Err(anyhow::anyhow!("no route")),
+                Ok(quote("Jup", 995)),
+                Ok(quote("SPool", 980)),
+                // duplicate source, only its best quote counts
+                Ok(quote("Jup", 970)),
+            ]
+        };
+
+        let srcs = |quotes: Vec<QuoteResponse>| {
+            quotes
+                .iter()
+                .map(|q| q.swap_src.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(srcs(top_quotes(quotes(), 2)), vec!["Jup", "Stakedex"]);
+        assert_eq!(
+            srcs(top_quotes(quotes(), 3)),
+            vec!["Jup", "Stakedex", "SPool"]
+        );
+        // fewer distinct sources than requested
+        assert_eq!(top_quotes(quotes(), 5).len(), 3);
+    }
+
+    #[test]
+    fn test_conservative_health_before_and_after() {
+        let info = |token_index: TokenIndex, balance: f64| TokenInfo {
+            token_index,
+            maint_asset_weight: I80F48::ONE,
+            init_asset_weight: I80F48::ONE,
+            init_scaled_asset_weight: I80F48::ONE,
+            maint_liab_weight: I80F48::ONE,
+            init_liab_weight: I80F48::ONE,
+            init_scaled_liab_weight: I80F48::ONE,
+            prices: Prices::new_single_price(I80F48::ONE),
+            balance_spot: I80F48::from_num(balance),
+            allow_asset_liquidation: true,
+        };
+        // both oracles at price 1 with a confidence of 0.25
+        let deviation = I80F48::from_num(0.25);
+
+        // account: 100 of token 0, borrowed 80 of token 1; spot init health 20
+        let spot_health = I80F48::from(20);
+        let swap = |sell: f64, buy: f64| {
+            conservative_health_before_and_after(
+                spot_health,
+                &[
+                    (info(0, 100.0), deviation, I80F48::from_num(-sell)),
+                    (info(1, -80.0), deviation, I80F48::from_num(buy)),
+                ],
+            )
+        };
+
+        // at the band edges: 100 * 0.75 - 80 * 1.25 = -25
+        let (before, after) = swap(0.0, 0.0);
+        assert_eq!(before, I80F48::from(-25));
+        assert_eq!(after, I80F48::from(-25));
+
+        // repaying the borrow at a fair spot rate improves conservative health
+        let (before, after) = swap(40.0, 40.0);
+        assert_eq!(before, I80F48::from(-25));
+        assert_eq!(after, I80F48::from(-5));
+
+        // an account healthy at spot and at the band edges
+        let (_, after) = conservative_health_before_and_after(
+            I80F48::from(100),
+            &[
+                (info(0, 100.0), deviation, I80F48::from(-50)),
+                (info(1, 0.0), deviation, I80F48::from(50)),
+            ],
+        );
+        // spot: 100, adverse: 50 * 0.75 + 50 * 0.75 = 75
+        assert_eq!(after, I80F48::from(75));
+
+        // buying into a new token looks neutral at spot, but not at the band edges
+        let (before, after) = conservative_health_before_and_after(
+            I80F48::ZERO,
+            &[
+                (info(0, 0.0), deviation, I80F48::from(-50)),
+                (info(1, 0.0), deviation, I80F48::from(50)),
+            ],
+        );
+        assert_eq!(before, I80F48::ZERO);
+        // -50 * 1.25 + 50 * 0.75
+        assert_eq!(after, I80F48::from(-25));
+    }
+
+    #[test]
+    fn test_effective_slippage_bps() {
+        // no floor: caller intent is kept
+        assert_eq!(effective_slippage_bps(1, None), 1);
+        // an over-tight slippage is raised to the floor
+        assert_eq!(effective_slippage_bps(1, Some(20)), 20);
+        // slippage above the floor is unchanged
+        assert_eq!(effective_slippage_bps(50, Some(20)), 50);
+        assert_eq!(effective_slippage_bps(20, Some(20)), 20);
+    }
+
+    #[test]
+    fn test_ata_creation_instructions() {
+        use spl_associated_token_account::instruction::create_associated_token_account;
+
+        // an owner without any token accounts, the fee payer pays the rent
+        let payer = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let input_mint = Pubkey::new_unique();
+        let output_mint = Pubkey::new_unique();
+
+        let mints = [
+            (input_mint, Token::id()),
+            (output_mint, anchor_spl::token_2022::ID),
+        ];
+        let ixs = ata_creation_instructions(&payer, &owner, &mints);
+        assert_eq!(ixs.len(), 2);
+        for (ix, (mint, token_program)) in ixs.iter().zip(mints) {
+            assert_eq!(ix.program_id, anchor_spl::associated_token::ID);
+            assert_eq!(ix.accounts[0].pubkey, payer);
+            assert_eq!(
+                ix.accounts[1].pubkey,
+                anchor_spl::associated_token::get_associated_token_address_with_program_id(
+                    &owner,
+                    &mint,
+                    &token_program
+                )
+            );
+            assert_eq!(ix.accounts[2].pubkey, owner);
+            assert_eq!(ix.accounts[3].pubkey, mint);
+            assert_eq!(ix.accounts[5].pubkey, token_program);
+            // CreateIdempotent
+            assert_eq!(ix.data, vec![1]);
+        }
+
+        // when the sanctum response creates the output account, only its creation stays
+        let sanctum_create = create_associated_token_account(
+            &owner,
+            &owner,
+            &output_mint,
+            &anchor_spl::token_2022::ID,
+        );
+        let setup = [vec![sanctum_create.clone()], ixs.clone()].concat();
+        assert_eq!(
+            dedupe_setup_instructions(setup),
+            vec![sanctum_create, ixs[0].clone()]
+        );
+
+        assert!(ata_creation_instructions(&payer, &owner, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_dedupe_setup_instructions() {
+        use spl_associated_token_account::instruction::{
+            create_associated_token_account, create_associated_token_account_idempotent,
+        };
+
+        let owner = Pubkey::new_unique();
+        let mint_a = Pubkey::new_unique();
+        let mint_b = Pubkey::new_unique();
+        let transfer = anchor_spl::token::spl_token::instruction::transfer(
+            &anchor_spl::token::ID,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &owner,
+            &[],
+            1,
+        )
+        .unwrap();
+
+        let setup = vec![
+            ComputeBudgetInstruction::set_compute_unit_limit(200_000),
+            ComputeBudgetInstruction::set_compute_unit_price(10),
+            create_associated_token_account_idempotent(&owner, &owner, &mint_a, &Token::id()),
+            ComputeBudgetInstruction::set_compute_unit_limit(400_000),
+            ComputeBudgetInstruction::set_compute_unit_price(20),
+            create_associated_token_account(&owner, &owner, &mint_a, &Token::id()),
+            create_associated_token_account_idempotent(&owner, &owner, &mint_b, &Token::id()),
+            transfer.clone(),
+            create_associated_token_account_idempotent(&owner, &owner, &mint_b, &Token::id()),
+        ];
+        let expected = vec![
+            setup[0].clone(),
+            setup[1].clone(),
+            setup[2].clone(),
+            // the create for a different token account is needed
+            setup[6].clone(),
+            transfer,
+        ];
+        assert_eq!(dedupe_setup_instructions(setup), expected);
+
+        // nothing to dedupe
+        let setup = vec![
+            ComputeBudgetInstruction::set_compute_unit_price(10),
+            create_associated_token_account_idempotent(&owner, &owner, &mint_a, &Token::id()),
+        ];
+        assert_eq!(dedupe_setup_instructions(setup.clone()), setup);
+    }
+
+    #[test]
+    fn test_swap_instruction_layout() {
+        let ix = |program_id: Pubkey, tag: u8| Instruction {
+            program_id,
+            accounts: vec![],
+            data: vec![tag],
+        };
+        let cu = |tag| ix(solana_sdk::compute_budget::ID, tag);
+        let ata = |tag| ix(anchor_spl::associated_token::ID, tag);
+        let token = |tag| ix(anchor_spl::token::ID, tag);
+        let swap_program = Pubkey::new_unique();
+        let swap = |tag| ix(swap_program, tag);
+
+        // the usual shape: setup, swap, cleanup
+        let layout = SwapInstructionLayout::new(vec![cu(0), ata(1), swap(2), token(3)]).unwrap();
+        assert_eq!(layout.pre_setup, vec![cu(0), ata(1)]);
+        assert_eq!(layout.action, vec![swap(2)]);
+        assert_eq!(layout.post_setup, vec![token(3)]);
+
+        // setup-like instructions between swap instructions stay inside the flash loan
+        let layout = SwapInstructionLayout::new(vec![ata(0), swap(1), token(2), swap(3)]).unwrap();
+        assert_eq!(layout.pre_setup, vec![ata(0)]);
+        assert_eq!(layout.action, vec![swap(1), token(2), swap(3)]);
+        assert!(layout.post_setup.is_empty());
+
+        // no setup at all
+        let layout = SwapInstructionLayout::new(vec![swap(0)]).unwrap();
+        assert!(layout.pre_setup.is_empty());
+        assert_eq!(layout.action, vec![swap(0)]);
+        assert!(layout.post_setup.is_empty());
+
+        // nothing to wrap
+        assert!(SwapInstructionLayout::new(vec![cu(0), token(1)]).is_err());
+        assert!(SwapInstructionLayout::new(vec![]).is_err());
+    }
+
+    #[test]
+    fn test_two_hop_swap_instructions() {
+        let ix = |program_id: Pubkey, tag: u8| Instruction {
+            program_id,
+            accounts: vec![],
+            data: vec![tag],
+        };
+        let cu_limit = ComputeBudgetInstruction::set_compute_unit_limit;
+        let owner = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let (input_mint, mid_mint, output_mint) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let first_swap = ix(Pubkey::new_unique(), 1);
+        let second_swap = ix(Pubkey::new_unique(), 2);
+        let close = ix(anchor_spl::token::ID, 3);
+
+        // the second hop swaps the first hop's minimum output
+        let quote = |in_amount: u64, out_amount: u64| QuoteResponse {
+            in_amount: Some(in_amount.to_string()),
+            out_amount: out_amount.to_string(),
+            fee_amount: "0".into(),
+            fee_mint: Pubkey::new_unique().to_string(),
+            fee_pct: "0".into(),
+            swap_src: "SPool".into(),
+            mode: SwapMode::ExactIn,
+        };
+        let amounts = |quote: &QuoteResponse| {
+            let (in_amount, out_amount) = quote.in_and_out_amounts().unwrap();
+            SwapAmounts::new(quote.mode, in_amount, out_amount, 100)
+        };
+        let first_amounts = amounts(&quote(1000, 2000));
+        assert_eq!(first_amounts.min_out, 1980);
+        let second_amounts = amounts(&quote(first_amounts.min_out, 990));
+        assert_eq!(second_amounts.max_in, 1980);
+
+        let first = SwapInstructionLayout::new(vec![
+            cu_limit(100_000),
+            ata_creation_instructions(&owner, &owner, &[(mid_mint, Token::id())]).remove(0),
+            first_swap.clone(),
+        ])
+        .unwrap();
+        let second = SwapInstructionLayout::new(vec![
+            cu_limit(50_000),
+            ata_creation_instructions(&owner, &owner, &[(output_mint, Token::id())]).remove(0),
+            second_swap.clone(),
+            close.clone(),
+        ])
+        .unwrap();
+        assert_eq!(
+            sanctum_cu_limit(&[first.clone(), second.clone()]),
+            Some(150_000)
+        );
+        let no_limit = SwapInstructionLayout::new(vec![second_swap.clone()]).unwrap();
+        assert_eq!(sanctum_cu_limit(&[first.clone(), no_limit]), None);
+        let layout = first.then(second);
+
+        let mut setup_ixs = layout.pre_setup;
+        setup_ixs.extend(ata_creation_instructions(
+            &payer,
+            &owner,
+            &[input_mint, mid_mint, output_mint].map(|mint| (mint, Token::id())),
+        ));
+        let mut instructions = dedupe_setup_instructions(setup_ixs);
+        let ata_count = instructions
+            .iter()
+            .filter(|ix| ix.program_id == anchor_spl::associated_token::ID)
+            .count();
+        assert_eq!(ata_count, 3);
+
+        let writable = |n: usize| {
+            (0..n)
+                .map(|_| util::to_writable_account_meta(Pubkey::new_unique()))
+                .collect::<Vec<_>>()
+        };
+        let setup_len = instructions.len();
+        instructions.extend(
+            FlashLoanSwap {
+                account: Pubkey::new_unique(),
+                owner,
+                group: Pubkey::new_unique(),
+                instructions_sysvar: solana_sdk::sysvar::instructions::id(),
+                banks: writable(2),
+                vaults: writable(2),
+                token_accounts: writable(2),
+                token_program: Token::id(),
+                loan_amounts: vec![first_amounts.max_in, 0],
+                health_accounts: writable(3),
+            }
+            .wrap(layout.action),
+        );
+        instructions.extend(layout.post_setup);
+        set_compute_unit_limit(&mut instructions, 150_000);
+
+        let begin_data =
+            anchor_lang::InstructionData::data(&mango_v4::instruction::FlashLoanBegin {
+                loan_amounts: vec![1000, 0],
+            });
+        let end_data = anchor_lang::InstructionData::data(&mango_v4::instruction::FlashLoanEndV2 {
+            num_loans: 2,
+            flash_loan_type: mango_v4::accounts_ix::FlashLoanType::Swap,
+        });
+        let positions = |data: &[u8]| {
+            instructions
+                .iter()
+                .positions(|ix| ix.program_id == mango_v4::id() && ix.data[..8] == data[..8])
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(positions(&begin_data), vec![setup_len]);
+        assert_eq!(positions(&end_data), vec![setup_len + 3]);
+        assert_eq!(instructions[setup_len].data, begin_data);
+        assert_eq!(instructions[setup_len + 1], first_swap);
+        assert_eq!(instructions[setup_len + 2], second_swap);
+        assert_eq!(instructions[setup_len + 3].data, end_data);
+        assert_eq!(instructions[setup_len + 4..], [close]);
+        assert_eq!(compute_unit_limit(&instructions), Some(150_000));
+    }
+
+    #[test]
+    fn test_net_output() {
+        use anchor_lang::__private::bytemuck::Zeroable;
+
+        let mut bank = Bank::zeroed();
+        bank.loan_origination_fee_rate = I80F48::from_num(0.0625);
+
+        // borrow 1600 input worth 0.5 output each, quoted to give 790 output
+        let in_amount = 1600;
+        let gross = 790;
+        let fee = bank.flash_loan_repayment(in_amount) - in_amount;
+        assert_eq!(fee, 100);
+        let net = net_output(gross, fee, I80F48::from_num(0.5), I80F48::ONE).unwrap();
+        assert_eq!(net, 740);
+        assert!(net < gross);
+
+        // no fee: net equals gross
+        assert_eq!(
+            net_output(gross, 0, I80F48::from_num(0.5), I80F48::ONE).unwrap(),
+            gross
+        );
+
+        // the fee can exceed the output
+        assert_eq!(net_output(10, fee, I80F48::ONE, I80F48::ONE).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_check_max_input() {
+        assert!(check_max_input(1000, 1000).is_ok());
+        assert!(check_max_input(999, 1000).is_ok());
+        let err = check_max_input(1001, 1000).unwrap_err();
+        assert!(err.to_string().contains("1001"));
+    }
+
+    #[test]
+    fn test_buffered_loan_amount() {
+        assert_eq!(buffered_loan_amount(1_000_000, 0), 1_000_000);
+        assert_eq!(buffered_loan_amount(1_000_000, 10), 1_001_000);
+        // the buffer rounds up, so small loans still get one native unit extra
+        assert_eq!(buffered_loan_amount(100, 1), 101);
+        assert_eq!(buffered_loan_amount(0, 10), 0);
+        assert_eq!(buffered_loan_amount(u64::MAX, 10), u64::MAX);
+    }
+
+    #[test]
+    fn test_swap_amounts() {
+        // ExactIn: the input is borrowed exactly, slippage lowers the minimum output
+        assert_eq!(
+            SwapAmounts::new(SwapMode::ExactIn, 1000, 2000, 50),
+            SwapAmounts {
+                max_in: 1000,
+                min_out: 1990,
+                request_amount: 1000,
+                quoted_amount: 1990,
+            }
+        );
+
+        // ExactOut: the output is exactly the requested amount and the borrow covers
+        // the quoted input plus slippage
+        let amounts = SwapAmounts::new(SwapMode::ExactOut, 1000, 2000, 50);
+        assert_eq!(
+            amounts,
+            SwapAmounts {
+                max_in: 1005,
+                min_out: 2000,
+                request_amount: 2000,
+                quoted_amount: 1005,
+            }
+        );
+        assert_eq!(amounts.min_out, amounts.request_amount);
+        assert_eq!(buffered_loan_amount(amounts.max_in, 0), 1005);
+
+        // without slippage, both modes agree on in and out
+        let exact_in = SwapAmounts::new(SwapMode::ExactIn, 1000, 2000, 0);
+        let exact_out = SwapAmounts::new(SwapMode::ExactOut, 1000, 2000, 0);
+        assert_eq!(
+            (exact_in.max_in, exact_in.min_out),
+            (exact_out.max_in, exact_out.min_out)
+        );
+    }
+
+    #[test]
+    fn test_max_in_amount() {
+        assert_eq!(max_in_amount(1000, 50), 1005);
+        assert_eq!(max_in_amount(1000, 0), 1000);
+        // rounds up
+        assert_eq!(max_in_amount(1001, 50), 1007);
+        assert_eq!(max_in_amount(1, 1), 2);
+        assert_eq!(max_in_amount(0, 50), 0);
+        // exact beyond f64 precision
+        assert_eq!(
+            max_in_amount(9_007_199_254_740_993, 100),
+            9_097_271_247_288_403
+        );
+        assert_eq!(max_in_amount(u64::MAX, 50), u64::MAX);
+    }
+
+    #[test]
+    fn test_swap_amounts_with_limit() {
+        // Bps behaves like before
+        assert_eq!(
+            SwapAmounts::with_limit(SwapMode::ExactIn, 1000, 2000, SlippageLimit::Bps(50)).unwrap(),
+            SwapAmounts::new(SwapMode::ExactIn, 1000, 2000, 50)
+        );
+        assert_eq!(
+            SwapAmounts::with_limit(SwapMode::ExactOut, 1000, 2000, SlippageLimit::Bps(50))
+                .unwrap(),
+            SwapAmounts::new(SwapMode::ExactOut, 1000, 2000, 50)
+        );
+
+        // MinOut is used as the floor directly
+        assert_eq!(
+            SwapAmounts::with_limit(SwapMode::ExactIn, 1000, 2000, SlippageLimit::MinOut(1997))
+                .unwrap(),
+            SwapAmounts {
+                max_in: 1000,
+                min_out: 1997,
+                request_amount: 1000,
+                quoted_amount: 1997,
+            }
+        );
+        assert_eq!(
+            SwapAmounts::with_limit(SwapMode::ExactIn, 1000, 2000, SlippageLimit::MinOut(2000))
+                .unwrap()
+                .quoted_amount,
+            2000
+        );
+
+        // but can't exceed the quote, and needs ExactIn
+        assert!(SwapAmounts::with_limit(
+            SwapMode::ExactIn,
+            1000,
+            2000,
+            SlippageLimit::MinOut(2001)
+        )
+        .is_err());
+        assert!(SwapAmounts::with_limit(
+            SwapMode::ExactOut,
+            1000,
+            2000,
+            SlippageLimit::MinOut(1990)
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_swap_outcome() {
+        let signature = Signature::new_unique();
+        let (input_mint, output_mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let quote = QuoteResponse {
+            in_amount: Some("1000".into()),
+            out_amount: "2000".into(),
+            fee_amount: "0".into(),
+            fee_mint: output_mint.to_string(),
+            fee_pct: "0".into(),
+            swap_src: "SPool".into(),
+            mode: SwapMode::ExactIn,
+        };
+        assert_eq!(
+            SwapOutcome::new(signature, input_mint, output_mint, &quote, 50, 1005, 1234).unwrap(),
+            SwapOutcome {
+                signature,
+                input_mint,
+                output_mint,
+                quoted_out: 2000,
+                min_out: 1990,
+                flash_loan_repayment: 1005,
+                slot: 1234,
+            }
+        );
+
+        // ExactOut swaps produce the quoted output exactly
+        let exact_out = QuoteResponse {
+            mode: SwapMode::ExactOut,
+            ..quote.clone()
+        };
+        let outcome = SwapOutcome::new(
+            signature,
+            input_mint,
+            output_mint,
+            &exact_out,
+            50,
+            1005,
+            1234,
+        )
+        .unwrap();
+        assert_eq!(outcome.min_out, 2000);
+
+        let no_in_amount = QuoteResponse {
+            in_amount: None,
+            ..quote
+        };
+        assert!(
+            SwapOutcome::new(signature, input_mint, output_mint, &no_in_amount, 50, 0, 1).is_err()
+        );
+    }
+
+    #[test]
+    fn test_quote_response_mode_not_serialized() {
+        let json = r#"{"inAmount":"1000","outAmount":"2000","feeAmount":"1","feeMint":"So11111111111111111111111111111111111111112","feePct":"0.1","swapSrc":"SPool"}"#;
+        let quote: QuoteResponse = serde_json::from_str(json).unwrap();
+        assert_eq!(quote.mode, SwapMode::ExactIn);
+        let serialized = serde_json::to_string(&QuoteResponse {
+            mode: SwapMode::ExactOut,
+            ..quote
+        })
+        .unwrap();
+        assert!(!serialized.contains("mode"));
+    }
+
+    #[test]
+    fn test_retry_policy_delay() {
+        let policy = RetryPolicy {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(100),
+            backoff_factor: 2.0,
+        };
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(200));
+        assert_eq!(policy.delay(3), Duration::from_millis(400));
+    }
+
+    /// Answers one request per connection with each of `statuses` in turn,
+    /// returns the url and the number of requests served
+    async fn mock_server(statuses: Vec<&'static str>) -> (String, tokio::task::JoinHandle<usize>) {
+        mock_server_with_body(statuses, "{}").await
+    }
+
+    async fn mock_server_with_body(
+        statuses: Vec<&'static str>,
+        body: &'static str,
+    ) -> (String, tokio::task::JoinHandle<usize>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let mut served = 0;
+            for status in statuses {
+                let Ok(Ok((mut stream, _))) =
+                    tokio::time::timeout(Duration::from_secs(2), listener.accept()).await
+                else {
+                    break;
+                };
+                let mut buf = [0u8; 4096];
+                let _ = stream.read(&mut buf).await.unwrap();
+                let response = format!(
+                    "HTTP/1.1 {status}\r\nconnection: close\r\ncontent-length: {}\r\n\r\n{body}",
+                    body.len()
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+                served += 1;
+            }
+            served
+        });
+        (url, handle)
+    }
+
+    /// A simulation failure of `program` with the custom error `code`
+    fn preflight_failure(code: u32, program: &str) -> crate::MangoClientError {
+        use solana_sdk::instruction::InstructionError;
+        crate::MangoClientError::SendTransactionPreflightFailure {
+            err: Some(TransactionError::InstructionError(
+                3,
+                InstructionError::Custom(code),
+            )),
+            logs: vec![
+                format!("Program {program} invoke [1]"),
+                "Program log: Error Message: Slippage tolerance exceeded.".into(),
+                format!("Program {program} failed: custom program error: {code:#x}"),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_is_slippage_error() {
+        let router = "stkitrT1Uoy18Dk1fTrgPw8W6MVzoCfYoAFT4MLsmhq";
+        let jupiter = "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4";
+        let check = |code: u32, program: &str| {
+            is_slippage_error(
+                &anyhow::Error::from(preflight_failure(code, program)).context("swap"),
+            )
+        };
+
+        assert!(check(0, router));
+        assert!(check(6001, jupiter));
+        // the same codes from other programs, or other codes, are something else
+        assert!(!check(6001, router));
+        assert!(!check(0, jupiter));
+        assert!(!check(0, &anchor_spl::token::ID.to_string()));
+        // a message mentioning slippage isn't enough
+        assert!(!is_slippage_error(&anyhow::anyhow!(
+            "Program log: Error Message: Slippage tolerance exceeded."
+        )));
+    }
+
+    #[tokio::test]
+    async fn test_retry_once_on_slippage() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        let slippage_error = || {
+            anyhow::Error::from(preflight_failure(
+                6001,
+                "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4",
+            ))
+            .context("sending transaction")
+        };
+        let attempts = AtomicU32::new(0);
+        let fails_then_succeeds = |failures: u32, error: fn() -> anyhow::Error| {
+            attempts.store(0, Ordering::SeqCst);
+            let attempts = &attempts;
+            move || async move {
+                if attempts.fetch_add(1, Ordering::SeqCst) < failures {
+                    Err(error())
+                } else {
+                    Ok(7)
+                }
+            }
+        };
+
+        // a slippage failure is retried
+        let result = retry_once_on_slippage(true, fails_then_succeeds(1, slippage_error)).await;
+        assert_eq!(result.unwrap(), 7);
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+
+        // but only once
+        let result = retry_once_on_slippage(true, fails_then_succeeds(2, slippage_error)).await;
+        assert!(is_slippage_error(&result.unwrap_err()));
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+
+        // not if disabled
+        let result = retry_once_on_slippage(false, fails_then_succeeds(1, slippage_error)).await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        // other failures aren't retried
+        let other_error = || anyhow::anyhow!("health must be positive");
+        let result = retry_once_on_slippage(true, fails_then_succeeds(1, other_error)).await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_quotes_concurrently() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let outputs = (0..3).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+
+        // The server only answers once all requests are in, and answers the last
+        // one first: serial requests would time out.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let n = outputs.len();
+        let server = tokio::spawn(async move {
+            let mut pending = vec![];
+            for _ in 0..n {
+                let Ok(Ok((mut stream, _))) =
+                    tokio::time::timeout(Duration::from_secs(2), listener.accept()).await
+                else {
+                    break;
+                };
+                let mut buf = [0u8; 4096];
+                let len = stream.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..len]).to_string();
+                let output = request
+                    .split(|c| c == '?' || c == '&' || c == ' ')
+                    .find_map(|arg| arg.strip_prefix("outputLstMint="))
+                    .unwrap()
+                    .to_string();
+                pending.push((stream, output));
+            }
+            let received = pending.len();
+            for (mut stream, output) in pending.into_iter().rev() {
+                let body = format!(
+                    r#"{{"inAmount":"100","outAmount":"99","feeAmount":"1","feeMint":"{output}","feePct":"1","swapSrc":"SPool"}}"#
+                );
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nconnection: close\r\ncontent-length: {}\r\n\r\n{body}",
+                    body.len()
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+            received
+        });
+
+        let policy = RetryPolicy {
+            max_attempts: 1,
+            base_delay: Duration::from_millis(10),
+            backoff_factor: 2.0,
+        };
+        let client = reqwest::Client::new();
+        let quotes =
+            futures::future::join_all(outputs.iter().map(|output| {
+                let query_args = vec![("outputLstMint", output.to_string())];
+                let client = &client;
+                let policy = &policy;
+                let url = &url;
+                async move {
+                    fetch_quote(client, policy, Duration::from_secs(5), url, &query_args).await
+                }
+            }))
+            .await;
+
+        assert_eq!(server.await.unwrap(), n);
+        let fee_mints = quotes
+            .into_iter()
+            .map(|q| q.unwrap().fee_mint)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fee_mints,
+            outputs.iter().map(|o| o.to_string()).collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_base_url_override() {
+        let config_url = "http://127.0.0.1:1";
+        assert_eq!(base_url(None, config_url), config_url);
+
+        let body = r#"{"inAmount":"1000","outAmount":"990","feeAmount":"10","feeMint":"So11111111111111111111111111111111111111112","feePct":"1","swapSrc":"SPool"}"#;
+        let (mirror_url, served) = mock_server_with_body(vec!["200 OK"], body).await;
+        let url = base_url(Some(&mirror_url), config_url);
+        assert_eq!(url, mirror_url);
+
+        let policy = RetryPolicy {
+            max_attempts: 1,
+            base_delay: Duration::from_millis(10),
+            backoff_factor: 2.0,
+        };
+        let quote = fetch_quote(
+            &reqwest::Client::new(),
+            &policy,
+            Duration::from_secs(5),
+            url,
+            &[],
+        )
+        .await
+        .unwrap();
+        assert_eq!(quote.in_and_out_amounts().unwrap(), (1000, 990));
+        assert_eq!(served.await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_send_with_retry() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(10),
+            backoff_factor: 2.0,
+        };
+        let client = reqwest::Client::new();
+        let timeout = Duration::from_secs(5);
+
+        // server errors are retried until success
+        let (url, served) =
+            mock_server(vec!["502 Bad Gateway", "503 Service Unavailable", "200 OK"]).await;
+        let response = send_with_retry(&policy, timeout, "test", || client.get(&url))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(served.await.unwrap(), 3);
+
+        // client errors are not retried
+        let (url, served) = mock_server(vec!["400 Bad Request", "200 OK"]).await;
+        let response = send_with_retry(&policy, timeout, "test", || client.get(&url))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400);
+        drop(response);
+        assert_eq!(served.await.unwrap(), 1);
+
+        // at most max_attempts
+        let (url, served) = mock_server(vec!["502 Bad Gateway"; 4]).await;
+        let response = send_with_retry(&policy, timeout, "test", || client.get(&url))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 502);
+        drop(response);
+        assert_eq!(served.await.unwrap(), 3);
+
+        // no retry if the delay would exceed the timeout
+        let (url, served) = mock_server(vec!["502 Bad Gateway", "200 OK"]).await;
+        let slow_policy = RetryPolicy {
+            base_delay: Duration::from_secs(10),
+            ..policy
+        };
+        let response = send_with_retry(&slow_policy, timeout, "test", || client.get(&url))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 502);
+        drop(response);
+        assert_eq!(served.await.unwrap(), 1);
+    }
+
+    #[test]
+    fn test_quote_fee_accessors() {
+        let fee_mint = Pubkey::new_unique();
+        let quote = QuoteResponse {
+            in_amount: Some("1000".into()),
+            out_amount: "990".into(),
+            fee_amount: "10".into(),
+            fee_mint: fee_mint.to_string(),
+            fee_pct: "0.25".into(),
+            swap_src: "SPool".into(),
+            mode: SwapMode::ExactIn,
+        };
+        assert_eq!(quote.fee_amount_u64().unwrap(), 10);
+        assert_eq!(quote.fee_pct_f64().unwrap(), 0.25);
+        assert_eq!(quote.fee_mint_pubkey().unwrap(), fee_mint);
+        assert_eq!(quote.fee_fraction().unwrap(), 0.0025);
+
+        let malformed = QuoteResponse {
+            fee_amount: "-10".into(),
+            fee_mint: "not a pubkey".into(),
+            fee_pct: "".into(),
+            ..quote
+        };
+        let err = malformed.fee_amount_u64().unwrap_err();
+        assert!(format!("{err:#}").contains("fee_amount \"-10\""));
+        assert!(malformed.fee_pct_f64().is_err());
+        assert!(malformed.fee_fraction().is_err());
+        let err = malformed.fee_mint_pubkey().unwrap_err();
+        assert!(format!("{err:#}").contains("fee_mint"));
+    }
+
+    #[test]
+    fn test_quote_in_and_out_amounts() {
+        let quote = QuoteResponse {
+            in_amount: Some("1000".into()),
+            out_amount: "990".into(),
+            fee_amount: "10".into(),
+            fee_mint: Pubkey::new_unique().to_string(),
+            fee_pct: "1".into(),
+            swap_src: "SPool".into(),
+            mode: SwapMode::ExactIn,
+        };
+        assert_eq!(quote.in_and_out_amounts().unwrap(), (1000, 990));
+
+        let no_in_amount = QuoteResponse {
+            in_amount: None,
+            ..quote.clone()
+        };
+        let err = no_in_amount.in_and_out_amounts().unwrap_err();
+        assert_eq!(err.to_string(), "quote has no in amount");
+
+        let bad_in_amount = QuoteResponse {
+            in_amount: Some("1e3".into()),
+            ..quote.clone()
+        };
+        assert!(bad_in_amount.in_and_out_amounts().is_err());
+        let bad_out_amount = QuoteResponse {
+            out_amount: "".into(),
+            ..quote
+        };
+        assert!(bad_out_amount.in_and_out_amounts().is_err());
+    }
+
+    #[test]
+    fn test_check_fee_pct() {
+        let quote = |fee_pct: &str| QuoteResponse {
+            in_amount: Some("1000".into()),
+            out_amount: "990".into(),
+            fee_amount: "10".into(),
+            fee_mint: Pubkey::new_unique().to_string(),
+            fee_pct: fee_pct.into(),
+            swap_src: "Spool".into(),
+            mode: SwapMode::ExactIn,
+        };
+        assert!(check_fee_pct(&quote("0.5"), 1.0).is_ok());
+        assert!(check_fee_pct(&quote("1"), 1.0).is_ok());
+
+        let err = check_fee_pct(&quote("1.5"), 1.0).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<SwapError>(),
+            Some(SwapError::FeeTooHigh { fee_pct, max_fee_pct })
+                if *fee_pct == 1.5 && *max_fee_pct == 1.0
+        ));
+
+        assert!(check_fee_pct(&quote("not a number"), 1.0).is_err());
+    }
+
+    #[test]
+    fn test_check_oracle_slot_age() {
+        assert!(check_oracle_slot_age(2, 0, 50).is_ok());
+        assert!(check_oracle_slot_age(2, 50, 50).is_ok());
+
+        // an oracle that hasn't updated for an hour
+        let err = check_oracle_slot_age(2, 9000, 50).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<SwapError>(),
+            Some(SwapError::StaleOracle {
+                token_index: 2,
+                slot_age: 9000,
+                max_slot_age: 50
+            })
+        ));
+    }
+
+    #[test]
+    fn test_min_out_amount_zero_slippage() {
+        assert_eq!(min_out_amount(0, 0), 0);
+        assert_eq!(min_out_amount(1, 0), 1);
+        assert_eq!(min_out_amount(1000, 0), 1000);
+    }
+
+    #[test]
+    fn test_min_out_amount_typical_slippage() {
+        assert_eq!(min_out_amount(2000, 50), 1990);
+        assert_eq!(min_out_amount(123_456_789, 50), 122_839_505);
+        assert_eq!(min_out_amount(1_000_000_000_000, 50), 995_000_000_000);
+        // fractional minimums round down
+        assert_eq!(min_out_amount(999, 1), 998);
+        assert_eq!(min_out_amount(3, 5000), 1);
+    }
+
+    #[test]
+    fn test_min_out_amount_max_slippage() {
+        assert_eq!(min_out_amount(1000, 10_000), 0);
+        assert_eq!(min_out_amount(u64::MAX, 10_000), 0);
+        assert_eq!(min_out_amount(1000, 20_000), 0);
+    }
+
+    #[test]
+    fn test_min_out_amount_precision_limits() {
+        assert_eq!(
+            min_out_amount(1_000_000_000_000_000_000, 50),
+            995_000_000_000_000_000
+        );
+        // exact above 2^53 too
+        assert_eq!(min_out_amount((1 << 53) + 1, 0), (1 << 53) + 1);
+        assert_eq!(min_out_amount((1 << 53) + 1, 50), 8_962_163_258_467_288);
+        assert_eq!(min_out_amount(u64::MAX, 0), u64::MAX);
+        assert_eq!(min_out_amount(u64::MAX, 1), 18_444_899_399_302_180_659);
+    }
+
+    #[test]
+    fn test_min_out_amount_properties() {
+        let amounts = (0..2_000u64)
+            .chain((0..64).map(|shift| 1u64 << shift))
+            .chain((1..64).map(|shift| (1u64 << shift) - 1))
+            .chain((1..64).map(|shift| (1u64 << shift) + 1))
+            .chain([123_456_789, 999_999_999_999, u64::MAX]);
+        let slippages = [
+            0, 1, 5, 10, 30, 50, 100, 333, 1000, 5000, 9999, 10_000, 12_000,
+        ];
+        for out_amount in amounts {
+            for bps in slippages {
+                let min_out = min_out_amount(out_amount, bps);
+                // never above the quote
+                assert!(min_out <= out_amount, "{out_amount} {bps}");
+                // the exact slippage bound, scaled by 10000: min_out is its floor
+                let bound = out_amount as u128 * 10_000u64.saturating_sub(bps) as u128;
+                assert!(min_out as u128 * 10_000 <= bound, "{out_amount} {bps}");
+                assert!((min_out as u128 + 1) * 10_000 > bound, "{out_amount} {bps}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_price_impact_bps() {
+        let quote = |in_amount: &str, out_amount: &str| QuoteResponse {
+            in_amount: Some(in_amount.into()),
+            out_amount: out_amount.into(),
+            fee_amount: "0".into(),
+            fee_mint: Pubkey::new_unique().to_string(),
+            fee_pct: "0".into(),
+            swap_src: "Spool".into(),
+            mode: SwapMode::ExactIn,
+        };
+        // input with 9 decimals, output with 6 decimals, equal ui value:
+        // a native output token is worth 1000 native input tokens
+        let input_price = I80F48::ONE;
+        let output_price = I80F48::from(1000);
+
+        // 2 ui in for 1.99 ui out: 50 bps worse than the oracles
+        let impact = price_impact_bps(
+            &quote("2000000000", "1990000"),
+            9,
+            6,
+            input_price,
+            output_price,
+        )
+        .unwrap();
+        assert!((impact - 50.0).abs() < 1e-9);
+
+        // exactly at the oracle rate
+        let impact = price_impact_bps(
+            &quote("2000000000", "2000000"),
+            9,
+            6,
+            input_price,
+            output_price,
+        )
+        .unwrap();
+        assert!(impact.abs() < 1e-9);
+
+        // better than the oracles
+        let impact = price_impact_bps(
+            &quote("2000000000", "2010000"),
+            9,
+            6,
+            input_price,
+            output_price,
+        )
+        .unwrap();
+        assert!((impact + 50.0).abs() < 1e-9);
+
+        assert!(price_impact_bps(
+            &quote("2000000000", "1990000"),
+            9,
+            6,
+            input_price,
+            I80F48::ZERO
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_flash_loan_begin_instruction() {
+        let account = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let group = Pubkey::new_unique();
+        let bank = util::to_writable_account_meta(Pubkey::new_unique());
+        let ix = |instructions_sysvar: Pubkey| {
+            flash_loan_begin_instruction(
+                account,
+                owner,
+                Token::id(),
+                instructions_sysvar,
+                group,
+                vec![bank.clone()],
+                vec![1000],
+            )
+        };
+
+        let canonical = ix(solana_sdk::sysvar::instructions::id());
+        let keys = canonical
+            .accounts
+            .iter()
+            .map(|am| am.pubkey)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec![
+                account,
+                owner,
+                Token::id(),
+                solana_sdk::sysvar::instructions::id(),
+                bank.pubkey,
+                group
+            ]
+        );
+
+        let sysvar_override = Pubkey::new_unique();
+        let overridden = ix(sysvar_override);
+        assert_eq!(overridden.accounts[3].pubkey, sysvar_override);
+        assert_eq!(overridden.data, canonical.data);
+    }
+
+    #[test]
+    fn test_health_check_passed() {
+        use mango_v4::error::MangoError;
+        use solana_sdk::instruction::InstructionError;
+
+        let custom =
+            |code: u32| TransactionError::InstructionError(3, InstructionError::Custom(code));
+        assert_eq!(health_check_passed(None), Some(true));
+        assert_eq!(
+            health_check_passed(Some(&custom(
+                MangoError::HealthMustBePositiveOrIncrease.into()
+            ))),
+            Some(false)
+        );
+        assert_eq!(
+            health_check_passed(Some(&custom(MangoError::HealthMustBePositive.into()))),
+            Some(false)
+        );
+        // failed before reaching the health check
+        assert_eq!(
+            health_check_passed(Some(&custom(MangoError::OracleStale.into()))),
+            None
+        );
+        assert_eq!(
+            health_check_passed(Some(&TransactionError::BlockhashNotFound)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_sanctum_extra_lst_mints() {
+        let mints = sanctum_extra_lst_mints().unwrap();
+        assert_eq!(mints.len(), SANCTUM_EXTRA_LST_MINTS.len());
+        assert_eq!(
+            mints.iter().unique().count(),
+            SANCTUM_EXTRA_LST_MINTS.len(),
+            "duplicate extra lst mint"
+        );
+    }
+
+    #[test]
+    fn test_swap_payer_and_signers() {
+        let owner = Arc::new(Keypair::new());
+        let relayer = Arc::new(Keypair::new());
+
+        let (payer, signers) = swap_payer_and_signers(&owner, None);
+        assert_eq!(payer, owner.pubkey());
+        assert_eq!(signers.len(), 1);
+
+        let (payer, signers) = swap_payer_and_signers(&owner, Some(&relayer));
+        assert_eq!(payer, relayer.pubkey());
+        assert_eq!(
+            signers.iter().map(|s| s.pubkey()).collect_vec(),
+            vec![owner.pubkey(), relayer.pubkey()]
+        );
+
+        // no duplicate signer if the owner is passed as fee payer
+        let (payer, signers) = swap_payer_and_signers(&owner, Some(&owner.clone()));
+        assert_eq!(payer, owner.pubkey());
+        assert_eq!(signers.len(), 1);
+    }
+
+    #[test]
+    fn test_strip_compute_budget_instructions() {
+        let owner = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let swap_ix = Instruction {
+            program_id: Pubkey::new_unique(),
+            accounts: vec![],
+            data: vec![1, 2, 3],
+        };
+        let ata_ix =
+            spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+                &owner,
+                &owner,
+                &mint,
+                &Token::id(),
+            );
+        let sanctum_ixs = vec![
+            ComputeBudgetInstruction::set_compute_unit_limit(400_000),
+            ComputeBudgetInstruction::set_compute_unit_price(1_000),
+            ata_ix.clone(),
+            swap_ix.clone(),
+        ];
+
+        let stripped = strip_compute_budget_instructions(sanctum_ixs);
+        assert_eq!(stripped, vec![ata_ix.clone(), swap_ix.clone()]);
+        assert_eq!(compute_unit_limit(&stripped), None);
+
+        let layout = SwapInstructionLayout::new(stripped).unwrap();
+        assert_eq!(layout.pre_setup, vec![ata_ix]);
+        assert_eq!(layout.action, vec![swap_ix]);
+    }
+
+    #[test]
+    fn test_describe_prepared_swap() {
+        use crate::TransactionBuilderConfig;
+
+        let owner = Arc::new(Keypair::new());
+        let mango_account = Pubkey::new_unique();
+        let vault = Pubkey::new_unique();
+        let swap_program = Pubkey::new_unique();
+        let flash_loan_begin = flash_loan_begin_instruction(
+            mango_account,
+            owner.pubkey(),
+            Token::id(),
+            solana_sdk::sysvar::instructions::id(),
+            Pubkey::new_unique(),
+            vec![AccountMeta::new(vault, false)],
+            vec![100],
+        );
+        let flash_loan_end = Instruction {
+            program_id: mango_v4::id(),
+            accounts: vec![
+                AccountMeta::new(mango_account, false),
+                AccountMeta::new_readonly(owner.pubkey(), true),
+            ],
+            data: anchor_lang::InstructionData::data(&mango_v4::instruction::FlashLoanEndV2 {
+                num_loans: 1,
+                flash_loan_type: mango_v4::accounts_ix::FlashLoanType::Swap,
+            }),
+        };
+        let swap_ix = Instruction {
+            program_id: swap_program,
+            accounts: vec![AccountMeta::new_readonly(vault, false)],
+            data: vec![1],
+        };
+        let mut tx_builder = TransactionBuilder {
+            instructions: vec![
+                ComputeBudgetInstruction::set_compute_unit_limit(400_000),
+                flash_loan_begin,
+                swap_ix.clone(),
+                swap_ix,
+                flash_loan_end,
+                memo_instruction("post"),
+            ],
+            address_lookup_tables: vec![AddressLookupTableAccount {
+                key: Pubkey::new_unique(),
+                addresses: vec![],
+            }],
+            signers: vec![owner.clone()],
+            payer: owner.pubkey(),
+            config: TransactionBuilderConfig::default(),
+        };
+
+        let layout = describe_prepared_swap(&tx_builder).unwrap();
+        assert_eq!(layout.setup, vec![0, 5]);
+        assert_eq!(layout.flash_loan_begin, 1);
+        assert_eq!(layout.action, vec![2, 3]);
+        assert_eq!(layout.flash_loan_end, 4);
+        assert_eq!(layout.address_lookup_table_count, 1);
+
+        let meta = |pubkey: &Pubkey| {
+            layout
+                .accounts
+                .iter()
+                .find(|am| am.pubkey == *pubkey)
+                .unwrap()
+                .clone()
+        };
+        assert_eq!(layout.accounts[0], AccountMeta::new(owner.pubkey(), true));
+        assert_eq!(
+            layout
+                .accounts
+                .iter()
+                .filter(|am| am.pubkey == owner.pubkey())
+                .count(),
+            1
+        );
+        // writable from FlashLoanBegin wins over readonly in the swap
+        assert_eq!(meta(&vault), AccountMeta::new(vault, false));
+        assert_eq!(meta(&mango_account), AccountMeta::new(mango_account, false));
+        assert_eq!(
+            meta(&swap_program),
+            AccountMeta::new_readonly(swap_program, false)
+        );
+
+        tx_builder.instructions.remove(1);
+        assert!(describe_prepared_swap(&tx_builder).is_err());
+    }
+
+    #[test]
+    fn test_check_swap_programs() {
+        let owner = Pubkey::new_unique();
+        let router = Pubkey::from_str(SANCTUM_SWAP_PROGRAMS[0]).unwrap();
+        let ix = |program_id: Pubkey| Instruction {
+            program_id,
+            accounts: vec![AccountMeta::new(owner, true)],
+            data: vec![1],
+        };
+        let layout = |action: Vec<Instruction>| SwapInstructionLayout {
+            pre_setup: vec![ComputeBudgetInstruction::set_compute_unit_limit(400_000)],
+            action,
+            post_setup: vec![],
+        };
+        let allowed = sanctum_swap_programs().unwrap();
+        assert_eq!(allowed.len(), SANCTUM_SWAP_PROGRAMS.len());
+
+        let input_ata = Pubkey::new_unique();
+        let output_ata = Pubkey::new_unique();
+        let owner_atas = [input_ata, output_ata];
+        let check = |layout: &SwapInstructionLayout, allowed: &[Pubkey]| {
+            check_swap_programs(layout, allowed, &owner, &owner_atas)
+        };
+        let transfer = |destination: &Pubkey| {
+            anchor_spl::token::spl_token::instruction::transfer(
+                &anchor_spl::token::ID,
+                &input_ata,
+                destination,
+                &owner,
+                &[],
+                1000,
+            )
+            .unwrap()
+        };
+
+        // router calls and token transfers into the owner's accounts are fine
+        check(
+            &layout(vec![ix(router), transfer(&output_ata), ix(router)]),
+            &allowed,
+        )
+        .unwrap();
+
+        // a transfer of the borrowed input to a third party
+        let thief = Pubkey::new_unique();
+        let err = check(
+            &layout(vec![ix(router), transfer(&thief), ix(router)]),
+            &allowed,
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            SwapError::TokensRedirected {
+                instruction_index: 2,
+                destination,
+            } if destination == thief
+        ));
+
+        // other token instructions, like approving a delegate, are rejected
+        let approve = anchor_spl::token::spl_token::instruction::approve(
+            &anchor_spl::token::ID,
+            &input_ata,
+            &thief,
+            &owner,
+            &[],
+            1000,
+        )
+        .unwrap();
+        let err = check(&layout(vec![ix(router), approve, ix(router)]), &allowed).unwrap_err();
+        assert!(matches!(
+            err,
+            SwapError::UnexpectedProgram {
+                instruction_index: 2,
+                program_id,
+            } if program_id == anchor_spl::token::ID
+        ));
+
+        // an unknown program between the swap instructions
+        let drainer = Pubkey::new_unique();
+        let err = check(&layout(vec![ix(router), ix(drainer)]), &allowed).unwrap_err();
+        assert!(matches!(
+            err,
+            SwapError::UnexpectedProgram {
+                instruction_index: 2,
+                program_id,
+            } if program_id == drainer
+        ));
+        let mut extended = allowed.clone();
+        extended.push(drainer);
+        check(&layout(vec![ix(router), ix(drainer)]), &extended).unwrap();
+
+        // calling into mango, for example to withdraw from the account, is never allowed
+        let mut with_mango = layout(vec![ix(router)]);
+        with_mango.post_setup.push(ix(mango_v4::id()));
+        let mut allowed_with_mango = allowed;
+        allowed_with_mango.push(mango_v4::id());
+        let err = check(&with_mango, &allowed_with_mango).unwrap_err();
+        assert!(matches!(
+            err,
+            SwapError::UnexpectedProgram {
+                instruction_index: 2,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_simulation_diagnostics() {
+        let router = "stkitrT1Uoy18Dk1fTrgPw8W6MVzoCfYoAFT4MLsmhq";
+        let mango = mango_v4::id().to_string();
+        let token = anchor_spl::token::ID.to_string();
+        let logs = [
+            "Program ComputeBudget111111111111111111111111111111 invoke [1]".to_string(),
+            "Program ComputeBudget111111111111111111111111111111 success".to_string(),
+            format!("Program {mango} invoke [1]"),
+            "Program log: Instruction: FlashLoanBegin".to_string(),
+            format!("Program {token} invoke [2]"),
+            "Program log: Instruction: Transfer".to_string(),
+            format!("Program {token} consumed 4645 of 380000 compute units"),
+            format!("Program {token} success"),
+            format!("Program {mango} consumed 20000 of 399850 compute units"),
+            format!("Program {mango} success"),
+            format!("Program {router} invoke [1]"),
+            "Program log: Instruction: StakeWrappedSol".to_string(),
+            format!("Program {router} consumed 30000 of 379850 compute units"),
+            format!("Program {router} success"),
+            format!("Program {mango} invoke [1]"),
+            "Program log: Instruction: FlashLoanEndV2".to_string(),
+            "Program log: pre_init_health: 1000, post_init_health: -5".to_string(),
+            "Program log: AnchorError occurred. Error Code: HealthMustBePositiveOrIncrease. Error Number: 6007. Error Message: health must be positive or not decrease.".to_string(),
+            format!("Program {mango} consumed 50000 of 349850 compute units"),
+            format!("Program {mango} failed: custom program error: 0x1777"),
+        ];
+
+        let diagnostics = SimulationDiagnostics::from_logs(&logs);
+        assert!(matches!(
+            diagnostics.mango_error,
+            Some(MangoError::HealthMustBePositiveOrIncrease)
+        ));
+        assert!(diagnostics.program_errors.is_empty());
+        assert_eq!(diagnostics.consumed_cu, 100_000);
+        assert_eq!(
+            diagnostics.other_logs,
+            vec![
+                "Program log: Instruction: FlashLoanBegin",
+                "Program log: Instruction: Transfer",
+                "Program log: Instruction: StakeWrappedSol",
+                "Program log: Instruction: FlashLoanEndV2",
+                "Program log: pre_init_health: 1000, post_init_health: -5",
+                "Program log: AnchorError occurred. Error Code: HealthMustBePositiveOrIncrease. Error Number: 6007. Error Message: health must be positive or not decrease.",
+            ]
+        );
+
+        // a failing stake pool inside the swap
+        let logs = [
+            format!("Program {router} invoke [1]"),
+            "Program log: Error: slippage tolerance exceeded".to_string(),
+            format!("Program {router} consumed 12000 of 400000 compute units"),
+            format!("Program {router} failed: custom program error: 0x1"),
+        ];
+        let diagnostics = SimulationDiagnostics::from_logs(&logs);
+        assert!(diagnostics.mango_error.is_none());
+        assert_eq!(
+            diagnostics.program_errors,
+            vec![(
+                Pubkey::from_str(router).unwrap(),
+                "custom program error: 0x1".to_string()
+            )]
+        );
+        assert_eq!(diagnostics.consumed_cu, 12_000);
+    }
+
+    #[test]
+    fn test_break_even_days() {
+        let quote = |fee_pct: &str| QuoteResponse {
+            in_amount: Some("1000".into()),
+            out_amount: "990".into(),
+            fee_amount: "1".into(),
+            fee_mint: Pubkey::new_unique().to_string(),
+            fee_pct: fee_pct.into(),
+            swap_src: "Spool".into(),
+            mode: SwapMode::ExactIn,
+        };
+
+        // 0.1% fee recovered by one percentage point of extra yield: a tenth of a year
+        let days = Sanctum::break_even_days(&quote("0.1"), 0.07, 0.08).unwrap();
+        assert!((days - 36.5).abs() < 1e-9);
+        // twice the fee takes twice as long, twice the advantage half as long
+        let days = Sanctum::break_even_days(&quote("0.2"), 0.07, 0.08).unwrap();
+        assert!((days - 73.0).abs() < 1e-9);
+        let days = Sanctum::break_even_days(&quote("0.1"), 0.06, 0.08).unwrap();
+        assert!((days - 18.25).abs() < 1e-9);
+
+        assert_eq!(
+            Sanctum::break_even_days(&quote("0"), 0.08, 0.07).unwrap(),
+            0.0
+        );
+        assert_eq!(
+            Sanctum::break_even_days(&quote("0.1"), 0.08, 0.08).unwrap(),
+            f64::INFINITY
+        );
+        assert!(Sanctum::break_even_days(&quote("x"), 0.07, 0.08).is_err());
+    }
 }
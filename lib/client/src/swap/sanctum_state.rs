@@ -1,9 +1,9 @@
 use {
-    borsh::BorshDeserialize,
+    borsh::{BorshDeserialize, BorshSerialize},
     solana_sdk::{pubkey::Pubkey, stake::state::Lockup},
 };
 
-#[derive(Clone, Debug, PartialEq, BorshDeserialize)]
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize)]
 pub enum AccountType {
     /// If the account has not been initialized, the enum will be 0
     Uninitialized,
@@ -14,7 +14,7 @@ pub enum AccountType {
 }
 
 #[repr(C)]
-#[derive(Clone, Debug, PartialEq, BorshDeserialize)]
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize)]
 pub struct StakePool {
     /// Account type, must be StakePool currently
     pub account_type: AccountType,
@@ -131,12 +131,36 @@ pub struct StakePool {
     pub last_epoch_total_lamports: u64,
 }
 
+/// Rough number of epochs per year, assuming two-day epochs
+pub const ESTIMATED_EPOCHS_PER_YEAR: f64 = 182.5;
+
+impl StakePool {
+    /// Lamports per pool token
+    pub fn exchange_rate(&self) -> Option<f64> {
+        (self.pool_token_supply > 0)
+            .then(|| self.total_lamports as f64 / self.pool_token_supply as f64)
+    }
+
+    /// Lamports per pool token in the previous epoch
+    pub fn last_epoch_exchange_rate(&self) -> Option<f64> {
+        (self.last_epoch_pool_token_supply > 0).then(|| {
+            self.last_epoch_total_lamports as f64 / self.last_epoch_pool_token_supply as f64
+        })
+    }
+
+    /// Staking yield, extrapolated from the exchange rate change over the last epoch
+    pub fn estimated_apy(&self) -> Option<f64> {
+        let growth = self.exchange_rate()? / self.last_epoch_exchange_rate()?;
+        Some(growth.powf(ESTIMATED_EPOCHS_PER_YEAR) - 1.0)
+    }
+}
+
 /// Fee rate as a ratio, minted on `UpdateStakePoolBalance` as a proportion of
 /// the rewards
 /// If either the numerator or the denominator is 0, the fee is considered to be
 /// 0
 #[repr(C)]
-#[derive(Clone, Copy, Debug, PartialEq, BorshDeserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, BorshDeserialize, BorshSerialize)]
 pub struct Fee {
     /// denominator of the fee ratio
     pub denominator: u64,
@@ -147,7 +171,7 @@ pub struct Fee {
 /// Wrapper type that "counts down" epochs, which is Borsh-compatible with the
 /// native `Option`
 #[repr(C)]
-#[derive(Clone, Copy, Debug, PartialEq, BorshDeserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, BorshDeserialize, BorshSerialize)]
 pub enum FutureEpoch<T> {
     /// Nothing is set
     None,
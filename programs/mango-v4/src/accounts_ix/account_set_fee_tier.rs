@@ -0,0 +1,20 @@
+use crate::error::*;
+use crate::state::*;
+use anchor_lang::prelude::*;
+
+#[derive(Accounts)]
+pub struct AccountSetFeeTier<'info> {
+    #[account(
+        constraint = group.load()?.is_ix_enabled(IxGate::AccountSetFeeTier) @ MangoError::IxIsDisabled,
+        has_one = admin,
+    )]
+    pub group: AccountLoader<'info, Group>,
+
+    #[account(
+        mut,
+        has_one = group,
+    )]
+    pub account: AccountLoader<'info, MangoAccountFixed>,
+
+    pub admin: Signer<'info>,
+}
@@ -2,7 +2,8 @@ use crate::error::*;
 use crate::state::*;
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::sysvar::instructions as tx_instructions;
-use anchor_spl::{associated_token::AssociatedToken, token::Token};
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token_interface::TokenInterface;
 
 pub mod jupiter_mainnet_6 {
     use solana_program::declare_id;
@@ -35,7 +36,8 @@ pub struct FlashLoanBegin<'info> {
     // owner is checked at #1
     pub owner: Signer<'info>,
 
-    pub token_program: Program<'info, Token>,
+    /// The token program of the loaned banks' vaults, token or token-2022
+    pub token_program: Interface<'info, TokenInterface>,
 
     /// Instructions Sysvar for instruction introspection
     /// CHECK: fixed instructions sysvar account
@@ -59,7 +61,8 @@ pub struct FlashLoanSwapBegin<'info> {
     pub output_mint: UncheckedAccount<'info>,
 
     pub system_program: Program<'info, System>,
-    pub token_program: Program<'info, Token>,
+    /// The token program of the loaned banks' vaults, token or token-2022
+    pub token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
 
     /// Instructions Sysvar for instruction introspection
@@ -86,13 +89,16 @@ pub struct FlashLoanEnd<'info> {
     // owner is checked at #1
     pub owner: Signer<'info>,
 
-    pub token_program: Program<'info, Token>,
+    /// The token program of the loaned banks' vaults, token or token-2022
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(PartialEq, Copy, Clone, Debug, AnchorSerialize, AnchorDeserialize)]
 #[repr(u8)]
 pub enum FlashLoanType {
     /// An arbitrary flash loan
+    ///
+    /// Requires non-negative init health at the end, like TokenWithdraw.
     Unknown,
     /// A flash loan used for a swap where one token is exchanged for another.
     ///
@@ -100,4 +106,9 @@ pub enum FlashLoanType {
     Swap,
     /// Like Swap, but without the flash_loan_swap_fee_rate
     SwapWithoutFee,
+    /// A flash loan that takes tokens out of the account.
+    ///
+    /// Requires non-negative init health at the end. Swap and SwapWithoutFee also
+    /// accept an account with negative init health as long as its health doesn't decrease.
+    TokenWithdraw,
 }
@@ -45,9 +45,16 @@ pub struct OpenbookV2RegisterMarket<'info> {
     )]
     pub index_reservation: AccountLoader<'info, OpenbookV2MarketIndexReservation>,
 
-    #[account(has_one = group)]
+    // openbook_v2 markets don't support token-2022 vaults
+    #[account(
+        has_one = group,
+        constraint = quote_bank.load()?.is_token_2022 == 0 @ MangoError::InvalidTokenProgram,
+    )]
     pub quote_bank: AccountLoader<'info, Bank>,
-    #[account(has_one = group)]
+    #[account(
+        has_one = group,
+        constraint = base_bank.load()?.is_token_2022 == 0 @ MangoError::InvalidTokenProgram,
+    )]
     pub base_bank: AccountLoader<'info, Bank>,
 
     #[account(mut)]
@@ -1,6 +1,5 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::Token;
-use anchor_spl::token::TokenAccount;
+use anchor_spl::token_interface::{TokenAccount, TokenInterface};
 
 use crate::error::*;
 use crate::state::*;
@@ -31,16 +30,19 @@ pub struct TokenDepositIntoExisting<'info> {
     pub bank: AccountLoader<'info, Bank>,
 
     #[account(mut)]
-    pub vault: Account<'info, TokenAccount>,
+    pub vault: InterfaceAccount<'info, TokenAccount>,
 
     /// CHECK: The oracle can be one of several different account types
     pub oracle: UncheckedAccount<'info>,
 
     #[account(mut)]
-    pub token_account: Box<Account<'info, TokenAccount>>,
+    pub token_account: Box<InterfaceAccount<'info, TokenAccount>>,
     pub token_authority: Signer<'info>,
 
-    pub token_program: Program<'info, Token>,
+    #[account(
+        constraint = token_program.key() == bank.load()?.vault_token_program() @ MangoError::InvalidTokenProgram,
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(Accounts)]
@@ -70,14 +72,17 @@ pub struct TokenDeposit<'info> {
     pub bank: AccountLoader<'info, Bank>,
 
     #[account(mut)]
-    pub vault: Account<'info, TokenAccount>,
+    pub vault: InterfaceAccount<'info, TokenAccount>,
 
     /// CHECK: The oracle can be one of several different account types
     pub oracle: UncheckedAccount<'info>,
 
     #[account(mut)]
-    pub token_account: Box<Account<'info, TokenAccount>>,
+    pub token_account: Box<InterfaceAccount<'info, TokenAccount>>,
     pub token_authority: Signer<'info>,
 
-    pub token_program: Program<'info, Token>,
+    #[account(
+        constraint = token_program.key() == bank.load()?.vault_token_program() @ MangoError::InvalidTokenProgram,
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
 }
@@ -1,6 +1,6 @@
 use crate::{error::MangoError, state::*};
 use anchor_lang::prelude::*;
-use anchor_spl::token::{Token, TokenAccount};
+use anchor_spl::token_interface::{TokenAccount, TokenInterface};
 
 /// In addition to these accounts, there must be remaining_accounts:
 /// all n pairs of bank and its corresponding vault account for a token
@@ -23,11 +23,12 @@ pub struct TokenDeregister<'info> {
     pub mint_info: AccountLoader<'info, MintInfo>,
 
     #[account(mut)]
-    pub dust_vault: Account<'info, TokenAccount>,
+    pub dust_vault: InterfaceAccount<'info, TokenAccount>,
 
     #[account(mut)]
     /// CHECK: target for account rent needs no checks
     pub sol_destination: UncheckedAccount<'info>,
 
-    pub token_program: Program<'info, Token>,
+    /// The token program of the banks' vaults
+    pub token_program: Interface<'info, TokenInterface>,
 }
@@ -1,7 +1,6 @@
 use anchor_lang::prelude::*;
-use anchor_spl::associated_token::get_associated_token_address;
-use anchor_spl::token::Token;
-use anchor_spl::token::TokenAccount;
+use anchor_spl::associated_token::get_associated_token_address_with_program_id;
+use anchor_spl::token_interface::{TokenAccount, TokenInterface};
 
 use crate::error::*;
 use crate::state::*;
@@ -26,29 +25,32 @@ pub struct TokenForceWithdraw<'info> {
         has_one = vault,
         has_one = oracle,
         // the mints of bank/vault/token_accounts are implicitly the same because
-        // spl::token::transfer succeeds between token_account and vault
+        // the token program's transfer succeeds between token_account and vault
     )]
     pub bank: AccountLoader<'info, Bank>,
 
     #[account(mut)]
-    pub vault: Box<Account<'info, TokenAccount>>,
+    pub vault: Box<InterfaceAccount<'info, TokenAccount>>,
 
     /// CHECK: The oracle can be one of several different account types
     pub oracle: UncheckedAccount<'info>,
 
     #[account(
         mut,
-        address = get_associated_token_address(&account.load()?.owner, &vault.mint),
+        address = get_associated_token_address_with_program_id(&account.load()?.owner, &vault.mint, &token_program.key()),
         // NOTE: the owner may have been changed (before immutable owner was a thing)
     )]
-    pub owner_ata_token_account: Box<Account<'info, TokenAccount>>,
+    pub owner_ata_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     /// Only for the unusual case where the owner_ata account is not owned by account.owner
     #[account(
         mut,
         constraint = alternate_owner_token_account.owner == account.load()?.owner,
     )]
-    pub alternate_owner_token_account: Box<Account<'info, TokenAccount>>,
+    pub alternate_owner_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    pub token_program: Program<'info, Token>,
+    #[account(
+        constraint = token_program.key() == bank.load()?.vault_token_program() @ MangoError::InvalidTokenProgram,
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
 }
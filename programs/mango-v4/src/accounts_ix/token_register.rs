@@ -1,10 +1,27 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{Mint, Token, TokenAccount};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
 
 use crate::error::*;
 use crate::state::*;
 
-const FIRST_BANK_NUM: u32 = 0;
+pub(crate) const FIRST_BANK_NUM: u32 = 0;
+
+/// Accounts that must not exist yet, checked before anchor creates them, so that a
+/// second registration fails with a clear error instead of in account creation
+#[derive(Accounts)]
+pub struct TokenRegisterPreChecks<'info> {
+    /// CHECK: The mint_info account of the parent, must be empty
+    #[account(
+        constraint = mint_info.data_is_empty() @ MangoError::MintAlreadyRegistered,
+    )]
+    pub mint_info: UncheckedAccount<'info>,
+
+    /// CHECK: The bank account of the parent, must be empty
+    #[account(
+        constraint = bank.data_is_empty() @ MangoError::TokenIndexAlreadyInUse,
+    )]
+    pub bank: UncheckedAccount<'info>,
+}
 
 #[derive(Accounts)]
 #[instruction(token_index: TokenIndex)]
@@ -16,7 +33,10 @@ pub struct TokenRegister<'info> {
     pub group: AccountLoader<'info, Group>,
     pub admin: Signer<'info>,
 
-    pub mint: Account<'info, Mint>,
+    #[account(
+        constraint = *mint.to_account_info().owner == token_program.key() @ MangoError::InvalidTokenProgram,
+    )]
+    pub mint: InterfaceAccount<'info, Mint>,
 
     #[account(
         init,
@@ -34,9 +54,10 @@ pub struct TokenRegister<'info> {
         bump,
         token::authority = group,
         token::mint = mint,
+        token::token_program = token_program,
         payer = payer
     )]
-    pub vault: Account<'info, TokenAccount>,
+    pub vault: InterfaceAccount<'info, TokenAccount>,
 
     #[account(
         init,
@@ -57,12 +78,19 @@ pub struct TokenRegister<'info> {
     #[account(mut)]
     pub payer: Signer<'info>,
 
-    pub token_program: Program<'info, Token>,
+    /// The token program or token-2022, must own the mint
+    pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
+
+    #[account(
+        constraint = pre_checks.mint_info.key() == mint_info.key(),
+        constraint = pre_checks.bank.key() == bank.key(),
+    )]
+    pub pre_checks: TokenRegisterPreChecks<'info>,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
 pub struct InterestRateParams {
     pub util0: f32,
     pub rate0: f32,
@@ -71,3 +99,27 @@ pub struct InterestRateParams {
     pub max_rate: f32,
     pub adjustment_factor: f32,
 }
+
+impl InterestRateParams {
+    /// Checks for a sensible curve: 0 <= util0 <= util1 <= 1 and
+    /// 0 <= rate0 <= rate1 <= max_rate
+    pub fn validate(&self) -> Result<()> {
+        require!(
+            self.rate0 >= 0.0 && self.rate1 >= 0.0 && self.max_rate >= 0.0,
+            MangoError::NegativeInterestRate
+        );
+        require!(
+            self.util0 >= 0.0 && self.util1 <= 1.0,
+            MangoError::InterestRateUtilizationOutOfRange
+        );
+        require!(
+            self.util0 <= self.util1,
+            MangoError::InterestRateUtilizationOrder
+        );
+        require!(
+            self.rate0 <= self.rate1 && self.rate1 <= self.max_rate,
+            MangoError::InterestRateOrder
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,20 @@
+use anchor_lang::prelude::*;
+
+use crate::error::*;
+use crate::state::*;
+
+#[derive(Accounts)]
+pub struct TokenSweepDust<'info> {
+    #[account(
+        has_one = admin,
+        constraint = group.load()?.is_ix_enabled(IxGate::TokenSweepDust) @ MangoError::IxIsDisabled,
+    )]
+    pub group: AccountLoader<'info, Group>,
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = group,
+    )]
+    pub bank: AccountLoader<'info, Bank>,
+}
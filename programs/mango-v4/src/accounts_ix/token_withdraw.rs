@@ -1,7 +1,5 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token;
-use anchor_spl::token::Token;
-use anchor_spl::token::TokenAccount;
+use anchor_spl::token_interface::{self, TokenAccount, TokenInterface};
 
 use crate::error::*;
 use crate::state::*;
@@ -37,21 +35,24 @@ pub struct TokenWithdraw<'info> {
     pub bank: AccountLoader<'info, Bank>,
 
     #[account(mut)]
-    pub vault: Account<'info, TokenAccount>,
+    pub vault: InterfaceAccount<'info, TokenAccount>,
 
     /// CHECK: The oracle can be one of several different account types
     pub oracle: UncheckedAccount<'info>,
 
     #[account(mut)]
-    pub token_account: Box<Account<'info, TokenAccount>>,
+    pub token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    pub token_program: Program<'info, Token>,
+    #[account(
+        constraint = token_program.key() == bank.load()?.vault_token_program() @ MangoError::InvalidTokenProgram,
+    )]
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
 impl<'info> TokenWithdraw<'info> {
-    pub fn transfer_ctx(&self) -> CpiContext<'_, '_, '_, 'info, token::Transfer<'info>> {
+    pub fn transfer_ctx(&self) -> CpiContext<'_, '_, '_, 'info, token_interface::Transfer<'info>> {
         let program = self.token_program.to_account_info();
-        let accounts = token::Transfer {
+        let accounts = token_interface::Transfer {
             from: self.vault.to_account_info(),
             to: self.token_account.to_account_info(),
             authority: self.group.to_account_info(),
@@ -1,9 +1,11 @@
 use anchor_lang::prelude::*;
 use core::fmt::Display;
+use num_enum::TryFromPrimitive;
 
 // todo: group error blocks by kind
 // todo: add comments which indicate decimal code for an error
 #[error_code]
+#[derive(TryFromPrimitive)]
 pub enum MangoError {
     #[msg("")]
     SomeError,
@@ -151,12 +153,38 @@ pub enum MangoError {
     InvalidSequenceNumber,
     #[msg("invalid health")]
     InvalidHealth,
+    #[msg("the token program does not match the mint or the bank's vault")]
+    InvalidTokenProgram,
+    #[msg("interest rate utilization points must be between 0 and 1")]
+    InterestRateUtilizationOutOfRange,
+    #[msg("interest rate util0 must not exceed util1")]
+    InterestRateUtilizationOrder,
+    #[msg("interest rates must satisfy rate0 <= rate1 <= max_rate")]
+    InterestRateOrder,
+    #[msg("interest rates must not be negative")]
+    NegativeInterestRate,
+    #[msg("the mint is already registered in this group")]
+    MintAlreadyRegistered,
+    #[msg("all bank slots of the token are in use")]
+    NoFreeBankSlot,
+    #[msg("invalid input")]
+    InvalidInput,
+    #[msg("borrow crosses the token's borrow limit")]
+    BankBorrowLimit,
+    #[msg("the token index is already in use in this group")]
+    TokenIndexAlreadyInUse,
 }
 
 impl MangoError {
     pub fn error_code(&self) -> u32 {
         (*self).into()
     }
+
+    /// The error with anchor error code `code`, None if it's not a mango error
+    pub fn from_error_code(code: u32) -> Option<Self> {
+        let first = MangoError::SomeError.error_code();
+        Self::try_from_primitive(code.checked_sub(first)?).ok()
+    }
 }
 
 pub trait IsAnchorErrorWithCode {
@@ -0,0 +1,18 @@
+use anchor_lang::prelude::*;
+
+use crate::accounts_ix::*;
+use crate::state::*;
+
+pub fn account_set_fee_tier(ctx: Context<AccountSetFeeTier>, fee_tier: u8) -> Result<()> {
+    require_gt!(FEE_TIER_LOAN_ORIGINATION_DISCOUNTS.len(), fee_tier as usize);
+
+    let mut account = ctx.accounts.account.load_full_mut()?;
+    msg!(
+        "Fee tier: old - {:?}, new - {:?}",
+        account.fixed.fee_tier,
+        fee_tier
+    );
+    account.fixed.fee_tier = fee_tier;
+
+    Ok(())
+}
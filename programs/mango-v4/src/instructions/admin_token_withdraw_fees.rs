@@ -1,5 +1,5 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token;
+use anchor_spl::token_interface;
 
 use crate::{accounts_ix::*, group_seeds};
 
@@ -8,9 +8,22 @@ pub fn admin_token_withdraw_fees(ctx: Context<AdminTokenWithdrawFees>) -> Result
     let mut bank = ctx.accounts.bank.load_mut()?;
 
     let group_seeds = group_seeds!(group);
+
+    // The vault must still cover what the bank owes its depositors: fees beyond the
+    // vault surplus stay for a later withdrawal
     let fees = bank.collected_fees_native.floor().to_num::<u64>() - bank.fees_withdrawn;
-    let amount = fees.min(ctx.accounts.vault.amount);
-    token::transfer(
+    let amount = bank.withdrawable_fees(ctx.accounts.vault.amount);
+    if amount < fees {
+        msg!(
+            "withdrawing {} of {} fees, limited by the vault surplus {}",
+            amount,
+            fees,
+            bank.vault_surplus(ctx.accounts.vault.amount)
+        );
+    }
+
+    #[allow(deprecated)]
+    token_interface::transfer(
         ctx.accounts.transfer_ctx().with_signer(&[group_seeds]),
         amount,
     )?;
@@ -11,7 +11,7 @@ use anchor_lang::prelude::*;
 use anchor_lang::solana_program::sysvar::instructions as tx_instructions;
 use anchor_lang::Discriminator;
 use anchor_spl::associated_token::AssociatedToken;
-use anchor_spl::token::{self, TokenAccount};
+use anchor_spl::token_interface::{self, TokenAccount};
 use fixed::types::I80F48;
 
 /// The `loan_amounts` argument lists the amount to be loaned from each bank/vault and
@@ -71,6 +71,12 @@ pub fn flash_loan_begin<'key, 'accounts, 'remaining, 'info>(
         let mut bank = bank_ai.load_mut::<Bank>()?;
         require_keys_eq!(bank.group, group_ai.key());
         require_keys_eq!(bank.vault, *vault_ai.key);
+        // All vaults must use the one token program passed in
+        require_keys_eq!(
+            bank.vault_token_program(),
+            token_program_ai.key(),
+            MangoError::InvalidTokenProgram
+        );
 
         require_msg!(
             !seen_token_indexes.contains(&bank.token_index),
@@ -78,8 +84,8 @@ pub fn flash_loan_begin<'key, 'accounts, 'remaining, 'info>(
         );
         seen_token_indexes.push(bank.token_index);
 
-        let vault = Account::<TokenAccount>::try_from(vault_ai)?;
-        let token_account = Account::<TokenAccount>::try_from(token_account_ai)?;
+        let vault = InterfaceAccount::<TokenAccount>::try_from(vault_ai)?;
+        let token_account = InterfaceAccount::<TokenAccount>::try_from(token_account_ai)?;
 
         require_keys_eq!(token_account.mint, bank.mint);
 
@@ -105,14 +111,15 @@ pub fn flash_loan_begin<'key, 'accounts, 'remaining, 'info>(
 
             let transfer_ctx = CpiContext::new(
                 token_program_ai.clone(),
-                token::Transfer {
+                token_interface::Transfer {
                     from: vault_ai.clone(),
                     to: token_account_ai.clone(),
                     authority: group_ai.clone(),
                 },
             )
             .with_signer(&seeds);
-            token::transfer(transfer_ctx, *amount)?;
+            #[allow(deprecated)]
+            token_interface::transfer(transfer_ctx, *amount)?;
         }
     }
 
@@ -323,10 +330,15 @@ pub fn flash_loan_end<'key, 'accounts, 'remaining, 'info>(
 
         vaults_with_banks[vault_index] = true;
         let token_account_ai = &token_accounts[vault_index];
-        let token_account = Account::<TokenAccount>::try_from(token_account_ai)?;
+        let token_account = InterfaceAccount::<TokenAccount>::try_from(token_account_ai)?;
 
         // The token account could have been re-initialized for a different mint
         require_keys_eq!(token_account.mint, bank.mint);
+        require_keys_eq!(
+            bank.vault_token_program(),
+            ctx.accounts.token_program.key(),
+            MangoError::InvalidTokenProgram
+        );
 
         // Ensure this bank/vault combination was mentioned in the Begin instruction:
         // The Begin instruction only checks that End ends with the same vault accounts -
@@ -344,14 +356,15 @@ pub fn flash_loan_end<'key, 'accounts, 'remaining, 'info>(
         if token_account.amount > bank.flash_loan_token_account_initial {
             let transfer_ctx = CpiContext::new(
                 ctx.accounts.token_program.to_account_info(),
-                token::Transfer {
+                token_interface::Transfer {
                     from: token_account_ai.clone(),
                     to: vault_ai.clone(),
                     authority: ctx.accounts.owner.to_account_info(),
                 },
             );
             let repay = token_account.amount - bank.flash_loan_token_account_initial;
-            token::transfer(transfer_ctx, repay)?;
+            #[allow(deprecated)]
+            token_interface::transfer(transfer_ctx, repay)?;
 
             let repay = I80F48::from(repay);
             change += repay;
@@ -379,7 +392,7 @@ pub fn flash_loan_end<'key, 'accounts, 'remaining, 'info>(
     }
 
     match flash_loan_type {
-        FlashLoanType::Unknown => {}
+        FlashLoanType::Unknown | FlashLoanType::TokenWithdraw => {}
         FlashLoanType::Swap | FlashLoanType::SwapWithoutFee => {
             require_msg!(
                 changes.len() == 2,
@@ -424,6 +437,8 @@ pub fn flash_loan_end<'key, 'accounts, 'remaining, 'info>(
     // Drop retriever as mut bank below uses health_ais
     drop(retriever);
 
+    let loan_origination_fee_factor = I80F48::ONE - account.fixed.loan_origination_fee_discount();
+
     // Apply the vault diffs to the bank positions
     let mut deactivated_token_positions = vec![];
     let mut token_loan_details = Vec::with_capacity(changes.len());
@@ -442,7 +457,8 @@ pub fn flash_loan_end<'key, 'accounts, 'remaining, 'info>(
             approved_amount
         };
 
-        let loan_origination_fee = loan * bank.loan_origination_fee_rate;
+        let loan_origination_fee =
+            loan * bank.loan_origination_fee_rate * loan_origination_fee_factor;
         bank.collected_fees_native += loan_origination_fee;
 
         let swap_fee = if change.amount < 0 && flash_loan_type == FlashLoanType::Swap {
@@ -479,6 +495,7 @@ pub fn flash_loan_end<'key, 'accounts, 'remaining, 'info>(
         if change_amount < 0 && native_after_change < 0 {
             bank.enforce_max_utilization_on_borrow()?;
             bank.check_net_borrows(*oracle_price)?;
+            bank.check_borrow_limit()?;
         } else {
             bank.enforce_borrows_lte_deposits()?;
         }
@@ -530,7 +547,15 @@ pub fn flash_loan_end<'key, 'accounts, 'remaining, 'info>(
         &retriever,
         now_ts,
     )?;
-    account.check_health_post(&health_cache, pre_init_health)?;
+    let post_init_health = account.check_health_post(&health_cache, pre_init_health)?;
+    if matches!(
+        flash_loan_type,
+        FlashLoanType::Unknown | FlashLoanType::TokenWithdraw
+    ) {
+        // Withdrawals and flash loans of unknown purpose must not leave the account
+        // below initial margin
+        account.check_health_post_checks_strict(post_init_health)?;
+    }
 
     // Deactivate inactive token accounts after health check
     for raw_token_index in deactivated_token_positions {
@@ -98,6 +98,8 @@ pub fn ix_gate_set(ctx: Context<IxGateSet>, ix_gate: u128) -> Result<()> {
     log_if_changed(&group, ix_gate, IxGate::TokenForceWithdraw);
     log_if_changed(&group, ix_gate, IxGate::SequenceCheck);
     log_if_changed(&group, ix_gate, IxGate::HealthCheck);
+    log_if_changed(&group, ix_gate, IxGate::AccountSetFeeTier);
+    log_if_changed(&group, ix_gate, IxGate::TokenSweepDust);
 
     group.ix_gate = ix_gate;
 
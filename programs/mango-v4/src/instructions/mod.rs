@@ -3,6 +3,7 @@ pub use account_close::*;
 pub use account_create::*;
 pub use account_edit::*;
 pub use account_expand::*;
+pub use account_set_fee_tier::*;
 pub use account_size_migration::*;
 pub use account_toggle_freeze::*;
 pub use admin_perp_withdraw_fees::*;
@@ -66,6 +67,7 @@ pub use token_liq_bankruptcy::*;
 pub use token_liq_with_token::*;
 pub use token_register::*;
 pub use token_register_trustless::*;
+pub use token_sweep_dust::*;
 pub use token_update_index_and_rate::*;
 pub use token_withdraw::*;
 
@@ -74,6 +76,7 @@ mod account_close;
 mod account_create;
 mod account_edit;
 mod account_expand;
+mod account_set_fee_tier;
 mod account_size_migration;
 mod account_toggle_freeze;
 mod admin_perp_withdraw_fees;
@@ -137,5 +140,6 @@ mod token_liq_bankruptcy;
 mod token_liq_with_token;
 mod token_register;
 mod token_register_trustless;
+mod token_sweep_dust;
 mod token_update_index_and_rate;
 mod token_withdraw;
@@ -353,6 +353,7 @@ pub fn serum3_place_order(
         );
         payer_bank.enforce_max_utilization_on_borrow()?;
         payer_bank.check_net_borrows(payer_bank_oracle)?;
+        payer_bank.check_borrow_limit()?;
 
         // Deposit limit check, payer side:
         // The payer bank deposits could increase when cancelling the order later:
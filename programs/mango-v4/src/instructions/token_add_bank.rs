@@ -11,16 +11,24 @@ pub fn token_add_bank(
     bank_num: u32,
 ) -> Result<()> {
     let existing_bank = ctx.accounts.existing_bank.load()?;
+    // the new vault is created with the token program
+    require_msg!(
+        existing_bank.is_token_2022 == 0,
+        "can't add banks to tokens with a token-2022 vault"
+    );
     let mut bank = ctx.accounts.bank.load_init()?;
     let bump = *ctx.bumps.get("bank").ok_or(MangoError::SomeError)?;
     *bank = Bank::from_existing_bank(&existing_bank, ctx.accounts.vault.key(), bank_num, bump);
 
     let mut mint_info = ctx.accounts.mint_info.load_mut()?;
-    let free_slot = mint_info
-        .banks
-        .iter()
-        .position(|bank| bank == &Pubkey::default())
-        .unwrap();
+    let free_slot = mint_info.num_banks();
+    require_msg_typed!(
+        free_slot < MAX_BANKS,
+        MangoError::NoFreeBankSlot,
+        "token {} already has {} banks",
+        token_index,
+        MAX_BANKS
+    );
     require_eq!(bank_num as usize, free_slot);
     mint_info.banks[free_slot] = ctx.accounts.bank.key();
     mint_info.vaults[free_slot] = ctx.accounts.vault.key();
@@ -1,7 +1,5 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token;
-use anchor_spl::token::Token;
-use anchor_spl::token::TokenAccount;
+use anchor_spl::token_interface::{self, TokenAccount, TokenInterface};
 use fixed::types::I80F48;
 
 use crate::accounts_zerocopy::AccountInfoRef;
@@ -17,17 +15,17 @@ struct DepositCommon<'a, 'info> {
     pub group: &'a AccountLoader<'info, Group>,
     pub account: &'a AccountLoader<'info, MangoAccountFixed>,
     pub bank: &'a AccountLoader<'info, Bank>,
-    pub vault: &'a Account<'info, TokenAccount>,
+    pub vault: &'a InterfaceAccount<'info, TokenAccount>,
     pub oracle: &'a UncheckedAccount<'info>,
-    pub token_account: &'a Box<Account<'info, TokenAccount>>,
+    pub token_account: &'a Box<InterfaceAccount<'info, TokenAccount>>,
     pub token_authority: &'a Signer<'info>,
-    pub token_program: &'a Program<'info, Token>,
+    pub token_program: &'a Interface<'info, TokenInterface>,
 }
 
 impl<'a, 'info> DepositCommon<'a, 'info> {
-    fn transfer_ctx(&self) -> CpiContext<'_, '_, '_, 'info, token::Transfer<'info>> {
+    fn transfer_ctx(&self) -> CpiContext<'_, '_, '_, 'info, token_interface::Transfer<'info>> {
         let program = self.token_program.to_account_info();
-        let accounts = token::Transfer {
+        let accounts = token_interface::Transfer {
             from: self.token_account.to_account_info(),
             to: self.vault.to_account_info(),
             authority: self.token_authority.to_account_info(),
@@ -85,17 +83,17 @@ impl<'a, 'info> DepositCommon<'a, 'info> {
         };
 
         // Transfer the actual tokens
-        token::transfer(self.transfer_ctx(), amount_i80f48.to_num::<u64>())?;
+        // Plain transfers work for token-2022 mints without transfer fee or hook extensions
+        #[allow(deprecated)]
+        token_interface::transfer(self.transfer_ctx(), amount_i80f48.to_num::<u64>())?;
 
         let indexed_position = position.indexed_position;
 
         // Get the oracle price, even if stale or unconfident: We want to allow users
         // to deposit to close borrows or do other fixes even if the oracle is bad.
         let oracle_ref = &AccountInfoRef::borrow(self.oracle.as_ref())?;
-        let unsafe_oracle_state = oracle_state_unchecked(
-            &OracleAccountInfos::from_reader(oracle_ref),
-            bank.mint_decimals,
-        )?;
+        let unsafe_oracle_state =
+            bank.oracle_state_unchecked(&OracleAccountInfos::from_reader(oracle_ref))?;
         let unsafe_oracle_price = unsafe_oracle_state.price;
 
         // If increasing total deposits, check deposit limits
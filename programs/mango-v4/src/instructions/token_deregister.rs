@@ -1,7 +1,8 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, CloseAccount, TokenAccount};
+use anchor_spl::token_interface::{self, CloseAccount, TokenAccount};
 
 use crate::accounts_ix::*;
+use crate::error::MangoError;
 use crate::{accounts_zerocopy::LoadZeroCopyRef, state::*};
 use anchor_lang::AccountsClose;
 
@@ -34,14 +35,22 @@ pub fn token_deregister<'key, 'accounts, 'remaining, 'info>(
             require_keys_eq!(bank.group, ctx.accounts.group.key());
             require_eq!(bank.token_index, mint_info.token_index);
             require_keys_eq!(bank.vault, vault_ai.key());
+            require_keys_eq!(
+                bank.vault_token_program(),
+                ctx.accounts.token_program.key(),
+                MangoError::InvalidTokenProgram
+            );
         }
 
         // transfer dust to another token account
-        let amount = Account::<TokenAccount>::try_from(vault_ai).unwrap().amount;
+        let amount = InterfaceAccount::<TokenAccount>::try_from(vault_ai)
+            .unwrap()
+            .amount;
         if amount > 0 {
-            token::transfer(
+            #[allow(deprecated)]
+            token_interface::transfer(
                 {
-                    let accounts = token::Transfer {
+                    let accounts = token_interface::Transfer {
                         from: vault_ai.to_account_info(),
                         to: dust_vault_ai.to_account_info(),
                         authority: ctx.accounts.group.to_account_info(),
@@ -60,7 +69,7 @@ pub fn token_deregister<'key, 'accounts, 'remaining, 'info>(
             authority: ctx.accounts.group.to_account_info(),
         };
         let cpi_program = ctx.accounts.token_program.to_account_info();
-        token::close_account(CpiContext::new_with_signer(
+        token_interface::close_account(CpiContext::new_with_signer(
             cpi_program,
             cpi_accounts,
             &[group_seeds],
@@ -56,6 +56,8 @@ pub fn token_edit(
     disable_asset_liquidation_opt: Option<bool>,
     collateral_fee_per_day: Option<f32>,
     force_withdraw_opt: Option<bool>,
+    borrow_limit_opt: Option<u64>,
+    fixed_price_opt: Option<I80F48>,
 ) -> Result<()> {
     let group = ctx.accounts.group.load()?;
 
@@ -83,6 +85,31 @@ pub fn token_edit(
             mint_info.oracle = oracle;
             require_group_admin = true;
         }
+        if let Some(fixed_price) = fixed_price_opt {
+            msg!(
+                "Fixed price: old - {:?}, new - {:?}",
+                bank.fixed_price,
+                fixed_price
+            );
+            require_gte!(fixed_price, I80F48::ZERO);
+            bank.fixed_price = fixed_price;
+            if fixed_price.is_positive() {
+                bank.oracle = fixed_price_oracle::ID;
+                mint_info.oracle = fixed_price_oracle::ID;
+                bank.stable_price_model.reset_to_price(
+                    fixed_price.to_num(),
+                    Clock::get()?.unix_timestamp.try_into().unwrap(),
+                );
+            } else {
+                // Going back to an oracle needs oracle_opt, and usually reset_stable_price
+                require_msg_typed!(
+                    bank.oracle != fixed_price_oracle::ID,
+                    MangoError::UnexpectedOracle,
+                    "removing the fixed price requires setting an oracle"
+                );
+            }
+            require_group_admin = true;
+        }
         if set_fallback_oracle {
             msg!(
                 "Fallback oracle old {:?}, new {:?}",
@@ -120,7 +147,7 @@ pub fn token_edit(
         };
 
         if let Some(ref interest_rate_params) = interest_rate_params_opt {
-            // TODO: add a require! verifying relation between the parameters
+            interest_rate_params.validate()?;
             msg!("Interest rate params: old - adjustment_factor {:?}, util0 {:?}, rate0 {:?}, util1 {:?}, rate1 {:?}, max_rate {:?}, new - adjustment_factor {:?}, util0 {:?}, rate0 {:?}, util1 {:?}, rate1 {:?}, max_rate {:?}",
             bank.adjustment_factor,
             bank.util0,
@@ -468,6 +495,16 @@ pub fn token_edit(
             require_group_admin = true;
         }
 
+        if let Some(borrow_limit) = borrow_limit_opt {
+            msg!(
+                "Borrow limit old {:?}, new {:?}",
+                bank.borrow_limit,
+                borrow_limit
+            );
+            bank.borrow_limit = borrow_limit;
+            require_group_admin = true;
+        }
+
         if let Some(zero_util_rate) = zero_util_rate {
             msg!(
                 "Zero utilization rate old {:?}, new {:?}",
@@ -2,7 +2,7 @@ use crate::accounts_zerocopy::AccountInfoRef;
 use crate::error::*;
 use crate::state::*;
 use anchor_lang::prelude::*;
-use anchor_spl::token;
+use anchor_spl::token_interface;
 use fixed::types::I80F48;
 
 use crate::accounts_ix::*;
@@ -47,10 +47,11 @@ pub fn token_force_withdraw(ctx: Context<TokenForceWithdraw>) -> Result<()> {
 
     // Transfer the actual tokens
     let group_seeds = group_seeds!(group);
-    token::transfer(
+    #[allow(deprecated)]
+    token_interface::transfer(
         CpiContext::new(
             ctx.accounts.token_program.to_account_info(),
-            token::Transfer {
+            token_interface::Transfer {
                 from: ctx.accounts.vault.to_account_info(),
                 to: withdraw_target.clone(),
                 authority: ctx.accounts.group.to_account_info(),
@@ -72,10 +73,8 @@ pub fn token_force_withdraw(ctx: Context<TokenForceWithdraw>) -> Result<()> {
     // Get the oracle price, even if stale or unconfident: We want to allow force withdraws
     // even if the oracle is bad.
     let oracle_ref = &AccountInfoRef::borrow(ctx.accounts.oracle.as_ref())?;
-    let unsafe_oracle_state = oracle_state_unchecked(
-        &OracleAccountInfos::from_reader(oracle_ref),
-        bank.mint_decimals,
-    )?;
+    let unsafe_oracle_state =
+        bank.oracle_state_unchecked(&OracleAccountInfos::from_reader(oracle_ref))?;
 
     // Update the net deposits - adjust by price so different tokens are on the same basis (in USD terms)
     let amount_usd = (amount_i80f48 * unsafe_oracle_state.price).to_num::<i64>();
@@ -1,4 +1,6 @@
 use anchor_lang::prelude::*;
+use anchor_spl::token_2022::spl_token_2022;
+use anchor_spl::token_2022::spl_token_2022::extension::ExtensionType;
 use fixed::types::I80F48;
 
 use crate::accounts_zerocopy::AccountInfoRef;
@@ -13,8 +15,8 @@ pub const INDEX_START: I80F48 = I80F48::from_bits(1_000_000 * I80F48::ONE.to_bit
 use crate::accounts_ix::*;
 
 #[allow(clippy::too_many_arguments)]
-pub fn token_register(
-    ctx: Context<TokenRegister>,
+pub fn token_register<'key, 'accounts, 'remaining, 'info>(
+    ctx: Context<'key, 'accounts, 'remaining, 'info, TokenRegister<'info>>,
     token_index: TokenIndex,
     name: String,
     oracle_config: OracleConfigParams,
@@ -46,6 +48,8 @@ pub fn token_register(
     platform_liquidation_fee: f32,
     disable_asset_liquidation: bool,
     collateral_fee_per_day: f32,
+    fixed_price: Option<I80F48>,
+    borrow_limit: u64,
 ) -> Result<()> {
     // Require token 0 to be in the insurance token
     if token_index == INSURANCE_TOKEN_INDEX {
@@ -55,6 +59,17 @@ pub fn token_register(
         );
     }
     require_neq!(token_index, TokenIndex::MAX);
+    interest_rate_params.validate()?;
+    match fixed_price {
+        Some(price) => {
+            require_gt!(price, I80F48::ZERO);
+            require_keys_eq!(ctx.accounts.oracle.key(), fixed_price_oracle::ID);
+        }
+        None => require_keys_neq!(ctx.accounts.oracle.key(), fixed_price_oracle::ID),
+    }
+
+    check_mint_extensions(&ctx.accounts.mint.to_account_info())?;
+    let is_token_2022 = ctx.accounts.token_program.key() == spl_token_2022::ID;
 
     let now_ts: u64 = Clock::get()?.unix_timestamp.try_into().unwrap();
 
@@ -71,7 +86,6 @@ pub fn token_register(
         indexed_borrows: I80F48::ZERO,
         index_last_updated: now_ts,
         bank_rate_last_updated: now_ts,
-        // TODO: add a require! verifying relation between the parameters
         avg_utilization: I80F48::ZERO,
         adjustment_factor: I80F48::from_num(interest_rate_params.adjustment_factor),
         util0: I80F48::from_num(interest_rate_params.util0),
@@ -133,7 +147,11 @@ pub fn token_register(
         collected_liquidation_fees: I80F48::ZERO,
         collected_collateral_fees: I80F48::ZERO,
         collateral_fee_per_day,
-        reserved: [0; 1900],
+        is_token_2022: u8::from(is_token_2022),
+        padding2: Default::default(),
+        fixed_price: fixed_price.unwrap_or(I80F48::ZERO),
+        borrow_limit,
+        reserved: [0; 1872],
     };
 
     let oracle_ref = &AccountInfoRef::borrow(ctx.accounts.oracle.as_ref())?;
@@ -167,6 +185,10 @@ pub fn token_register(
 
     mint_info.banks[0] = ctx.accounts.bank.key();
     mint_info.vaults[0] = ctx.accounts.vault.key();
+    drop(mint_info);
+
+    #[cfg(feature = "token-register-alt")]
+    extend_address_lookup_table(&ctx)?;
 
     emit_stack(TokenMetaDataLogV2 {
         mango_group: ctx.accounts.group.key(),
@@ -180,3 +202,95 @@ pub fn token_register(
 
     Ok(())
 }
+
+/// Token-2022 mint extensions that banks accept.
+///
+/// Everything else is rejected: transfer fees, transfer hooks, permanent delegates,
+/// default frozen accounts and the like would let the vault balance diverge from the
+/// bank's bookkeeping or block vault transfers. Mint close authorities are harmless since
+/// closing requires zero supply.
+const ALLOWED_MINT_EXTENSIONS: [ExtensionType; 1] = [ExtensionType::MintCloseAuthority];
+
+/// Fails if `mint` has extensions outside of ALLOWED_MINT_EXTENSIONS.
+///
+/// None of the allowed extensions require account extensions, so the vault has the
+/// size of a plain token account.
+fn check_mint_extensions(mint: &AccountInfo) -> Result<()> {
+    use spl_token_2022::extension::{BaseStateWithExtensions, StateWithExtensions};
+
+    if *mint.owner != spl_token_2022::ID {
+        return Ok(());
+    }
+    let data = mint.try_borrow_data()?;
+    // fails for extension types unknown to this version of spl-token-2022
+    let mint_state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&data)?;
+    for extension in mint_state.get_extension_types()?.iter() {
+        require_msg!(
+            ALLOWED_MINT_EXTENSIONS.contains(extension),
+            "mint extension {:?} is not supported",
+            extension
+        );
+    }
+    Ok(())
+}
+
+/// Appends the new bank and oracle to a group address lookup table.
+///
+/// The table is optional and passed in the remaining accounts, followed by the
+/// address lookup table program. It must be one of the group's tables and have
+/// the group as authority.
+#[cfg(feature = "token-register-alt")]
+fn extend_address_lookup_table<'info>(
+    ctx: &Context<'_, '_, '_, 'info, TokenRegister<'info>>,
+) -> Result<()> {
+    use crate::address_lookup_table_program;
+    use solana_address_lookup_table_program as solana_alt;
+
+    let (alt_ai, alt_program_ai) = match ctx.remaining_accounts {
+        [] => return Ok(()),
+        [alt_ai, alt_program_ai] => (alt_ai, alt_program_ai),
+        _ => {
+            return Err(error_msg!(
+                "expected an address lookup table and its program"
+            ))
+        }
+    };
+    require_keys_eq!(*alt_program_ai.key, solana_alt::ID);
+    require_keys_eq!(*alt_ai.owner, solana_alt::ID);
+
+    let group = ctx.accounts.group.load()?;
+    require_msg!(
+        group.address_lookup_tables.contains(alt_ai.key),
+        "address lookup table {} is not registered in the group",
+        alt_ai.key
+    );
+
+    let bank_index = {
+        let alt_data = alt_ai.try_borrow_data()?;
+        let alt = solana_alt::state::AddressLookupTable::deserialize(&alt_data)
+            .map_err(|e| error_msg!("could not deserialize alt: {}", e))?;
+        require_msg!(
+            alt.meta.authority == Some(ctx.accounts.group.key()),
+            "address lookup table must have the group as authority"
+        );
+        alt.addresses.len()
+    };
+    let oracle_index = bank_index + 1;
+
+    let group_seeds = group_seeds!(group);
+    address_lookup_table_program::cpi_extend(
+        alt_ai.clone(),
+        ctx.accounts.group.to_account_info(),
+        ctx.accounts.payer.to_account_info(),
+        &[group_seeds],
+        vec![ctx.accounts.bank.key(), ctx.accounts.oracle.key()],
+    )?;
+    msg!(
+        "added bank at index {} and oracle at index {} of address lookup table {}",
+        bank_index,
+        oracle_index,
+        alt_ai.key
+    );
+
+    Ok(())
+}
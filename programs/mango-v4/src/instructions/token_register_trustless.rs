@@ -111,7 +111,11 @@ pub fn token_register_trustless(
         collected_liquidation_fees: I80F48::ZERO,
         collected_collateral_fees: I80F48::ZERO,
         collateral_fee_per_day: 0.0, // TODO
-        reserved: [0; 1900],
+        is_token_2022: 0,
+        padding2: Default::default(),
+        fixed_price: I80F48::ZERO,
+        borrow_limit: 0,
+        reserved: [0; 1872],
     };
     let oracle_ref = &AccountInfoRef::borrow(ctx.accounts.oracle.as_ref())?;
     if let Ok(oracle_price) = bank.oracle_price(&OracleAccountInfos::from_reader(oracle_ref), None)
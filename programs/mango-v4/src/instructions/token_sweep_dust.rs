@@ -0,0 +1,23 @@
+use anchor_lang::prelude::*;
+
+use crate::accounts_ix::*;
+
+/// Moves the whole native tokens collected in the bank's dust into its collected fees,
+/// where admin_token_withdraw_fees can withdraw them.
+///
+/// The fractional remainder stays in dust.
+pub fn token_sweep_dust(ctx: Context<TokenSweepDust>) -> Result<()> {
+    let mut bank = ctx.accounts.bank.load_mut()?;
+
+    let sweep = bank.dust.floor();
+    if sweep <= 0 {
+        msg!("dust of {} is below one native token", bank.dust);
+        return Ok(());
+    }
+
+    bank.dust -= sweep;
+    bank.collected_fees_native += sweep;
+    msg!("swept {} native tokens of dust into collected fees", sweep);
+
+    Ok(())
+}
@@ -5,7 +5,7 @@ use crate::state::*;
 use crate::util::clock_now;
 use anchor_lang::prelude::*;
 use anchor_spl::associated_token;
-use anchor_spl::token;
+use anchor_spl::token_interface;
 use fixed::types::I80F48;
 
 use crate::accounts_ix::*;
@@ -43,6 +43,7 @@ pub fn token_withdraw(ctx: Context<TokenWithdraw>, amount: u64, allow_borrow: bo
         None
     };
 
+    let fee_discount = account.fixed.loan_origination_fee_discount();
     let mut bank = ctx.accounts.bank.load_mut()?;
     let position = account.token_position_mut_by_raw_index(raw_token_index);
     let native_position = position.native(&bank);
@@ -71,15 +72,14 @@ pub fn token_withdraw(ctx: Context<TokenWithdraw>, amount: u64, allow_borrow: bo
     // Get the oracle price, even if stale or unconfident: We want to allow users
     // to withdraw deposits (while staying healthy otherwise) if the oracle is bad.
     let oracle_ref = &AccountInfoRef::borrow(ctx.accounts.oracle.as_ref())?;
-    let unsafe_oracle_state = oracle_state_unchecked(
-        &OracleAccountInfos::from_reader(oracle_ref),
-        bank.mint_decimals,
-    )?;
+    let unsafe_oracle_state =
+        bank.oracle_state_unchecked(&OracleAccountInfos::from_reader(oracle_ref))?;
 
     // Update the bank and position
-    let withdraw_result = bank.withdraw_with_fee(
+    let withdraw_result = bank.withdraw_with_discounted_fee(
         position,
         amount_i80f48,
+        fee_discount,
         Clock::get()?.unix_timestamp.try_into().unwrap(),
     )?;
     let native_position_after = position.native(&bank);
@@ -100,7 +100,9 @@ pub fn token_withdraw(ctx: Context<TokenWithdraw>, amount: u64, allow_borrow: bo
 
     // Transfer the actual tokens
     let group_seeds = group_seeds!(group);
-    token::transfer(
+    // Plain transfers work for token-2022 mints without transfer fee or hook extensions
+    #[allow(deprecated)]
+    token_interface::transfer(
         ctx.accounts.transfer_ctx().with_signer(&[group_seeds]),
         amount,
     )?;
@@ -121,9 +123,10 @@ pub fn token_withdraw(ctx: Context<TokenWithdraw>, amount: u64, allow_borrow: bo
     // Delegates have heavy restrictions on withdraws. #1
     if account.fixed.is_delegate(ctx.accounts.owner.key()) {
         // Delegates can only withdrawing into the actual owner's ATA
-        let owner_ata = associated_token::get_associated_token_address(
+        let owner_ata = associated_token::get_associated_token_address_with_program_id(
             &account.fixed.owner,
             &ctx.accounts.vault.mint,
+            &ctx.accounts.token_program.key(),
         );
         require_keys_eq!(
             ctx.accounts.token_account.key(),
@@ -220,6 +223,7 @@ pub fn token_withdraw(ctx: Context<TokenWithdraw>, amount: u64, allow_borrow: bo
                 )
             })?;
         bank.check_net_borrows(unsafe_oracle_state.price)?;
+        bank.check_borrow_limit()?;
     } else {
         bank.enforce_borrows_lte_deposits()?;
     }
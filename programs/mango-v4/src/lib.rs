@@ -128,8 +128,8 @@ pub mod mango_v4 {
     }
 
     #[allow(clippy::too_many_arguments)]
-    pub fn token_register(
-        ctx: Context<TokenRegister>,
+    pub fn token_register<'key, 'accounts, 'remaining, 'info>(
+        ctx: Context<'key, 'accounts, 'remaining, 'info, TokenRegister<'info>>,
         token_index: TokenIndex,
         name: String,
         oracle_config: OracleConfigParams,
@@ -161,6 +161,8 @@ pub mod mango_v4 {
         platform_liquidation_fee: f32,
         disable_asset_liquidation: bool,
         collateral_fee_per_day: f32,
+        fixed_price: Option<I80F48>,
+        borrow_limit: u64,
     ) -> Result<()> {
         #[cfg(feature = "enable-gpl")]
         instructions::token_register(
@@ -196,6 +198,8 @@ pub mod mango_v4 {
             platform_liquidation_fee,
             disable_asset_liquidation,
             collateral_fee_per_day,
+            fixed_price,
+            borrow_limit,
         )?;
         Ok(())
     }
@@ -254,6 +258,8 @@ pub mod mango_v4 {
         disable_asset_liquidation_opt: Option<bool>,
         collateral_fee_per_day_opt: Option<f32>,
         force_withdraw_opt: Option<bool>,
+        borrow_limit_opt: Option<u64>,
+        fixed_price_opt: Option<I80F48>,
     ) -> Result<()> {
         #[cfg(feature = "enable-gpl")]
         instructions::token_edit(
@@ -299,6 +305,8 @@ pub mod mango_v4 {
             disable_asset_liquidation_opt,
             collateral_fee_per_day_opt,
             force_withdraw_opt,
+            borrow_limit_opt,
+            fixed_price_opt,
         )?;
         Ok(())
     }
@@ -443,6 +451,12 @@ pub mod mango_v4 {
         Ok(())
     }
 
+    pub fn account_set_fee_tier(ctx: Context<AccountSetFeeTier>, fee_tier: u8) -> Result<()> {
+        #[cfg(feature = "enable-gpl")]
+        instructions::account_set_fee_tier(ctx, fee_tier)?;
+        Ok(())
+    }
+
     pub fn account_close(ctx: Context<AccountClose>, force_close: bool) -> Result<()> {
         #[cfg(feature = "enable-gpl")]
         instructions::account_close(ctx, force_close)?;
@@ -841,6 +855,12 @@ pub mod mango_v4 {
         Ok(())
     }
 
+    pub fn token_sweep_dust(ctx: Context<TokenSweepDust>) -> Result<()> {
+        #[cfg(feature = "enable-gpl")]
+        instructions::token_sweep_dust(ctx)?;
+        Ok(())
+    }
+
     ///
     /// Perps
     ///
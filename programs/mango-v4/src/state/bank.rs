@@ -1,8 +1,9 @@
 use super::{OracleAccountInfos, OracleConfig, TokenIndex, TokenPosition};
+use crate::accounts_ix::InterestRateParams;
 use crate::accounts_zerocopy::KeyedAccountReader;
 use crate::error::*;
 use crate::i80f48::ClampToInt;
-use crate::state::{oracle, StablePriceModel};
+use crate::state::{oracle, OracleState, OracleType, StablePriceModel};
 use crate::util;
 
 use anchor_lang::prelude::*;
@@ -231,8 +232,25 @@ pub struct Bank {
     /// The daily collateral fees rate for fully utilized collateral.
     pub collateral_fee_per_day: f32,
 
+    /// Whether the vault is a token-2022 account instead of a token program account
+    ///
+    /// Flash loans, token_add_bank and serum3/openbook_v2 markets don't support these banks.
+    pub is_token_2022: u8,
+
     #[derivative(Debug = "ignore")]
-    pub reserved: [u8; 1900],
+    pub padding2: [u8; 3],
+
+    /// If nonzero, the price of the token in native quote per native token.
+    ///
+    /// Such banks have no oracle: their oracle is set to fixed_price_oracle::ID and the
+    /// oracle account is never read.
+    pub fixed_price: I80F48,
+
+    /// zero means none, in token native
+    pub borrow_limit: u64,
+
+    #[derivative(Debug = "ignore")]
+    pub reserved: [u8; 1872],
 }
 const_assert_eq!(
     size_of::<Bank>(),
@@ -271,7 +289,11 @@ const_assert_eq!(
         + 8
         + 16 * 4
         + 4
-        + 1900
+        + 1
+        + 3
+        + 16
+        + 8
+        + 1872
 );
 const_assert_eq!(size_of::<Bank>(), 3064);
 const_assert_eq!(size_of::<Bank>() % 8, 0);
@@ -382,7 +404,11 @@ impl Bank {
             zero_util_rate: existing_bank.zero_util_rate,
             platform_liquidation_fee: existing_bank.platform_liquidation_fee,
             collateral_fee_per_day: existing_bank.collateral_fee_per_day,
-            reserved: [0; 1900],
+            is_token_2022: existing_bank.is_token_2022,
+            padding2: Default::default(),
+            fixed_price: existing_bank.fixed_price,
+            borrow_limit: existing_bank.borrow_limit,
+            reserved: [0; 1872],
         }
     }
 
@@ -426,6 +452,13 @@ impl Bank {
             require_eq!(self.maint_asset_weight, I80F48::ZERO);
         }
         require_gte!(self.collateral_fee_per_day, 0.0);
+        require_gte!(self.fixed_price, I80F48::ZERO);
+        require_gte!(1, self.is_token_2022);
+        require_eq!(
+            self.has_fixed_price(),
+            self.oracle == oracle::fixed_price_oracle::ID,
+            MangoError::UnexpectedOracle
+        );
         if self.is_force_withdraw() {
             require!(self.are_deposits_reduce_only(), MangoError::SomeError);
             require!(!self.allows_asset_liquidation(), MangoError::SomeError);
@@ -520,6 +553,59 @@ impl Bank {
         Ok(())
     }
 
+    /// The interest rate curve as passed to token_register or token_edit.
+    ///
+    /// Values are converted back from I80F48 and may differ from the original f32 by
+    /// rounding. The curve scaling is not applied, see interest_curve_scaling.
+    pub fn interest_rate_params(&self) -> InterestRateParams {
+        InterestRateParams {
+            util0: self.util0.to_num(),
+            rate0: self.rate0.to_num(),
+            util1: self.util1.to_num(),
+            rate1: self.rate1.to_num(),
+            max_rate: self.max_rate.to_num(),
+            adjustment_factor: self.adjustment_factor.to_num(),
+        }
+    }
+
+    /// Non-negative native balances below this are dusted when a position closes:
+    /// zeroed, with the remainder collected in `dust`.
+    ///
+    /// Note that `dust` itself is the sum of dusted amounts, not a threshold.
+    pub fn dust_threshold(&self) -> I80F48 {
+        I80F48::ONE
+    }
+
+    /// Whether a position with `native` balance would be dusted when it closes
+    pub fn is_dust(&self, native: I80F48) -> bool {
+        native >= 0 && native < self.dust_threshold()
+    }
+
+    /// Native amount that can currently be borrowed from this bank.
+    ///
+    /// That's what's left until enforce_max_utilization_on_borrow() or, if set,
+    /// check_borrow_limit() would fail, capped by the vault balance. Net borrow limits
+    /// are not considered, since they need a price.
+    pub fn available_liquidity(&self, vault_balance: u64) -> I80F48 {
+        let max_utilization = I80F48::ONE - I80F48::from_num(self.min_vault_to_deposits_ratio);
+        let native_borrows = self.native_borrows();
+        let mut available = max_utilization * self.native_deposits() - native_borrows;
+        if self.borrow_limit > 0 {
+            available = available.min(I80F48::from(self.borrow_limit) - native_borrows);
+        }
+        available.min(I80F48::from(vault_balance)).max(I80F48::ZERO)
+    }
+
+    /// Native amount owed at FlashLoanEnd for a flash loan of `borrowed`: the loan
+    /// plus the loan origination fee, rounded up.
+    ///
+    /// Account-level loan origination fee discounts are not considered, so this is
+    /// an upper bound for accounts that have one.
+    pub fn flash_loan_repayment(&self, borrowed: u64) -> u64 {
+        let fee = (I80F48::from(borrowed) * self.loan_origination_fee_rate).ceil();
+        borrowed.saturating_add(fee.checked_to_num::<u64>().unwrap_or(u64::MAX))
+    }
+
     /// Deposits `native_amount`.
     ///
     /// If the token position ends up positive but below one native token and this token
@@ -603,7 +689,7 @@ impl Bank {
                 self.indexed_borrows -= indexed_change;
                 position.indexed_position = new_indexed_value;
                 return Ok(true);
-            } else if new_native_position < I80F48::ONE && allow_dusting {
+            } else if new_native_position < self.dust_threshold() && allow_dusting {
                 // if there's less than one token deposited, zero the position
                 self.dust += new_native_position;
                 self.indexed_borrows += position.indexed_position;
@@ -645,7 +731,7 @@ impl Bank {
             .withdraw_internal_wrapper(
                 position,
                 native_amount,
-                false,
+                I80F48::ZERO,
                 !position.is_in_use(),
                 now_ts,
             )?
@@ -663,7 +749,7 @@ impl Bank {
         native_amount: I80F48,
         now_ts: u64,
     ) -> Result<bool> {
-        self.withdraw_internal_wrapper(position, native_amount, false, true, now_ts)
+        self.withdraw_internal_wrapper(position, native_amount, I80F48::ZERO, true, now_ts)
             .map(|withdraw_result| withdraw_result.position_is_active || position.is_in_use())
     }
 
@@ -681,7 +767,25 @@ impl Bank {
         native_amount: I80F48,
         now_ts: u64,
     ) -> Result<WithdrawResult> {
-        self.withdraw_internal_wrapper(position, native_amount, true, !position.is_in_use(), now_ts)
+        self.withdraw_with_discounted_fee(position, native_amount, I80F48::ZERO, now_ts)
+    }
+
+    /// Like `withdraw_with_fee()`, but waives `fee_discount` of the loan origination fee,
+    /// see MangoAccountFixed::loan_origination_fee_discount().
+    pub fn withdraw_with_discounted_fee(
+        &mut self,
+        position: &mut TokenPosition,
+        native_amount: I80F48,
+        fee_discount: I80F48,
+        now_ts: u64,
+    ) -> Result<WithdrawResult> {
+        self.withdraw_internal_wrapper(
+            position,
+            native_amount,
+            I80F48::ONE - fee_discount,
+            !position.is_in_use(),
+            now_ts,
+        )
     }
 
     /// Internal function to withdraw funds
@@ -689,7 +793,7 @@ impl Bank {
         &mut self,
         position: &mut TokenPosition,
         native_amount: I80F48,
-        with_loan_origination_fee: bool,
+        loan_origination_fee_factor: I80F48,
         allow_dusting: bool,
         now_ts: u64,
     ) -> Result<WithdrawResult> {
@@ -697,7 +801,7 @@ impl Bank {
         let res = self.withdraw_internal(
             position,
             native_amount,
-            with_loan_origination_fee,
+            loan_origination_fee_factor,
             allow_dusting,
             now_ts,
         );
@@ -710,7 +814,7 @@ impl Bank {
         &mut self,
         position: &mut TokenPosition,
         mut native_amount: I80F48,
-        with_loan_origination_fee: bool,
+        loan_origination_fee_factor: I80F48,
         allow_dusting: bool,
         now_ts: u64,
     ) -> Result<WithdrawResult> {
@@ -721,7 +825,7 @@ impl Bank {
             let new_native_position = native_position - native_amount;
             if !new_native_position.is_negative() {
                 // withdraw deposits only
-                if new_native_position < I80F48::ONE && allow_dusting {
+                if new_native_position < self.dust_threshold() && allow_dusting {
                     // zero the account collecting the leftovers in `dust`
                     self.dust += new_native_position;
                     self.indexed_deposits -= position.indexed_position;
@@ -752,8 +856,9 @@ impl Bank {
         }
 
         let mut loan_origination_fee = I80F48::ZERO;
-        if with_loan_origination_fee {
-            loan_origination_fee = self.loan_origination_fee_rate * native_amount;
+        if loan_origination_fee_factor.is_positive() {
+            loan_origination_fee =
+                self.loan_origination_fee_rate * native_amount * loan_origination_fee_factor;
             self.collected_fees_native += loan_origination_fee;
             native_amount += loan_origination_fee;
         }
@@ -788,7 +893,7 @@ impl Bank {
             .withdraw_internal_wrapper(
                 position,
                 loan_origination_fee,
-                false,
+                I80F48::ZERO,
                 !position.is_in_use(),
                 now_ts,
             )?
@@ -808,7 +913,7 @@ impl Bank {
             return Ok(true);
         }
         let native = position.native(self);
-        if native >= 0 && native < 1 {
+        if self.is_dust(native) {
             // Withdrawing 0 triggers the dusting check
             return self.withdraw_without_fee(position, I80F48::ZERO, now_ts);
         }
@@ -892,6 +997,7 @@ impl Bank {
         // Adding DELTA here covers the case where we add slightly more than we withdraw
         if self.indexed_borrows > before_borrows + I80F48::DELTA {
             self.check_net_borrows(oracle_price)?;
+            self.check_borrow_limit()?;
         }
         if self.indexed_deposits > before_deposits + I80F48::DELTA {
             self.check_deposit_and_oo_limit()?;
@@ -993,6 +1099,48 @@ impl Bank {
         Ok(())
     }
 
+    /// Native tokens in a vault of `vault_balance` beyond the bank's deposits minus
+    /// borrows. Negative if the vault can't cover the deposits.
+    pub fn vault_surplus(&self, vault_balance: u64) -> I80F48 {
+        I80F48::from(vault_balance) - self.native_deposits() + self.native_borrows()
+    }
+
+    /// Whole native tokens of collected fees that can be withdrawn from a vault of
+    /// `vault_balance` without leaving it short of the bank's deposits minus borrows
+    pub fn withdrawable_fees(&self, vault_balance: u64) -> u64 {
+        let unwithdrawn_fees = self
+            .collected_fees_native
+            .floor()
+            .to_num::<u64>()
+            .saturating_sub(self.fees_withdrawn);
+        let surplus = self
+            .vault_surplus(vault_balance)
+            .floor()
+            .max(I80F48::ZERO)
+            .to_num::<u64>();
+        unwithdrawn_fees.min(surplus)
+    }
+
+    pub fn check_borrow_limit(&self) -> Result<()> {
+        if self.borrow_limit == 0 {
+            return Ok(());
+        }
+
+        let borrows = self.native_borrows();
+        let remaining = I80F48::from(self.borrow_limit) - borrows;
+        if remaining < 0 {
+            return Err(error_msg_typed!(
+                MangoError::BankBorrowLimit,
+                "borrow limit exceeded: remaining: {}, borrows: {}, limit: {}",
+                remaining,
+                borrows,
+                self.borrow_limit,
+            ));
+        }
+
+        Ok(())
+    }
+
     pub fn update_cumulative_interest(
         &self,
         position: &mut TokenPosition,
@@ -1094,6 +1242,22 @@ impl Bank {
         )
     }
 
+    /// Current utilization of the bank's deposits by its borrows, clamped to 0..1
+    pub fn utilization(&self) -> I80F48 {
+        Self::instantaneous_utilization(self.native_deposits(), self.native_borrows())
+    }
+
+    /// Current borrow APR at utilization(), not including the loan fee
+    pub fn borrow_rate(&self) -> I80F48 {
+        self.compute_interest_rate(self.utilization())
+    }
+
+    /// Current deposit APR: the borrow interest spread over all deposits, like in
+    /// compute_index()
+    pub fn deposit_rate(&self) -> I80F48 {
+        self.borrow_rate() * self.utilization()
+    }
+
     /// calculator function that can be used to compute an interest
     /// rate based on the given parameters
     #[inline(always)]
@@ -1110,10 +1274,12 @@ impl Bank {
         // Clamp to avoid negative or extremely high interest
         let utilization = utilization.max(I80F48::ZERO).min(I80F48::ONE);
 
-        let v = if utilization <= util0 {
+        // util0 == 0 or util0 == util1 are valid curves, skip the empty segments
+        // to avoid dividing by zero
+        let v = if utilization <= util0 && util0 > I80F48::ZERO {
             let slope = (rate0 - zero_util_rate) / util0;
             zero_util_rate + slope * utilization
-        } else if utilization <= util1 {
+        } else if utilization <= util1 && util1 > util0 {
             let extra_util = utilization - util0;
             let slope = (rate1 - rate0) / (util1 - util0);
             rate0 + slope * extra_util
@@ -1180,6 +1346,40 @@ impl Bank {
         self.interest_curve_scaling = (self.interest_curve_scaling * adjustment).max(1.0)
     }
 
+    /// The token program that owns the bank's vault
+    pub fn vault_token_program(&self) -> Pubkey {
+        if self.is_token_2022 == 1 {
+            anchor_spl::token_2022::ID
+        } else {
+            anchor_spl::token::ID
+        }
+    }
+
+    /// Whether the bank was registered with a fixed price instead of an oracle
+    pub fn has_fixed_price(&self) -> bool {
+        self.fixed_price > 0
+    }
+
+    /// Like oracle::oracle_state_unchecked(), but doesn't read the oracle for fixed price banks
+    pub fn oracle_state_unchecked<T: KeyedAccountReader>(
+        &self,
+        oracle_acc_infos: &OracleAccountInfos<T>,
+    ) -> Result<OracleState> {
+        if self.has_fixed_price() {
+            return Ok(self.fixed_price_oracle_state());
+        }
+        oracle::oracle_state_unchecked(oracle_acc_infos, self.mint_decimals)
+    }
+
+    fn fixed_price_oracle_state(&self) -> OracleState {
+        OracleState {
+            price: self.fixed_price,
+            deviation: I80F48::ZERO,
+            last_update_slot: u64::MAX,
+            oracle_type: OracleType::Fixed,
+        }
+    }
+
     /// Tries to return the primary oracle price, and if there is a confidence or staleness issue returns the fallback oracle price if possible.
     pub fn oracle_price<T: KeyedAccountReader>(
         &self,
@@ -1187,6 +1387,9 @@ impl Bank {
         staleness_slot: Option<u64>,
     ) -> Result<I80F48> {
         require_keys_eq!(self.oracle, *oracle_acc_infos.oracle.key());
+        if self.has_fixed_price() {
+            return Ok(self.fixed_price);
+        }
         let primary_state = oracle::oracle_state_unchecked(oracle_acc_infos, self.mint_decimals)?;
         let primary_ok =
             primary_state.check_confidence_and_maybe_staleness(&self.oracle_config, staleness_slot);
@@ -1770,6 +1973,164 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_bank_available_liquidity() {
+        let mut bank = Bank::zeroed();
+        bank.deposit_index = I80F48::ONE;
+        bank.borrow_index = I80F48::ONE;
+        bank.min_vault_to_deposits_ratio = 0.25;
+        bank.indexed_deposits = I80F48::from(1000);
+
+        // no borrows: 75% of deposits can be borrowed
+        assert_eq!(bank.available_liquidity(1000), I80F48::from(750));
+        // but never more than what's in the vault
+        assert_eq!(bank.available_liquidity(500), I80F48::from(500));
+
+        // near full utilization
+        bank.indexed_borrows = I80F48::from(700);
+        assert_eq!(bank.available_liquidity(300), I80F48::from(50));
+
+        // beyond the utilization limit, nothing is available
+        bank.indexed_borrows = I80F48::from(800);
+        assert_eq!(bank.available_liquidity(200), I80F48::ZERO);
+
+        // a borrow limit caps what's left to borrow
+        bank.indexed_borrows = I80F48::from(100);
+        bank.borrow_limit = 300;
+        assert_eq!(bank.available_liquidity(900), I80F48::from(200));
+        bank.borrow_limit = 100;
+        assert_eq!(bank.available_liquidity(900), I80F48::ZERO);
+        // unless the utilization limit is lower
+        bank.borrow_limit = 10_000;
+        assert_eq!(bank.available_liquidity(900), I80F48::from(650));
+    }
+
+    #[test]
+    fn test_bank_vault_surplus() {
+        let mut bank = Bank::zeroed();
+        bank.deposit_index = I80F48::ONE;
+        bank.borrow_index = I80F48::ONE;
+        bank.indexed_deposits = I80F48::from(1000);
+        bank.indexed_borrows = I80F48::from(300);
+
+        // 700 net deposits, 15 of collected fees
+        assert_eq!(bank.vault_surplus(715), I80F48::from(15));
+        assert_eq!(bank.vault_surplus(700), I80F48::ZERO);
+        assert_eq!(bank.vault_surplus(690), I80F48::from(-10));
+    }
+
+    #[test]
+    fn test_bank_withdrawable_fees() {
+        let mut bank = Bank::zeroed();
+        bank.deposit_index = I80F48::ONE;
+        bank.borrow_index = I80F48::ONE;
+        bank.indexed_deposits = I80F48::from(1000);
+        bank.indexed_borrows = I80F48::from(300);
+        bank.collected_fees_native = I80F48::from_num(15.5);
+
+        // the surplus covers all whole fees
+        assert_eq!(bank.withdrawable_fees(720), 15);
+        // capped at the surplus
+        assert_eq!(bank.withdrawable_fees(710), 10);
+        assert_eq!(bank.withdrawable_fees(690), 0);
+
+        // a fractional surplus rounds down
+        bank.indexed_deposits = I80F48::from_num(1000.5);
+        assert_eq!(bank.vault_surplus(715), I80F48::from_num(14.5));
+        assert_eq!(bank.withdrawable_fees(715), 14);
+
+        // already withdrawn fees don't count
+        bank.fees_withdrawn = 5;
+        assert_eq!(bank.withdrawable_fees(715), 10);
+    }
+
+    #[test]
+    fn test_bank_dust_threshold() {
+        let mut bank = Bank::zeroed();
+        bank.deposit_index = I80F48::ONE;
+        bank.borrow_index = I80F48::ONE;
+        bank.net_borrow_limit_window_size_ts = 1;
+        bank.net_borrow_limit_per_window_quote = i64::MAX;
+
+        let threshold = bank.dust_threshold();
+        assert!(bank.is_dust(I80F48::ZERO));
+        assert!(bank.is_dust(threshold - I80F48::DELTA));
+        assert!(!bank.is_dust(threshold));
+        assert!(!bank.is_dust(-I80F48::DELTA));
+
+        let mut position = TokenPosition {
+            indexed_position: I80F48::ZERO,
+            token_index: 0,
+            in_use_count: 0,
+            cumulative_deposit_interest: 0.0,
+            cumulative_borrow_interest: 0.0,
+            previous_index: I80F48::ZERO,
+            padding: Default::default(),
+            reserved: [0; 128],
+        };
+
+        // above the threshold, the balance stays
+        let above = threshold + I80F48::from_num(0.5);
+        position.indexed_position = above;
+        bank.indexed_deposits = above;
+        assert!(bank.dust_if_possible(&mut position, 1).unwrap());
+        assert_eq!(position.indexed_position, above);
+        assert_eq!(bank.dust, I80F48::ZERO);
+
+        // below the threshold, it moves into the dust field
+        let below = threshold - I80F48::from_num(0.5);
+        position.indexed_position = below;
+        bank.indexed_deposits = below;
+        assert!(!bank.dust_if_possible(&mut position, 1).unwrap());
+        assert_eq!(position.indexed_position, I80F48::ZERO);
+        assert_eq!(bank.dust, below);
+    }
+
+    #[test]
+    fn test_bank_interest_rate_params() {
+        let params = InterestRateParams {
+            util0: 0.4,
+            rate0: 0.07,
+            util1: 0.8,
+            rate1: 0.9,
+            max_rate: 1.5,
+            adjustment_factor: 0.004,
+        };
+
+        // same conversion as token_register
+        let mut bank = Bank::zeroed();
+        bank.util0 = I80F48::from_num(params.util0);
+        bank.rate0 = I80F48::from_num(params.rate0);
+        bank.util1 = I80F48::from_num(params.util1);
+        bank.rate1 = I80F48::from_num(params.rate1);
+        bank.max_rate = I80F48::from_num(params.max_rate);
+        bank.adjustment_factor = I80F48::from_num(params.adjustment_factor);
+
+        let read_back = bank.interest_rate_params();
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-7;
+        assert!(close(read_back.util0, params.util0));
+        assert!(close(read_back.rate0, params.rate0));
+        assert!(close(read_back.util1, params.util1));
+        assert!(close(read_back.rate1, params.rate1));
+        assert!(close(read_back.max_rate, params.max_rate));
+        assert!(close(read_back.adjustment_factor, params.adjustment_factor));
+    }
+
+    #[test]
+    fn test_bank_flash_loan_repayment() {
+        let mut bank = Bank::zeroed();
+
+        // no fee: repay exactly what was borrowed
+        assert_eq!(bank.flash_loan_repayment(1000), 1000);
+
+        bank.loan_origination_fee_rate = I80F48::from_num(0.0625);
+        assert_eq!(bank.flash_loan_repayment(0), 0);
+        assert_eq!(bank.flash_loan_repayment(1600), 1700);
+        // fractional fees round up
+        assert_eq!(bank.flash_loan_repayment(1000), 1063);
+        assert_eq!(bank.flash_loan_repayment(1), 2);
+    }
+
     #[test]
     fn test_bank_interest_rate_curve() {
         let mut bank = Bank::zeroed();
@@ -1809,5 +2170,58 @@ mod tests {
         assert!(d(interest(0.75 - delta), 7.0) <= eps);
         assert!(d(interest(0.75 + delta), 7.0) <= eps);
         assert!(d(interest(1.0 - delta), 13.0) <= eps);
+
+        // degenerate curves don't divide by zero
+        bank.util0 = I80F48::ZERO;
+        bank.util1 = I80F48::ZERO;
+        assert!(d(interest(0.0), 7.0) <= eps);
+        assert!(d(interest(0.5), 10.0) <= eps);
+        assert!(d(interest(1.0), 13.0) <= eps);
+
+        bank.util0 = I80F48::from_num(0.5);
+        bank.util1 = I80F48::from_num(0.5);
+        assert!(d(interest(0.25), 2.0) <= eps);
+        assert!(d(interest(0.5), 3.0) <= eps);
+        assert!(d(interest(0.75), 10.0) <= eps);
+
+        bank.util0 = I80F48::ONE;
+        bank.util1 = I80F48::ONE;
+        assert!(d(interest(1.0), 3.0) <= eps);
+    }
+
+    #[test]
+    fn test_bank_utilization_and_rates() {
+        let mut bank = Bank::zeroed();
+        bank.deposit_index = I80F48::ONE;
+        bank.borrow_index = I80F48::ONE;
+        bank.zero_util_rate = I80F48::from_num(0.01);
+        bank.util0 = I80F48::from_num(0.5);
+        bank.rate0 = I80F48::from_num(0.1);
+        bank.util1 = I80F48::from_num(0.8);
+        bank.rate1 = I80F48::from_num(0.4);
+        bank.max_rate = I80F48::from_num(2.0);
+        bank.indexed_deposits = I80F48::from(1000);
+
+        let eps = 0.0001;
+        let mut check = |borrows: i32, utilization: f64, borrow_rate: f64| {
+            bank.indexed_borrows = I80F48::from(borrows);
+            assert!((bank.utilization().to_num::<f64>() - utilization).abs() <= eps);
+            assert!((bank.borrow_rate().to_num::<f64>() - borrow_rate).abs() <= eps);
+            assert!((bank.deposit_rate().to_num::<f64>() - borrow_rate * utilization).abs() <= eps);
+        };
+
+        check(0, 0.0, 0.01);
+        check(500, 0.5, 0.1);
+        check(650, 0.65, 0.25);
+        check(800, 0.8, 0.4);
+        check(900, 0.9, 1.2);
+        // above 100% utilization is clamped
+        check(1200, 1.0, 2.0);
+
+        // no deposits
+        bank.indexed_deposits = I80F48::ZERO;
+        bank.indexed_borrows = I80F48::ZERO;
+        assert_eq!(bank.utilization(), I80F48::ZERO);
+        assert_eq!(bank.deposit_rate(), I80F48::ZERO);
     }
 }
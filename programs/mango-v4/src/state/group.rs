@@ -248,6 +248,8 @@ pub enum IxGate {
     TokenForceWithdraw = 72,
     SequenceCheck = 73,
     HealthCheck = 74,
+    AccountSetFeeTier = 75,
+    TokenSweepDust = 76,
     // NOTE: Adding new variants requires matching changes in ts and the ix_gate_set instruction.
 }
 
@@ -36,6 +36,15 @@ const BORSH_VEC_SIZE_BYTES: usize = 4;
 const DEFAULT_MANGO_ACCOUNT_VERSION: u8 = 1;
 const DYNAMIC_RESERVED_BYTES: usize = 64;
 
+/// Share of the loan origination fee that is waived on flash loans and token withdraws,
+/// indexed by MangoAccountFixed::fee_tier
+pub const FEE_TIER_LOAN_ORIGINATION_DISCOUNTS: [I80F48; 4] = [
+    I80F48::ZERO,
+    I80F48::from_bits(I80F48::ONE.to_bits() / 10),
+    I80F48::from_bits(I80F48::ONE.to_bits() / 4),
+    I80F48::from_bits(I80F48::ONE.to_bits() / 2),
+];
+
 // Return variants for check_liquidatable method, should be wrapped in a Result
 // for a future possiblity of returning any error
 #[derive(PartialEq)]
@@ -156,8 +165,11 @@ pub struct MangoAccount {
     /// Time at which the last collateral fee was charged
     pub last_collateral_fee_charge: u64,
 
+    /// Fee discount tier, set by the group admin. See FEE_TIER_LOAN_ORIGINATION_DISCOUNTS.
+    pub fee_tier: u8,
+
     #[derivative(Debug = "ignore")]
-    pub reserved: [u8; 152],
+    pub reserved: [u8; 151],
 
     // dynamic
     pub header_version: u8,
@@ -211,7 +223,8 @@ impl MangoAccount {
             temporary_delegate: Pubkey::default(),
             temporary_delegate_expiry: 0,
             last_collateral_fee_charge: 0,
-            reserved: [0; 152],
+            fee_tier: 0,
+            reserved: [0; 151],
             header_version: DEFAULT_MANGO_ACCOUNT_VERSION,
             padding3: Default::default(),
             padding4: Default::default(),
@@ -336,11 +349,12 @@ pub struct MangoAccountFixed {
     pub temporary_delegate: Pubkey,
     pub temporary_delegate_expiry: u64,
     pub last_collateral_fee_charge: u64,
-    pub reserved: [u8; 152],
+    pub fee_tier: u8,
+    pub reserved: [u8; 151],
 }
 const_assert_eq!(
     size_of::<MangoAccountFixed>(),
-    32 * 4 + 8 + 8 * 8 + 32 + 8 + 8 + 152
+    32 * 4 + 8 + 8 * 8 + 32 + 8 + 8 + 1 + 151
 );
 const_assert_eq!(size_of::<MangoAccountFixed>(), 400);
 const_assert_eq!(size_of::<MangoAccountFixed>() % 8, 0);
@@ -401,6 +415,14 @@ impl MangoAccountFixed {
         }
     }
 
+    /// Fraction of the loan origination fee that is waived for this account
+    pub fn loan_origination_fee_discount(&self) -> I80F48 {
+        FEE_TIER_LOAN_ORIGINATION_DISCOUNTS
+            .get(self.fee_tier as usize)
+            .copied()
+            .unwrap_or(I80F48::ZERO)
+    }
+
     /// Updates the buyback_fees_* fields for staggered expiry of available amounts.
     pub fn expire_buyback_fees(&mut self, now_ts: u64, interval: u64) {
         if interval == 0 || now_ts < self.buyback_fees_expiry_timestamp {
@@ -2915,7 +2937,8 @@ mod tests {
                 temporary_delegate: fixed.temporary_delegate,
                 temporary_delegate_expiry: fixed.temporary_delegate_expiry,
                 last_collateral_fee_charge: fixed.last_collateral_fee_charge,
-                reserved: [0u8; 152],
+                fee_tier: fixed.fee_tier,
+                reserved: [0u8; 151],
 
                 header_version: *zerocopy_reader.header_version(),
                 padding3: Default::default(),
@@ -66,6 +66,16 @@ impl MintInfo {
         &self.banks[..self.num_banks()]
     }
 
+    /// Slot of `bank` in the banks and vaults arrays, if it's one of the banks
+    pub fn bank_index(&self, bank: &Pubkey) -> Option<usize> {
+        self.banks().iter().position(|b| b == bank)
+    }
+
+    /// The vault that belongs to `bank`, if it's one of the banks
+    pub fn vault_for_bank(&self, bank: &Pubkey) -> Option<Pubkey> {
+        self.bank_index(bank).map(|index| self.vaults[index])
+    }
+
     pub fn verify_banks_ais(&self, all_bank_ais: &[AccountInfo]) -> Result<()> {
         require_msg!(
             all_bank_ais.iter().map(|ai| ai.key).eq(self.banks().iter()),
@@ -76,7 +86,107 @@ impl MintInfo {
         Ok(())
     }
 
+    pub fn vaults(&self) -> &[Pubkey] {
+        &self.vaults[..self.num_banks()]
+    }
+
+    pub fn verify_vaults_ais(&self, all_vault_ais: &[AccountInfo]) -> Result<()> {
+        require_msg!(
+            all_vault_ais
+                .iter()
+                .map(|ai| ai.key)
+                .eq(self.vaults().iter()),
+            "the passed vaults {:?} don't match vaults in mint_info {:?}",
+            all_vault_ais.iter().map(|ai| ai.key).collect::<Vec<_>>(),
+            self.vaults()
+        );
+        Ok(())
+    }
+
+    pub fn verify_banks_and_vaults_ais(
+        &self,
+        all_bank_ais: &[AccountInfo],
+        all_vault_ais: &[AccountInfo],
+    ) -> Result<()> {
+        self.verify_banks_ais(all_bank_ais)?;
+        self.verify_vaults_ais(all_vault_ais)
+    }
+
     pub fn elligible_for_group_insurance_fund(&self) -> bool {
         self.group_insurance_fund == 1
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bytemuck::Zeroable;
+
+    use super::*;
+
+    #[test]
+    fn test_bank_index() {
+        let mut mint_info = MintInfo::zeroed();
+        let banks = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let vaults = [Pubkey::new_unique(), Pubkey::new_unique()];
+        mint_info.banks[..2].copy_from_slice(&banks);
+        mint_info.vaults[..2].copy_from_slice(&vaults);
+
+        assert_eq!(mint_info.bank_index(&banks[0]), Some(0));
+        assert_eq!(mint_info.bank_index(&banks[1]), Some(1));
+        assert_eq!(mint_info.vault_for_bank(&banks[1]), Some(vaults[1]));
+
+        // empty slots don't match
+        assert_eq!(mint_info.bank_index(&Pubkey::default()), None);
+        assert_eq!(mint_info.vault_for_bank(&Pubkey::default()), None);
+
+        let unknown = Pubkey::new_unique();
+        assert_eq!(mint_info.bank_index(&unknown), None);
+        assert_eq!(mint_info.vault_for_bank(&unknown), None);
+    }
+
+    #[test]
+    fn test_verify_banks_and_vaults_ais() {
+        let mut mint_info = MintInfo::zeroed();
+        let banks = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let vaults = [Pubkey::new_unique(), Pubkey::new_unique()];
+        mint_info.banks[..2].copy_from_slice(&banks);
+        mint_info.vaults[..2].copy_from_slice(&vaults);
+
+        let owner = Pubkey::default();
+        let mut lamports = [0u64; 4];
+        let mut data = [[0u8; 0]; 4];
+        let [l0, l1, l2, l3] = &mut lamports;
+        let [d0, d1, d2, d3] = &mut data;
+        let bank_ais = [
+            AccountInfo::new(&banks[0], false, false, l0, d0, &owner, false, 0),
+            AccountInfo::new(&banks[1], false, false, l1, d1, &owner, false, 0),
+        ];
+        let vault_ais = [
+            AccountInfo::new(&vaults[0], false, true, l2, d2, &owner, false, 0),
+            AccountInfo::new(&vaults[1], false, true, l3, d3, &owner, false, 0),
+        ];
+
+        assert!(mint_info.verify_vaults_ais(&vault_ais).is_ok());
+        assert!(mint_info
+            .verify_banks_and_vaults_ais(&bank_ais, &vault_ais)
+            .is_ok());
+
+        // reordered
+        let reordered = [vault_ais[1].clone(), vault_ais[0].clone()];
+        assert!(mint_info.verify_vaults_ais(&reordered).is_err());
+
+        // wrong length
+        assert!(mint_info.verify_vaults_ais(&vault_ais[..1]).is_err());
+        let too_many = [
+            vault_ais[0].clone(),
+            vault_ais[1].clone(),
+            vault_ais[0].clone(),
+        ];
+        assert!(mint_info.verify_vaults_ais(&too_many).is_err());
+
+        // vaults passed as banks
+        assert!(mint_info
+            .verify_banks_and_vaults_ais(&vault_ais, &vault_ais)
+            .is_err());
+    }
+}
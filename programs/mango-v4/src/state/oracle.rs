@@ -71,6 +71,12 @@ pub mod pyth_mainnet_sol_oracle {
     declare_id!("H6ARHf6YXhGYeQfUzQNGk6rDNnLBQKrenN712K4AQJEG");
 }
 
+/// Oracle address of banks with a fixed price, see Bank::fixed_price
+pub mod fixed_price_oracle {
+    use solana_program::declare_id;
+    declare_id!("FixedPrice1111111111111111111111111111111111");
+}
+
 pub mod usdc_mint_mainnet {
     use solana_program::declare_id;
     declare_id!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
@@ -118,6 +124,8 @@ pub enum OracleType {
     SwitchboardV2,
     OrcaCLMM,
     RaydiumCLMM,
+    /// Banks with a fixed price, never returned by determine_oracle_type()
+    Fixed,
 }
 
 pub struct OracleState {
@@ -431,6 +439,7 @@ fn oracle_state_unchecked_inner<T: KeyedAccountReader>(
                 oracle_type: OracleType::RaydiumCLMM,
             }
         }
+        OracleType::Fixed => return Err(MangoError::UnknownOracleType.into()),
     })
 }
 
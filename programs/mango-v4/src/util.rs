@@ -1,4 +1,4 @@
-use crate::error::MangoError;
+use crate::error::*;
 use anchor_lang::prelude::*;
 
 #[macro_export]
@@ -12,9 +12,20 @@ macro_rules! zip {
 #[allow(unused_imports)]
 pub(crate) use zip;
 
+/// Copies `name` into a zero-padded byte array.
+///
+/// The limit is N bytes of UTF-8, not N characters: multibyte characters count
+/// for several bytes. Longer names are rejected, never truncated.
 pub fn fill_from_str<const N: usize>(name: &str) -> Result<[u8; N]> {
     let name_bytes = name.as_bytes();
-    require!(name_bytes.len() <= N, MangoError::SomeError);
+    require_msg_typed!(
+        name_bytes.len() <= N,
+        MangoError::InvalidInput,
+        "name {:?} is {} bytes long, at most {} bytes are allowed",
+        name,
+        name_bytes.len(),
+        N
+    );
     let mut name_ = [0u8; N];
     name_[..name_bytes.len()].copy_from_slice(name_bytes);
     Ok(name_)
@@ -54,4 +65,20 @@ mod tests {
         );
         assert!(fill_from_str::<4>("abcde").is_err());
     }
+
+    #[test]
+    fn test_fill_from_str_byte_limit() {
+        let is_invalid_input = |r: Result<[u8; 16]>| {
+            r.is_anchor_error_with_code(MangoError::InvalidInput.error_code())
+        };
+
+        assert!(fill_from_str::<16>("ABCDEFGHIJKLMNOP").is_ok());
+        assert!(is_invalid_input(fill_from_str::<16>("ABCDEFGHIJKLMNOPQ")));
+        // 8 characters, but 32 bytes
+        let emoji = "🥭🥭🥭🥭🥭🥭🥭🥭";
+        assert_eq!(emoji.chars().count(), 8);
+        assert!(is_invalid_input(fill_from_str::<16>(emoji)));
+        // 4 characters, exactly 16 bytes
+        assert!(fill_from_str::<16>("🥭🥭🥭🥭").is_ok());
+    }
 }
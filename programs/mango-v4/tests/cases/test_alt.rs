@@ -67,3 +67,98 @@ async fn test_alt() -> Result<(), TransportError> {
 
     Ok(())
 }
+
+#[cfg(feature = "token-register-alt")]
+#[tokio::test]
+async fn test_token_register_extends_alt() -> Result<(), TransportError> {
+    use solana_sdk::instruction::AccountMeta;
+
+    let context = TestContext::new().await;
+    let solana = &context.solana.clone();
+
+    let admin = TestKeypair::new();
+    let payer = context.users[1].key;
+    let mints = &context.mints[0..2];
+
+    let GroupWithTokens { group, .. } = GroupWithTokensConfig {
+        admin,
+        payer,
+        mints: mints[0..1].to_vec(),
+        ..GroupWithTokensConfig::default()
+    }
+    .create(solana)
+    .await;
+
+    let address_lookup_table = solana.create_address_lookup_table_for(group, payer).await;
+    send_tx(
+        solana,
+        AltSetInstruction {
+            group,
+            admin,
+            index: 0,
+            address_lookup_table,
+        },
+    )
+    .await
+    .unwrap();
+
+    //
+    // TEST: Registering a token adds its bank and oracle to the lookup table
+    //
+    let result = send_tx_with_extra_accounts(
+        solana,
+        TokenRegisterInstruction {
+            token_index: 1,
+            decimals: mints[1].decimals,
+            adjustment_factor: 0.01,
+            util0: 0.40,
+            rate0: 0.07,
+            util1: 0.80,
+            rate1: 0.9,
+            max_rate: 1.50,
+            loan_origination_fee_rate: 0.0005,
+            loan_fee_rate: 0.0005,
+            maint_asset_weight: 0.8,
+            init_asset_weight: 0.6,
+            maint_liab_weight: 1.2,
+            init_liab_weight: 1.4,
+            liquidation_fee: 0.02,
+            platform_liquidation_fee: 0.0,
+            min_vault_to_deposits_ratio: 0.2,
+            net_borrow_limit_per_window_quote: 1_000_000_000_000,
+            net_borrow_limit_window_size_ts: 24 * 60 * 60,
+            fixed_price: Some(I80F48::ONE),
+            group,
+            admin,
+            mint: mints[1].pubkey,
+            oracle: fixed_price_oracle::ID,
+            payer,
+            token_program: spl_token::ID,
+        },
+        vec![
+            AccountMeta::new(address_lookup_table, false),
+            AccountMeta::new_readonly(solana_address_lookup_table_program::ID, false),
+        ],
+    )
+    .await
+    .unwrap();
+    assert!(result.result.is_ok());
+
+    let mint_info_address = Pubkey::find_program_address(
+        &[
+            b"MintInfo".as_ref(),
+            group.as_ref(),
+            mints[1].pubkey.as_ref(),
+        ],
+        &mango_v4::id(),
+    )
+    .0;
+    let mint_info = solana.get_account::<MintInfo>(mint_info_address).await;
+    let alt_data = solana.get_account_data(address_lookup_table).await.unwrap();
+    assert_eq!(
+        mango_v4::address_lookup_table_program::addresses(&alt_data),
+        &[mint_info.first_bank(), fixed_price_oracle::ID]
+    );
+
+    Ok(())
+}
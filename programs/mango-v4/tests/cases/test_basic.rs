@@ -1049,3 +1049,940 @@ async fn test_sequence_check() -> Result<(), TransportError> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_token_2022_vault() -> Result<(), TransportError> {
+    let context = TestContext::new().await;
+    let solana = &context.solana.clone();
+
+    let admin = TestKeypair::new();
+    let owner = context.users[0].key;
+    let payer = context.users[1].key;
+    let mints = &context.mints[0..1];
+
+    let mango_setup::GroupWithTokens { group, .. } = mango_setup::GroupWithTokensConfig {
+        admin,
+        payer,
+        mints: mints.to_vec(),
+        zero_token_is_quote: true,
+        ..mango_setup::GroupWithTokensConfig::default()
+    }
+    .create(solana)
+    .await;
+
+    let mint_authority = TestKeypair::new();
+    let mint = solana
+        .create_token_2022_mint(&mint_authority.pubkey(), 6)
+        .await;
+
+    let register = |mint: Pubkey, token_program: Pubkey| TokenRegisterInstruction {
+        token_index: 1,
+        decimals: 6,
+        adjustment_factor: 0.01,
+        util0: 0.40,
+        rate0: 0.07,
+        util1: 0.80,
+        rate1: 0.9,
+        max_rate: 1.50,
+        loan_origination_fee_rate: 0.0005,
+        loan_fee_rate: 0.0005,
+        maint_asset_weight: 0.8,
+        init_asset_weight: 0.6,
+        maint_liab_weight: 1.2,
+        init_liab_weight: 1.4,
+        liquidation_fee: 0.02,
+        platform_liquidation_fee: 0.0,
+        min_vault_to_deposits_ratio: 0.2,
+        net_borrow_limit_per_window_quote: 1_000_000_000_000,
+        net_borrow_limit_window_size_ts: 24 * 60 * 60,
+        fixed_price: Some(I80F48::ONE),
+        group,
+        admin,
+        mint,
+        oracle: fixed_price_oracle::ID,
+        payer,
+        token_program,
+    };
+
+    //
+    // TEST: The token program must own the mint
+    //
+
+    send_tx_expect_error!(
+        solana,
+        register(mint, spl_token::ID),
+        MangoError::InvalidTokenProgram
+    );
+
+    //
+    // TEST: Mints with extensions that aren't allowlisted are rejected
+    //
+
+    let transfer_fee_mint = solana
+        .create_token_2022_mint_with_transfer_fee(&mint_authority.pubkey(), 6, 100)
+        .await;
+    assert!(send_tx(
+        solana,
+        register(transfer_fee_mint, anchor_spl::token_2022::ID)
+    )
+    .await
+    .is_err());
+
+    //
+    // TEST: Registering with token-2022 creates a token-2022 vault
+    //
+
+    let registered = send_tx(solana, register(mint, anchor_spl::token_2022::ID))
+        .await
+        .unwrap();
+    let bank = registered.bank;
+    let vault = registered.vault;
+
+    let bank_data: Bank = solana.get_account(bank).await;
+    assert_eq!(bank_data.is_token_2022, 1);
+    assert_eq!(bank_data.vault_token_program(), anchor_spl::token_2022::ID);
+    let vault_account = solana
+        .context
+        .borrow_mut()
+        .banks_client
+        .get_account(vault)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(vault_account.owner, anchor_spl::token_2022::ID);
+
+    //
+    // TEST: Deposits and withdraws go through the token-2022 program
+    //
+
+    let user_token_account = solana
+        .create_token_2022_account(&payer.pubkey(), mint)
+        .await;
+    solana
+        .mint_token_2022(mint, mint_authority, user_token_account, 1000)
+        .await;
+
+    let account = create_funded_account(
+        &solana,
+        group,
+        owner,
+        0,
+        &context.users[1],
+        &mints[0..0],
+        0,
+        0,
+    )
+    .await;
+
+    send_tx(
+        solana,
+        TokenDepositInstruction {
+            amount: 1000,
+            reduce_only: false,
+            account,
+            owner,
+            token_account: user_token_account,
+            token_authority: payer,
+            bank_index: 0,
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(solana.token_account_balance(vault).await, 1000);
+    assert_eq!(solana.token_account_balance(user_token_account).await, 0);
+
+    send_tx(
+        solana,
+        TokenWithdrawInstruction {
+            amount: 400,
+            allow_borrow: false,
+            account,
+            owner,
+            token_account: user_token_account,
+            bank_index: 0,
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(solana.token_account_balance(vault).await, 600);
+    assert_eq!(solana.token_account_balance(user_token_account).await, 400);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_token_edit_interest_rate_curve() -> Result<(), TransportError> {
+    let context = TestContext::new().await;
+    let solana = &context.solana.clone();
+
+    let admin = TestKeypair::new();
+    let payer = context.users[1].key;
+    let mints = &context.mints[0..1];
+
+    let mango_setup::GroupWithTokens { group, tokens, .. } = mango_setup::GroupWithTokensConfig {
+        admin,
+        payer,
+        mints: mints.to_vec(),
+        ..mango_setup::GroupWithTokensConfig::default()
+    }
+    .create(solana)
+    .await;
+    let bank = tokens[0].bank;
+
+    //
+    // TEST: Changing only max_rate of the curve leaves the rest of the bank config alone
+    //
+    let before = solana.get_account::<Bank>(bank).await;
+    let new_max_rate = 3.5f32;
+    assert_ne!(before.max_rate, I80F48::from_num(new_max_rate));
+
+    send_tx(
+        solana,
+        TokenEdit {
+            group,
+            admin,
+            mint: mints[0].pubkey,
+            fallback_oracle: Pubkey::default(),
+            options: mango_v4::instruction::TokenEdit {
+                interest_rate_params_opt: Some(mango_v4::accounts_ix::InterestRateParams {
+                    util0: before.util0.to_num(),
+                    rate0: before.rate0.to_num(),
+                    util1: before.util1.to_num(),
+                    rate1: before.rate1.to_num(),
+                    max_rate: new_max_rate,
+                    adjustment_factor: before.adjustment_factor.to_num(),
+                }),
+                ..token_edit_instruction_default()
+            },
+        },
+    )
+    .await
+    .unwrap();
+
+    let after = solana.get_account::<Bank>(bank).await;
+    assert_eq!(after.max_rate, I80F48::from_num(new_max_rate));
+    assert_eq!(after.util0, before.util0);
+    assert_eq!(after.rate0, before.rate0);
+    assert_eq!(after.util1, before.util1);
+    assert_eq!(after.rate1, before.rate1);
+    assert_eq!(after.adjustment_factor, before.adjustment_factor);
+    assert_eq!(after.loan_fee_rate, before.loan_fee_rate);
+    assert_eq!(
+        after.loan_origination_fee_rate,
+        before.loan_origination_fee_rate
+    );
+    assert_eq!(after.maint_asset_weight, before.maint_asset_weight);
+    assert_eq!(after.init_asset_weight, before.init_asset_weight);
+    assert_eq!(after.maint_liab_weight, before.maint_liab_weight);
+    assert_eq!(after.init_liab_weight, before.init_liab_weight);
+    assert_eq!(after.liquidation_fee, before.liquidation_fee);
+
+    //
+    // TEST: Only the group admin can edit the curve
+    //
+    assert!(send_tx(
+        solana,
+        TokenEdit {
+            group,
+            admin: payer,
+            mint: mints[0].pubkey,
+            fallback_oracle: Pubkey::default(),
+            options: mango_v4::instruction::TokenEdit {
+                interest_rate_params_opt: Some(mango_v4::accounts_ix::InterestRateParams {
+                    util0: 0.5,
+                    rate0: 0.1,
+                    util1: 0.8,
+                    rate1: 0.5,
+                    max_rate: 1.0,
+                    adjustment_factor: 0.0,
+                }),
+                ..token_edit_instruction_default()
+            },
+        },
+    )
+    .await
+    .is_err());
+    assert_eq!(
+        solana.get_account::<Bank>(bank).await.max_rate,
+        I80F48::from_num(new_max_rate)
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_interest_rate_curve_validation() -> Result<(), TransportError> {
+    let context = TestContext::new().await;
+    let solana = &context.solana.clone();
+
+    let admin = TestKeypair::new();
+    let payer = context.users[1].key;
+    let mints = &context.mints[0..2];
+
+    let mango_setup::GroupWithTokens { group, .. } = mango_setup::GroupWithTokensConfig {
+        admin,
+        payer,
+        mints: mints[0..1].to_vec(),
+        ..mango_setup::GroupWithTokensConfig::default()
+    }
+    .create(solana)
+    .await;
+
+    // (util0, rate0, util1, rate1, max_rate)
+    let register = |curve: (f32, f32, f32, f32, f32)| TokenRegisterInstruction {
+        token_index: 1,
+        decimals: mints[1].decimals,
+        adjustment_factor: 0.01,
+        util0: curve.0,
+        rate0: curve.1,
+        util1: curve.2,
+        rate1: curve.3,
+        max_rate: curve.4,
+        loan_origination_fee_rate: 0.0005,
+        loan_fee_rate: 0.0005,
+        maint_asset_weight: 0.8,
+        init_asset_weight: 0.6,
+        maint_liab_weight: 1.2,
+        init_liab_weight: 1.4,
+        liquidation_fee: 0.02,
+        platform_liquidation_fee: 0.0,
+        min_vault_to_deposits_ratio: 0.2,
+        net_borrow_limit_per_window_quote: 1_000_000_000_000,
+        net_borrow_limit_window_size_ts: 24 * 60 * 60,
+        fixed_price: Some(I80F48::ONE),
+        group,
+        admin,
+        mint: mints[1].pubkey,
+        oracle: fixed_price_oracle::ID,
+        payer,
+        token_program: spl_token::ID,
+    };
+
+    let invalid_curves = [
+        (
+            (0.4, -0.07, 0.8, 0.9, 1.5),
+            MangoError::NegativeInterestRate,
+        ),
+        (
+            (0.4, 0.07, 0.8, -0.9, 1.5),
+            MangoError::NegativeInterestRate,
+        ),
+        (
+            (0.4, 0.07, 0.8, 0.9, -1.5),
+            MangoError::NegativeInterestRate,
+        ),
+        (
+            (-0.1, 0.07, 0.8, 0.9, 1.5),
+            MangoError::InterestRateUtilizationOutOfRange,
+        ),
+        (
+            (0.4, 0.07, 1.1, 0.9, 1.5),
+            MangoError::InterestRateUtilizationOutOfRange,
+        ),
+        (
+            (0.8, 0.07, 0.4, 0.9, 1.5),
+            MangoError::InterestRateUtilizationOrder,
+        ),
+        ((0.4, 0.9, 0.8, 0.07, 1.5), MangoError::InterestRateOrder),
+        ((0.4, 0.07, 0.8, 1.5, 0.9), MangoError::InterestRateOrder),
+    ];
+
+    //
+    // TEST: token_register rejects each invalid curve
+    //
+    for (curve, error) in invalid_curves {
+        send_tx_expect_error!(solana, register(curve), error);
+    }
+
+    //
+    // TEST: Boundary curves are fine
+    //
+    send_tx(solana, register((0.0, 0.0, 1.0, 1.5, 1.5)))
+        .await
+        .unwrap();
+
+    //
+    // TEST: token_edit rejects each invalid curve
+    //
+    for ((util0, rate0, util1, rate1, max_rate), error) in invalid_curves {
+        send_tx_expect_error!(
+            solana,
+            TokenEdit {
+                group,
+                admin,
+                mint: mints[1].pubkey,
+                fallback_oracle: Pubkey::default(),
+                options: mango_v4::instruction::TokenEdit {
+                    interest_rate_params_opt: Some(mango_v4::accounts_ix::InterestRateParams {
+                        util0,
+                        rate0,
+                        util1,
+                        rate1,
+                        max_rate,
+                        adjustment_factor: 0.01,
+                    }),
+                    ..token_edit_instruction_default()
+                },
+            },
+            error
+        );
+    }
+
+    //
+    // TEST: token_edit accepts a curve with util0 == util1 == 0
+    //
+    send_tx(
+        solana,
+        TokenEdit {
+            group,
+            admin,
+            mint: mints[1].pubkey,
+            fallback_oracle: Pubkey::default(),
+            options: mango_v4::instruction::TokenEdit {
+                interest_rate_params_opt: Some(mango_v4::accounts_ix::InterestRateParams {
+                    util0: 0.0,
+                    rate0: 0.0,
+                    util1: 0.0,
+                    rate1: 0.0,
+                    max_rate: 0.51,
+                    adjustment_factor: 0.0,
+                }),
+                ..token_edit_instruction_default()
+            },
+        },
+    )
+    .await
+    .unwrap();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_token_register_mint_twice() -> Result<(), TransportError> {
+    let context = TestContext::new().await;
+    let solana = &context.solana.clone();
+
+    let admin = TestKeypair::new();
+    let payer = context.users[1].key;
+    let mints = &context.mints[0..1];
+
+    let mango_setup::GroupWithTokens { group, .. } = mango_setup::GroupWithTokensConfig {
+        admin,
+        payer,
+        mints: mints.to_vec(),
+        ..mango_setup::GroupWithTokensConfig::default()
+    }
+    .create(solana)
+    .await;
+
+    let register = |token_index: TokenIndex, mint: Pubkey| TokenRegisterInstruction {
+        token_index,
+        decimals: mints[0].decimals,
+        adjustment_factor: 0.01,
+        util0: 0.40,
+        rate0: 0.07,
+        util1: 0.80,
+        rate1: 0.9,
+        max_rate: 1.50,
+        loan_origination_fee_rate: 0.0005,
+        loan_fee_rate: 0.0005,
+        maint_asset_weight: 0.8,
+        init_asset_weight: 0.6,
+        maint_liab_weight: 1.2,
+        init_liab_weight: 1.4,
+        liquidation_fee: 0.02,
+        platform_liquidation_fee: 0.0,
+        min_vault_to_deposits_ratio: 0.2,
+        net_borrow_limit_per_window_quote: 1_000_000_000_000,
+        net_borrow_limit_window_size_ts: 24 * 60 * 60,
+        fixed_price: Some(I80F48::ONE),
+        group,
+        admin,
+        mint,
+        oracle: fixed_price_oracle::ID,
+        payer,
+        token_program: spl_token::ID,
+    };
+
+    //
+    // TEST: Registering mint0 again under a new token index fails with a clear error
+    //
+    send_tx_expect_error!(
+        solana,
+        register(1, mints[0].pubkey),
+        MangoError::MintAlreadyRegistered
+    );
+
+    //
+    // TEST: Registering mint0 again under its own token index fails the same way
+    //
+    send_tx_expect_error!(
+        solana,
+        register(0, mints[0].pubkey),
+        MangoError::MintAlreadyRegistered
+    );
+
+    //
+    // TEST: A new mint can't reuse a token index
+    //
+    send_tx_expect_error!(
+        solana,
+        register(0, context.mints[1].pubkey),
+        MangoError::TokenIndexAlreadyInUse
+    );
+    send_tx(solana, register(1, context.mints[1].pubkey))
+        .await
+        .unwrap();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_token_register_three_tokens() -> Result<(), TransportError> {
+    let context = TestContext::new().await;
+    let solana = &context.solana.clone();
+
+    let admin = TestKeypair::new();
+    let payer = context.users[1].key;
+    let mints = &context.mints[0..1];
+
+    let mango_setup::GroupWithTokens { group, .. } = mango_setup::GroupWithTokensConfig {
+        admin,
+        payer,
+        mints: mints.to_vec(),
+        ..mango_setup::GroupWithTokensConfig::default()
+    }
+    .create(solana)
+    .await;
+
+    let register = |token_index: TokenIndex, mint: Pubkey| TokenRegisterInstruction {
+        token_index,
+        decimals: mints[0].decimals,
+        adjustment_factor: 0.01,
+        util0: 0.40,
+        rate0: 0.07,
+        util1: 0.80,
+        rate1: 0.9,
+        max_rate: 1.50,
+        loan_origination_fee_rate: 0.0005,
+        loan_fee_rate: 0.0005,
+        maint_asset_weight: 0.8,
+        init_asset_weight: 0.6,
+        maint_liab_weight: 1.2,
+        init_liab_weight: 1.4,
+        liquidation_fee: 0.02,
+        platform_liquidation_fee: 0.0,
+        min_vault_to_deposits_ratio: 0.2,
+        net_borrow_limit_per_window_quote: 1_000_000_000_000,
+        net_borrow_limit_window_size_ts: 24 * 60 * 60,
+        fixed_price: Some(I80F48::ONE),
+        group,
+        admin,
+        mint,
+        oracle: fixed_price_oracle::ID,
+        payer,
+        token_program: spl_token::ID,
+    };
+
+    //
+    // TEST: Register three tokens with one transaction each, like the client's
+    // register_tokens(), the last one reusing the already registered mint0
+    //
+    let configs = [
+        (1, context.mints[1].pubkey),
+        (2, context.mints[2].pubkey),
+        (3, mints[0].pubkey),
+    ];
+    let mut results = vec![];
+    for (token_index, mint) in configs {
+        results.push(send_tx(solana, register(token_index, mint)).await);
+    }
+
+    // each successful registration has its own bank and mint info
+    for (result, (token_index, mint)) in results.iter().zip(configs).take(2) {
+        let accounts = result.as_ref().unwrap();
+        let bank = solana.get_account::<Bank>(accounts.bank).await;
+        assert_eq!(bank.token_index, token_index);
+        assert_eq!(bank.mint, mint);
+        let mint_info = solana.get_account::<MintInfo>(accounts.mint_info).await;
+        assert_eq!(mint_info.token_index, token_index);
+        assert_eq!(mint_info.first_bank(), accounts.bank);
+    }
+
+    // the failed registration doesn't affect the others
+    let expected_err: u32 = MangoError::MintAlreadyRegistered.into();
+    match &results[2] {
+        Err(TransportError::TransactionError(
+            solana_sdk::transaction::TransactionError::InstructionError(
+                _,
+                solana_program::instruction::InstructionError::Custom(err_num),
+            ),
+        )) => assert_eq!(*err_num, expected_err, "wrong error code"),
+        _ => assert!(false, "expected MintAlreadyRegistered"),
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_token_add_bank() -> Result<(), TransportError> {
+    let context = TestContext::new().await;
+    let solana = &context.solana.clone();
+
+    let admin = TestKeypair::new();
+    let payer = context.users[1].key;
+    let mints = &context.mints[0..1];
+
+    // the setup already adds bank_num 1 to every token
+    let mango_setup::GroupWithTokens { group, tokens, .. } = mango_setup::GroupWithTokensConfig {
+        admin,
+        payer,
+        mints: mints.to_vec(),
+        ..mango_setup::GroupWithTokensConfig::default()
+    }
+    .create(solana)
+    .await;
+    let mint_info = solana.get_account::<MintInfo>(tokens[0].mint_info).await;
+    assert_eq!(mint_info.num_banks(), 2);
+    assert_eq!(mint_info.banks[1], tokens[0].bank1);
+
+    //
+    // TEST: Banks must be added in slot order
+    //
+    assert!(send_tx(
+        solana,
+        TokenAddBankInstruction {
+            token_index: 0,
+            bank_num: 3,
+            group,
+            admin,
+            payer,
+        },
+    )
+    .await
+    .is_err());
+
+    //
+    // TEST: Fill the remaining slots
+    //
+    for bank_num in 2..MAX_BANKS as u32 {
+        let accounts = send_tx(
+            solana,
+            TokenAddBankInstruction {
+                token_index: 0,
+                bank_num,
+                group,
+                admin,
+                payer,
+            },
+        )
+        .await
+        .unwrap();
+
+        let mint_info = solana.get_account::<MintInfo>(tokens[0].mint_info).await;
+        assert_eq!(mint_info.num_banks(), bank_num as usize + 1);
+        assert_eq!(mint_info.banks[bank_num as usize], accounts.bank);
+        assert_eq!(mint_info.vaults[bank_num as usize], accounts.vault);
+        let bank = solana.get_account::<Bank>(accounts.bank).await;
+        assert_eq!(bank.bank_num, bank_num);
+        assert_eq!(bank.mint, mints[0].pubkey);
+    }
+
+    //
+    // TEST: No more banks once all slots are in use
+    //
+    send_tx_expect_error!(
+        solana,
+        TokenAddBankInstruction {
+            token_index: 0,
+            bank_num: MAX_BANKS as u32,
+            group,
+            admin,
+            payer,
+        },
+        MangoError::NoFreeBankSlot
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_token_sweep_dust() -> Result<(), TransportError> {
+    let context = TestContext::new().await;
+    let solana = &context.solana.clone();
+
+    let admin = TestKeypair::new();
+    let owner = context.users[0].key;
+    let payer = context.users[1].key;
+    let mints = &context.mints[0..2];
+    let payer_mint_accounts = &context.users[1].token_accounts[0..2];
+
+    let mango_setup::GroupWithTokens { group, tokens, .. } = mango_setup::GroupWithTokensConfig {
+        admin,
+        payer,
+        mints: mints.to_vec(),
+        ..mango_setup::GroupWithTokensConfig::default()
+    }
+    .create(solana)
+    .await;
+    let bank = tokens[0].bank;
+
+    // provides the mint0 liquidity to borrow
+    create_funded_account(
+        &solana,
+        group,
+        owner,
+        0,
+        &context.users[1],
+        &mints[0..1],
+        100_000,
+        0,
+    )
+    .await;
+    // the borrower, with mint1 collateral
+    let account = create_funded_account(
+        &solana,
+        group,
+        owner,
+        1,
+        &context.users[1],
+        &mints[1..2],
+        100_000,
+        0,
+    )
+    .await;
+
+    //
+    // SETUP: Each borrow of 1000 costs a loan origination fee of 0.5. Repaying 1001
+    // leaves 0.5 on the closed position, which goes to dust.
+    //
+    for _ in 0..3 {
+        send_tx(
+            solana,
+            TokenWithdrawInstruction {
+                amount: 1000,
+                allow_borrow: true,
+                account,
+                owner,
+                token_account: payer_mint_accounts[0],
+                bank_index: 0,
+            },
+        )
+        .await
+        .unwrap();
+        send_tx(
+            solana,
+            TokenDepositInstruction {
+                amount: 1001,
+                account,
+                owner,
+                token_authority: payer,
+                token_account: payer_mint_accounts[0],
+                bank_index: 0,
+                reduce_only: false,
+            },
+        )
+        .await
+        .unwrap();
+    }
+
+    let bank_before = solana.get_account::<Bank>(bank).await;
+    assert!(bank_before.dust > 1 && bank_before.dust < 2);
+
+    //
+    // TEST: Only the admin can sweep
+    //
+    assert!(send_tx(
+        solana,
+        TokenSweepDustInstruction {
+            group,
+            admin: owner,
+            bank,
+        },
+    )
+    .await
+    .is_err());
+
+    //
+    // TEST: Sweep moves the whole native tokens of dust into the collected fees
+    //
+    send_tx(solana, TokenSweepDustInstruction { group, admin, bank })
+        .await
+        .unwrap();
+    let bank_after = solana.get_account::<Bank>(bank).await;
+    assert_eq!(bank_after.dust, bank_before.dust - I80F48::ONE);
+    assert_eq!(
+        bank_after.collected_fees_native,
+        bank_before.collected_fees_native + I80F48::ONE
+    );
+
+    //
+    // TEST: Sweeping less than one native token does nothing
+    //
+    send_tx(solana, TokenSweepDustInstruction { group, admin, bank })
+        .await
+        .unwrap();
+    let bank_again = solana.get_account::<Bank>(bank).await;
+    assert_eq!(bank_again.dust, bank_after.dust);
+    assert_eq!(
+        bank_again.collected_fees_native,
+        bank_after.collected_fees_native
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_admin_token_withdraw_fees() -> Result<(), TransportError> {
+    let context = TestContext::new().await;
+    let solana = &context.solana.clone();
+
+    let admin = TestKeypair::new();
+    let owner = context.users[0].key;
+    let payer = context.users[1].key;
+    let mints = &context.mints[0..2];
+    let payer_mint_accounts = &context.users[1].token_accounts[0..2];
+    let treasury = context.users[0].token_accounts[0];
+
+    let mango_setup::GroupWithTokens { group, tokens, .. } = mango_setup::GroupWithTokensConfig {
+        admin,
+        payer,
+        mints: mints.to_vec(),
+        ..mango_setup::GroupWithTokensConfig::default()
+    }
+    .create(solana)
+    .await;
+    let bank = tokens[0].bank;
+    let vault = tokens[0].vault;
+
+    // provides the mint0 liquidity to borrow
+    create_funded_account(
+        &solana,
+        group,
+        owner,
+        0,
+        &context.users[1],
+        &mints[0..1],
+        100_000,
+        0,
+    )
+    .await;
+    // the borrower, with mint1 collateral
+    let account = create_funded_account(
+        &solana,
+        group,
+        owner,
+        1,
+        &context.users[1],
+        &mints[1..2],
+        100_000,
+        0,
+    )
+    .await;
+
+    //
+    // SETUP: Each borrow of 10000 costs a loan origination fee of 5, which stays
+    // in the vault when the borrow is repaid
+    //
+    for _ in 0..3 {
+        send_tx(
+            solana,
+            TokenWithdrawInstruction {
+                amount: 10_000,
+                allow_borrow: true,
+                account,
+                owner,
+                token_account: payer_mint_accounts[0],
+                bank_index: 0,
+            },
+        )
+        .await
+        .unwrap();
+        send_tx(
+            solana,
+            TokenDepositInstruction {
+                amount: 10_005,
+                account,
+                owner,
+                token_authority: payer,
+                token_account: payer_mint_accounts[0],
+                bank_index: 0,
+                reduce_only: false,
+            },
+        )
+        .await
+        .unwrap();
+    }
+
+    let bank_before = solana.get_account::<Bank>(bank).await;
+    let fees = bank_before.collected_fees_native.floor().to_num::<u64>();
+    assert!((14..=15).contains(&fees));
+    assert_eq!(bank_before.fees_withdrawn, 0);
+    let vault_before = solana.token_account_balance(vault).await;
+    assert!(bank_before.vault_surplus(vault_before) >= fees);
+
+    //
+    // TEST: Only the admin can withdraw
+    //
+    assert!(send_tx(
+        solana,
+        AdminTokenWithdrawFeesInstruction {
+            group,
+            admin: owner,
+            bank,
+            token_account: treasury,
+        },
+    )
+    .await
+    .is_err());
+
+    //
+    // TEST: The admin withdraws the whole native tokens of collected fees
+    //
+    let treasury_before = solana.token_account_balance(treasury).await;
+    send_tx(
+        solana,
+        AdminTokenWithdrawFeesInstruction {
+            group,
+            admin,
+            bank,
+            token_account: treasury,
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        solana.token_account_balance(treasury).await,
+        treasury_before + fees
+    );
+    assert_eq!(
+        solana.token_account_balance(vault).await,
+        vault_before - fees
+    );
+    let bank_after = solana.get_account::<Bank>(bank).await;
+    assert_eq!(bank_after.fees_withdrawn, fees);
+    assert!(bank_after.vault_surplus(vault_before - fees) >= 0);
+
+    //
+    // TEST: Withdrawing again transfers nothing
+    //
+    solana.advance_clock().await;
+    send_tx(
+        solana,
+        AdminTokenWithdrawFeesInstruction {
+            group,
+            admin,
+            bank,
+            token_account: treasury,
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        solana.token_account_balance(treasury).await,
+        treasury_before + fees
+    );
+    assert_eq!(solana.get_account::<Bank>(bank).await.fees_withdrawn, fees);
+
+    Ok(())
+}
@@ -372,3 +372,110 @@ async fn test_bank_net_borrows_based_borrow_limit() -> Result<(), TransportError
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_bank_absolute_borrow_limit() -> Result<(), TransportError> {
+    let context = TestContext::new().await;
+    let solana = &context.solana.clone();
+
+    let admin = TestKeypair::new();
+    let owner = context.users[0].key;
+    let payer = context.users[1].key;
+    let mints = &context.mints[0..2];
+    let payer_mint_accounts = &context.users[1].token_accounts[0..=2];
+
+    //
+    // SETUP: Create a group and an account
+    //
+
+    let GroupWithTokens { group, tokens, .. } = GroupWithTokensConfig {
+        admin,
+        payer,
+        mints: mints.to_vec(),
+        ..GroupWithTokensConfig::default()
+    }
+    .create(solana)
+    .await;
+
+    let set_borrow_limit = |limit: u64| {
+        let mint = tokens[0].mint.pubkey;
+        async move {
+            send_tx(
+                solana,
+                TokenEdit {
+                    group,
+                    admin,
+                    mint,
+                    fallback_oracle: Pubkey::default(),
+                    options: mango_v4::instruction::TokenEdit {
+                        borrow_limit_opt: Some(limit),
+                        ..token_edit_instruction_default()
+                    },
+                },
+            )
+            .await
+            .unwrap();
+        }
+    };
+
+    //
+    // SETUP: Prepare accounts
+    //
+    create_funded_account(
+        &solana,
+        group,
+        owner,
+        0,
+        &context.users[1],
+        &mints[0..1],
+        10_000,
+        0,
+    )
+    .await;
+    let account_1 = create_funded_account(
+        &solana,
+        group,
+        owner,
+        1,
+        &context.users[1],
+        &mints[1..2],
+        100_000,
+        1,
+    )
+    .await;
+
+    set_borrow_limit(1000).await;
+
+    let withdraw = |amount: u64| TokenWithdrawInstruction {
+        amount,
+        allow_borrow: true,
+        account: account_1,
+        owner,
+        token_account: payer_mint_accounts[0],
+        bank_index: 0,
+    };
+
+    //
+    // TEST: Borrowing up to the limit works, crossing it fails
+    //
+    {
+        send_tx(solana, withdraw(900)).await.unwrap();
+
+        // borrows would be 900 + 100 + loan fees > limit 1000
+        send_tx_expect_error!(solana, withdraw(100), MangoError::BankBorrowLimit);
+
+        send_tx(solana, withdraw(99)).await.unwrap();
+        let bank = solana.get_account::<Bank>(tokens[0].bank).await;
+        assert!(bank.native_borrows() <= 1000);
+    }
+
+    //
+    // TEST: A zero limit means unlimited
+    //
+    {
+        set_borrow_limit(0).await;
+        send_tx(solana, withdraw(500)).await.unwrap();
+    }
+
+    Ok(())
+}
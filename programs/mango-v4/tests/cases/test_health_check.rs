@@ -1,10 +1,13 @@
 use crate::cases::{
-    create_funded_account, mango_setup, send_tx, tokio, HealthAccountSkipping,
-    HealthCheckInstruction, TestContext, TestKeypair, TokenWithdrawInstruction,
+    account_init_health, create_funded_account, mango_setup, send_tx,
+    token_edit_instruction_default, tokio, HealthAccountSkipping, HealthCheckInstruction, Pubkey,
+    StubOracleCreate, StubOracleSetInstruction, TestContext, TestKeypair, TokenEdit,
+    TokenRegisterInstruction, TokenWithdrawInstruction, I80F48,
 };
-use crate::send_tx_expect_error;
+use crate::{assert_eq_f64, send_tx_expect_error};
 use mango_v4::accounts_ix::{HealthCheck, HealthCheckKind};
 use mango_v4::error::MangoError;
+use mango_v4::state::fixed_price_oracle;
 use solana_sdk::transport::TransportError;
 
 #[tokio::test]
@@ -174,3 +177,171 @@ async fn test_health_check() -> Result<(), TransportError> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_health_fixed_price_bank() -> Result<(), TransportError> {
+    let context = TestContext::new().await;
+    let solana = &context.solana.clone();
+
+    let admin = TestKeypair::new();
+    let owner = context.users[0].key;
+    let payer = context.users[1].key;
+    let mints = &context.mints[0..2];
+    let fixed_mint = &context.mints[2];
+
+    let mango_setup::GroupWithTokens { group, .. } = mango_setup::GroupWithTokensConfig {
+        admin,
+        payer,
+        mints: mints.to_vec(),
+        zero_token_is_quote: true,
+        ..mango_setup::GroupWithTokensConfig::default()
+    }
+    .create(solana)
+    .await;
+
+    let register = |fixed_price: Option<I80F48>, oracle: Pubkey| TokenRegisterInstruction {
+        token_index: 2,
+        decimals: fixed_mint.decimals,
+        adjustment_factor: 0.01,
+        util0: 0.40,
+        rate0: 0.07,
+        util1: 0.80,
+        rate1: 0.9,
+        max_rate: 1.50,
+        loan_origination_fee_rate: 0.0005,
+        loan_fee_rate: 0.0005,
+        maint_asset_weight: 0.8,
+        init_asset_weight: 0.6,
+        maint_liab_weight: 1.2,
+        init_liab_weight: 1.4,
+        liquidation_fee: 0.02,
+        platform_liquidation_fee: 0.0,
+        min_vault_to_deposits_ratio: 0.2,
+        net_borrow_limit_per_window_quote: 1_000_000_000_000,
+        net_borrow_limit_window_size_ts: 24 * 60 * 60,
+        fixed_price,
+        group,
+        admin,
+        mint: fixed_mint.pubkey,
+        oracle,
+        payer,
+        token_program: spl_token::ID,
+    };
+
+    //
+    // TEST: The fixed price must be positive and requires the fixed price oracle
+    //
+    assert!(
+        send_tx(solana, register(Some(I80F48::ZERO), fixed_price_oracle::ID))
+            .await
+            .is_err()
+    );
+    assert!(send_tx(
+        solana,
+        register(Some(I80F48::from(2)), Pubkey::new_unique())
+    )
+    .await
+    .is_err());
+    assert!(send_tx(solana, register(None, fixed_price_oracle::ID))
+        .await
+        .is_err());
+
+    //
+    // TEST: Health uses the fixed price without reading an oracle
+    //
+    send_tx(
+        solana,
+        register(Some(I80F48::from(2)), fixed_price_oracle::ID),
+    )
+    .await
+    .unwrap();
+
+    let account = create_funded_account(
+        &solana,
+        group,
+        owner,
+        0,
+        &context.users[1],
+        &[fixed_mint.clone()],
+        1000,
+        0,
+    )
+    .await;
+
+    // 1000 native tokens at a price of 2, with init asset weight 0.6
+    assert_eq_f64!(account_init_health(solana, account).await, 1200.0, 0.001);
+
+    let edit = |options| TokenEdit {
+        group,
+        admin,
+        mint: fixed_mint.pubkey,
+        fallback_oracle: Pubkey::default(),
+        options,
+    };
+
+    //
+    // TEST: token_edit can change the fixed price
+    //
+    send_tx(
+        solana,
+        edit(mango_v4::instruction::TokenEdit {
+            fixed_price_opt: Some(I80F48::from(3)),
+            ..token_edit_instruction_default()
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq_f64!(account_init_health(solana, account).await, 1800.0, 0.001);
+
+    //
+    // TEST: Removing the fixed price requires an oracle, and health then uses it
+    //
+    assert!(send_tx(
+        solana,
+        edit(mango_v4::instruction::TokenEdit {
+            fixed_price_opt: Some(I80F48::ZERO),
+            ..token_edit_instruction_default()
+        }),
+    )
+    .await
+    .is_err());
+
+    let oracle = send_tx(
+        solana,
+        StubOracleCreate {
+            oracle: TestKeypair::new(),
+            group,
+            mint: fixed_mint.pubkey,
+            admin,
+            payer,
+        },
+    )
+    .await
+    .unwrap()
+    .oracle;
+    send_tx(
+        solana,
+        StubOracleSetInstruction {
+            group,
+            admin,
+            mint: fixed_mint.pubkey,
+            price: 1.5,
+            oracle,
+        },
+    )
+    .await
+    .unwrap();
+    send_tx(
+        solana,
+        edit(mango_v4::instruction::TokenEdit {
+            oracle_opt: Some(oracle),
+            fixed_price_opt: Some(I80F48::ZERO),
+            ..token_edit_instruction_default()
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq_f64!(account_init_health(solana, account).await, 900.0, 0.001);
+
+    Ok(())
+}
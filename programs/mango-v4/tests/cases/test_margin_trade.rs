@@ -423,6 +423,183 @@ async fn test_flash_loan_swap_fee() -> Result<(), BanksClientError> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_flash_loan_fee_tier() -> Result<(), BanksClientError> {
+    let mut test_builder = TestContextBuilder::new();
+    test_builder.test().set_compute_max_units(100_000);
+    let context = test_builder.start_default().await;
+    let solana = &context.solana.clone();
+
+    let admin = TestKeypair::new();
+    let owner = context.users[0].key;
+    let payer = context.users[1].key;
+    let mints = &context.mints[0..2];
+    let owner_token0 = context.users[0].token_accounts[0];
+    let loan_origination_fee = 0.0005;
+
+    //
+    // SETUP: Create a group, and two accounts with collateral in token 1
+    //
+
+    let GroupWithTokens { group, tokens, .. } = GroupWithTokensConfig {
+        admin,
+        payer,
+        mints: mints.to_vec(),
+        ..GroupWithTokensConfig::default()
+    }
+    .create(solana)
+    .await;
+    let bank = tokens[0].bank;
+
+    let provided_amount = 100_000;
+    create_funded_account(
+        &solana,
+        group,
+        owner,
+        2,
+        &context.users[1],
+        mints,
+        provided_amount,
+        0,
+    )
+    .await;
+
+    let account0 = create_funded_account(
+        &solana,
+        group,
+        owner,
+        0,
+        &context.users[1],
+        &mints[1..2],
+        provided_amount,
+        0,
+    )
+    .await;
+    let account1 = create_funded_account(
+        &solana,
+        group,
+        owner,
+        1,
+        &context.users[1],
+        &mints[1..2],
+        provided_amount,
+        0,
+    )
+    .await;
+
+    //
+    // TEST: Only the admin can set the fee tier, and only to known tiers
+    //
+    assert!(send_tx(
+        solana,
+        AccountSetFeeTierInstruction {
+            group,
+            admin: owner,
+            account: account1,
+            fee_tier: 3,
+        },
+    )
+    .await
+    .is_err());
+    assert!(send_tx(
+        solana,
+        AccountSetFeeTierInstruction {
+            group,
+            admin,
+            account: account1,
+            fee_tier: FEE_TIER_LOAN_ORIGINATION_DISCOUNTS.len() as u8,
+        },
+    )
+    .await
+    .is_err());
+
+    send_tx(
+        solana,
+        AccountSetFeeTierInstruction {
+            group,
+            admin,
+            account: account1,
+            fee_tier: 3,
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(get_mango_account(solana, account1).await.fixed.fee_tier, 3);
+
+    //
+    // TEST: Borrow the same amount with both accounts, the tier 3 account pays half the fee
+    //
+    let withdraw_amount = 10_000;
+    for account in [account0, account1] {
+        let mut tx = ClientTransaction::new(solana);
+        let loans = vec![FlashLoanPart {
+            bank,
+            token_account: owner_token0,
+            withdraw_amount,
+        }];
+        tx.add_instruction(FlashLoanBeginInstruction {
+            account,
+            owner,
+            loans: loans.clone(),
+        })
+        .await;
+        tx.add_instruction(FlashLoanEndInstruction {
+            account,
+            owner,
+            loans,
+            flash_loan_type: mango_v4::accounts_ix::FlashLoanType::Unknown,
+        })
+        .await;
+        tx.send().await.unwrap();
+    }
+
+    let full_fee = withdraw_amount as f64 * loan_origination_fee;
+    let discount = FEE_TIER_LOAN_ORIGINATION_DISCOUNTS[3].to_num::<f64>();
+    assert_eq_f64!(
+        account_position_f64(solana, account0, bank).await,
+        -full_fee,
+        0.0001
+    );
+    assert_eq_f64!(
+        account_position_f64(solana, account1, bank).await,
+        -full_fee * (1.0 - discount),
+        0.0001
+    );
+
+    //
+    // TEST: The discount also applies to borrows through token_withdraw
+    //
+    for account in [account0, account1] {
+        send_tx(
+            solana,
+            TokenWithdrawInstruction {
+                amount: withdraw_amount,
+                allow_borrow: true,
+                account,
+                owner,
+                token_account: owner_token0,
+                bank_index: 0,
+            },
+        )
+        .await
+        .unwrap();
+    }
+
+    // the accounts already borrow, so all of the withdraw is a new loan
+    assert_eq_f64!(
+        account_position_f64(solana, account0, bank).await,
+        -2.0 * full_fee - withdraw_amount as f64,
+        0.0001
+    );
+    assert_eq_f64!(
+        account_position_f64(solana, account1, bank).await,
+        -2.0 * full_fee * (1.0 - discount) - withdraw_amount as f64,
+        0.0001
+    );
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_flash_loan_creates_ata_accounts() -> Result<(), BanksClientError> {
     let mut test_builder = TestContextBuilder::new();
@@ -839,3 +1016,448 @@ async fn test_margin_trade_skip_bank() -> Result<(), BanksClientError> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_flash_loan_partial_health_accounts() -> Result<(), BanksClientError> {
+    let mut test_builder = TestContextBuilder::new();
+    test_builder.test().set_compute_max_units(150_000);
+    let context = test_builder.start_default().await;
+    let solana = &context.solana.clone();
+
+    let admin = TestKeypair::new();
+    let owner = context.users[0].key;
+    let payer = context.users[1].key;
+    let mints = &context.mints[0..3];
+    let owner_token0 = context.users[0].token_accounts[0];
+
+    //
+    // SETUP: Create a group with three tokens and an account with deposits in all of them
+    //
+
+    let GroupWithTokens { group, tokens, .. } = GroupWithTokensConfig {
+        admin,
+        payer,
+        mints: mints.to_vec(),
+        ..GroupWithTokensConfig::default()
+    }
+    .create(solana)
+    .await;
+    let bank0 = tokens[0].bank;
+
+    // liquidity to borrow
+    create_funded_account(&solana, group, owner, 1, &context.users[1], mints, 10000, 0).await;
+
+    let account =
+        create_funded_account(&solana, group, owner, 0, &context.users[1], mints, 1000, 0).await;
+
+    // Borrows `amount` of token 0 and keeps it, leaving `skip_banks` out of the
+    // health accounts of FlashLoanEnd
+    let borrow_tx = |amount, skip_banks| async move {
+        let mut tx = ClientTransaction::new(solana);
+        let loans = vec![FlashLoanPart {
+            bank: bank0,
+            token_account: owner_token0,
+            withdraw_amount: amount,
+        }];
+        tx.add_instruction(FlashLoanBeginInstruction {
+            account,
+            owner,
+            loans: loans.clone(),
+        })
+        .await;
+        tx.add_instruction(HealthAccountSkipping {
+            inner: FlashLoanEndInstruction {
+                account,
+                owner,
+                loans,
+                flash_loan_type: mango_v4::accounts_ix::FlashLoanType::Unknown,
+            },
+            skip_banks,
+        })
+        .await;
+        tx
+    };
+
+    //
+    // TEST: FlashLoanEnd accepts health accounts without a deposit-only bank
+    //
+    borrow_tx(1200, vec![tokens[2].bank])
+        .await
+        .send()
+        .await
+        .unwrap();
+    assert!(account_position(solana, account, bank0).await < 0);
+
+    //
+    // TEST: The left out deposit doesn't count towards health. A borrow that only the
+    // left out deposit can back is rejected...
+    //
+    borrow_tx(500, vec![tokens[2].bank])
+        .await
+        .send_expect_error(MangoError::HealthMustBePositiveOrIncrease)
+        .await
+        .unwrap();
+
+    //
+    // TEST: ...while the full set of health accounts accepts it
+    //
+    borrow_tx(500, vec![]).await.send().await.unwrap();
+    assert!(account_init_health(solana, account).await > 0.0);
+
+    //
+    // TEST: The bank of a borrowed token can't be left out
+    //
+    borrow_tx(1, vec![tokens[0].bank])
+        .await
+        .send_expect_error(MangoError::InvalidBank)
+        .await
+        .unwrap();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_flash_loan_swap_with_mock_program() -> Result<(), BanksClientError> {
+    let mut test_builder = TestContextBuilder::new();
+    test_builder.test().set_compute_max_units(200_000);
+    let mock_swap = test_builder.add_mock_swap_program();
+    let context = test_builder.start_default().await;
+    let solana = &context.solana.clone();
+
+    let admin = TestKeypair::new();
+    let owner = context.users[0].key;
+    let payer = context.users[1].key;
+    let mints = &context.mints[0..2];
+    let owner_accounts = context.users[0].token_accounts.clone();
+    let payer_accounts = context.users[1].token_accounts.clone();
+
+    //
+    // SETUP: Create a group with two tokens, a liquidity provider and a funded pool
+    //
+
+    let GroupWithTokens { group, tokens, .. } = GroupWithTokensConfig {
+        admin,
+        payer,
+        mints: mints.to_vec(),
+        ..GroupWithTokensConfig::default()
+    }
+    .create(solana)
+    .await;
+
+    let provided_amount = 10000;
+    create_funded_account(
+        &solana,
+        group,
+        owner,
+        1,
+        &context.users[1],
+        mints,
+        provided_amount,
+        0,
+    )
+    .await;
+
+    let pool_funding = 5000;
+    let pool0 = solana
+        .create_token_account(&mock_swap.pool_authority, mints[0].pubkey)
+        .await;
+    let pool1 = solana
+        .create_token_account(&mock_swap.pool_authority, mints[1].pubkey)
+        .await;
+    solana
+        .process_transaction(
+            &[spl_token::instruction::transfer(
+                &spl_token::ID,
+                &payer_accounts[1],
+                &pool1,
+                &payer.pubkey(),
+                &[],
+                pool_funding,
+            )
+            .unwrap()],
+            Some(&[payer]),
+        )
+        .await
+        .unwrap();
+
+    //
+    // create the test user account with collateral in token 0
+    //
+
+    let initial_deposit = 5000;
+    let account = create_funded_account(
+        &solana,
+        group,
+        owner,
+        0,
+        &context.users[1],
+        &mints[0..1],
+        initial_deposit,
+        0,
+    )
+    .await;
+
+    let swap_fee_rate = solana
+        .get_account::<Bank>(tokens[0].bank)
+        .await
+        .flash_loan_swap_fee_rate as f64;
+
+    // flash loan token 0, swap it for token 1 in the mock program
+    let (bank0, bank1) = (tokens[0].bank, tokens[1].bank);
+    let (owner_account0, owner_account1) = (owner_accounts[0], owner_accounts[1]);
+    let mock_swap = &mock_swap;
+    let swap_tx = |amount_in: u64, amount_out: u64| async move {
+        let mut tx = ClientTransaction::new(solana);
+        let loans = vec![
+            FlashLoanPart {
+                bank: bank0,
+                token_account: owner_account0,
+                withdraw_amount: amount_in,
+            },
+            FlashLoanPart {
+                bank: bank1,
+                token_account: owner_account1,
+                withdraw_amount: 0,
+            },
+        ];
+        tx.add_instruction(FlashLoanBeginInstruction {
+            account,
+            owner,
+            loans: loans.clone(),
+        })
+        .await;
+        tx.add_instruction_direct(mock_swap.swap_instruction(
+            owner_account0,
+            pool0,
+            pool1,
+            owner_account1,
+            owner.pubkey(),
+            amount_in,
+            amount_out,
+        ));
+        tx.add_instruction(FlashLoanEndInstruction {
+            account,
+            owner,
+            loans,
+            flash_loan_type: mango_v4::accounts_ix::FlashLoanType::Swap,
+        })
+        .await;
+        tx
+    };
+
+    //
+    // TEST: A swap that leaves the account with unbacked borrows fails the health check
+    //
+    {
+        let result = swap_tx(8000, 0).await.send_get_metadata().await.unwrap();
+        assert!(result.result.is_err());
+        let logs = result.metadata.unwrap().log_messages;
+        assert!(logs
+            .iter()
+            .any(|line| line.contains("Error Code: HealthMustBePositiveOrIncrease")));
+        assert_eq!(solana.token_account_balance(pool0).await, 0);
+    }
+
+    //
+    // TEST: Swapping token 0 for token 1 through the mock program
+    //
+    let initial_owner_balance0 = solana.token_account_balance(owner_accounts[0]).await;
+    let initial_owner_balance1 = solana.token_account_balance(owner_accounts[1]).await;
+
+    let amount_in = 1000;
+    let amount_out = 900;
+    swap_tx(amount_in, amount_out).await.send().await.unwrap();
+
+    // the owner's token accounts are only used in passing
+    assert_eq!(
+        solana.token_account_balance(owner_accounts[0]).await,
+        initial_owner_balance0
+    );
+    assert_eq!(
+        solana.token_account_balance(owner_accounts[1]).await,
+        initial_owner_balance1
+    );
+
+    assert_eq!(solana.token_account_balance(pool0).await, amount_in);
+    assert_eq!(
+        solana.token_account_balance(pool1).await,
+        pool_funding - amount_out
+    );
+    assert_eq!(
+        solana.token_account_balance(tokens[0].vault).await,
+        provided_amount + initial_deposit - amount_in
+    );
+    assert_eq!(
+        solana.token_account_balance(tokens[1].vault).await,
+        provided_amount + amount_out
+    );
+
+    assert_eq_f64!(
+        account_position_f64(solana, account, tokens[0].bank).await,
+        initial_deposit as f64 - amount_in as f64 * (1.0 + swap_fee_rate),
+        0.0001
+    );
+    assert_eq_f64!(
+        account_position_f64(solana, account, tokens[1].bank).await,
+        amount_out as f64,
+        0.0001
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_flash_loan_token_withdraw_health() -> Result<(), BanksClientError> {
+    let context = TestContext::new().await;
+    let solana = &context.solana.clone();
+
+    let admin = TestKeypair::new();
+    let owner = context.users[0].key;
+    let payer = context.users[1].key;
+    let mints = &context.mints[0..2];
+    let owner_accounts = context.users[0].token_accounts.clone();
+    let payer_accounts = context.users[1].token_accounts.clone();
+
+    let GroupWithTokens { group, tokens, .. } = GroupWithTokensConfig {
+        admin,
+        payer,
+        mints: mints.to_vec(),
+        ..GroupWithTokensConfig::default()
+    }
+    .create(solana)
+    .await;
+
+    // liquidity to borrow
+    create_funded_account(&solana, group, owner, 1, &context.users[1], mints, 10000, 0).await;
+
+    let account = create_funded_account(
+        &solana,
+        group,
+        owner,
+        0,
+        &context.users[1],
+        &mints[0..1],
+        1000,
+        0,
+    )
+    .await;
+
+    //
+    // SETUP: Borrow token 1, then raise its price so init health is negative while
+    // maint health stays positive
+    //
+    send_tx(
+        solana,
+        TokenWithdrawInstruction {
+            amount: 300,
+            allow_borrow: true,
+            account,
+            owner,
+            token_account: owner_accounts[1],
+            bank_index: 0,
+        },
+    )
+    .await
+    .unwrap();
+    send_tx(
+        solana,
+        StubOracleSetInstruction {
+            group,
+            admin,
+            mint: mints[1].pubkey,
+            price: 1.6,
+            oracle: tokens[1].oracle,
+        },
+    )
+    .await
+    .unwrap();
+    assert!(account_init_health(solana, account).await < 0.0);
+    assert!(account_maint_health(solana, account).await > 0.0);
+
+    // A flash loan swap of 10 token 0 for 10 token 1: health increases but stays
+    // below zero
+    let (bank0, bank1) = (tokens[0].bank, tokens[1].bank);
+    let (owner_token0, owner_token1) = (owner_accounts[0], owner_accounts[1]);
+    let (payer_token0, payer_token1) = (payer_accounts[0], payer_accounts[1]);
+    let swap_tx = move |flash_loan_type| async move {
+        let loans = vec![
+            FlashLoanPart {
+                bank: bank0,
+                token_account: owner_token0,
+                withdraw_amount: 10,
+            },
+            FlashLoanPart {
+                bank: bank1,
+                token_account: owner_token1,
+                withdraw_amount: 0,
+            },
+        ];
+        let mut tx = ClientTransaction::new(solana);
+        tx.add_instruction(FlashLoanBeginInstruction {
+            account,
+            owner,
+            loans: loans.clone(),
+        })
+        .await;
+        tx.add_instruction_direct(
+            spl_token::instruction::transfer(
+                &spl_token::ID,
+                &owner_token0,
+                &payer_token0,
+                &owner.pubkey(),
+                &[&owner.pubkey()],
+                10,
+            )
+            .unwrap(),
+        );
+        tx.add_instruction_direct(
+            spl_token::instruction::transfer(
+                &spl_token::ID,
+                &payer_token1,
+                &owner_token1,
+                &payer.pubkey(),
+                &[&payer.pubkey()],
+                10,
+            )
+            .unwrap(),
+        );
+        tx.add_signer(payer);
+        tx.add_instruction(FlashLoanEndInstruction {
+            account,
+            owner,
+            loans,
+            flash_loan_type,
+        })
+        .await;
+        tx
+    };
+
+    //
+    // TEST: TokenWithdraw and Unknown require non-negative init health at the end
+    //
+    swap_tx(mango_v4::accounts_ix::FlashLoanType::TokenWithdraw)
+        .await
+        .send_expect_error(MangoError::HealthMustBePositive)
+        .await
+        .unwrap();
+    swap_tx(mango_v4::accounts_ix::FlashLoanType::Unknown)
+        .await
+        .send_expect_error(MangoError::HealthMustBePositive)
+        .await
+        .unwrap();
+
+    //
+    // TEST: Swap accepts it, since health doesn't decrease
+    //
+    let health_before = account_init_health(solana, account).await;
+    swap_tx(mango_v4::accounts_ix::FlashLoanType::Swap)
+        .await
+        .send()
+        .await
+        .unwrap();
+    let health_after = account_init_health(solana, account).await;
+    assert!(health_after > health_before);
+    assert!(health_after < 0.0);
+
+    Ok(())
+}
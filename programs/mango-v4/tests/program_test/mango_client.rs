@@ -816,6 +816,10 @@ impl ClientInstruction for TokenWithdrawInstruction {
         )
         .0;
         let mint_info: MintInfo = account_loader.load(&mint_info).await.unwrap();
+        let bank: Bank = account_loader
+            .load(&mint_info.banks[self.bank_index])
+            .await
+            .unwrap();
 
         let health_check_metas = derive_health_check_remaining_account_metas(
             account_loader,
@@ -834,7 +838,7 @@ impl ClientInstruction for TokenWithdrawInstruction {
             vault: mint_info.vaults[self.bank_index],
             oracle: mint_info.oracle,
             token_account: self.token_account,
-            token_program: Token::id(),
+            token_program: bank.vault_token_program(),
         };
 
         let mut instruction = make_instruction(program_id, &accounts, &instruction);
@@ -888,6 +892,10 @@ impl ClientInstruction for TokenDepositInstruction {
         )
         .0;
         let mint_info: MintInfo = account_loader.load(&mint_info).await.unwrap();
+        let bank: Bank = account_loader
+            .load(&mint_info.banks[self.bank_index])
+            .await
+            .unwrap();
 
         let health_check_metas = derive_health_check_remaining_account_metas(
             account_loader,
@@ -907,7 +915,7 @@ impl ClientInstruction for TokenDepositInstruction {
             oracle: mint_info.oracle,
             token_account: self.token_account,
             token_authority: self.token_authority.pubkey(),
-            token_program: Token::id(),
+            token_program: bank.vault_token_program(),
         };
 
         let mut instruction = make_instruction(program_id, &accounts, &instruction);
@@ -959,6 +967,10 @@ impl ClientInstruction for TokenDepositIntoExistingInstruction {
         )
         .0;
         let mint_info: MintInfo = account_loader.load(&mint_info).await.unwrap();
+        let bank: Bank = account_loader
+            .load(&mint_info.banks[self.bank_index])
+            .await
+            .unwrap();
 
         let health_check_metas = derive_health_check_remaining_account_metas(
             account_loader,
@@ -977,7 +989,7 @@ impl ClientInstruction for TokenDepositIntoExistingInstruction {
             oracle: mint_info.oracle,
             token_account: self.token_account,
             token_authority: self.token_authority.pubkey(),
-            token_program: Token::id(),
+            token_program: bank.vault_token_program(),
         };
 
         let mut instruction = make_instruction(program_id, &accounts, &instruction);
@@ -1012,12 +1024,15 @@ pub struct TokenRegisterInstruction {
     pub min_vault_to_deposits_ratio: f64,
     pub net_borrow_limit_per_window_quote: i64,
     pub net_borrow_limit_window_size_ts: u64,
+    pub fixed_price: Option<I80F48>,
 
     pub group: Pubkey,
     pub admin: TestKeypair,
     pub mint: Pubkey,
     pub oracle: Pubkey,
     pub payer: TestKeypair,
+    /// The token program or token-2022, whichever owns the mint
+    pub token_program: Pubkey,
 }
 #[async_trait::async_trait(?Send)]
 impl ClientInstruction for TokenRegisterInstruction {
@@ -1074,6 +1089,8 @@ impl ClientInstruction for TokenRegisterInstruction {
             platform_liquidation_fee: self.platform_liquidation_fee,
             disable_asset_liquidation: false,
             collateral_fee_per_day: 0.0,
+            fixed_price: self.fixed_price,
+            borrow_limit: 0,
         };
 
         let bank = Pubkey::find_program_address(
@@ -1117,9 +1134,10 @@ impl ClientInstruction for TokenRegisterInstruction {
             oracle: self.oracle,
             fallback_oracle,
             payer: self.payer.pubkey(),
-            token_program: Token::id(),
+            token_program: self.token_program,
             system_program: System::id(),
             rent: sysvar::rent::Rent::id(),
+            pre_checks: mango_v4::accounts::TokenRegisterPreChecks { mint_info, bank },
         };
 
         let instruction = make_instruction(program_id, &accounts, &instruction);
@@ -1324,6 +1342,8 @@ pub fn token_edit_instruction_default() -> mango_v4::instruction::TokenEdit {
         disable_asset_liquidation_opt: None,
         collateral_fee_per_day_opt: None,
         force_withdraw_opt: None,
+        borrow_limit_opt: None,
+        fixed_price_opt: None,
     }
 }
 
@@ -2201,6 +2221,40 @@ impl ClientInstruction for AccountEditInstruction {
     }
 }
 
+pub struct AccountSetFeeTierInstruction {
+    pub group: Pubkey,
+    pub admin: TestKeypair,
+    pub account: Pubkey,
+    pub fee_tier: u8,
+}
+#[async_trait::async_trait(?Send)]
+impl ClientInstruction for AccountSetFeeTierInstruction {
+    type Accounts = mango_v4::accounts::AccountSetFeeTier;
+    type Instruction = mango_v4::instruction::AccountSetFeeTier;
+    async fn to_instruction(
+        &self,
+        _account_loader: &(impl ClientAccountLoader + 'async_trait),
+    ) -> (Self::Accounts, instruction::Instruction) {
+        let program_id = mango_v4::id();
+        let instruction = Self::Instruction {
+            fee_tier: self.fee_tier,
+        };
+
+        let accounts = Self::Accounts {
+            group: self.group,
+            account: self.account,
+            admin: self.admin.pubkey(),
+        };
+
+        let instruction = make_instruction(program_id, &accounts, &instruction);
+        (accounts, instruction)
+    }
+
+    fn signers(&self) -> Vec<TestKeypair> {
+        vec![self.admin]
+    }
+}
+
 pub struct AccountCloseInstruction {
     pub group: Pubkey,
     pub account: Pubkey,
@@ -3160,6 +3214,73 @@ impl ClientInstruction for TokenForceWithdrawInstruction {
     }
 }
 
+pub struct TokenSweepDustInstruction {
+    pub group: Pubkey,
+    pub admin: TestKeypair,
+    pub bank: Pubkey,
+}
+#[async_trait::async_trait(?Send)]
+impl ClientInstruction for TokenSweepDustInstruction {
+    type Accounts = mango_v4::accounts::TokenSweepDust;
+    type Instruction = mango_v4::instruction::TokenSweepDust;
+    async fn to_instruction(
+        &self,
+        _account_loader: &(impl ClientAccountLoader + 'async_trait),
+    ) -> (Self::Accounts, instruction::Instruction) {
+        let program_id = mango_v4::id();
+        let instruction = Self::Instruction {};
+
+        let accounts = Self::Accounts {
+            group: self.group,
+            admin: self.admin.pubkey(),
+            bank: self.bank,
+        };
+
+        let instruction = make_instruction(program_id, &accounts, &instruction);
+        (accounts, instruction)
+    }
+
+    fn signers(&self) -> Vec<TestKeypair> {
+        vec![self.admin]
+    }
+}
+
+pub struct AdminTokenWithdrawFeesInstruction {
+    pub group: Pubkey,
+    pub admin: TestKeypair,
+    pub bank: Pubkey,
+    pub token_account: Pubkey,
+}
+#[async_trait::async_trait(?Send)]
+impl ClientInstruction for AdminTokenWithdrawFeesInstruction {
+    type Accounts = mango_v4::accounts::AdminTokenWithdrawFees;
+    type Instruction = mango_v4::instruction::AdminTokenWithdrawFees;
+    async fn to_instruction(
+        &self,
+        account_loader: &(impl ClientAccountLoader + 'async_trait),
+    ) -> (Self::Accounts, instruction::Instruction) {
+        let program_id = mango_v4::id();
+        let instruction = Self::Instruction {};
+
+        let bank: Bank = account_loader.load(&self.bank).await.unwrap();
+        let accounts = Self::Accounts {
+            group: self.group,
+            bank: self.bank,
+            vault: bank.vault,
+            token_account: self.token_account,
+            token_program: Token::id(),
+            admin: self.admin.pubkey(),
+        };
+
+        let instruction = make_instruction(program_id, &accounts, &instruction);
+        (accounts, instruction)
+    }
+
+    fn signers(&self) -> Vec<TestKeypair> {
+        vec![self.admin]
+    }
+}
+
 pub struct TokenLiqWithTokenInstruction {
     pub liqee: Pubkey,
     pub liqor: Pubkey,
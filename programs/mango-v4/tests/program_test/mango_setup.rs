@@ -113,6 +113,8 @@ impl<'a> GroupWithTokensConfig {
                     net_borrow_limit_per_window_quote: 1_000_000_000_000,
                     net_borrow_limit_window_size_ts: 24 * 60 * 60,
                     platform_liquidation_fee: 0.0,
+                    fixed_price: None,
+                    token_program: spl_token::ID,
                 },
             )
             .await
@@ -0,0 +1,107 @@
+use solana_program::account_info::AccountInfo;
+use solana_program::entrypoint::ProgramResult;
+use solana_program::instruction::{AccountMeta, Instruction};
+use solana_program::program::{invoke, invoke_signed};
+use solana_sdk::pubkey::Pubkey;
+
+const POOL_AUTHORITY_SEED: &[u8] = b"MockSwap";
+
+/// A dex-like program for tests: takes `amount_in` of the user's source token into a
+/// pool token account and pays out `amount_out` from another pool token account.
+///
+/// Pool token accounts must be owned by MockSwapCookie::pool_authority.
+pub struct MockSwapCookie {
+    pub program: Pubkey,
+    pub pool_authority: Pubkey,
+}
+
+impl MockSwapCookie {
+    pub fn new(program: Pubkey) -> Self {
+        let (pool_authority, _) = Pubkey::find_program_address(&[POOL_AUTHORITY_SEED], &program);
+        Self {
+            program,
+            pool_authority,
+        }
+    }
+
+    pub fn swap_instruction(
+        &self,
+        user_source: Pubkey,
+        pool_source: Pubkey,
+        pool_destination: Pubkey,
+        user_destination: Pubkey,
+        user_authority: Pubkey,
+        amount_in: u64,
+        amount_out: u64,
+    ) -> Instruction {
+        let mut data = amount_in.to_le_bytes().to_vec();
+        data.extend_from_slice(&amount_out.to_le_bytes());
+        Instruction {
+            program_id: self.program,
+            accounts: vec![
+                AccountMeta::new(user_source, false),
+                AccountMeta::new(pool_source, false),
+                AccountMeta::new(pool_destination, false),
+                AccountMeta::new(user_destination, false),
+                AccountMeta::new_readonly(user_authority, true),
+                AccountMeta::new_readonly(self.pool_authority, false),
+                AccountMeta::new_readonly(spl_token::ID, false),
+            ],
+            data,
+        }
+    }
+}
+
+pub fn mock_swap_process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> ProgramResult {
+    let amount_in = u64::from_le_bytes(data[0..8].try_into().unwrap());
+    let amount_out = u64::from_le_bytes(data[8..16].try_into().unwrap());
+    let [user_source, pool_source, pool_destination, user_destination, user_authority, pool_authority, token_program] =
+        accounts
+    else {
+        panic!("mock swap needs exactly 7 accounts");
+    };
+
+    invoke(
+        &spl_token::instruction::transfer(
+            token_program.key,
+            user_source.key,
+            pool_source.key,
+            user_authority.key,
+            &[],
+            amount_in,
+        )?,
+        &[
+            user_source.clone(),
+            pool_source.clone(),
+            user_authority.clone(),
+            token_program.clone(),
+        ],
+    )?;
+
+    let (expected_pool_authority, bump) =
+        Pubkey::find_program_address(&[POOL_AUTHORITY_SEED], program_id);
+    assert_eq!(*pool_authority.key, expected_pool_authority);
+    invoke_signed(
+        &spl_token::instruction::transfer(
+            token_program.key,
+            pool_destination.key,
+            user_destination.key,
+            pool_authority.key,
+            &[],
+            amount_out,
+        )?,
+        &[
+            pool_destination.clone(),
+            user_destination.clone(),
+            pool_authority.clone(),
+            token_program.clone(),
+        ],
+        &[&[POOL_AUTHORITY_SEED, &[bump]]],
+    )?;
+
+    Ok(())
+}
@@ -11,6 +11,7 @@ use spl_token::{state::*, *};
 
 pub use cookies::*;
 pub use mango_client::*;
+pub use mock_swap::*;
 pub use serum::*;
 pub use solana::*;
 pub use utils::*;
@@ -18,6 +19,7 @@ pub use utils::*;
 pub mod cookies;
 pub mod mango_client;
 pub mod mango_setup;
+pub mod mock_swap;
 pub mod serum;
 pub mod solana;
 pub mod utils;
@@ -218,6 +220,16 @@ impl TestContextBuilder {
         }
     }
 
+    pub fn add_mock_swap_program(&mut self) -> MockSwapCookie {
+        let program = Pubkey::new_unique();
+        self.test.add_program(
+            "mock_swap",
+            program,
+            processor!(mock_swap_process_instruction),
+        );
+        MockSwapCookie::new(program)
+    }
+
     pub async fn start_default(mut self) -> TestContext {
         let mints = self.create_mints();
         let users = self.create_users(&mints);
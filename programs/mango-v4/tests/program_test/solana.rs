@@ -6,6 +6,7 @@ use std::io::{Cursor, Write};
 use super::utils::TestKeypair;
 use anchor_lang::AccountDeserialize;
 use anchor_spl::token::TokenAccount;
+use anchor_spl::token_2022::spl_token_2022;
 use solana_program::{program_pack::Pack, rent::*, system_instruction};
 use solana_program_test::*;
 use solana_sdk::{
@@ -191,6 +192,131 @@ impl SolanaCookie {
         return keypair.pubkey();
     }
 
+    pub async fn create_token_2022_mint(&self, authority: &Pubkey, decimals: u8) -> Pubkey {
+        let keypair = TestKeypair::new();
+        let rent = self.rent.minimum_balance(spl_token_2022::state::Mint::LEN);
+
+        let instructions = [
+            system_instruction::create_account(
+                &self.context.borrow().payer.pubkey(),
+                &keypair.pubkey(),
+                rent,
+                spl_token_2022::state::Mint::LEN as u64,
+                &spl_token_2022::id(),
+            ),
+            spl_token_2022::instruction::initialize_mint2(
+                &spl_token_2022::id(),
+                &keypair.pubkey(),
+                authority,
+                None,
+                decimals,
+            )
+            .unwrap(),
+        ];
+
+        self.process_transaction(&instructions, Some(&[keypair]))
+            .await
+            .unwrap();
+        keypair.pubkey()
+    }
+
+    pub async fn create_token_2022_mint_with_transfer_fee(
+        &self,
+        authority: &Pubkey,
+        decimals: u8,
+        transfer_fee_basis_points: u16,
+    ) -> Pubkey {
+        use spl_token_2022::extension::{transfer_fee, ExtensionType};
+
+        let keypair = TestKeypair::new();
+        let space = ExtensionType::get_account_len::<spl_token_2022::state::Mint>(&[
+            ExtensionType::TransferFeeConfig,
+        ]);
+        let rent = self.rent.minimum_balance(space);
+
+        let instructions = [
+            system_instruction::create_account(
+                &self.context.borrow().payer.pubkey(),
+                &keypair.pubkey(),
+                rent,
+                space as u64,
+                &spl_token_2022::id(),
+            ),
+            transfer_fee::instruction::initialize_transfer_fee_config(
+                &spl_token_2022::id(),
+                &keypair.pubkey(),
+                Some(authority),
+                Some(authority),
+                transfer_fee_basis_points,
+                u64::MAX,
+            )
+            .unwrap(),
+            spl_token_2022::instruction::initialize_mint2(
+                &spl_token_2022::id(),
+                &keypair.pubkey(),
+                authority,
+                None,
+                decimals,
+            )
+            .unwrap(),
+        ];
+
+        self.process_transaction(&instructions, Some(&[keypair]))
+            .await
+            .unwrap();
+        keypair.pubkey()
+    }
+
+    pub async fn create_token_2022_account(&self, owner: &Pubkey, mint: Pubkey) -> Pubkey {
+        let keypair = TestKeypair::new();
+        let rent = self
+            .rent
+            .minimum_balance(spl_token_2022::state::Account::LEN);
+
+        let instructions = [
+            system_instruction::create_account(
+                &self.context.borrow().payer.pubkey(),
+                &keypair.pubkey(),
+                rent,
+                spl_token_2022::state::Account::LEN as u64,
+                &spl_token_2022::id(),
+            ),
+            spl_token_2022::instruction::initialize_account3(
+                &spl_token_2022::id(),
+                &keypair.pubkey(),
+                &mint,
+                owner,
+            )
+            .unwrap(),
+        ];
+
+        self.process_transaction(&instructions, Some(&[keypair]))
+            .await
+            .unwrap();
+        keypair.pubkey()
+    }
+
+    pub async fn mint_token_2022(
+        &self,
+        mint: Pubkey,
+        authority: TestKeypair,
+        destination: Pubkey,
+        amount: u64,
+    ) {
+        let instruction = spl_token_2022::instruction::mint_to(
+            &spl_token_2022::id(),
+            &mint,
+            &destination,
+            &authority.pubkey(),
+            &[],
+            amount,
+        )
+        .unwrap();
+        self.process_transaction(&[instruction], Some(&[authority]))
+            .await
+            .unwrap();
+    }
+
     // Note: Only one table can be created per authority per slot!
     pub async fn create_address_lookup_table(
         &self,
@@ -209,6 +335,25 @@ impl SolanaCookie {
         alt_address
     }
 
+    /// Like create_address_lookup_table(), but the authority doesn't sign, so it can
+    /// be a PDA like the group
+    pub async fn create_address_lookup_table_for(
+        &self,
+        authority: Pubkey,
+        payer: TestKeypair,
+    ) -> Pubkey {
+        let (instruction, alt_address) =
+            solana_address_lookup_table_program::instruction::create_lookup_table(
+                authority,
+                payer.pubkey(),
+                self.get_newest_slot_from_history().await,
+            );
+        self.process_transaction(&[instruction], Some(&[payer]))
+            .await
+            .unwrap();
+        alt_address
+    }
+
     pub async fn get_account_data(&self, address: Pubkey) -> Option<Vec<u8>> {
         Some(
             self.context
@@ -83,6 +83,7 @@ export class Bank implements BankForHealth {
   public platformLiquidationFee: I80F48;
   public collectedLiquidationFees: I80F48;
   public collectedCollateralFees: I80F48;
+  public fixedPrice: I80F48;
 
   static from(
     publicKey: PublicKey,
@@ -151,6 +152,9 @@ export class Bank implements BankForHealth {
       collectedLiquidationFees: I80F48Dto;
       collectedCollateralFees: I80F48Dto;
       collateralFeePerDay: number;
+      isToken2022: number;
+      fixedPrice: I80F48Dto;
+      borrowLimit: BN;
     },
   ): Bank {
     return new Bank(
@@ -219,6 +223,9 @@ export class Bank implements BankForHealth {
       obj.collectedCollateralFees,
       obj.collateralFeePerDay,
       obj.forceWithdraw == 1,
+      obj.isToken2022 == 1,
+      obj.fixedPrice,
+      obj.borrowLimit,
     );
   }
 
@@ -288,6 +295,9 @@ export class Bank implements BankForHealth {
     collectedCollateralFees: I80F48Dto,
     public collateralFeePerDay: number,
     public forceWithdraw: boolean,
+    public isToken2022: boolean,
+    fixedPrice: I80F48Dto,
+    public borrowLimit: BN,
   ) {
     this.name = utf8.decode(new Uint8Array(name)).split('\x00')[0];
     this.oracleConfig = {
@@ -321,6 +331,7 @@ export class Bank implements BankForHealth {
     this.platformLiquidationFee = I80F48.from(platformLiquidationFee);
     this.collectedLiquidationFees = I80F48.from(collectedLiquidationFees);
     this.collectedCollateralFees = I80F48.from(collectedCollateralFees);
+    this.fixedPrice = I80F48.from(fixedPrice);
     this._price = undefined;
     this._uiPrice = undefined;
     this._oracleLastUpdatedSlot = undefined;
@@ -271,6 +271,26 @@ export class MangoClient {
     return await this.sendAndConfirmTransaction([ix]);
   }
 
+  /**
+   * Moves the whole native tokens of the bank's dust into its collected fees,
+   * where adminTokenWithdrawFees can withdraw them
+   */
+  public async tokenSweepDust(
+    group: Group,
+    bank: Bank,
+  ): Promise<MangoSignatureStatus> {
+    const admin = (this.program.provider as AnchorProvider).wallet.publicKey;
+    const ix = await this.program.methods
+      .tokenSweepDust()
+      .accounts({
+        group: group.publicKey,
+        bank: bank.publicKey,
+        admin,
+      })
+      .instruction();
+    return await this.sendAndConfirmTransaction([ix]);
+  }
+
   public async adminPerpWithdrawFees(
     group: Group,
     perpMarket: PerpMarket,
@@ -453,6 +473,25 @@ export class MangoClient {
     name: string,
     params: TokenRegisterParams,
   ): Promise<MangoSignatureStatus> {
+    // checked to be empty before the bank and mint info are created
+    const tokenIndexBuf = Buffer.alloc(2);
+    tokenIndexBuf.writeUInt16LE(tokenIndex);
+    const bankNumBuf = Buffer.alloc(4);
+    bankNumBuf.writeUInt32LE(0);
+    const [bank] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from('Bank'),
+        group.publicKey.toBuffer(),
+        tokenIndexBuf,
+        bankNumBuf,
+      ],
+      this.program.programId,
+    );
+    const [mintInfo] = await PublicKey.findProgramAddress(
+      [Buffer.from('MintInfo'), group.publicKey.toBuffer(), mintPk.toBuffer()],
+      this.program.programId,
+    );
+
     const ix = await this.program.methods
       .tokenRegister(
         tokenIndex,
@@ -486,6 +525,10 @@ export class MangoClient {
         params.platformLiquidationFee,
         params.disableAssetLiquidation,
         params.collateralFeePerDay,
+        params.fixedPrice !== null
+          ? { val: I80F48.fromNumber(params.fixedPrice).getData() }
+          : null,
+        params.borrowLimit,
       )
       .accounts({
         group: group.publicKey,
@@ -495,6 +538,7 @@ export class MangoClient {
         fallbackOracle: fallbackOraclePk,
         payer: (this.program.provider as AnchorProvider).wallet.publicKey,
         rent: SYSVAR_RENT_PUBKEY,
+        preChecks: { mintInfo, bank },
       })
       .instruction();
     return await this.sendAndConfirmTransactionForGroup(group, [ix]);
@@ -576,6 +620,10 @@ export class MangoClient {
         params.disableAssetLiquidation,
         params.collateralFeePerDay,
         params.forceWithdraw,
+        params.borrowLimit,
+        params.fixedPrice !== null
+          ? { val: I80F48.fromNumber(params.fixedPrice).getData() }
+          : null,
       )
       .accounts({
         group: group.publicKey,
@@ -1051,6 +1099,26 @@ export class MangoClient {
     return await this.sendAndConfirmTransactionForGroup(group, [ix]);
   }
 
+  /**
+   * Sets the account's fee discount tier, 0 for none, up to 3. The tier waives part
+   * of the loan origination fee on flash loans and token withdraws.
+   */
+  public async accountSetFeeTier(
+    group: Group,
+    mangoAccount: MangoAccount,
+    feeTier: number,
+  ): Promise<MangoSignatureStatus> {
+    const ix = await this.program.methods
+      .accountSetFeeTier(feeTier)
+      .accounts({
+        group: group.publicKey,
+        account: mangoAccount.publicKey,
+        admin: (this.program.provider as AnchorProvider).wallet.publicKey,
+      })
+      .instruction();
+    return await this.sendAndConfirmTransactionForGroup(group, [ix]);
+  }
+
   public async sequenceCheckIx(
     group: Group,
     mangoAccount: MangoAccount,
@@ -3702,6 +3770,7 @@ export class MangoClient {
     userDefinedAlts = [],
     // margin trade is a general function
     // set flash_loan_type to FlashLoanType.swap if you desire the transaction to be recorded as a swap
+    // FlashLoanType.unknown requires non-negative init health at the end
     flashLoanType,
   }: {
     group: Group;
@@ -32,6 +32,8 @@ export interface TokenRegisterParams {
   platformLiquidationFee: number;
   disableAssetLiquidation: boolean;
   collateralFeePerDay: number;
+  fixedPrice: number | null;
+  borrowLimit: BN;
 }
 
 export const DefaultTokenRegisterParams: TokenRegisterParams = {
@@ -74,6 +76,8 @@ export const DefaultTokenRegisterParams: TokenRegisterParams = {
   platformLiquidationFee: 0.0,
   disableAssetLiquidation: false,
   collateralFeePerDay: 0.0,
+  fixedPrice: null,
+  borrowLimit: new BN(0),
 };
 
 export interface TokenEditParams {
@@ -118,6 +122,8 @@ export interface TokenEditParams {
   disableAssetLiquidation: boolean | null;
   collateralFeePerDay: number | null;
   forceWithdraw: boolean | null;
+  borrowLimit: BN | null;
+  fixedPrice: number | null;
 }
 
 export const NullTokenEditParams: TokenEditParams = {
@@ -162,6 +168,8 @@ export const NullTokenEditParams: TokenEditParams = {
   disableAssetLiquidation: null,
   collateralFeePerDay: null,
   forceWithdraw: null,
+  borrowLimit: null,
+  fixedPrice: null,
 };
 
 export interface PerpEditParams {
@@ -312,6 +320,8 @@ export interface IxGateParams {
   TokenForceWithdraw: boolean;
   SequenceCheck: boolean;
   HealthCheck: boolean;
+  AccountSetFeeTier: boolean;
+  TokenSweepDust: boolean;
 }
 
 // Default with all ixs enabled, use with buildIxGate
@@ -394,6 +404,8 @@ export const TrueIxGateParams: IxGateParams = {
   TokenForceWithdraw: true,
   SequenceCheck: true,
   HealthCheck: true,
+  AccountSetFeeTier: true,
+  TokenSweepDust: true,
 };
 
 // build ix gate e.g. buildIxGate(Builder(TrueIxGateParams).TokenDeposit(false).build()).toNumber(),
@@ -486,6 +498,8 @@ export function buildIxGate(p: IxGateParams): BN {
   toggleIx(ixGate, p, 'TokenForceWithdraw', 72);
   toggleIx(ixGate, p, 'SequenceCheck', 73);
   toggleIx(ixGate, p, 'HealthCheck', 74);
+  toggleIx(ixGate, p, 'AccountSetFeeTier', 75);
+  toggleIx(ixGate, p, 'TokenSweepDust', 76);
 
   return ixGate;
 }
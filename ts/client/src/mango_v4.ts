@@ -504,7 +504,10 @@ export type MangoV4 = {
         {
           "name": "tokenProgram",
           "isMut": false,
-          "isSigner": false
+          "isSigner": false,
+          "docs": [
+            "The token program or token-2022, must own the mint"
+          ]
         },
         {
           "name": "systemProgram",
@@ -515,6 +518,21 @@ export type MangoV4 = {
           "name": "rent",
           "isMut": false,
           "isSigner": false
+        },
+        {
+          "name": "preChecks",
+          "accounts": [
+            {
+              "name": "mintInfo",
+              "isMut": false,
+              "isSigner": false
+            },
+            {
+              "name": "bank",
+              "isMut": false,
+              "isSigner": false
+            }
+          ]
         }
       ],
       "args": [
@@ -645,6 +663,18 @@ export type MangoV4 = {
         {
           "name": "collateralFeePerDay",
           "type": "f32"
+        },
+        {
+          "name": "fixedPrice",
+          "type": {
+            "option": {
+              "defined": "I80F48"
+            }
+          }
+        },
+        {
+          "name": "borrowLimit",
+          "type": "u64"
         }
       ]
     },
@@ -1073,6 +1103,20 @@ export type MangoV4 = {
           "type": {
             "option": "bool"
           }
+        },
+        {
+          "name": "borrowLimitOpt",
+          "type": {
+            "option": "u64"
+          }
+        },
+        {
+          "name": "fixedPriceOpt",
+          "type": {
+            "option": {
+              "defined": "I80F48"
+            }
+          }
         }
       ]
     },
@@ -1242,7 +1286,10 @@ export type MangoV4 = {
         {
           "name": "tokenProgram",
           "isMut": false,
-          "isSigner": false
+          "isSigner": false,
+          "docs": [
+            "The token program of the banks' vaults"
+          ]
         }
       ],
       "args": []
@@ -1657,6 +1704,38 @@ export type MangoV4 = {
         }
       ]
     },
+    {
+      "name": "accountSetFeeTier",
+      "accounts": [
+        {
+          "name": "group",
+          "isMut": false,
+          "isSigner": false,
+          "relations": [
+            "admin"
+          ]
+        },
+        {
+          "name": "account",
+          "isMut": true,
+          "isSigner": false,
+          "relations": [
+            "group"
+          ]
+        },
+        {
+          "name": "admin",
+          "isMut": false,
+          "isSigner": true
+        }
+      ],
+      "args": [
+        {
+          "name": "feeTier",
+          "type": "u8"
+        }
+      ]
+    },
     {
       "name": "accountClose",
       "accounts": [
@@ -2191,7 +2270,10 @@ export type MangoV4 = {
         {
           "name": "tokenProgram",
           "isMut": false,
-          "isSigner": false
+          "isSigner": false,
+          "docs": [
+            "The token program of the loaned banks' vaults, token or token-2022"
+          ]
         },
         {
           "name": "instructions",
@@ -2246,7 +2328,10 @@ export type MangoV4 = {
         {
           "name": "tokenProgram",
           "isMut": false,
-          "isSigner": false
+          "isSigner": false,
+          "docs": [
+            "The token program of the loaned banks' vaults, token or token-2022"
+          ]
         },
         {
           "name": "associatedTokenProgram",
@@ -2285,7 +2370,10 @@ export type MangoV4 = {
         {
           "name": "tokenProgram",
           "isMut": false,
-          "isSigner": false
+          "isSigner": false,
+          "docs": [
+            "The token program of the loaned banks' vaults, token or token-2022"
+          ]
         }
       ],
       "args": [
@@ -2313,7 +2401,10 @@ export type MangoV4 = {
         {
           "name": "tokenProgram",
           "isMut": false,
-          "isSigner": false
+          "isSigner": false,
+          "docs": [
+            "The token program of the loaned banks' vaults, token or token-2022"
+          ]
         }
       ],
       "args": [
@@ -3912,6 +4003,33 @@ export type MangoV4 = {
       ],
       "args": []
     },
+    {
+      "name": "tokenSweepDust",
+      "accounts": [
+        {
+          "name": "group",
+          "isMut": false,
+          "isSigner": false,
+          "relations": [
+            "admin"
+          ]
+        },
+        {
+          "name": "admin",
+          "isMut": false,
+          "isSigner": true
+        },
+        {
+          "name": "bank",
+          "isMut": true,
+          "isSigner": false,
+          "relations": [
+            "group"
+          ]
+        }
+      ],
+      "args": []
+    },
     {
       "name": "perpCreateMarket",
       "docs": [
@@ -7711,12 +7829,49 @@ export type MangoV4 = {
             ],
             "type": "f32"
           },
+          {
+            "name": "isToken2022",
+            "docs": [
+              "Whether the vault is a token-2022 account instead of a token program account",
+              "",
+              "Flash loans, token_add_bank and serum3/openbook_v2 markets don't support these banks."
+            ],
+            "type": "u8"
+          },
+          {
+            "name": "padding2",
+            "type": {
+              "array": [
+                "u8",
+                3
+              ]
+            }
+          },
+          {
+            "name": "fixedPrice",
+            "docs": [
+              "If nonzero, the price of the token in native quote per native token.",
+              "",
+              "Such banks have no oracle: their oracle is set to fixed_price_oracle::ID and the",
+              "oracle account is never read."
+            ],
+            "type": {
+              "defined": "I80F48"
+            }
+          },
+          {
+            "name": "borrowLimit",
+            "docs": [
+              "zero means none, in token native"
+            ],
+            "type": "u64"
+          },
           {
             "name": "reserved",
             "type": {
               "array": [
                 "u8",
-                1900
+                1872
               ]
             }
           }
@@ -7997,12 +8152,19 @@ export type MangoV4 = {
             ],
             "type": "u64"
           },
+          {
+            "name": "feeTier",
+            "docs": [
+              "Fee discount tier, set by the group admin. See FEE_TIER_LOAN_ORIGINATION_DISCOUNTS."
+            ],
+            "type": "u8"
+          },
           {
             "name": "reserved",
             "type": {
               "array": [
                 "u8",
-                152
+                151
               ]
             }
           },
@@ -9771,12 +9933,16 @@ export type MangoV4 = {
             "name": "lastCollateralFeeCharge",
             "type": "u64"
           },
+          {
+            "name": "feeTier",
+            "type": "u8"
+          },
           {
             "name": "reserved",
             "type": {
               "array": [
                 "u8",
-                152
+                151
               ]
             }
           }
@@ -10700,6 +10866,9 @@ export type MangoV4 = {
           },
           {
             "name": "SwapWithoutFee"
+          },
+          {
+            "name": "TokenWithdraw"
           }
         ]
       }
@@ -11090,6 +11259,12 @@ export type MangoV4 = {
           },
           {
             "name": "HealthCheck"
+          },
+          {
+            "name": "AccountSetFeeTier"
+          },
+          {
+            "name": "TokenSweepDust"
           }
         ]
       }
@@ -11133,6 +11308,9 @@ export type MangoV4 = {
           },
           {
             "name": "RaydiumCLMM"
+          },
+          {
+            "name": "Fixed"
           }
         ]
       }
@@ -14447,6 +14625,56 @@ export type MangoV4 = {
       "code": 6072,
       "name": "InvalidHealth",
       "msg": "invalid health"
+    },
+    {
+      "code": 6073,
+      "name": "InvalidTokenProgram",
+      "msg": "the token program does not match the mint or the bank's vault"
+    },
+    {
+      "code": 6074,
+      "name": "InterestRateUtilizationOutOfRange",
+      "msg": "interest rate utilization points must be between 0 and 1"
+    },
+    {
+      "code": 6075,
+      "name": "InterestRateUtilizationOrder",
+      "msg": "interest rate util0 must not exceed util1"
+    },
+    {
+      "code": 6076,
+      "name": "InterestRateOrder",
+      "msg": "interest rates must satisfy rate0 <= rate1 <= max_rate"
+    },
+    {
+      "code": 6077,
+      "name": "NegativeInterestRate",
+      "msg": "interest rates must not be negative"
+    },
+    {
+      "code": 6078,
+      "name": "MintAlreadyRegistered",
+      "msg": "the mint is already registered in this group"
+    },
+    {
+      "code": 6079,
+      "name": "NoFreeBankSlot",
+      "msg": "all bank slots of the token are in use"
+    },
+    {
+      "code": 6080,
+      "name": "InvalidInput",
+      "msg": "invalid input"
+    },
+    {
+      "code": 6081,
+      "name": "BankBorrowLimit",
+      "msg": "borrow crosses the token's borrow limit"
+    },
+    {
+      "code": 6082,
+      "name": "TokenIndexAlreadyInUse",
+      "msg": "the token index is already in use in this group"
     }
   ]
 };
@@ -14957,7 +15185,10 @@ export const IDL: MangoV4 = {
         {
           "name": "tokenProgram",
           "isMut": false,
-          "isSigner": false
+          "isSigner": false,
+          "docs": [
+            "The token program or token-2022, must own the mint"
+          ]
         },
         {
           "name": "systemProgram",
@@ -14968,6 +15199,21 @@ export const IDL: MangoV4 = {
           "name": "rent",
           "isMut": false,
           "isSigner": false
+        },
+        {
+          "name": "preChecks",
+          "accounts": [
+            {
+              "name": "mintInfo",
+              "isMut": false,
+              "isSigner": false
+            },
+            {
+              "name": "bank",
+              "isMut": false,
+              "isSigner": false
+            }
+          ]
         }
       ],
       "args": [
@@ -15098,6 +15344,18 @@ export const IDL: MangoV4 = {
         {
           "name": "collateralFeePerDay",
           "type": "f32"
+        },
+        {
+          "name": "fixedPrice",
+          "type": {
+            "option": {
+              "defined": "I80F48"
+            }
+          }
+        },
+        {
+          "name": "borrowLimit",
+          "type": "u64"
         }
       ]
     },
@@ -15526,6 +15784,20 @@ export const IDL: MangoV4 = {
           "type": {
             "option": "bool"
           }
+        },
+        {
+          "name": "borrowLimitOpt",
+          "type": {
+            "option": "u64"
+          }
+        },
+        {
+          "name": "fixedPriceOpt",
+          "type": {
+            "option": {
+              "defined": "I80F48"
+            }
+          }
         }
       ]
     },
@@ -15695,7 +15967,10 @@ export const IDL: MangoV4 = {
         {
           "name": "tokenProgram",
           "isMut": false,
-          "isSigner": false
+          "isSigner": false,
+          "docs": [
+            "The token program of the banks' vaults"
+          ]
         }
       ],
       "args": []
@@ -16110,6 +16385,38 @@ export const IDL: MangoV4 = {
         }
       ]
     },
+    {
+      "name": "accountSetFeeTier",
+      "accounts": [
+        {
+          "name": "group",
+          "isMut": false,
+          "isSigner": false,
+          "relations": [
+            "admin"
+          ]
+        },
+        {
+          "name": "account",
+          "isMut": true,
+          "isSigner": false,
+          "relations": [
+            "group"
+          ]
+        },
+        {
+          "name": "admin",
+          "isMut": false,
+          "isSigner": true
+        }
+      ],
+      "args": [
+        {
+          "name": "feeTier",
+          "type": "u8"
+        }
+      ]
+    },
     {
       "name": "accountClose",
       "accounts": [
@@ -16644,7 +16951,10 @@ export const IDL: MangoV4 = {
         {
           "name": "tokenProgram",
           "isMut": false,
-          "isSigner": false
+          "isSigner": false,
+          "docs": [
+            "The token program of the loaned banks' vaults, token or token-2022"
+          ]
         },
         {
           "name": "instructions",
@@ -16699,7 +17009,10 @@ export const IDL: MangoV4 = {
         {
           "name": "tokenProgram",
           "isMut": false,
-          "isSigner": false
+          "isSigner": false,
+          "docs": [
+            "The token program of the loaned banks' vaults, token or token-2022"
+          ]
         },
         {
           "name": "associatedTokenProgram",
@@ -16738,7 +17051,10 @@ export const IDL: MangoV4 = {
         {
           "name": "tokenProgram",
           "isMut": false,
-          "isSigner": false
+          "isSigner": false,
+          "docs": [
+            "The token program of the loaned banks' vaults, token or token-2022"
+          ]
         }
       ],
       "args": [
@@ -16766,7 +17082,10 @@ export const IDL: MangoV4 = {
         {
           "name": "tokenProgram",
           "isMut": false,
-          "isSigner": false
+          "isSigner": false,
+          "docs": [
+            "The token program of the loaned banks' vaults, token or token-2022"
+          ]
         }
       ],
       "args": [
@@ -18365,6 +18684,33 @@ export const IDL: MangoV4 = {
       ],
       "args": []
     },
+    {
+      "name": "tokenSweepDust",
+      "accounts": [
+        {
+          "name": "group",
+          "isMut": false,
+          "isSigner": false,
+          "relations": [
+            "admin"
+          ]
+        },
+        {
+          "name": "admin",
+          "isMut": false,
+          "isSigner": true
+        },
+        {
+          "name": "bank",
+          "isMut": true,
+          "isSigner": false,
+          "relations": [
+            "group"
+          ]
+        }
+      ],
+      "args": []
+    },
     {
       "name": "perpCreateMarket",
       "docs": [
@@ -22164,12 +22510,49 @@ export const IDL: MangoV4 = {
             ],
             "type": "f32"
           },
+          {
+            "name": "isToken2022",
+            "docs": [
+              "Whether the vault is a token-2022 account instead of a token program account",
+              "",
+              "Flash loans, token_add_bank and serum3/openbook_v2 markets don't support these banks."
+            ],
+            "type": "u8"
+          },
+          {
+            "name": "padding2",
+            "type": {
+              "array": [
+                "u8",
+                3
+              ]
+            }
+          },
+          {
+            "name": "fixedPrice",
+            "docs": [
+              "If nonzero, the price of the token in native quote per native token.",
+              "",
+              "Such banks have no oracle: their oracle is set to fixed_price_oracle::ID and the",
+              "oracle account is never read."
+            ],
+            "type": {
+              "defined": "I80F48"
+            }
+          },
+          {
+            "name": "borrowLimit",
+            "docs": [
+              "zero means none, in token native"
+            ],
+            "type": "u64"
+          },
           {
             "name": "reserved",
             "type": {
               "array": [
                 "u8",
-                1900
+                1872
               ]
             }
           }
@@ -22450,12 +22833,19 @@ export const IDL: MangoV4 = {
             ],
             "type": "u64"
           },
+          {
+            "name": "feeTier",
+            "docs": [
+              "Fee discount tier, set by the group admin. See FEE_TIER_LOAN_ORIGINATION_DISCOUNTS."
+            ],
+            "type": "u8"
+          },
           {
             "name": "reserved",
             "type": {
               "array": [
                 "u8",
-                152
+                151
               ]
             }
           },
@@ -24224,12 +24614,16 @@ export const IDL: MangoV4 = {
             "name": "lastCollateralFeeCharge",
             "type": "u64"
           },
+          {
+            "name": "feeTier",
+            "type": "u8"
+          },
           {
             "name": "reserved",
             "type": {
               "array": [
                 "u8",
-                152
+                151
               ]
             }
           }
@@ -25153,6 +25547,9 @@ export const IDL: MangoV4 = {
           },
           {
             "name": "SwapWithoutFee"
+          },
+          {
+            "name": "TokenWithdraw"
           }
         ]
       }
@@ -25543,6 +25940,12 @@ export const IDL: MangoV4 = {
           },
           {
             "name": "HealthCheck"
+          },
+          {
+            "name": "AccountSetFeeTier"
+          },
+          {
+            "name": "TokenSweepDust"
           }
         ]
       }
@@ -25586,6 +25989,9 @@ export const IDL: MangoV4 = {
           },
           {
             "name": "RaydiumCLMM"
+          },
+          {
+            "name": "Fixed"
           }
         ]
       }
@@ -28900,6 +29306,56 @@ export const IDL: MangoV4 = {
       "code": 6072,
       "name": "InvalidHealth",
       "msg": "invalid health"
+    },
+    {
+      "code": 6073,
+      "name": "InvalidTokenProgram",
+      "msg": "the token program does not match the mint or the bank's vault"
+    },
+    {
+      "code": 6074,
+      "name": "InterestRateUtilizationOutOfRange",
+      "msg": "interest rate utilization points must be between 0 and 1"
+    },
+    {
+      "code": 6075,
+      "name": "InterestRateUtilizationOrder",
+      "msg": "interest rate util0 must not exceed util1"
+    },
+    {
+      "code": 6076,
+      "name": "InterestRateOrder",
+      "msg": "interest rates must satisfy rate0 <= rate1 <= max_rate"
+    },
+    {
+      "code": 6077,
+      "name": "NegativeInterestRate",
+      "msg": "interest rates must not be negative"
+    },
+    {
+      "code": 6078,
+      "name": "MintAlreadyRegistered",
+      "msg": "the mint is already registered in this group"
+    },
+    {
+      "code": 6079,
+      "name": "NoFreeBankSlot",
+      "msg": "all bank slots of the token are in use"
+    },
+    {
+      "code": 6080,
+      "name": "InvalidInput",
+      "msg": "invalid input"
+    },
+    {
+      "code": 6081,
+      "name": "BankBorrowLimit",
+      "msg": "borrow crosses the token's borrow limit"
+    },
+    {
+      "code": 6082,
+      "name": "TokenIndexAlreadyInUse",
+      "msg": "the token index is already in use in this group"
     }
   ]
 };
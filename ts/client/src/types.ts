@@ -10,12 +10,14 @@ export class FlashLoanWithdraw {
 export type FlashLoanType =
   | { unknown: Record<string, never> }
   | { swap: Record<string, never> }
-  | { swapWithoutFee: Record<string, never> };
+  | { swapWithoutFee: Record<string, never> }
+  | { tokenWithdraw: Record<string, never> };
 // eslint-disable-next-line @typescript-eslint/no-namespace
 export namespace FlashLoanType {
   export const unknown = { unknown: {} };
   export const swap = { swap: {} };
   export const swapWithoutFee = { swapWithoutFee: {} };
+  export const tokenWithdraw = { tokenWithdraw: {} };
 }
 
 export type HealthCheckKind =